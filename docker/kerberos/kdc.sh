@@ -0,0 +1,15 @@
+#!/bin/sh
+# Create the HDFESSE.TEST realm with the namenode principals, export
+# their keytab for the namenode and run the KDC.
+set -e
+export DEBIAN_FRONTEND=noninteractive
+apt-get update && apt-get install -y krb5-kdc krb5-admin-server
+cp /kerberos/krb5.conf /etc/krb5.conf
+kdb5_util create -s -r HDFESSE.TEST -P hdfesse
+kadmin.local -q "addprinc -randkey nn/nn@HDFESSE.TEST"
+kadmin.local -q "addprinc -randkey HTTP/nn@HDFESSE.TEST"
+rm -f /keytabs/nn.keytab.tmp
+kadmin.local -q "ktadd -k /keytabs/nn.keytab.tmp nn/nn@HDFESSE.TEST HTTP/nn@HDFESSE.TEST"
+chmod 644 /keytabs/nn.keytab.tmp
+mv /keytabs/nn.keytab.tmp /keytabs/nn.keytab
+exec krb5kdc -n
@@ -0,0 +1,227 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * `debug blocks`: the block layout of a file, with the replica
+ * locations and their storage types, like `fsck -files -blocks
+ * -locations` for a single file but without walking the namespace.
+ */
+use super::{Command, PathArgs};
+use anyhow::Result;
+use hdfesse_proto::hdfs::HdfsFileStatusProto;
+use libhdfesse::{
+    fs::{ensure_not_dir, Hdfs, HdfsErrorKind},
+    path::Path,
+    status::{DatanodeInfo, LocatedBlock},
+};
+use std::io::{self, Write};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "blocks",
+    about = "Print the blocks of a file and the locations of their replicas"
+)]
+pub struct BlocksArgs {
+    #[structopt(name = "path", required = true)]
+    paths: Vec<String>,
+}
+
+impl PathArgs for BlocksArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.paths.iter().map(String::as_str).collect()
+    }
+}
+
+fn replica_line(dn: &DatanodeInfo, storage: &str, storage_id: &str, cached: bool) -> String {
+    let mut line = format!(
+        "    {}:{} ({}) {}",
+        dn.id.ip_addr, dn.id.xfer_port, dn.id.host_name, storage
+    );
+    if !storage_id.is_empty() {
+        line.push(' ');
+        line.push_str(storage_id);
+    }
+    if let Some(rack) = &dn.network_location {
+        line.push(' ');
+        line.push_str(rack);
+    }
+    if cached {
+        line.push_str(" cached");
+    }
+    line
+}
+
+fn print_blocks<W: Write>(
+    out: &mut W,
+    path: &str,
+    status: &HdfsFileStatusProto,
+    blocks: &[LocatedBlock],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "{}: {} bytes, {} block(s), replication {}",
+        path,
+        status.get_length(),
+        blocks.len(),
+        status.get_block_replication()
+    )?;
+    for (idx, block) in blocks.iter().enumerate() {
+        write!(
+            out,
+            "#{} {}:blk_{}_{} offset {} length {}",
+            idx,
+            block.b.pool_id,
+            block.b.block_id,
+            block.b.generation_stamp,
+            block.offset,
+            block.b.num_bytes
+        )?;
+        if block.corrupt {
+            write!(out, " CORRUPT")?;
+        }
+        writeln!(out)?;
+        if block.locs.is_empty() {
+            writeln!(out, "    no live replicas")?;
+        }
+        for (pos, dn) in block.locs.iter().enumerate() {
+            let storage = block
+                .storage_types
+                .get(pos)
+                .map(|storage| format!("{:?}", storage))
+                .unwrap_or_else(|| "UNKNOWN".to_owned());
+            let storage_id = block.storage_ids.get(pos).map(String::as_str).unwrap_or("");
+            let cached = block
+                .cached_locs
+                .iter()
+                .any(|cached| cached.id.datanode_uuid == dn.id.datanode_uuid);
+            writeln!(out, "{}", replica_line(dn, &storage, storage_id, cached))?;
+        }
+    }
+    Ok(())
+}
+
+pub struct Blocks<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Blocks<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+
+    fn blocks(&mut self, path_str: &str) -> Result<()> {
+        let path = Path::new(path_str)?;
+        let status = self.hdfs.get_file_info(&path)?;
+        ensure_not_dir(&status, path_str.into(), HdfsErrorKind::Src)?;
+        let blocks =
+            self.hdfs
+                .get_file_block_locations_cached(&path, &status, 0, status.get_length())?;
+        let stdout = io::stdout();
+        print_blocks(&mut stdout.lock(), path_str, &status, &blocks)?;
+        Ok(())
+    }
+}
+
+impl<'a> Command for Blocks<'a> {
+    type Args = BlocksArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let mut has_error = false;
+        for path_str in &args.paths {
+            if let Err(e) = self.blocks(path_str) {
+                has_error = true;
+                eprintln!("blocks: {}", e);
+            }
+        }
+        Ok(i32::from(has_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libhdfesse::status::{AdminState, DatanodeID, ExtendedBlock, StorageType, Token};
+    use std::sync::Arc;
+
+    fn datanode(host: &str, uuid: &str) -> Arc<DatanodeInfo> {
+        Arc::new(DatanodeInfo {
+            id: DatanodeID {
+                ip_addr: "10.0.0.1".into(),
+                host_name: host.into(),
+                datanode_uuid: uuid.into(),
+                xfer_port: 9866,
+                info_port: 9864,
+                info_secure_port: None,
+                ipc_port: 9867,
+            },
+            network_location: Some("/rack1".into()),
+            upgrade_domain: None,
+            capacity: 0,
+            dfs_used: 0,
+            non_dfs_used: 0,
+            remaining: 0,
+            block_pool_used: 0,
+            cache_capacity: 0,
+            cache_used: 0,
+            last_update: 0,
+            last_update_monotonic: 0,
+            xceiver_count: 0,
+            admin_state: AdminState::NORMAL,
+            last_block_report_time: 0,
+            last_block_report_monotonic: 0,
+            num_blocks: 0,
+        })
+    }
+
+    #[test]
+    fn test_print_blocks() {
+        let dn1 = datanode("dn1", "u1");
+        let block = LocatedBlock {
+            b: ExtendedBlock {
+                pool_id: "BP-1".into(),
+                block_id: 1073741825,
+                num_bytes: 100,
+                generation_stamp: 1001,
+            },
+            offset: 0,
+            locs: vec![dn1.clone(), datanode("dn2", "u2")],
+            storage_ids: vec!["DS-1".into()],
+            storage_types: vec![StorageType::SSD, StorageType::DISK],
+            corrupt: true,
+            block_token: Token {
+                identifier: vec![],
+                password: vec![],
+                kind: "".into(),
+                service: "".into(),
+            },
+            cached_locs: vec![dn1],
+        };
+        let mut status = HdfsFileStatusProto::default();
+        status.set_length(100);
+        status.set_block_replication(2);
+
+        let mut out = vec![];
+        print_blocks(&mut out, "/f", &status, &[block]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/f: 100 bytes, 1 block(s), replication 2\n\
+             #0 BP-1:blk_1073741825_1001 offset 0 length 100 CORRUPT\n    \
+             10.0.0.1:9866 (dn1) SSD DS-1 /rack1 cached\n    \
+             10.0.0.1:9866 (dn2) DISK /rack1\n"
+        );
+    }
+}
@@ -0,0 +1,379 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * dfsadmin -setBalancerBandwidth, -getStorageReport and
+ * -listDecommissioning.
+ */
+use super::{
+    units::{byte_desc, format_percent, string2long},
+    Command,
+};
+use anyhow::Result;
+use libhdfesse::{
+    fs::Hdfs,
+    status::{AdminState, DatanodeInfo, DatanodeReportType, DatanodeStorageReport},
+};
+use std::io::{self, Write};
+use structopt::StructOpt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DatanodeError {
+    #[error("Unknown datanode report type \"{0}\"")]
+    ReportType(String),
+}
+
+pub(crate) fn parse_report_type(s: &str) -> Result<DatanodeReportType, DatanodeError> {
+    match s.to_ascii_lowercase().as_str() {
+        "all" => Ok(DatanodeReportType::ALL),
+        "live" => Ok(DatanodeReportType::LIVE),
+        "dead" => Ok(DatanodeReportType::DEAD),
+        "decommissioning" => Ok(DatanodeReportType::DECOMMISSIONING),
+        "enteringmaintenance" => Ok(DatanodeReportType::ENTERING_MAINTENANCE),
+        "inmaintenance" => Ok(DatanodeReportType::IN_MAINTENANCE),
+        _ => Err(DatanodeError::ReportType(s.to_owned())),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "setBalancerBandwidth",
+    about = "Change the network bandwidth used by each datanode during balancing"
+)]
+pub struct SetBalancerBandwidthArgs {
+    #[structopt(
+        name = "bandwidth",
+        parse(try_from_str = string2long),
+        help = "Bytes per second; k, m, g, etc. suffixes are accepted"
+    )]
+    bandwidth: u64,
+}
+
+pub struct SetBalancerBandwidth<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> SetBalancerBandwidth<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for SetBalancerBandwidth<'a> {
+    type Args = SetBalancerBandwidthArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        self.hdfs.set_balancer_bandwidth(args.bandwidth)?;
+        println!("Balancer bandwidth is set to {}", args.bandwidth);
+        Ok(0)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "getStorageReport",
+    about = "Report storage utilization of each datanode volume"
+)]
+pub struct StorageReportArgs {
+    #[structopt(
+        long = "type",
+        default_value = "all",
+        parse(try_from_str = parse_report_type),
+        help = "all, live, dead, decommissioning, enteringmaintenance or inmaintenance"
+    )]
+    report_type: DatanodeReportType,
+}
+
+fn print_storage_report<W: Write>(out: &mut W, report: &DatanodeStorageReport) -> io::Result<()> {
+    let id = &report.datanode_info.id;
+    writeln!(
+        out,
+        "Name: {}:{} ({})",
+        id.ip_addr, id.xfer_port, id.host_name
+    )?;
+    for storage in &report.storage_reports {
+        writeln!(
+            out,
+            "\tStorage: {} ({:?}, {:?})",
+            storage.storage.storage_id, storage.storage.storage_type, storage.storage.state
+        )?;
+        writeln!(
+            out,
+            "\t\tCapacity: {} ({})",
+            storage.capacity,
+            byte_desc(storage.capacity)
+        )?;
+        writeln!(
+            out,
+            "\t\tDFS Used: {} ({})",
+            storage.dfs_used,
+            byte_desc(storage.dfs_used)
+        )?;
+        writeln!(
+            out,
+            "\t\tNon DFS Used: {} ({})",
+            storage.non_dfs_used,
+            byte_desc(storage.non_dfs_used)
+        )?;
+        writeln!(
+            out,
+            "\t\tDFS Remaining: {} ({})",
+            storage.remaining,
+            byte_desc(storage.remaining)
+        )?;
+        writeln!(
+            out,
+            "\t\tBlock Pool Used: {} ({})",
+            storage.block_pool_used,
+            byte_desc(storage.block_pool_used)
+        )?;
+        let used_fraction = if storage.capacity != 0 {
+            storage.dfs_used as f64 / storage.capacity as f64
+        } else {
+            0.0
+        };
+        writeln!(out, "\t\tDFS Used%: {}", format_percent(used_fraction))?;
+        if let Some(mount) = &storage.mount {
+            writeln!(out, "\t\tMount: {}", mount)?;
+        }
+        if storage.failed {
+            writeln!(out, "\t\tFailed: true")?;
+        }
+    }
+    Ok(())
+}
+
+pub struct StorageReport<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> StorageReport<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for StorageReport<'a> {
+    type Args = StorageReportArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let reports = self.hdfs.get_datanode_storage_report(args.report_type)?;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for (idx, report) in reports.iter().enumerate() {
+            if idx != 0 {
+                writeln!(out)?;
+            }
+            print_storage_report(&mut out, report)?;
+        }
+        Ok(0)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "listDecommissioning",
+    about = "List datanodes being decommissioned"
+)]
+pub struct ListDecommissioningArgs {}
+
+// As in Java's DatanodeInfo.getDatanodeReport.
+fn admin_state_name(state: AdminState) -> &'static str {
+    match state {
+        AdminState::NORMAL => "Normal",
+        AdminState::DECOMMISSION_INPROGRESS => "Decommission in progress",
+        AdminState::DECOMMISSIONED => "Decommissioned",
+        AdminState::ENTERING_MAINTENANCE => "Entering maintenance",
+        AdminState::IN_MAINTENANCE => "In maintenance",
+    }
+}
+
+/// The RPC reports neither the blocks left to replicate nor the
+/// progress (they are in the namenode JMX only), so the number of
+/// blocks on the node is printed.
+fn print_decommissioning<W: Write>(out: &mut W, info: &DatanodeInfo, now: u64) -> io::Result<()> {
+    let id = &info.id;
+    writeln!(
+        out,
+        "Name: {}:{} ({})",
+        id.ip_addr, id.xfer_port, id.host_name
+    )?;
+    if let Some(rack) = &info.network_location {
+        writeln!(out, "Rack: {}", rack)?;
+    }
+    writeln!(
+        out,
+        "Decommission Status : {}",
+        admin_state_name(info.admin_state)
+    )?;
+    writeln!(
+        out,
+        "Configured Capacity: {} ({})",
+        info.capacity,
+        byte_desc(info.capacity)
+    )?;
+    writeln!(
+        out,
+        "DFS Used: {} ({})",
+        info.dfs_used,
+        byte_desc(info.dfs_used)
+    )?;
+    writeln!(out, "Blocks: {}", info.num_blocks)?;
+    writeln!(
+        out,
+        "Last contact: {}s ago",
+        now.saturating_sub(info.last_update) / 1000
+    )
+}
+
+pub struct ListDecommissioning<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> ListDecommissioning<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for ListDecommissioning<'a> {
+    type Args = ListDecommissioningArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, _args: Self::Args) -> Result<i32> {
+        let nodes = self
+            .hdfs
+            .get_datanode_report(DatanodeReportType::DECOMMISSIONING)?;
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        writeln!(out, "Decommissioning datanodes ({}):", nodes.len())?;
+        for node in &nodes {
+            writeln!(out)?;
+            print_decommissioning(&mut out, node, now)?;
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libhdfesse::status::{
+        DatanodeID, DatanodeStorage, StorageReport, StorageState, StorageType,
+    };
+
+    #[test]
+    fn test_parse_report_type() {
+        assert_eq!(parse_report_type("Live").unwrap(), DatanodeReportType::LIVE);
+        assert_eq!(
+            parse_report_type("enteringmaintenance").unwrap(),
+            DatanodeReportType::ENTERING_MAINTENANCE
+        );
+        assert!(parse_report_type("alive").is_err());
+    }
+
+    fn datanode_info(admin_state: AdminState) -> DatanodeInfo {
+        DatanodeInfo {
+            id: DatanodeID {
+                ip_addr: "10.0.0.1".into(),
+                host_name: "dn1".into(),
+                datanode_uuid: "uuid".into(),
+                xfer_port: 9866,
+                info_port: 9864,
+                info_secure_port: None,
+                ipc_port: 9867,
+            },
+            network_location: None,
+            upgrade_domain: None,
+            capacity: 4096,
+            dfs_used: 1024,
+            non_dfs_used: 0,
+            remaining: 3072,
+            block_pool_used: 1024,
+            cache_capacity: 0,
+            cache_used: 0,
+            last_update: 0,
+            last_update_monotonic: 0,
+            xceiver_count: 0,
+            admin_state,
+            last_block_report_time: 0,
+            last_block_report_monotonic: 0,
+            num_blocks: 0,
+        }
+    }
+
+    #[test]
+    fn test_storage_report_layout() {
+        let report = DatanodeStorageReport {
+            datanode_info: datanode_info(AdminState::NORMAL),
+            storage_reports: vec![StorageReport {
+                storage: DatanodeStorage {
+                    storage_id: "DS-1".into(),
+                    state: StorageState::NORMAL,
+                    storage_type: StorageType::SSD,
+                },
+                failed: false,
+                capacity: 4096,
+                dfs_used: 1024,
+                non_dfs_used: 0,
+                remaining: 3072,
+                block_pool_used: 1024,
+                mount: Some("/data/1".into()),
+            }],
+        };
+        let mut out = Vec::new();
+        print_storage_report(&mut out, &report).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Name: 10.0.0.1:9866 (dn1)
+\tStorage: DS-1 (SSD, NORMAL)
+\t\tCapacity: 4096 (4 KB)
+\t\tDFS Used: 1024 (1 KB)
+\t\tNon DFS Used: 0 (0 B)
+\t\tDFS Remaining: 3072 (3 KB)
+\t\tBlock Pool Used: 1024 (1 KB)
+\t\tDFS Used%: 25.00%
+\t\tMount: /data/1
+"
+        );
+    }
+
+    #[test]
+    fn test_decommissioning_layout() {
+        let mut info = datanode_info(AdminState::DECOMMISSION_INPROGRESS);
+        info.network_location = Some("/rack1".into());
+        info.num_blocks = 42;
+        info.last_update = 1_000_000;
+        let mut out = Vec::new();
+        print_decommissioning(&mut out, &info, 1_003_500).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Name: 10.0.0.1:9866 (dn1)
+Rack: /rack1
+Decommission Status : Decommission in progress
+Configured Capacity: 4096 (4 KB)
+DFS Used: 1024 (1 KB)
+Blocks: 42
+Last contact: 3s ago
+"
+        );
+    }
+}
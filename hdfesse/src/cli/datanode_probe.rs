@@ -0,0 +1,349 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * `debug datanode`: check that a datanode is reachable over the data
+ * transfer protocol from this host.  Most read failures are network
+ * problems between the client and datanodes that the namenode never
+ * sees.
+ *
+ * The probe sends a zero-length READ_BLOCK.  Without --block-of, the
+ * block does not exist: any well-formed error response shows that
+ * the datanode speaks the plain protocol.  A datanode that requires
+ * SASL (dfs.data.transfer.protection) closes such a connection.
+ * With --block-of, the address has to hold a replica of the block for
+ * the read to be checked; otherwise the block is as good as a
+ * nonexistent one.
+ */
+use super::{
+    doctor::{Finding, Severity},
+    Command, PathArgs,
+};
+use anyhow::Result;
+use hdfesse_proto::{
+    datatransfer::{BlockOpResponseProto, Status},
+    Security::TokenProto,
+};
+use libhdfesse::{
+    data_transfer::{read_block_op, read_response, send_op, DataTransferError, Op},
+    fs::{ensure_not_dir, Hdfs, HdfsErrorKind},
+    path::Path,
+    status::{DatanodeInfo, ExtendedBlock, LocatedBlock, Token},
+};
+use std::{
+    io::{self, BufReader, BufWriter},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+use structopt::StructOpt;
+
+/// Java's DFSConfigKeys.DFS_DATANODE_DEFAULT_PORT.
+const DEFAULT_XFER_PORT: u16 = 9866;
+
+const SASL_HINT: &str = "The datanode may require SASL (dfs.data.transfer.protection), \
+                         which hdfesse does not support yet.";
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "datanode",
+    about = "Check that a datanode is reachable over the data transfer protocol"
+)]
+pub struct DatanodeProbeArgs {
+    #[structopt(
+        long = "timeout",
+        default_value = "5",
+        help = "Connection and response timeout, seconds"
+    )]
+    timeout: u64,
+    #[structopt(
+        long = "block-of",
+        name = "file",
+        help = "Probe with the first block of the file instead of a nonexistent one"
+    )]
+    block_of: Option<String>,
+    #[structopt(
+        name = "host:port",
+        help = "Data transfer address; the port is 9866 by default"
+    )]
+    address: String,
+}
+
+impl PathArgs for DatanodeProbeArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.block_of.iter().map(String::as_str).collect()
+    }
+}
+
+fn with_default_port(address: &str) -> String {
+    // IPv6 addresses are in brackets with the port.
+    if address.ends_with(']') || !address.contains(':') {
+        format!("{}:{}", address, DEFAULT_XFER_PORT)
+    } else {
+        address.to_owned()
+    }
+}
+
+/// A block no datanode has.
+fn probe_block() -> LocatedBlock {
+    LocatedBlock {
+        b: ExtendedBlock {
+            pool_id: "".into(),
+            block_id: 0,
+            num_bytes: 0,
+            generation_stamp: 0,
+        },
+        offset: 0,
+        locs: vec![],
+        storage_ids: vec![],
+        storage_types: vec![],
+        corrupt: false,
+        block_token: Token::from(TokenProto::default()),
+        cached_locs: vec![],
+    }
+}
+
+/// The finding of the response; real is whether the block exists.
+fn judge_response(
+    address: &str,
+    resp: Result<BlockOpResponseProto, DataTransferError>,
+    real: bool,
+) -> Finding {
+    match resp {
+        Ok(resp) => match resp.get_status() {
+            Status::SUCCESS => Finding::ok(format!("{}: the block is readable", address)),
+            Status::ERROR_ACCESS_TOKEN if !real => Finding::ok(format!(
+                "{}: the datanode responds; block access tokens are enabled",
+                address
+            )),
+            status if !real => Finding::ok(format!(
+                "{}: the datanode responds ({:?}: {})",
+                address,
+                status,
+                resp.get_message()
+            )),
+            status => Finding::fail(
+                format!(
+                    "{}: the block is not readable ({:?}: {})",
+                    address,
+                    status,
+                    resp.get_message()
+                ),
+                "The replica may be missing or corrupt; check `hdfesse fsck`.",
+            ),
+        },
+        Err(DataTransferError::Io(e))
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Finding::fail(format!("{}: no response", address), SASL_HINT)
+        }
+        Err(DataTransferError::Io(e)) => Finding::fail(
+            format!("{}: the connection is closed: {}", address, e),
+            SASL_HINT,
+        ),
+        Err(e) => Finding::fail(
+            format!("{}: malformed response: {}", address, e),
+            "The port may belong to another service, e.g. the datanode's HTTP or IPC port.",
+        ),
+    }
+}
+
+/// The transfer address of a replica; datanodes register with their
+/// IP.
+fn replica_addr(loc: &DatanodeInfo) -> Option<SocketAddr> {
+    let ip = loc.id.ip_addr.parse::<IpAddr>().ok()?;
+    Some(SocketAddr::new(ip, loc.id.xfer_port as u16))
+}
+
+/// The transfer addresses of the replicas of the block.
+fn replica_addrs(block: &LocatedBlock) -> Vec<String> {
+    block
+        .locs
+        .iter()
+        .map(|loc| match replica_addr(loc) {
+            Some(addr) => addr.to_string(),
+            None => format!("{}:{}", loc.id.ip_addr, loc.id.xfer_port),
+        })
+        .collect()
+}
+
+/// Whether one of the replicas is at one of the resolved addresses.
+fn holds_replica(block: &LocatedBlock, addrs: &[SocketAddr]) -> bool {
+    block
+        .locs
+        .iter()
+        .filter_map(|loc| replica_addr(loc))
+        .any(|addr| addrs.contains(&addr))
+}
+
+fn exchange(
+    stream: &TcpStream,
+    block: &LocatedBlock,
+    client_name: &str,
+) -> Result<BlockOpResponseProto, DataTransferError> {
+    let op = read_block_op(block, client_name, 0, 0, false);
+    send_op(&mut BufWriter::new(stream), Op::ReadBlock, &op)?;
+    // Only the response is needed; the read-ahead is dropped with the
+    // reader.
+    read_response(&mut BufReader::new(stream))
+}
+
+pub struct DatanodeProbe<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> DatanodeProbe<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+
+    fn first_block(&mut self, path_str: &str) -> Result<LocatedBlock> {
+        let path = Path::new(path_str)?;
+        let status = self.hdfs.get_file_info(&path)?;
+        ensure_not_dir(&status, path_str.into(), HdfsErrorKind::Src)?;
+        self.hdfs
+            .get_file_block_locations_cached(&path, &status, 0, 1)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{}: the file has no blocks", path_str))
+    }
+
+    fn probe(&mut self, args: &DatanodeProbeArgs) -> Result<Vec<Finding>> {
+        let timeout = Duration::from_secs(args.timeout);
+        let address = with_default_port(&args.address);
+        let block = match &args.block_of {
+            Some(path_str) => self.first_block(path_str)?,
+            None => probe_block(),
+        };
+
+        let addrs: Vec<SocketAddr> = match address.to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => {
+                return Ok(vec![Finding::fail(
+                    format!("{}: cannot resolve: {}", address, e),
+                    "Check the host name and DNS.",
+                )])
+            }
+        };
+        let mut findings = vec![];
+        let real = args.block_of.is_some() && holds_replica(&block, &addrs);
+        if let (Some(path_str), false) = (&args.block_of, real) {
+            findings.push(Finding::warn(
+                format!(
+                    "{}: no replica of the first block of {} (replicas: {})",
+                    address,
+                    path_str,
+                    replica_addrs(&block).join(", ")
+                ),
+                "Only the connection is checked; give one of the replica addresses \
+                 to check the read.",
+            ));
+        }
+        let stream = addrs
+            .iter()
+            .map(|addr| TcpStream::connect_timeout(addr, timeout))
+            .find_map(Result::ok);
+        let stream = match stream {
+            Some(stream) => stream,
+            None => {
+                findings.push(Finding::fail(
+                    format!("{}: cannot connect", address),
+                    "Check that the datanode is running and dfs.datanode.address \
+                     is not blocked by a firewall.",
+                ));
+                return Ok(findings);
+            }
+        };
+        findings.push(Finding::ok(format!("{}: accepts connections", address)));
+
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let resp = exchange(&stream, &block, self.hdfs.client_name());
+        findings.push(judge_response(&address, resp, real));
+        Ok(findings)
+    }
+}
+
+impl<'a> Command for DatanodeProbe<'a> {
+    type Args = DatanodeProbeArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let findings = self.probe(&args)?;
+        for finding in &findings {
+            println!("{}", finding);
+        }
+        let failed = findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Fail);
+        Ok(i32::from(failed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: Status) -> Result<BlockOpResponseProto, DataTransferError> {
+        let mut resp = BlockOpResponseProto::default();
+        resp.set_status(status);
+        resp.set_message("Replica not found".to_owned());
+        Ok(resp)
+    }
+
+    #[test]
+    fn test_default_port() {
+        assert_eq!(with_default_port("dn1"), "dn1:9866");
+        assert_eq!(with_default_port("dn1:50010"), "dn1:50010");
+        assert_eq!(with_default_port("[::1]"), "[::1]:9866");
+        assert_eq!(with_default_port("[::1]:1004"), "[::1]:1004");
+    }
+
+    #[test]
+    fn test_holds_replica() {
+        let mut block = probe_block();
+        let mut loc = DatanodeInfo::from(hdfesse_proto::hdfs::DatanodeInfoProto::default());
+        loc.id.ip_addr = "10.0.0.2".into();
+        loc.id.xfer_port = 9866;
+        block.locs.push(loc.into());
+
+        let addr = |s: &str| vec![s.parse::<SocketAddr>().unwrap()];
+        assert!(holds_replica(&block, &addr("10.0.0.2:9866")));
+        assert!(!holds_replica(&block, &addr("10.0.0.3:9866")));
+        assert!(!holds_replica(&block, &addr("10.0.0.2:1004")));
+        assert!(!holds_replica(&probe_block(), &addr("10.0.0.2:9866")));
+        assert_eq!(replica_addrs(&block), ["10.0.0.2:9866"]);
+    }
+
+    #[test]
+    fn test_judge_response() {
+        let judge = |resp, real| judge_response("dn1:9866", resp, real).severity;
+        // Any answer about the nonexistent block is fine.
+        assert_eq!(judge(response(Status::ERROR), false), Severity::Ok);
+        assert_eq!(
+            judge(response(Status::ERROR_ACCESS_TOKEN), false),
+            Severity::Ok
+        );
+        assert_eq!(judge(response(Status::ERROR), true), Severity::Fail);
+        assert_eq!(judge(response(Status::SUCCESS), true), Severity::Ok);
+
+        let closed = Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        let finding = judge_response("dn1:9866", closed, false);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.to_string().contains("SASL"), "{}", finding);
+    }
+}
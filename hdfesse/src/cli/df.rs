@@ -0,0 +1,159 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The `-df` command.  HDFS has one set of statistics for the whole
+ * filesystem, so every path argument reports the same numbers, as in
+ * Java's `hdfs dfs -df`; the paths are only checked to exist.
+ */
+use super::{units::format_size, Command, PathArgs};
+use anyhow::Result;
+use libhdfesse::{
+    fs::{FsStatus, Hdfs},
+    path::Path,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "df",
+    about = "Show the capacity, free and used space of the filesystem"
+)]
+pub struct DfArgs {
+    #[structopt(
+        short = "h",
+        help = "Formats the sizes of files in a human-readable fashion"
+    )]
+    human: bool,
+    #[structopt(name = "path")]
+    paths: Vec<String>,
+}
+
+impl PathArgs for DfArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.paths.iter().map(String::as_str).collect()
+    }
+}
+
+const HEADER: [&str; 5] = ["Filesystem", "Size", "Used", "Available", "Use%"];
+
+fn row(filesystem: &str, status: &FsStatus, human: bool) -> [String; 5] {
+    let percent = if status.capacity != 0 {
+        // Java rounds the fraction with no decimals.
+        (status.used as f64 * 100.0 / status.capacity as f64).round()
+    } else {
+        0.0
+    };
+    [
+        filesystem.to_owned(),
+        format_size(status.capacity, human),
+        format_size(status.used, human),
+        format_size(status.remaining, human),
+        format!("{:.0}%", percent),
+    ]
+}
+
+/// The filesystem column is aligned left, the numbers right.
+fn format_table(rows: &[[String; 5]]) -> Vec<String> {
+    let mut widths = HEADER.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let header = HEADER.map(str::to_owned);
+    std::iter::once(&header)
+        .chain(rows)
+        .map(|row| {
+            let mut line = format!("{:<width$}", row[0], width = widths[0]);
+            for (width, cell) in widths.iter().zip(row).skip(1) {
+                line.push_str(&format!("  {:>width$}", cell, width = width));
+            }
+            line
+        })
+        .collect()
+}
+
+pub struct Df<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Df<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+
+    fn check_path(&mut self, path_str: &str) -> Result<()> {
+        let path = Path::new(path_str)?;
+        self.hdfs.get_file_info(&path)?;
+        Ok(())
+    }
+}
+
+impl<'a> Command for Df<'a> {
+    type Args = DfArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let mut has_error = false;
+        let mut count = usize::from(args.paths.is_empty());
+        for path_str in &args.paths {
+            match self.check_path(path_str) {
+                Ok(()) => count += 1,
+                Err(e) => {
+                    has_error = true;
+                    eprintln!("df: {}", e);
+                }
+            }
+        }
+
+        if count > 0 {
+            let status = self.hdfs.get_status()?;
+            let filesystem = format!("hdfs://{}", self.hdfs.authority());
+            let rows = vec![row(&filesystem, &status, args.human); count];
+            for line in format_table(&rows) {
+                println!("{}", line);
+            }
+        }
+        Ok(i32::from(has_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table() {
+        let status = FsStatus {
+            capacity: 10 << 30,
+            used: 1288490189,
+            remaining: 8 << 30,
+            ..FsStatus::default()
+        };
+        assert_eq!(
+            format_table(&[row("hdfs://mycluster", &status, true)]),
+            [
+                "Filesystem          Size   Used  Available  Use%",
+                "hdfs://mycluster  10.0 G  1.2 G      8.0 G   12%",
+            ]
+        );
+        assert_eq!(
+            row("hdfs://mycluster", &status, false)[1..],
+            ["10737418240", "1288490189", "8589934592", "12%"]
+        );
+        assert_eq!(row("hdfs://empty", &FsStatus::default(), false)[4], "0%");
+    }
+}
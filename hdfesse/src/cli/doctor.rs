@@ -0,0 +1,538 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The `doctor` command: it checks the setup step by step, from the
+ * config directory to the namenodes' HA states, and tells what to fix.
+ * It runs before any filesystem is opened, so it works with the
+ * configs that make other commands fail.
+ */
+use super::Command;
+use anyhow::Result;
+use hdfesse_proto::{
+    ClientNamenodeProtocol::HAServiceStateRequestProto, HAServiceProtocol::HAServiceStateProto,
+};
+use libhdfesse::{
+    hdconfig::{Config, ConfigMap, NamenodeConfig, HDFS_CONFIG},
+    path::Path,
+    rpc::{CallOptions, Connector, HdfsConnection, RpcConnection},
+    service::ClientNamenodeService,
+};
+use std::{
+    fmt, io,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    num::NonZeroU64,
+    path::Path as FsPath,
+    time::Duration,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "doctor",
+    about = "Check the config and the namenodes, and explain setup problems"
+)]
+pub struct DoctorArgs {
+    #[structopt(
+        long = "timeout",
+        default_value = "5",
+        help = "Timeout of each network check, in seconds"
+    )]
+    timeout: NonZeroU64,
+    #[structopt(long = "offline", help = "Check the config only")]
+    offline: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Finding {
+    pub(crate) severity: Severity,
+    message: String,
+    hint: Option<String>,
+}
+
+impl Finding {
+    pub(crate) fn ok(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    pub(crate) fn warn(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    pub(crate) fn fail(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Fail,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = match self.severity {
+            Severity::Ok => "[ OK ]",
+            Severity::Warn => "[WARN]",
+            Severity::Fail => "[FAIL]",
+        };
+        write!(f, "{} {}", tag, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_conf_dir(conf_dir: &FsPath) -> Vec<Finding> {
+    if !conf_dir.is_dir() {
+        return vec![Finding::fail(
+            format!("config directory {:?} does not exist", conf_dir),
+            "Set HADOOP_CONF_DIR or pass --conf with the directory of core-site.xml and hdfs-site.xml.",
+        )];
+    }
+    let mut findings = vec![Finding::ok(format!("config directory {:?}", conf_dir))];
+    for name in HDFS_CONFIG
+        .iter()
+        .filter(|name| name.ends_with("-site.xml"))
+    {
+        if conf_dir.join(name).is_file() {
+            findings.push(Finding::ok(format!("found {}", name)));
+        } else if matches!(name, "core-site.xml" | "hdfs-site.xml") {
+            findings.push(Finding::warn(
+                format!("{} is not found", name),
+                "Copy it from the cluster's client configuration.",
+            ));
+        }
+    }
+    findings
+}
+
+/// Config checks; the namenodes to probe are returned with the
+/// findings.
+fn check_config<'c>(
+    config_map: &ConfigMap,
+    config: &'c Config,
+) -> (Vec<Finding>, Vec<&'c NamenodeConfig>) {
+    let mut findings = vec![];
+    let mut namenodes = vec![];
+
+    let auth = config_map
+        .get_expanded("hadoop.security.authentication")
+        .map(|auth| auth.map(|auth| auth.trim().to_ascii_lowercase()));
+    match auth.as_ref().map(Option::as_deref) {
+        Ok(None) | Ok(Some("simple")) => findings.push(Finding::ok("SIMPLE authentication")),
+        Ok(Some(other)) => findings.push(Finding::fail(
+            format!("hadoop.security.authentication is {:?}", other),
+            "Only SIMPLE authentication is supported.",
+        )),
+        Err(e) => findings.push(Finding::fail(
+            format!("hadoop.security.authentication: {}", e),
+            "Fix the variables of the property.",
+        )),
+    }
+
+    let default_fs = match config.default_fs.as_deref() {
+        Some(default_fs) => default_fs,
+        None => {
+            findings.push(Finding::fail(
+                "fs.defaultFS is not set",
+                "Set it in core-site.xml, or pass -D fs.defaultFS=hdfs://<nameservice>.",
+            ));
+            return (findings, namenodes);
+        }
+    };
+    let path = match Path::new(default_fs) {
+        Ok(path) => path,
+        Err(e) => {
+            findings.push(Finding::fail(
+                format!("fs.defaultFS {:?} is not a valid URI: {}", default_fs, e),
+                "Use the hdfs://<nameservice> form.",
+            ));
+            return (findings, namenodes);
+        }
+    };
+    if !matches!(path.scheme().as_deref(), Some("hdfs")) {
+        findings.push(Finding::fail(
+            format!("fs.defaultFS {:?} is not an hdfs:// URI", default_fs),
+            "Use the hdfs://<nameservice> form.",
+        ));
+        return (findings, namenodes);
+    }
+    let authority = path.host().unwrap_or_default();
+    match config.nameservice(&authority) {
+        Some(nameservice) if nameservice.rpc_nodes.is_empty() => {
+            findings.push(Finding::fail(
+                format!("nameservice {:?} has no namenodes", authority),
+                format!(
+                    "Set dfs.ha.namenodes.{0} and dfs.namenode.rpc-address.{0}.<namenode> in hdfs-site.xml.",
+                    authority
+                ),
+            ));
+        }
+        Some(nameservice) => {
+            findings.push(Finding::ok(format!(
+                "fs.defaultFS {} has {} namenode(s)",
+                default_fs,
+                nameservice.rpc_nodes.len()
+            )));
+            namenodes.extend(nameservice.rpc_nodes.iter());
+        }
+        None => {
+            let known = config
+                .services
+                .iter()
+                .map(|s| s.name.as_ref())
+                .filter(|name| !name.is_empty())
+                .collect::<Vec<_>>();
+            findings.push(Finding::fail(
+                format!(
+                    "{:?} of fs.defaultFS is not in dfs.nameservices ({})",
+                    authority,
+                    if known.is_empty() {
+                        "none configured".to_owned()
+                    } else {
+                        known.join(", ")
+                    }
+                ),
+                "Add the nameservice to dfs.nameservices in hdfs-site.xml.",
+            ));
+        }
+    }
+    (findings, namenodes)
+}
+
+/// Connects with a timeout, unlike SimpleConnector.
+struct TimeoutConnector {
+    timeout: Duration,
+}
+
+impl Connector for TimeoutConnector {
+    fn get_connection<T: ToSocketAddrs>(&self, addr: T) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no addresses");
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+fn resolve(namenode: &NamenodeConfig) -> Result<Vec<SocketAddr>, Finding> {
+    let address = namenode.rpc_address.as_ref();
+    match address.to_socket_addrs() {
+        Ok(addrs) => Ok(addrs.collect()),
+        Err(e) => Err(Finding::fail(
+            format!(
+                "namenode {} ({}): cannot resolve: {}",
+                namenode.name, address, e
+            ),
+            "Check the host name in dfs.namenode.rpc-address and DNS.",
+        )),
+    }
+}
+
+fn probe_tcp(namenode: &NamenodeConfig, addrs: &[SocketAddr], timeout: Duration) -> Finding {
+    let connector = TimeoutConnector { timeout };
+    match connector.get_connection(addrs) {
+        Ok(_) => Finding::ok(format!(
+            "namenode {} ({}) accepts connections",
+            namenode.name, namenode.rpc_address
+        )),
+        Err(e) => Finding::fail(
+            format!(
+                "namenode {} ({}): cannot connect: {}",
+                namenode.name, namenode.rpc_address, e
+            ),
+            "Check that the namenode is running and the port is not blocked by a firewall.",
+        ),
+    }
+}
+
+/// The finding and whether the namenode is active.
+fn probe_ha_state(
+    namenode: &NamenodeConfig,
+    addrs: &[SocketAddr],
+    timeout: Duration,
+) -> (Finding, bool) {
+    let state = HdfsConnection::new_without_user(addrs, &TimeoutConnector { timeout }).and_then(
+        |mut conn| {
+            conn.set_call_options(CallOptions::default().with_timeout(timeout));
+            let mut service = ClientNamenodeService::new(conn);
+            let state = service.getHAServiceState(&HAServiceStateRequestProto::default());
+            let _ = service.shutdown();
+            state
+        },
+    );
+    match state {
+        Ok(resp) => {
+            let state = resp.get_state();
+            let message = format!("namenode {} is {:?}", namenode.name, state);
+            let finding = match state {
+                HAServiceStateProto::ACTIVE
+                | HAServiceStateProto::STANDBY
+                | HAServiceStateProto::OBSERVER => Finding::ok(message),
+                HAServiceStateProto::INITIALIZING => {
+                    Finding::warn(message, "The namenode is starting; retry later.")
+                }
+            };
+            (finding, state == HAServiceStateProto::ACTIVE)
+        }
+        Err(e) => (
+            Finding::fail(
+                format!("namenode {}: RPC failed: {}", namenode.name, e),
+                "Check that the address is the namenode's client RPC port (dfs.namenode.rpc-address).",
+            ),
+            false,
+        ),
+    }
+}
+
+/// Network checks of the nameservice; exactly one namenode is expected
+/// to be active.
+fn probe_namenodes(namenodes: &[&NamenodeConfig], timeout: Duration) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut active = 0;
+    for namenode in namenodes {
+        let addrs = match resolve(namenode) {
+            Ok(addrs) => addrs,
+            Err(finding) => {
+                findings.push(finding);
+                continue;
+            }
+        };
+        let tcp = probe_tcp(namenode, &addrs, timeout);
+        let reachable = tcp.severity == Severity::Ok;
+        findings.push(tcp);
+        if reachable {
+            let (state, is_active) = probe_ha_state(namenode, &addrs, timeout);
+            active += usize::from(is_active);
+            findings.push(state);
+        }
+    }
+    if !namenodes.is_empty() {
+        findings.extend(active_finding(active));
+    }
+    findings
+}
+
+/// The problem with the number of active namenodes, if any.
+fn active_finding(active: usize) -> Option<Finding> {
+    match active {
+        0 => Some(Finding::fail(
+            "no active namenode",
+            "Start the namenodes or fail over to a healthy one.",
+        )),
+        1 => None,
+        active => Some(Finding::fail(
+            format!("{} namenodes are active", active),
+            "Split brain: fence all but one of them, e.g. with `hdfs haadmin -transitionToStandby`.",
+        )),
+    }
+}
+
+pub struct Doctor<'a> {
+    conf_dir: &'a FsPath,
+    config_map: &'a ConfigMap,
+    config: &'a Config,
+}
+
+impl<'a> Doctor<'a> {
+    pub fn new(conf_dir: &'a FsPath, config_map: &'a ConfigMap, config: &'a Config) -> Self {
+        Self {
+            conf_dir,
+            config_map,
+            config,
+        }
+    }
+}
+
+impl<'a> Command for Doctor<'a> {
+    type Args = DoctorArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let mut findings = check_conf_dir(self.conf_dir);
+        let (config_findings, namenodes) = check_config(self.config_map, self.config);
+        findings.extend(config_findings);
+        if !args.offline {
+            findings.extend(probe_namenodes(
+                &namenodes,
+                Duration::from_secs(args.timeout.get()),
+            ));
+        }
+
+        for finding in &findings {
+            println!("{}", finding);
+        }
+        let failed = findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Fail);
+        Ok(i32::from(failed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libhdfesse::hdconfig::parse_config;
+    use std::net::TcpListener;
+
+    fn config_map(pairs: &[(&str, &str)]) -> ConfigMap {
+        let mut map = ConfigMap::new();
+        for (key, value) in pairs {
+            map.set(*key, *value);
+        }
+        map
+    }
+
+    fn severities(findings: &[Finding]) -> Vec<Severity> {
+        findings.iter().map(|finding| finding.severity).collect()
+    }
+
+    #[test]
+    fn test_check_config_no_default_fs() {
+        let map = config_map(&[]);
+        let config = parse_config(&map);
+        let (findings, namenodes) = check_config(&map, &config);
+        assert_eq!(severities(&findings), [Severity::Ok, Severity::Fail]);
+        assert!(findings[1].message.contains("fs.defaultFS"));
+        assert!(namenodes.is_empty());
+    }
+
+    #[test]
+    fn test_check_config_single_namenode() {
+        let map = config_map(&[
+            ("fs.defaultFS", "hdfs://other"),
+            ("dfs.nameservices", "mycluster"),
+            ("hadoop.security.authentication", "kerberos"),
+        ]);
+        let config = parse_config(&map);
+        let (findings, namenodes) = check_config(&map, &config);
+        assert_eq!(severities(&findings), [Severity::Fail, Severity::Ok]);
+        assert_eq!(namenodes[0].rpc_address.as_ref(), "other:8020");
+    }
+
+    #[test]
+    fn test_check_config_expanded_auth() {
+        let map = config_map(&[
+            ("fs.defaultFS", "hdfs://other"),
+            ("auth.method", "kerberos"),
+            ("hadoop.security.authentication", "${auth.method}"),
+        ]);
+        let config = parse_config(&map);
+        let (findings, _) = check_config(&map, &config);
+        assert_eq!(severities(&findings), [Severity::Fail, Severity::Ok]);
+        assert!(findings[0].message.contains("kerberos"), "{}", findings[0]);
+    }
+
+    #[test]
+    fn test_active_finding() {
+        assert!(active_finding(0).is_some());
+        assert!(active_finding(1).is_none());
+        let split = active_finding(2).unwrap();
+        assert_eq!(split.severity, Severity::Fail);
+        assert!(split.message.contains("2 namenodes"), "{}", split);
+    }
+
+    #[test]
+    fn test_zero_timeout() {
+        let args = |args: &[&str]| {
+            DoctorArgs::from_iter_safe(std::iter::once("doctor").chain(args.iter().copied()))
+        };
+        assert_eq!(args(&[]).unwrap().timeout.get(), 5);
+        assert!(args(&["--timeout", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_check_config_no_authority() {
+        let map = config_map(&[
+            ("fs.defaultFS", "hdfs:///"),
+            ("dfs.nameservices", "mycluster"),
+        ]);
+        let config = parse_config(&map);
+        let (findings, namenodes) = check_config(&map, &config);
+        assert_eq!(severities(&findings), [Severity::Ok, Severity::Fail]);
+        assert!(
+            findings[1].message.contains("(mycluster)"),
+            "{}",
+            findings[1]
+        );
+        assert!(namenodes.is_empty());
+    }
+
+    #[test]
+    fn test_check_config_namenodes() {
+        let map = config_map(&[
+            ("fs.defaultFS", "hdfs://mycluster"),
+            ("dfs.nameservices", "mycluster"),
+            ("dfs.ha.namenodes.mycluster", "nn1,nn2"),
+            (
+                "dfs.namenode.rpc-address.mycluster.nn1",
+                "nn1.example.com:8020",
+            ),
+            (
+                "dfs.namenode.rpc-address.mycluster.nn2",
+                "nn2.example.com:8020",
+            ),
+        ]);
+        let config = parse_config(&map);
+        let (findings, namenodes) = check_config(&map, &config);
+        assert_eq!(severities(&findings), [Severity::Ok, Severity::Ok]);
+        assert_eq!(namenodes.len(), 2);
+    }
+
+    #[test]
+    fn test_probe_closed_port() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        // The listener is dropped: the port is closed.
+        let namenode = NamenodeConfig {
+            name: "nn1".into(),
+            rpc_address: addr.to_string().into(),
+            servicerpc_address: addr.to_string().into(),
+        };
+        let findings = probe_namenodes(&[&namenode], Duration::from_secs(1));
+        assert_eq!(severities(&findings), [Severity::Fail, Severity::Fail]);
+        assert!(findings[0].message.contains("cannot connect"));
+    }
+
+    #[test]
+    fn test_display() {
+        let finding = Finding::warn("hdfs-site.xml is not found", "Copy it.");
+        assert_eq!(
+            finding.to_string(),
+            "[WARN] hdfs-site.xml is not found\n       Copy it."
+        );
+    }
+}
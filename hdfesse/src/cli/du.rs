@@ -0,0 +1,281 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The `-du` command.  Like FsShell, it asks the namenode for the
+ * content summary of each top-level child, which is fast even on
+ * huge trees.  The summary is not atomic, though: the namenode
+ * releases its lock while walking, so concurrent changes make it
+ * approximate; `--approximate` marks such sizes.  With
+ * `--client-side`, the tree is walked on the client instead, one
+ * listing per directory.  The entry filters, `--name` and
+ * `--skip-hidden`, need the walk and imply it.
+ */
+use super::{units::format_size, Command, FilterArgs, PathArgs};
+use anyhow::Result;
+use hdfesse_proto::hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType};
+use libhdfesse::{
+    filter::{AllOf, PathFilter},
+    fs::Hdfs,
+    path::Path,
+    status::DirEntry,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "du", about = "Show the amount of space used by files")]
+pub struct DuArgs {
+    #[structopt(short = "s", help = "Show the total of each path, not of its children")]
+    summary: bool,
+    #[structopt(
+        short = "h",
+        help = "Formats the sizes of files in a human-readable fashion"
+    )]
+    human: bool,
+    #[structopt(
+        long = "approximate",
+        conflicts_with = "client-side",
+        help = "Mark the sizes of directories as approximate"
+    )]
+    approximate: bool,
+    #[structopt(
+        long = "client-side",
+        help = "Walk the tree on the client instead of asking for the content summary"
+    )]
+    client_side: bool,
+    #[structopt(flatten)]
+    filter: FilterArgs,
+    #[structopt(name = "path", required = true)]
+    paths: Vec<String>,
+}
+
+impl PathArgs for DuArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.paths.iter().map(String::as_str).collect()
+    }
+}
+
+/// File length and disk space consumed with all replicas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Usage {
+    length: u64,
+    consumed: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: Usage) {
+        self.length = self.length.saturating_add(other.length);
+        self.consumed = self.consumed.saturating_add(other.consumed);
+    }
+}
+
+fn file_usage(status: &HdfsFileStatusProto) -> Usage {
+    let length = status.get_length();
+    let consumed = if status.has_ecPolicy() {
+        // Cell padding of the last stripe is ignored.
+        let schema = status.get_ecPolicy().get_schema();
+        let data = u64::from(schema.get_dataUnits().max(1));
+        let parity = u64::from(schema.get_parityUnits());
+        (u128::from(length) * u128::from(data + parity) / u128::from(data)) as u64
+    } else {
+        length.saturating_mul(u64::from(status.get_block_replication()))
+    };
+    Usage { length, consumed }
+}
+
+fn is_dir(status: &HdfsFileStatusProto) -> bool {
+    status.get_fileType() == HdfsFileStatusProto_FileType::IS_DIR
+}
+
+fn format_line(usage: Usage, path: &str, human: bool, approximate: bool) -> String {
+    let mark = if approximate { "~" } else { "" };
+    format!(
+        "{:<10} {:<10} {}",
+        format!("{}{}", mark, format_size(usage.length, human)),
+        format!("{}{}", mark, format_size(usage.consumed, human)),
+        path
+    )
+}
+
+pub struct Du<'a> {
+    hdfs: &'a mut Hdfs,
+    filter: AllOf,
+}
+
+impl<'a> Du<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self {
+            hdfs,
+            filter: AllOf::default(),
+        }
+    }
+
+    fn children(&mut self, dir: &Path<'_>) -> Result<Vec<HdfsFileStatusProto>> {
+        Ok(self.hdfs.list_status(dir)?.collect::<Result<_, _>>()?)
+    }
+
+    /// The usage of the files of the tree that the filter accepts.
+    fn walk(&mut self, dir: &Path<'_>) -> Result<Usage> {
+        let mut usage = Usage::default();
+        for entry in self.hdfs.walk(dir, &self.filter)? {
+            let (_, status) = entry?;
+            if !is_dir(&status) {
+                usage.add(file_usage(&status));
+            }
+        }
+        Ok(usage)
+    }
+
+    fn entry_usage(
+        &mut self,
+        path: &Path<'_>,
+        status: &HdfsFileStatusProto,
+        client_side: bool,
+    ) -> Result<Usage> {
+        if !is_dir(status) {
+            Ok(file_usage(status))
+        } else if client_side {
+            self.walk(path)
+        } else {
+            let summary = self.hdfs.get_content_summary(path)?;
+            Ok(Usage {
+                length: summary.length,
+                consumed: summary.space_consumed,
+            })
+        }
+    }
+
+    fn du(&mut self, path_str: &str, args: &DuArgs) -> Result<()> {
+        let path = Path::new(path_str)?;
+        let status = self.hdfs.get_file_info(&path)?;
+        if !is_dir(&status) {
+            println!(
+                "{}",
+                format_line(file_usage(&status), path_str, args.human, false)
+            );
+            return Ok(());
+        }
+
+        // The filters apply to the children too.
+        let client_side = args.client_side || !args.filter.is_empty();
+        let approximate = args.approximate && !client_side;
+        let mut total = Usage::default();
+        for entry in self.children(&path)? {
+            let name = entry.name();
+            let child = entry.absolute_path(&path)?;
+            let included = if is_dir(&entry) {
+                self.filter.descend(&child, &entry)
+            } else {
+                self.filter.accept(&child, &entry)
+            };
+            if !included {
+                continue;
+            }
+            let usage = self.entry_usage(&child, &entry, client_side)?;
+            if args.summary {
+                total.add(usage);
+            } else {
+                let child_str = format!("{}/{}", path_str.trim_end_matches('/'), name);
+                let approximate = approximate && is_dir(&entry);
+                println!(
+                    "{}",
+                    format_line(usage, &child_str, args.human, approximate)
+                );
+            }
+        }
+        if args.summary {
+            println!("{}", format_line(total, path_str, args.human, approximate));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Command for Du<'a> {
+    type Args = DuArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        self.filter = args.filter.filter()?;
+        let mut has_error = false;
+
+        for path_str in &args.paths {
+            if let Err(e) = self.du(path_str, &args) {
+                has_error = true;
+                eprintln!("du: {}", e);
+            }
+        }
+
+        Ok(i32::from(has_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfesse_proto::hdfs::{ECSchemaProto, ErasureCodingPolicyProto};
+
+    #[test]
+    fn test_file_usage() {
+        let mut status = HdfsFileStatusProto::default();
+        status.set_length(100);
+        status.set_block_replication(3);
+        assert_eq!(
+            file_usage(&status),
+            Usage {
+                length: 100,
+                consumed: 300
+            }
+        );
+
+        let mut schema = ECSchemaProto::default();
+        schema.set_dataUnits(6);
+        schema.set_parityUnits(3);
+        let mut policy = ErasureCodingPolicyProto::default();
+        policy.set_schema(schema);
+        status.set_block_replication(0);
+        status.set_length(600);
+        status.set_ecPolicy(policy);
+        assert_eq!(file_usage(&status).consumed, 900);
+    }
+
+    #[test]
+    fn test_args() {
+        let args = |args: &[&str]| {
+            DuArgs::from_iter_safe(std::iter::once("du").chain(args.iter().copied()))
+        };
+        let parsed = args(&["/a"]).unwrap();
+        assert!(!parsed.client_side);
+        assert!(!parsed.approximate);
+        assert!(args(&["--client-side", "/a"]).unwrap().client_side);
+        assert!(args(&["--client-side", "--approximate", "/a"]).is_err());
+        assert!(!args(&["--skip-hidden", "/a"]).unwrap().filter.is_empty());
+    }
+
+    #[test]
+    fn test_format_line() {
+        let usage = Usage {
+            length: 1536,
+            consumed: 4608,
+        };
+        assert_eq!(
+            format_line(usage, "/a", false, false),
+            "1536       4608       /a"
+        );
+        assert_eq!(
+            format_line(usage, "/a", true, true),
+            "~1.5 K     ~4.5 K     /a"
+        );
+    }
+}
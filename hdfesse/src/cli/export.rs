@@ -0,0 +1,246 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * `export-metadata`: one CSV record per entry of a tree, for
+ * inventories and offline analysis.  The tree is walked with
+ * Hdfs::walk and records are written as listing pages arrive, so only
+ * the directories still to be listed are kept in memory.
+ */
+use super::{Command, FilterArgs, PathArgs};
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use hdfesse_proto::hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType};
+use libhdfesse::{filter::AllOf, fs::Hdfs, path::Path};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+const OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+const HEADER: &str =
+    "path,type,size,replication,block_size,owner,group,permission,mtime,storage_policy";
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "export-metadata",
+    about = "Write the metadata of every entry of the trees as CSV"
+)]
+pub struct ExportArgs {
+    #[structopt(
+        short = "o",
+        long = "out",
+        help = "Output file; the standard output by default"
+    )]
+    out: Option<PathBuf>,
+    #[structopt(flatten)]
+    filter: FilterArgs,
+    #[structopt(name = "path", required = true)]
+    paths: Vec<String>,
+}
+
+impl PathArgs for ExportArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.paths.iter().map(String::as_str).collect()
+    }
+}
+
+/// Quote the field if it contains a separator, a quote or a line
+/// break, as in RFC 4180.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+fn file_type(status: &HdfsFileStatusProto) -> &'static str {
+    match status.get_fileType() {
+        HdfsFileStatusProto_FileType::IS_DIR => "dir",
+        HdfsFileStatusProto_FileType::IS_FILE => "file",
+        HdfsFileStatusProto_FileType::IS_SYMLINK => "symlink",
+    }
+}
+
+/// The CSV record of the entry.  The storage policy is empty if it is
+/// not set on the entry itself.
+fn record(path: &str, status: &HdfsFileStatusProto, policies: &HashMap<u32, String>) -> String {
+    let mtime = Utc
+        .timestamp_millis(status.get_modification_time() as i64)
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    let policy = match status.get_storagePolicy() {
+        0 => Cow::Borrowed(""),
+        id => policies
+            .get(&id)
+            .map(|name| Cow::Borrowed(name.as_str()))
+            .unwrap_or_else(|| id.to_string().into()),
+    };
+    format!(
+        "{},{},{},{},{},{},{},{:04o},{},{}",
+        csv_field(path),
+        file_type(status),
+        status.get_length(),
+        status.get_block_replication(),
+        status.get_blocksize(),
+        csv_field(status.get_owner()),
+        csv_field(status.get_group()),
+        status.get_permission().get_perm(),
+        mtime,
+        csv_field(&policy),
+    )
+}
+
+fn is_dir(status: &HdfsFileStatusProto) -> bool {
+    status.get_fileType() == HdfsFileStatusProto_FileType::IS_DIR
+}
+
+pub struct Export<'a> {
+    hdfs: &'a mut Hdfs,
+    policies: HashMap<u32, String>,
+    filter: AllOf,
+}
+
+impl<'a> Export<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self {
+            hdfs,
+            policies: HashMap::new(),
+            filter: AllOf::default(),
+        }
+    }
+
+    /// Export the tree.  Errors in subdirectories are reported and do
+    /// not stop the walk; the result tells if there were any.
+    fn export<W: Write>(&mut self, out: &mut W, path_str: &str) -> Result<bool> {
+        let path = Path::new(path_str)?;
+        let status = self.hdfs.get_file_info(&path)?;
+        writeln!(out, "{}", record(path_str, &status, &self.policies))?;
+        if !is_dir(&status) {
+            return Ok(false);
+        }
+
+        let mut has_error = false;
+        let mut walk = self.hdfs.walk(&path, &self.filter)?;
+        while let Some(entry) = walk.next() {
+            match entry {
+                Ok((child, status)) => writeln!(
+                    out,
+                    "{}",
+                    record(&child.to_string(), &status, &self.policies)
+                )?,
+                Err(e) => {
+                    has_error = true;
+                    eprintln!("export-metadata: {}: {}", walk.dir(), e);
+                }
+            }
+        }
+        Ok(has_error)
+    }
+
+    fn export_all<W: Write>(&mut self, out: &mut W, args: &ExportArgs) -> Result<bool> {
+        writeln!(out, "{}", HEADER)?;
+        let mut has_error = false;
+        for path_str in &args.paths {
+            match self.export(out, path_str) {
+                Ok(sub_err) => has_error |= sub_err,
+                Err(e) if e.is::<io::Error>() => return Err(e),
+                Err(e) => {
+                    has_error = true;
+                    eprintln!("export-metadata: {}", e);
+                }
+            }
+        }
+        out.flush()?;
+        Ok(has_error)
+    }
+}
+
+impl<'a> Command for Export<'a> {
+    type Args = ExportArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        self.policies = self
+            .hdfs
+            .get_storage_policies()?
+            .into_iter()
+            .map(|policy| (policy.get_policyId(), policy.get_name().to_owned()))
+            .collect();
+        self.filter = args.filter.filter()?;
+
+        let has_error = match &args.out {
+            Some(out_path) => {
+                let file = File::create(out_path)
+                    .with_context(|| format!("cannot create {}", out_path.display()))?;
+                let mut out = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, file);
+                self.export_all(&mut out, &args)?
+            }
+            None => {
+                let stdout = io::stdout();
+                let mut out = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, stdout.lock());
+                self.export_all(&mut out, &args)?
+            }
+        };
+        Ok(i32::from(has_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfesse_proto::acl::FsPermissionProto;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("/user/alice"), "/user/alice");
+        assert_eq!(csv_field("/a,b"), "\"/a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn test_record() {
+        let mut perm = FsPermissionProto::default();
+        perm.set_perm(0o644);
+        let mut status = HdfsFileStatusProto::default();
+        status.set_fileType(HdfsFileStatusProto_FileType::IS_FILE);
+        status.set_length(1024);
+        status.set_block_replication(3);
+        status.set_blocksize(128 << 20);
+        status.set_owner("alice".to_owned());
+        status.set_group("staff,admins".to_owned());
+        status.set_permission(perm);
+        status.set_modification_time(1_600_000_000_123);
+        status.set_storagePolicy(7);
+
+        let policies = vec![(7, "HOT".to_owned())].into_iter().collect();
+        assert_eq!(
+            record("/data/f", &status, &policies),
+            "/data/f,file,1024,3,134217728,alice,\"staff,admins\",0644,\
+             2020-09-13T12:26:40.123Z,HOT"
+        );
+
+        status.set_storagePolicy(0);
+        assert!(record("/data/f", &status, &policies).ends_with("Z,"));
+        status.set_storagePolicy(12);
+        assert!(record("/data/f", &status, &policies).ends_with("Z,12"));
+    }
+}
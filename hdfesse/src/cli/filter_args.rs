@@ -0,0 +1,55 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The entry filters of the commands that walk trees.
+ */
+use libhdfesse::{
+    filter::{AllOf, GlobFilter, HiddenFilter},
+    glob::GlobError,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct FilterArgs {
+    #[structopt(
+        long = "name",
+        help = "Only the entries whose name matches the glob pattern"
+    )]
+    name: Option<String>,
+    #[structopt(
+        long = "skip-hidden",
+        help = "Skip the entries whose name starts with . or _, and their subtrees"
+    )]
+    skip_hidden: bool,
+}
+
+impl FilterArgs {
+    /// Whether all entries are accepted.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.name.is_none() && !self.skip_hidden
+    }
+
+    pub(crate) fn filter(&self) -> Result<AllOf, GlobError> {
+        let mut filter = AllOf::default();
+        if let Some(name) = &self.name {
+            filter.0.push(Box::new(GlobFilter::new(name)?));
+        }
+        if self.skip_hidden {
+            filter.0.push(Box::new(HiddenFilter));
+        }
+        Ok(filter)
+    }
+}
@@ -0,0 +1,92 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * A small subset of fsck that doesn't need the namenode's HTTP
+ * servlet: listing of files with corrupt blocks.
+ */
+use super::{Command, PathArgs};
+use anyhow::Result;
+use libhdfesse::{fs::Hdfs, path::Path};
+use std::io::{self, Write};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "fsck", about = "Check the filesystem health")]
+pub struct FsckArgs {
+    #[structopt(name = "path", default_value = "/")]
+    path: String,
+    #[structopt(
+        long = "list-corruptfileblocks",
+        help = "Print the list of files with missing or corrupt blocks"
+    )]
+    list_corrupt_file_blocks: bool,
+}
+
+impl PathArgs for FsckArgs {
+    fn paths(&self) -> Vec<&str> {
+        vec![&self.path]
+    }
+}
+
+pub struct Fsck<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Fsck<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+
+    fn list_corrupt_file_blocks<W: Write>(&mut self, out: &mut W, path_str: &str) -> Result<usize> {
+        let path = Path::new(path_str)?;
+        let mut count = 0;
+        for file in self.hdfs.list_corrupt_file_blocks(&path)? {
+            let file = file?;
+            if count == 0 {
+                writeln!(
+                    out,
+                    "The list of corrupt files under path '{}' are:",
+                    path_str
+                )?;
+            }
+            writeln!(out, "{}", file)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<'a> Command for Fsck<'a> {
+    type Args = FsckArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        if !args.list_corrupt_file_blocks {
+            eprintln!("fsck: only --list-corruptfileblocks is supported");
+            return Ok(1);
+        }
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let count = self.list_corrupt_file_blocks(&mut out, &args.path)?;
+        writeln!(
+            out,
+            "The filesystem under path '{}' has {} CORRUPT files",
+            args.path, count
+        )?;
+        Ok(i32::from(count != 0))
+    }
+}
@@ -13,17 +13,36 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use std::cmp::Reverse;
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
 
-use super::Command;
-use crate::cli::ls_output::{LineFormat, Record};
-use hdfesse_proto::hdfs::HdfsFileStatusProto_FileType;
-use libhdfesse::fs::{Hdfs, HdfsError};
+use super::{Command, FilterArgs, PathArgs};
+use crate::cli::ls_output::{LineFormat, Record, SizeStyle, TimeFormat, TimeStyle, Zone};
+use crate::cli::units::SizeBase;
+use hdfesse_proto::hdfs::{
+    HdfsFileStatusProto, HdfsFileStatusProto_FileType, HdfsFileStatusProto_Flags,
+};
+use libhdfesse::filter::{AllOf, PathFilter};
+use libhdfesse::fs::{FsError, Hdfs, HdfsError};
+use libhdfesse::glob::{self, GlobPattern};
+use libhdfesse::ha_rpc::HaHdfsConnection;
 use libhdfesse::path::{Path, PathError};
+use libhdfesse::rpc::{RpcConnection, SimpleConnector};
+use libhdfesse::status::DirEntry;
+use libhdfesse::symlink::SymlinkPolicy;
 use structopt::StructOpt;
 use thiserror::Error;
 use tracing::{span, trace, Level};
 
+/// Entries requested from the listing at once; the same as the
+/// namenode's default dfs.ls.limit.
+const LIST_BATCH_SIZE: usize = 1000;
+const OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
 /*
  * See
  * hadoop/hadoop-common-project/hadoop-common/src/main/java/org/apache/hadoop/fs/shell/Ls.java
@@ -35,7 +54,6 @@ use tracing::{span, trace, Level};
     about = "List the contents that match the specified file pattern."
 )]
 pub struct LsOpts {
-    // TODO unimplemented
     #[structopt(
         short,
         name = "directory",
@@ -64,7 +82,6 @@ pub struct LsOpts {
         conflicts_with = "directory",
         help = "Recursively list subdirectories encountered"
     )]
-    // TODO unimplemented
     recursive: bool,
     #[structopt(
         short = "S",
@@ -73,6 +90,13 @@ pub struct LsOpts {
         help = "Sort output by file size"
     )]
     sort_size: bool,
+    #[structopt(
+        long = "time-style",
+        help = "Timestamp style: full-iso, long-iso (default), iso, locale or +FORMAT"
+    )]
+    time_style: Option<TimeStyle>,
+    #[structopt(long = "utc", help = "Show timestamps in UTC")]
+    utc: bool,
     #[structopt(short = "q", help = "Print ? instead of non-printable characters")]
     quote: bool,
     #[structopt(
@@ -80,9 +104,62 @@ pub struct LsOpts {
         help = "Formats the sizes of files in a human-readable fashion"
     )]
     human: bool,
-    // TODO unimplemented
-    #[structopt(long = "--stream", help = "Streaiming mode")]
+    #[structopt(
+        long = "bytes",
+        requires = "human",
+        help = "With -h, also show exact sizes in bytes"
+    )]
+    bytes: bool,
+    #[structopt(
+        long = "si",
+        requires = "human",
+        help = "With -h, use powers of 1000, not 1024"
+    )]
+    si: bool,
+    #[structopt(
+        long = "max-depth",
+        requires = "recursive",
+        help = "With -R, descend at most N levels below the paths"
+    )]
+    max_depth: Option<usize>,
+    #[structopt(
+        long = "min-depth",
+        requires = "recursive",
+        help = "With -R, do not list entries less than N levels below the paths"
+    )]
+    min_depth: Option<usize>,
+    #[structopt(
+        long = "one-zone",
+        requires = "recursive",
+        help = "With -R, do not descend into snapshottable directories or other encryption zones"
+    )]
+    one_zone: bool,
+    #[structopt(
+        short = "P",
+        conflicts_with_all = &["follow-arguments", "follow-all"],
+        help = "Never follow symbolic links"
+    )]
+    physical: bool,
+    #[structopt(
+        short = "H",
+        conflicts_with = "follow-all",
+        help = "Follow symbolic links given as arguments, but not the ones found with -R (default)"
+    )]
+    // It is the default, the flag is accepted for compatibility.
+    #[allow(unused)]
+    follow_arguments: bool,
+    #[structopt(
+        short = "L",
+        help = "Follow all symbolic links; with -R, descend into linked directories"
+    )]
+    follow_all: bool,
+    #[structopt(
+        long = "--stream",
+        help = "Streaming mode: unsorted, tab-separated output in constant memory"
+    )]
     stream: bool,
+    #[structopt(flatten)]
+    filter: FilterArgs,
     // TODO ...
 }
 
@@ -90,10 +167,30 @@ pub struct LsOpts {
 pub struct LsArgs {
     #[structopt(flatten)]
     opts: LsOpts,
-    #[structopt(name = "path")]
+    #[structopt(
+        long = "paths-from",
+        name = "file",
+        help = "Also list the paths from the file, one per line; - is stdin"
+    )]
+    paths_from: Option<PathBuf>,
+    #[structopt(name = "path", help = "Paths to list; - reads the paths from stdin")]
     paths: Vec<String>,
 }
 
+impl PathArgs for LsArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.paths.iter().map(String::as_str).collect()
+    }
+}
+
+/// Paths of the batch input, one per line; empty lines are skipped.
+fn read_paths<R: BufRead>(input: R) -> impl Iterator<Item = io::Result<String>> {
+    input
+        .lines()
+        .map(|line| line.map(|line| line.trim_end_matches('\r').to_owned()))
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+}
+
 #[derive(Debug, Error)]
 pub enum LsError {
     #[error(transparent)]
@@ -102,141 +199,823 @@ pub enum LsError {
     Fs(#[from] HdfsError),
     #[error(transparent)]
     LocalIo(std::io::Error),
+    #[error("ls: {0:?}: {1}")]
+    Input(PathBuf, std::io::Error),
 }
 
-pub struct Ls<'a> {
-    hdfs: &'a mut Hdfs,
+/// Destination of the listings or of the error messages.
+type Output<'a> = Box<dyn Write + 'a>;
+
+pub struct Ls<'a, R: RpcConnection = HaHdfsConnection<SimpleConnector>> {
+    hdfs: &'a mut Hdfs<R>,
+    out: Output<'a>,
+    err: Output<'a>,
+    filter: AllOf,
 }
 
-impl<'a> Ls<'a> {
-    pub fn new(hdfs: &'a mut Hdfs) -> Self {
-        Self { hdfs }
+impl<'a, R: RpcConnection> Ls<'a, R> {
+    pub fn new(hdfs: &'a mut Hdfs<R>) -> Self {
+        Self::with_output(
+            hdfs,
+            Box::new(io::BufWriter::with_capacity(
+                OUTPUT_BUFFER_SIZE,
+                io::stdout(),
+            )),
+            Box::new(io::stderr()),
+        )
+    }
+
+    /// Write the listings and the error messages to the outputs
+    /// rather than to stdout and stderr.
+    pub fn with_output(hdfs: &'a mut Hdfs<R>, out: Output<'a>, err: Output<'a>) -> Self {
+        Self {
+            hdfs,
+            out,
+            err,
+            filter: AllOf::default(),
+        }
     }
 
-    fn list_dir(&mut self, path: &str, args: &LsOpts) -> Result<(), LsError> {
+    /// Print the error message after the listings printed so far.
+    fn print_error(&mut self, e: &LsError) -> Result<(), LsError> {
+        Ls::write_error(&mut self.out, &mut self.err, e)
+    }
+
+    fn stat(
+        &mut self,
+        path_str: &str,
+        args: &LsOpts,
+    ) -> Result<(Path<'static>, HdfsFileStatusProto), LsError> {
         // TODO resolving
-        let path = Path::new(path).map_err(LsError::Uri)?;
+        let path = Path::new(path_str).map_err(LsError::Uri)?.into_owned();
+        let status = if Ls::symlink_policy(args).follow_arguments() {
+            self.hdfs.get_file_info(&path)
+        } else {
+            self.hdfs.get_file_link_info(&path)
+        };
+        let status = status
+            .map_err(|e| match e {
+                // FsShell names the path as it is given.
+                FsError::NotFound(_) => FsError::NotFound(path_str.to_owned()),
+                e => e,
+            })
+            .map_err(HdfsError::src)?;
+        Ok((path, status))
+    }
 
-        let stdout_obj = std::io::stdout();
-        let mut stdout = std::io::LineWriter::new(stdout_obj.lock());
+    /**
+    List the path of an argument.  A pattern is replaced by the paths
+    that match it, and each of them is listed as if it were an
+    argument; it is an error if none matches.  An error of a matched
+    path is reported and does not stop the others.
+    */
+    fn list_arg(&mut self, arg: &str, args: &LsOpts) -> Result<bool, LsError> {
+        if !glob::has_wildcard(arg) {
+            // The escapes are removed, like glob_status does.
+            let literal = GlobPattern::new(arg)
+                .map_err(HdfsError::src)?
+                .literal()
+                .unwrap_or_else(|| arg.to_owned());
+            let (path, status) = self.stat(&literal, args)?;
+            return self.list_status(&path, status, args);
+        }
+        let found = self.hdfs.glob_status(arg)?;
+        if found.is_empty() {
+            return Err(HdfsError::src(FsError::NotFound(arg.to_owned())).into());
+        }
+        let mut has_err = false;
+        for (path_str, mut status) in found {
+            let res = if status.get_fileType() == HdfsFileStatusProto_FileType::IS_SYMLINK {
+                // Matched links are not resolved.
+                self.stat(&path_str, args)
+            } else {
+                // The listing's entry name is not to be printed.
+                status.clear_path();
+                Path::new(&path_str)
+                    .map(|path| (path.into_owned(), status))
+                    .map_err(LsError::Uri)
+            };
+            match res.and_then(|(path, status)| self.list_status(&path, status, args)) {
+                Ok(sub_err) => has_err |= sub_err,
+                Err(LsError::LocalIo(e)) => return Err(LsError::LocalIo(e)),
+                Err(e) => {
+                    self.print_error(&e)?;
+                    has_err = true;
+                }
+            }
+        }
+        Ok(has_err)
+    }
 
-        let status = self
-            .hdfs
-            .get_file_info(&path)
-            .map_err(HdfsError::src)
-            .map_err(LsError::Fs)?;
+    /**
+    List the paths of the batch input.  Consecutive files are printed
+    as one group with aligned columns, up to LIST_BATCH_SIZE of them;
+    directories are listed as usual.  Errors of a path are reported
+    and do not stop the batch.
+    */
+    fn list_batch<B: BufRead>(
+        &mut self,
+        input: B,
+        input_name: &std::path::Path,
+        args: &LsOpts,
+    ) -> Result<bool, LsError> {
+        let time_format = Ls::time_format(args);
+        let mut files = vec![];
+        let mut has_err = false;
+        for path_str in read_paths(input) {
+            let path_str = path_str.map_err(|e| LsError::Input(input_name.to_owned(), e))?;
+            let (path, status) = match self.stat(&path_str, args) {
+                Ok(res) => res,
+                Err(e) => {
+                    self.print_files(&mut files, args)?;
+                    self.print_error(&e)?;
+                    has_err = true;
+                    continue;
+                }
+            };
+            if args.directory || status.get_fileType() != HdfsFileStatusProto_FileType::IS_DIR {
+                let mut rec = Record::from_hdfs_file_status(status, args.atime, &time_format);
+                rec.path = path_str.into();
+                files.push(rec);
+                if files.len() == LIST_BATCH_SIZE {
+                    self.print_files(&mut files, args)?;
+                }
+            } else {
+                self.print_files(&mut files, args)?;
+                match self.list_status(&path, status, args) {
+                    Ok(sub_err) => has_err |= sub_err,
+                    Err(LsError::LocalIo(e)) => return Err(LsError::LocalIo(e)),
+                    Err(e) => {
+                        self.print_error(&e)?;
+                        has_err = true;
+                    }
+                }
+            }
+        }
+        self.print_files(&mut files, args)?;
+        Ok(has_err)
+    }
 
-        // Haha, our installation uses old Java with old timezone
-        // data; but the hdfesse uses local timezone data which is
-        // updated with system updates.  And for Europe/Moscow it
-        // does matter.
-        let tz_offset = *chrono::Local::now().offset();
+    fn list_stdin(&mut self, args: &LsOpts) -> Result<bool, LsError> {
+        let stdin = io::stdin();
+        self.list_batch(stdin.lock(), std::path::Path::new("-"), args)
+    }
+
+    /// Print and clear the group of files of the batch input.
+    fn print_files(&mut self, files: &mut Vec<Record>, args: &LsOpts) -> Result<(), LsError> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        Ls::print_records(&mut self.out, None, std::mem::take(files), 1, args)?;
+        self.out.flush().map_err(LsError::LocalIo)
+    }
+
+    fn list_status(
+        &mut self,
+        path: &Path<'_>,
+        status: HdfsFileStatusProto,
+        args: &LsOpts,
+    ) -> Result<bool, LsError> {
+        let time_format = Ls::time_format(args);
 
-        let data_iter =
+        let has_err =
             if args.directory | (status.get_fileType() != HdfsFileStatusProto_FileType::IS_DIR) {
-                itertools::Either::Left(
-                    vec![Ok(Record::from_hdfs_file_status(
-                        status, args.atime, tz_offset,
-                    ))]
-                    .into_iter(),
-                )
+                let data = vec![Record::from_hdfs_file_status(
+                    status,
+                    args.atime,
+                    &time_format,
+                )];
+                Ls::print_records(&mut self.out, Some(path), data, 1, args)?;
+                false
+            } else if args.stream {
+                // Using streaming approach is crucial for huge
+                // directories where data does not fit into memory.  For
+                // sorted data, one has to collect everything in memory;
+                // but in case of problem, you can at least get default
+                // list and sort it with some external tool.  A batch is
+                // written out before the next one is fetched, so the
+                // memory use is constant.
+                let format = Ls::line_format(Some(path), args);
+                for batch in self.hdfs.list_status_batches(path, LIST_BATCH_SIZE)? {
+                    for ent in batch? {
+                        if !self.filter.0.is_empty() {
+                            let child = ent.absolute_path(path).map_err(LsError::Uri)?;
+                            if !self.filter.accept(&child, &ent) {
+                                continue;
+                            }
+                        }
+                        let rec = Record::from_hdfs_file_status(ent, args.atime, &time_format);
+                        format
+                            .print_streaming(&mut self.out, &rec)
+                            .map_err(LsError::LocalIo)?;
+                    }
+                    self.out.flush().map_err(LsError::LocalIo)?;
+                }
+                false
             } else {
-                itertools::Either::Right(self.hdfs.list_status(&path)?.map(|res| {
-                    res.map(|ent| Record::from_hdfs_file_status(ent, args.atime, tz_offset))
-                }))
+                self.list_tree(path, &status, args, &time_format)?
             };
+        self.out.flush().map_err(LsError::LocalIo)?;
+        Ok(has_err)
+    }
 
-        let mut format = if args.path_only {
-            LineFormat::compact(path, args.quote)
+    /// List the directory and, with -R, its subdirectories, depth
+    /// first and in the order of the listing.  Errors in
+    /// subdirectories are reported and do not stop the traversal;
+    /// the result tells if there were any.  With -L, links to
+    /// directories are descended into, unless they make a loop.
+    fn list_tree(
+        &mut self,
+        dir: &Path<'_>,
+        status: &HdfsFileStatusProto,
+        args: &LsOpts,
+        time_format: &TimeFormat,
+    ) -> Result<bool, LsError> {
+        let filter = TreeFilter {
+            filter: &self.filter,
+            zone: if args.one_zone {
+                Some(has_flag(status, HdfsFileStatusProto_Flags::HAS_CRYPT))
+            } else {
+                None
+            },
+        };
+        let max_depth = if args.recursive {
+            args.max_depth.unwrap_or(usize::MAX)
         } else {
-            LineFormat::full(path, args.human, args.quote)
+            1
         };
+        let mut walk = self
+            .hdfs
+            .walk(dir, &filter)?
+            .follow_links(args.recursive && Ls::symlink_policy(args).follow_in_tree())
+            .max_depth(max_depth)
+            .sort_subdirs_by(|a, b| Ls::compare_status(a, b, args));
 
-        if args.stream {
-            // Using streaming approach is crucial for huge
-            // directories where data does not fit into memory.  For
-            // sorted data, one has to collect everything in memory;
-            // but in case of problem, you can
-            for rec in data_iter {
-                let rec = rec?;
-                format
-                    .print_streaming(&mut stdout, &rec)
-                    .map_err(LsError::LocalIo)?;
+        // The entries of a directory come in a row; they are printed
+        // together, sorted.
+        let mut group: Option<(Path<'static>, usize)> = None;
+        let mut data = vec![];
+        let mut has_err = false;
+        loop {
+            let entry = walk.next();
+            if !matches!((&entry, &group), (Some(Ok(_)), Some((dir, _))) if dir == walk.dir()) {
+                if let Some((dir, depth)) = group.take() {
+                    // Like in FsShell, only the listings of arguments
+                    // have the header.
+                    if !(args.recursive || args.path_only) {
+                        writeln!(self.out, "Found {} items", data.len())
+                            .map_err(LsError::LocalIo)?;
+                    }
+                    Ls::print_records(
+                        &mut self.out,
+                        Some(&dir),
+                        std::mem::take(&mut data),
+                        depth,
+                        args,
+                    )?;
+                }
             }
-        } else {
-            let mut data = data_iter.collect::<Result<Vec<_>, HdfsError>>()?;
-            if !args.recursive {
-                println!("Found {} items", data.len());
+            match entry {
+                Some(Ok((_, status))) => {
+                    if group.is_none() {
+                        group = Some((walk.dir().clone(), walk.depth()));
+                    }
+                    data.push(Record::from_hdfs_file_status(
+                        status,
+                        args.atime,
+                        time_format,
+                    ));
+                }
+                Some(Err(e)) => {
+                    Ls::write_error(&mut self.out, &mut self.err, &e.into())?;
+                    has_err = true;
+                }
+                None => return Ok(has_err),
             }
+        }
+    }
 
-            {
-                let span = span!(Level::TRACE, "sort", len = data.len());
-                let _enter = span.enter();
-
-                if args.sort_mtime {
-                    if args.sort_reversed {
-                        data.sort_unstable_by_key(|a| a.timestamp);
-                    } else {
-                        // Please note that by default `hdfs dfs -ls` sorts
-                        // by timestamp from older to newer.
-                        data.sort_unstable_by_key(|a| Reverse(a.timestamp));
-                    }
-                } else if args.sort_size {
-                    if args.sort_reversed {
-                        data.sort_unstable_by_key(|a| a.size);
-                    } else {
-                        // Please note that by default `hdfs dfs -ls` sorts
-                        // by file size from largest to smallerst.
-                        data.sort_unstable_by_key(|a| Reverse(a.size));
-                    }
-                } else {
-                    // Default sort is sort by name; can be just reversed if
-                    // needed.
-                    if args.sort_reversed {
-                        data.reverse();
+    /// Report the error of a path, if any; false means that the
+    /// output is closed and nothing else is to be listed.
+    fn report(&mut self, res: Result<bool, LsError>, has_err: &mut bool) -> bool {
+        match res {
+            Ok(sub_err) => *has_err |= sub_err,
+            Err(e) => {
+                if let LsError::LocalIo(ioe) = &e {
+                    if ioe.kind() == std::io::ErrorKind::BrokenPipe {
+                        // Exit early because of EPIPE
+                        return false;
                     }
                 }
-                trace!("sorted");
+                *has_err = true;
+                if self.print_error(&e).is_err() {
+                    return false;
+                }
             }
+        }
+        true
+    }
+}
+
+/// The helpers that do not depend on the connection.
+impl Ls<'_> {
+    fn symlink_policy(args: &LsOpts) -> SymlinkPolicy {
+        if args.physical {
+            SymlinkPolicy::Physical
+        } else if args.follow_all {
+            SymlinkPolicy::Logical
+        } else {
+            SymlinkPolicy::Arguments
+        }
+    }
 
-            // Using streaming approach is crucial for huge directories where
-            // data does not fit into memory.  For sorted data, one has to
-            // collect everything in memory; but in case of problem, you can
-            // at least get default list and sort it with some external tool.
+    fn time_format(args: &LsOpts) -> TimeFormat {
+        // Haha, our installation uses old Java with old timezone
+        // data; but the hdfesse uses local timezone data which is
+        // updated with system updates.  And for Europe/Moscow it
+        // does matter.
+        let zone = if args.utc {
+            Zone::Fixed(chrono::FixedOffset::east(0))
+        } else {
+            Zone::Local
+        };
+        TimeFormat::new(args.time_style.clone().unwrap_or(TimeStyle::LongIso), zone)
+    }
+
+    /// Sort and print the entries of a listing, unless they are above
+    /// the minimal depth.  Entry paths are relative to the base, if
+    /// any.
+    fn print_records<W: Write>(
+        out: &mut W,
+        base: Option<&Path<'_>>,
+        mut data: Vec<Record>,
+        depth: usize,
+        args: &LsOpts,
+    ) -> Result<(), LsError> {
+        {
+            let span = span!(Level::TRACE, "sort", len = data.len());
+            let _enter = span.enter();
+
+            Ls::sort_records(&mut data, args);
+            trace!("sorted");
+        }
+
+        if depth >= args.min_depth.unwrap_or(0) {
+            let mut format = Ls::line_format(base, args);
             for entry in data.iter() {
                 format.update_len(entry);
             }
             for entry in data.iter() {
-                format.print(&mut stdout, entry).map_err(LsError::LocalIo)?;
+                format.print(out, entry).map_err(LsError::LocalIo)?;
             }
         }
         Ok(())
     }
+
+    /// Print the error message after the listings printed so far.
+    fn write_error(out: &mut dyn Write, err: &mut dyn Write, e: &LsError) -> Result<(), LsError> {
+        out.flush().map_err(LsError::LocalIo)?;
+        // Nothing else is to be done if stderr is closed.
+        let _ = writeln!(err, "{}", e);
+        Ok(())
+    }
+
+    fn line_format<W: Write>(base: Option<&Path<'_>>, args: &LsOpts) -> LineFormat<W> {
+        if args.path_only {
+            LineFormat::compact(base.cloned(), args.quote)
+        } else {
+            let size = SizeStyle {
+                human: args.human,
+                exact: args.bytes,
+                base: if args.si {
+                    SizeBase::Decimal
+                } else {
+                    SizeBase::Binary
+                },
+            };
+            LineFormat::full(base.cloned(), size, args.quote)
+        }
+    }
+
+    /// Order of Java's FsShell: by name, unless sorted by time or
+    /// size, with ties in the name order even if reversed.  The
+    /// namenode returns the entries by name too, but the order must
+    /// not depend on how the pages of the listing were fetched.
+    fn sort_records(data: &mut [Record], args: &LsOpts) {
+        data.sort_by(|a, b| {
+            Ls::compare(
+                (a.path.as_bytes(), a.timestamp, a.size),
+                (b.path.as_bytes(), b.timestamp, b.size),
+                args,
+            )
+        });
+    }
+
+    /// The order of sort_records for the listing entries; -R walks
+    /// the subdirectories in it.
+    fn compare_status(a: &HdfsFileStatusProto, b: &HdfsFileStatusProto, args: &LsOpts) -> Ordering {
+        let timestamp = |status: &HdfsFileStatusProto| {
+            if args.atime {
+                status.get_access_time()
+            } else {
+                status.get_modification_time()
+            }
+        };
+        Ls::compare(
+            (a.get_path(), timestamp(a), a.get_length()),
+            (b.get_path(), timestamp(b), b.get_length()),
+            args,
+        )
+    }
+
+    /// Compare (name, timestamp, size) of the entries.
+    fn compare(a: (&[u8], u64, u64), b: (&[u8], u64, u64), args: &LsOpts) -> Ordering {
+        // Names are compared byte-wise, like the namenode does.
+        let by_name = if args.sort_reversed && !(args.sort_mtime || args.sort_size) {
+            b.0.cmp(a.0)
+        } else {
+            a.0.cmp(b.0)
+        };
+        // Please note that by default `hdfs dfs -ls` sorts by
+        // timestamp from newer to older, and by file size from
+        // largest to smallest.
+        let by_key = if args.sort_mtime {
+            b.1.cmp(&a.1)
+        } else if args.sort_size {
+            b.2.cmp(&a.2)
+        } else {
+            Ordering::Equal
+        };
+        let by_key = if args.sort_reversed {
+            by_key.reverse()
+        } else {
+            by_key
+        };
+        by_key.then(by_name)
+    }
+}
+
+fn has_flag(status: &HdfsFileStatusProto, flag: HdfsFileStatusProto_Flags) -> bool {
+    status.get_flags() & (flag as u32) != 0
 }
 
-impl<'a> Command for Ls<'a> {
+/// The filter of the -R walk: the entry filters and, with --one-zone,
+/// only the directories of the root's encryption zone that are not
+/// snapshottable.
+struct TreeFilter<'f> {
+    filter: &'f AllOf,
+    /// Whether the root is encrypted, with --one-zone.
+    zone: Option<bool>,
+}
+
+impl PathFilter for TreeFilter<'_> {
+    fn accept(&self, path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        self.filter.accept(path, status)
+    }
+
+    fn descend(&self, path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        let in_zone = self
+            .zone
+            .map(|encrypted| {
+                !has_flag(status, HdfsFileStatusProto_Flags::SNAPSHOT_ENABLED)
+                    && has_flag(status, HdfsFileStatusProto_Flags::HAS_CRYPT) == encrypted
+            })
+            .unwrap_or(true);
+        in_zone && self.filter.descend(path, status)
+    }
+}
+
+impl<'a, R: RpcConnection> Command for Ls<'a, R> {
     type Args = LsArgs;
     type Error = LsError;
 
     fn run(&mut self, mut args: Self::Args) -> Result<i32, Self::Error> {
         let mut has_err = false;
+        self.filter = args.opts.filter.filter().map_err(HdfsError::src)?;
 
-        if args.paths.is_empty() {
+        if args.paths.is_empty() && args.paths_from.is_none() {
             args.paths.push(".".into());
         }
 
-        for path in args.paths {
-            if let Err(e) = self.list_dir(&path, &args.opts) {
-                if let LsError::LocalIo(ioe) = &e {
-                    if ioe.kind() == std::io::ErrorKind::BrokenPipe {
-                        // Exit early because of EPIPE
-                        break;
-                    }
-                }
-                has_err = true;
-                eprintln!("{}", e);
+        for path in &args.paths {
+            let res = if path == "-" {
+                self.list_stdin(&args.opts)
+            } else {
+                self.list_arg(path, &args.opts)
+            };
+            if !self.report(res, &mut has_err) {
+                return Ok(i32::from(has_err));
             }
         }
+        if let Some(file) = &args.paths_from {
+            let res = if file.as_os_str() == "-" {
+                self.list_stdin(&args.opts)
+            } else {
+                File::open(file)
+                    .map_err(|e| LsError::Input(file.clone(), e))
+                    .and_then(|input| self.list_batch(BufReader::new(input), file, &args.opts))
+            };
+            self.report(res, &mut has_err);
+        }
         Ok(i32::from(has_err))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libhdfesse::testing::{mock_hdfs, MockConnection};
+
+    fn dir_record() -> Record {
+        Record {
+            file_type: HdfsFileStatusProto_FileType::IS_DIR,
+            perm: 0o755,
+            has_acl: false,
+            replication: 0,
+            owner: "hdfs".into(),
+            group: "supergroup".into(),
+            size: 0,
+            timestamp: 0,
+            timestmap_str: String::new(),
+            path: "dir".into(),
+        }
+    }
+
+    fn opts(args: &[&str]) -> LsOpts {
+        LsOpts::from_iter(std::iter::once("ls").chain(args.iter().copied()))
+    }
+
+    fn dir_status(encrypted: bool, snapshot_enabled: bool) -> HdfsFileStatusProto {
+        let mut status = HdfsFileStatusProto::default();
+        status.set_fileType(HdfsFileStatusProto_FileType::IS_DIR);
+        let mut flags = 0;
+        if encrypted {
+            flags |= HdfsFileStatusProto_Flags::HAS_CRYPT as u32;
+        }
+        if snapshot_enabled {
+            flags |= HdfsFileStatusProto_Flags::SNAPSHOT_ENABLED as u32;
+        }
+        status.set_flags(flags);
+        status
+    }
+
+    #[test]
+    fn test_descend_one_zone() {
+        let path = Path::new("/dir").unwrap();
+        let filter = AllOf::default();
+        let one_zone = |encrypted| TreeFilter {
+            filter: &filter,
+            zone: Some(encrypted),
+        };
+        assert!(one_zone(true).descend(&path, &dir_status(true, false)));
+        assert!(!one_zone(false).descend(&path, &dir_status(true, false)));
+        assert!(!one_zone(false).descend(&path, &dir_status(false, true)));
+        let any_zone = TreeFilter {
+            filter: &filter,
+            zone: None,
+        };
+        assert!(any_zone.descend(&path, &dir_status(true, true)));
+    }
+
+    fn sorted(args: &[&str], entries: &[(&str, u64, u64)]) -> Vec<String> {
+        let mut data = entries
+            .iter()
+            .map(|(name, timestamp, size)| Record {
+                path: (*name).into(),
+                timestamp: *timestamp,
+                size: *size,
+                ..dir_record()
+            })
+            .collect::<Vec<_>>();
+        Ls::sort_records(&mut data, &opts(args));
+        data.into_iter().map(|rec| rec.path.into()).collect()
+    }
+
+    #[test]
+    fn test_sort_records() {
+        // Pages fetched with startAfter may be out of order if a file
+        // is created concurrently.
+        let entries = [("b", 2, 10), ("c", 1, 10), ("a", 2, 5), ("B", 3, 5)];
+        assert_eq!(sorted(&[], &entries), ["B", "a", "b", "c"]);
+        assert_eq!(sorted(&["-r"], &entries), ["c", "b", "a", "B"]);
+        assert_eq!(sorted(&["-t"], &entries), ["B", "a", "b", "c"]);
+        assert_eq!(sorted(&["-t", "-r"], &entries), ["c", "a", "b", "B"]);
+        assert_eq!(sorted(&["-S"], &entries), ["b", "c", "B", "a"]);
+        assert_eq!(sorted(&["-S", "-r"], &entries), ["B", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_read_paths() {
+        let input = &b"/a\r\n\n/b c\nrel/d"[..];
+        let paths = read_paths(input).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(paths, ["/a", "/b c", "rel/d"]);
+    }
+
+    #[test]
+    fn test_depth_requires_recursive() {
+        assert!(LsOpts::from_iter_safe(["ls", "--max-depth", "2"].iter().copied()).is_err());
+    }
+
+    #[test]
+    fn test_symlink_policy() {
+        assert_eq!(Ls::symlink_policy(&opts(&[])), SymlinkPolicy::Arguments);
+        assert_eq!(Ls::symlink_policy(&opts(&["-H"])), SymlinkPolicy::Arguments);
+        assert_eq!(Ls::symlink_policy(&opts(&["-P"])), SymlinkPolicy::Physical);
+        assert_eq!(Ls::symlink_policy(&opts(&["-RL"])), SymlinkPolicy::Logical);
+        assert!(LsOpts::from_iter_safe(["ls", "-P", "-L"].iter().copied()).is_err());
+    }
+
+    const TREE: &[(&str, bool, u64)] = &[
+        ("/data/a1/part-0", false, 1024),
+        ("/data/a1/part-1", false, 42),
+        ("/data/a2", false, 7),
+        ("/data/b1/part-0", false, 5),
+        ("/data/empty", true, 0),
+    ];
+
+    /// Exit code, stdout and stderr of ls over TREE.
+    fn run_ls(args: &[&str]) -> (i32, String, String) {
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(TREE);
+        let mut hdfs = mock_hdfs(conn);
+        let (mut out, mut err) = (vec![], vec![]);
+        let args = LsArgs::from_iter(["ls", "--utc"].iter().chain(args).copied());
+        let code = Ls::with_output(&mut hdfs, Box::new(&mut out), Box::new(&mut err))
+            .run(args)
+            .unwrap();
+        (
+            code,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    /// The output and the errors of ls, in the order of writing.
+    struct SharedLog<'l>(&'l std::cell::RefCell<Vec<u8>>);
+
+    impl Write for SharedLog<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_list_batch() {
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(TREE);
+        let mut hdfs = mock_hdfs(conn);
+        let log = std::cell::RefCell::new(vec![]);
+        let input = &b"/data/a2\n/nx\n/data/a1/part-0\n/data/a1/part-1\n/data/b1\n/data/a2\n"[..];
+        let has_err = Ls::with_output(
+            &mut hdfs,
+            Box::new(SharedLog(&log)),
+            Box::new(SharedLog(&log)),
+        )
+        .list_batch(input, std::path::Path::new("-"), &opts(&["--utc"]))
+        .unwrap();
+        assert!(has_err);
+        // Files are grouped until a directory or an error; the error
+        // comes after the files before it.
+        assert_eq!(
+            String::from_utf8(log.into_inner()).unwrap(),
+            "\
+-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2
+ls: `/nx': No such file or directory
+-rw-r--r--   0 hdfs supergroup       1024 1970-01-01 00:00 /data/a1/part-0
+-rw-r--r--   0 hdfs supergroup         42 1970-01-01 00:00 /data/a1/part-1
+Found 1 items
+-rw-r--r--   0 hdfs supergroup          5 1970-01-01 00:00 /data/b1/part-0
+-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2
+"
+        );
+    }
+
+    // The expected outputs are of `hadoop fs -ls` over the same tree,
+    // up to the replication that the mock does not set.
+
+    #[test]
+    fn test_ls_arguments() {
+        let (code, out, err) = run_ls(&["/data/a1", "/data/empty", "/nx", "/data/a2"]);
+        assert_eq!(code, 1);
+        assert_eq!(
+            out,
+            "\
+Found 2 items
+-rw-r--r--   0 hdfs supergroup       1024 1970-01-01 00:00 /data/a1/part-0
+-rw-r--r--   0 hdfs supergroup         42 1970-01-01 00:00 /data/a1/part-1
+-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2
+"
+        );
+        assert_eq!(err, "ls: `/nx': No such file or directory\n");
+    }
+
+    #[test]
+    fn test_ls_glob() {
+        let (code, out, err) = run_ls(&["/data/*1"]);
+        assert_eq!((code, err.as_str()), (0, ""));
+        assert_eq!(
+            out,
+            "\
+Found 2 items
+-rw-r--r--   0 hdfs supergroup       1024 1970-01-01 00:00 /data/a1/part-0
+-rw-r--r--   0 hdfs supergroup         42 1970-01-01 00:00 /data/a1/part-1
+Found 1 items
+-rw-r--r--   0 hdfs supergroup          5 1970-01-01 00:00 /data/b1/part-0
+"
+        );
+    }
+
+    #[test]
+    fn test_ls_escaped() {
+        let (code, out, err) = run_ls(&["/data/a\\2"]);
+        assert_eq!((code, err.as_str()), (0, ""));
+        assert_eq!(
+            out,
+            "-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2\n"
+        );
+    }
+
+    #[test]
+    fn test_ls_directory_glob() {
+        let (code, out, err) = run_ls(&["-d", "/data/{b,a}*"]);
+        assert_eq!((code, err.as_str()), (0, ""));
+        assert_eq!(
+            out,
+            "\
+drwxr-xr-x   - hdfs supergroup          0 1970-01-01 00:00 /data/a1
+-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2
+drwxr-xr-x   - hdfs supergroup          0 1970-01-01 00:00 /data/b1
+"
+        );
+    }
+
+    #[test]
+    fn test_ls_glob_no_match() {
+        let (code, out, err) = run_ls(&["/data/c*", "/data/a2"]);
+        assert_eq!(code, 1);
+        assert_eq!(
+            out,
+            "-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2\n"
+        );
+        assert_eq!(err, "ls: `/data/c*': No such file or directory\n");
+    }
+
+    #[test]
+    fn test_ls_path_only() {
+        let (code, out, _) = run_ls(&["-C", "/data"]);
+        assert_eq!(code, 0);
+        assert_eq!(out, "/data/a1\n/data/a2\n/data/b1\n/data/empty\n");
+    }
+
+    #[test]
+    fn test_ls_recursive() {
+        let (code, out, err) = run_ls(&["-R", "/data"]);
+        assert_eq!((code, err.as_str()), (0, ""));
+        assert_eq!(
+            out,
+            "\
+drwxr-xr-x   - hdfs supergroup          0 1970-01-01 00:00 /data/a1
+-rw-r--r--   0 hdfs supergroup          7 1970-01-01 00:00 /data/a2
+drwxr-xr-x   - hdfs supergroup          0 1970-01-01 00:00 /data/b1
+drwxr-xr-x   - hdfs supergroup          0 1970-01-01 00:00 /data/empty
+-rw-r--r--   0 hdfs supergroup       1024 1970-01-01 00:00 /data/a1/part-0
+-rw-r--r--   0 hdfs supergroup         42 1970-01-01 00:00 /data/a1/part-1
+-rw-r--r--   0 hdfs supergroup          5 1970-01-01 00:00 /data/b1/part-0
+"
+        );
+
+        // The subdirectories are walked in the listing order.
+        let (_, out, _) = run_ls(&["-R", "-r", "-C", "/data"]);
+        assert_eq!(
+            out,
+            "/data/empty\n/data/b1\n/data/a2\n/data/a1\n\
+             /data/b1/part-0\n/data/a1/part-1\n/data/a1/part-0\n"
+        );
+    }
+
+    #[test]
+    fn test_ls_recursive_filters() {
+        let paths = |args: &[&str]| {
+            let args = ["-R", "-C"].iter().chain(args).copied().collect::<Vec<_>>();
+            let (code, out, err) = run_ls(&args);
+            assert_eq!((code, err.as_str()), (0, ""));
+            out
+        };
+        assert_eq!(
+            paths(&["--max-depth", "1", "/data"]),
+            "/data/a1\n/data/a2\n/data/b1\n/data/empty\n"
+        );
+        assert_eq!(
+            paths(&["--min-depth", "2", "/data"]),
+            "/data/a1/part-0\n/data/a1/part-1\n/data/b1/part-0\n"
+        );
+        assert_eq!(
+            paths(&["--name", "part-0", "/data"]),
+            "/data/a1/part-0\n/data/b1/part-0\n"
+        );
+    }
+}
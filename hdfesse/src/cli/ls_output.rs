@@ -13,14 +13,20 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
+use super::units::{format_human, SizeBase};
+use chrono::{
+    format::{Item, StrftimeItems},
+    TimeZone,
+};
 use hdfesse_proto::hdfs::{
     HdfsFileStatusProto, HdfsFileStatusProto_FileType, HdfsFileStatusProto_Flags,
 };
 use libhdfesse::path;
-use number_prefix::NumberPrefix;
 use std::borrow::Cow;
 use std::cmp::max;
 use std::io::Write;
+use std::str::FromStr;
+use thiserror::Error;
 
 fn format_flag_group(group: u32) -> &'static str {
     match group {
@@ -48,7 +54,7 @@ fn format_type(type_: HdfsFileStatusProto_FileType) -> char {
 
 // TODO It can be optimized to write, not to create a string.  But
 // does it worth it?
-fn format_flags(flags: u32) -> String {
+pub(crate) fn format_flags(flags: u32) -> String {
     let mut res = String::with_capacity(9);
     for offset in [6u32, 3, 0].iter() {
         res.push_str(format_flag_group((flags >> offset) & 0x7));
@@ -89,7 +95,7 @@ impl Record {
     pub(crate) fn from_hdfs_file_status(
         mut entry: HdfsFileStatusProto,
         atime: bool,
-        tz_offset: chrono::FixedOffset,
+        time_format: &TimeFormat,
     ) -> Self {
         let timestamp = if atime {
             entry.get_access_time()
@@ -109,7 +115,7 @@ impl Record {
             } else {
                 entry.get_modification_time()
             },
-            timestmap_str: DateFormatter::format_datetime(timestamp, tz_offset),
+            timestmap_str: time_format.format(timestamp),
             // TODO: move formatting option to formatter.
             // Record should hold a Vec.
             path: String::from_utf8_lossy(entry.get_path()).into(),
@@ -182,51 +188,181 @@ impl<W: Write> FieldFormatter<W> for ReplicationFormatter {
     }
 }
 
-#[derive(Default)]
-struct SimpleSizeFormatter {}
+/// How the size column is printed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SizeStyle {
+    /// Human-readable size with a unit prefix.
+    pub(crate) human: bool,
+    /// Exact size in bytes; implied when not human.
+    pub(crate) exact: bool,
+    pub(crate) base: SizeBase,
+}
 
-impl<W: Write> FieldFormatter<W> for SimpleSizeFormatter {
-    fn update_len(&mut self, _entry: &Record) {}
+impl Default for SizeStyle {
+    fn default() -> Self {
+        Self {
+            human: false,
+            exact: true,
+            base: SizeBase::Binary,
+        }
+    }
+}
+
+struct SizeFormatter {
+    style: SizeStyle,
+    human_len: usize,
+}
+
+impl SizeFormatter {
+    fn new(style: SizeStyle) -> Self {
+        Self {
+            style,
+            human_len: 10,
+        }
+    }
+
+    fn format_human(&self, val: u64) -> String {
+        format_human(val, self.style.base)
+    }
+
+    fn has_exact(&self) -> bool {
+        self.style.exact || !self.style.human
+    }
+}
+
+impl<W: Write> FieldFormatter<W> for SizeFormatter {
+    fn update_len(&mut self, entry: &Record) {
+        if self.style.human {
+            self.human_len = max(
+                self.human_len,
+                self.format_human(entry.size).chars().count(),
+            );
+        }
+    }
 
     fn print(&self, out: &mut W, entry: &Record) -> std::io::Result<()> {
-        write!(out, " {0:>10}", entry.size)
+        if self.style.human {
+            write!(
+                out,
+                "{0:>1$}",
+                self.format_human(entry.size),
+                self.human_len + 1
+            )?;
+        }
+        if self.has_exact() {
+            write!(out, " {0:>10}", entry.size)?;
+        }
+        Ok(())
     }
 
     fn print_streaming(&self, out: &mut W, entry: &Record) -> std::io::Result<()> {
-        write!(out, "{}", entry.size)
+        if self.style.human {
+            write!(out, "{}", self.format_human(entry.size))?;
+            if self.has_exact() {
+                write!(out, "\t")?;
+            }
+        }
+        if self.has_exact() {
+            write!(out, "{}", entry.size)?;
+        }
+        Ok(())
     }
 }
 
-struct HumanSizeFormatter {
-    max_len: usize,
+/// Six months, as GNU ls defines it: half of the average Gregorian
+/// year.
+const SIX_MONTHS_MILLIS: i64 = 31_556_952_000 / 2;
+
+/// Timestamp format, like GNU ls --time-style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TimeStyle {
+    /// `2021-03-04 12:34:56.789000000 +0300`
+    FullIso,
+    /// `2021-03-04 12:34`, the Hadoop format and the default.
+    LongIso,
+    /// `03-04 12:34` for recent files, `2020-03-04` for older ones.
+    Iso,
+    /// `Mar  4 12:34` for recent files, `Mar  4  2020` for older ones.
+    Locale,
+    /// strftime format.
+    Format(String),
 }
 
-impl HumanSizeFormatter {
-    fn format(val: u64) -> String {
-        match NumberPrefix::binary(val as f64) {
-            NumberPrefix::Standalone(bytes) => format!("{:.0}", bytes),
-            NumberPrefix::Prefixed(pref, n) => format!("{:.1} {}", n, &pref.symbol()[0..1]),
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid time style '{0}'; expected full-iso, long-iso, iso, locale or +FORMAT")]
+pub(crate) struct TimeStyleError(String);
+
+impl FromStr for TimeStyle {
+    type Err = TimeStyleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full-iso" => Ok(TimeStyle::FullIso),
+            "long-iso" => Ok(TimeStyle::LongIso),
+            "iso" => Ok(TimeStyle::Iso),
+            "locale" => Ok(TimeStyle::Locale),
+            _ => match s.strip_prefix('+') {
+                // Invalid specifiers make formatting fail later.
+                Some(format) if StrftimeItems::new(format).all(|item| item != Item::Error) => {
+                    Ok(TimeStyle::Format(format.to_owned()))
+                }
+                _ => Err(TimeStyleError(s.to_owned())),
+            },
         }
     }
 }
 
-impl Default for HumanSizeFormatter {
-    fn default() -> Self {
-        Self { max_len: 10 }
-    }
+/// Time zone of the displayed timestamps.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Zone {
+    Local,
+    Fixed(chrono::FixedOffset),
 }
-impl<W: Write> FieldFormatter<W> for HumanSizeFormatter {
-    // TODO implement real units formatter
-    fn update_len(&mut self, entry: &Record) {
-        self.max_len = max(self.max_len, Self::format(entry.size as _).chars().count());
+
+/**
+ * Formats timestamps.  The current time, needed for the styles that
+ * treat recent files differently, is fixed at creation.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct TimeFormat {
+    style: TimeStyle,
+    zone: Zone,
+    now: i64,
+}
+
+impl TimeFormat {
+    pub(crate) fn new(style: TimeStyle, zone: Zone) -> Self {
+        Self::with_now(style, zone, chrono::Utc::now().timestamp_millis())
     }
 
-    fn print(&self, out: &mut W, entry: &Record) -> std::io::Result<()> {
-        write!(out, "{0:>1$}", Self::format(entry.size), self.max_len + 1)
+    pub(crate) fn with_now(style: TimeStyle, zone: Zone, now: i64) -> Self {
+        Self { style, zone, now }
     }
 
-    fn print_streaming(&self, out: &mut W, entry: &Record) -> std::io::Result<()> {
-        write!(out, "{}", Self::format(entry.size))
+    /// Timestamp in milliseconds.
+    pub(crate) fn format(&self, timestamp: u64) -> String {
+        match self.zone {
+            Zone::Local => self.format_in(&chrono::Local, timestamp),
+            Zone::Fixed(offset) => self.format_in(&offset, timestamp),
+        }
+    }
+
+    fn format_in<Tz: TimeZone>(&self, tz: &Tz, timestamp: u64) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let timestamp = timestamp as i64;
+        let recent = self.now - SIX_MONTHS_MILLIS <= timestamp && timestamp <= self.now;
+        let format = match &self.style {
+            TimeStyle::FullIso => "%Y-%m-%d %H:%M:%S%.9f %z",
+            TimeStyle::LongIso => "%Y-%m-%d %H:%M",
+            TimeStyle::Iso if recent => "%m-%d %H:%M",
+            TimeStyle::Iso => "%Y-%m-%d",
+            TimeStyle::Locale if recent => "%b %e %H:%M",
+            TimeStyle::Locale => "%b %e  %Y",
+            TimeStyle::Format(format) => format,
+        };
+        tz.timestamp_millis(timestamp).format(format).to_string()
     }
 }
 
@@ -235,19 +371,6 @@ struct DateFormatter {
     max_len: usize,
 }
 
-impl DateFormatter {
-    fn format_datetime(timestamp: u64, tz_offset: chrono::FixedOffset) -> String {
-        let time = chrono::NaiveDateTime::from_timestamp(
-            timestamp as i64 / 1000, // millisec to secs
-            // We don't need the millisecond part
-            0,
-        );
-        let time_tz = chrono::DateTime::<chrono::Local>::from_utc(time, tz_offset);
-
-        time_tz.format("%Y-%m-%d %H:%M").to_string()
-    }
-}
-
 impl<W: Write> FieldFormatter<W> for DateFormatter {
     fn update_len(&mut self, entry: &Record) {
         self.max_len = max(self.max_len, entry.timestmap_str.chars().count());
@@ -301,15 +424,31 @@ impl<W: Write> FieldFormatter<W> for GroupFormatter {
 }
 
 struct NameFormatter {
-    base: path::Path<'static>,
+    // Without the base, entry paths are printed as is.
+    base: Option<path::Path<'static>>,
     quote: bool,
+    // Separated from the previous field; FsShell's -C prints the
+    // paths alone.
+    separated: bool,
 }
 
 impl NameFormatter {
-    fn new(base: path::Path<'_>, quote: bool) -> Self {
+    fn new(base: Option<path::Path<'_>>, quote: bool, separated: bool) -> Self {
         Self {
-            base: base.into_owned(),
+            base: base.map(path::Path::into_owned),
             quote,
+            separated,
+        }
+    }
+
+    fn name(&self, entry: &Record) -> String {
+        match &self.base {
+            Some(base) => match base.child(&entry.path) {
+                Ok(path) => path.to_string(),
+                // A name the URI parser rejects is printed as is.
+                Err(_) => format!("{}/{}", base.to_string().trim_end_matches('/'), entry.path),
+            },
+            None => entry.path.to_string(),
         }
     }
 }
@@ -318,10 +457,11 @@ impl<W: Write> FieldFormatter<W> for NameFormatter {
     fn update_len(&mut self, _entry: &Record) {}
 
     fn print(&self, out: &mut W, entry: &Record) -> std::io::Result<()> {
-        let joined = self.base.join(&entry.path).unwrap().to_string(); // TODO
+        let joined = self.name(entry);
         write!(
             out,
-            " {}",
+            "{}{}",
+            if self.separated { " " } else { "" },
             if self.quote {
                 to_printable(&joined)
             } else {
@@ -331,7 +471,7 @@ impl<W: Write> FieldFormatter<W> for NameFormatter {
     }
 
     fn print_streaming(&self, out: &mut W, entry: &Record) -> std::io::Result<()> {
-        let joined = self.base.join(&entry.path).unwrap().to_string(); // TODO
+        let joined = self.name(entry);
         write!(
             out,
             "{}",
@@ -349,29 +489,24 @@ pub(crate) struct LineFormat<W: Write> {
 }
 
 impl<W: Write> LineFormat<W> {
-    /// Path-only output
-    pub(crate) fn compact(base: path::Path<'_>, quote: bool) -> Self {
+    /// Path-only output; entry paths are relative to the base, if any.
+    pub(crate) fn compact(base: Option<path::Path<'_>>, quote: bool) -> Self {
         Self {
-            formatters: vec![Box::new(NameFormatter::new(base, quote))],
+            formatters: vec![Box::new(NameFormatter::new(base, quote, false))],
         }
     }
 
-    /// Full output; human is the flag that enables human-readable
-    /// file size output.
-    pub(crate) fn full(base: path::Path<'_>, human: bool, quote: bool) -> Self {
+    /// Full output with the size column in the given style.
+    pub(crate) fn full(base: Option<path::Path<'_>>, size: SizeStyle, quote: bool) -> Self {
         Self {
             formatters: vec![
                 Box::<PermFormatter>::default(),
                 Box::<ReplicationFormatter>::default(),
                 Box::<OwnerFormatter>::default(),
                 Box::<GroupFormatter>::default(),
-                if human {
-                    Box::<HumanSizeFormatter>::default()
-                } else {
-                    Box::<SimpleSizeFormatter>::default()
-                },
+                Box::new(SizeFormatter::new(size)),
                 Box::<DateFormatter>::default(),
-                Box::new(NameFormatter::new(base, quote)),
+                Box::new(NameFormatter::new(base, quote, true)),
             ],
         }
     }
@@ -466,4 +601,133 @@ mod tests {
         // The original testsuite also had some surrogate tests that
         // are not appropriate for Rust.
     }
+
+    fn size_record(size: u64) -> Record {
+        Record {
+            file_type: HdfsFileStatusProto_FileType::IS_FILE,
+            perm: 0o644,
+            has_acl: false,
+            replication: 3,
+            owner: "hdfs".into(),
+            group: "supergroup".into(),
+            size,
+            timestamp: 0,
+            timestmap_str: String::new(),
+            path: "file".into(),
+        }
+    }
+
+    fn print_size(style: SizeStyle, size: u64, streaming: bool) -> String {
+        let rec = size_record(size);
+        let mut fmt = SizeFormatter::new(style);
+        let mut out = Vec::<u8>::new();
+        if streaming {
+            FieldFormatter::<Vec<u8>>::print_streaming(&fmt, &mut out, &rec).unwrap();
+        } else {
+            FieldFormatter::<Vec<u8>>::update_len(&mut fmt, &rec);
+            FieldFormatter::<Vec<u8>>::print(&fmt, &mut out, &rec).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_size_exact() {
+        let style = SizeStyle::default();
+        assert_eq!(print_size(style, 1536, false), "       1536");
+        assert_eq!(print_size(style, 1536, true), "1536");
+    }
+
+    #[test]
+    fn test_size_human() {
+        let style = SizeStyle {
+            human: true,
+            exact: false,
+            base: SizeBase::Binary,
+        };
+        assert_eq!(print_size(style, 1536, false), "      1.5 K");
+        assert_eq!(print_size(style, 1000, true), "1000");
+        // Beyond 1024 PiB.
+        assert_eq!(print_size(style, 2 << 60, true), "2.0 E");
+        assert_eq!(print_size(style, u64::MAX, true), "16.0 E");
+    }
+
+    #[test]
+    fn test_size_human_decimal() {
+        let style = SizeStyle {
+            human: true,
+            exact: false,
+            base: SizeBase::Decimal,
+        };
+        assert_eq!(print_size(style, 1536, true), "1.5 k");
+        assert_eq!(print_size(style, 2_000_000, true), "2.0 M");
+    }
+
+    #[test]
+    fn test_size_human_and_exact() {
+        let style = SizeStyle {
+            human: true,
+            exact: true,
+            base: SizeBase::Binary,
+        };
+        assert_eq!(print_size(style, 1536, false), "      1.5 K       1536");
+        assert_eq!(print_size(style, 1536, true), "1.5 K\t1536");
+    }
+
+    const MARCH_4_2021: u64 = 1_614_861_296_789; // 2021-03-04 12:34:56.789 UTC
+
+    fn time_format(style: TimeStyle) -> TimeFormat {
+        let msk = chrono::FixedOffset::east(3 * 3600);
+        TimeFormat::with_now(style, Zone::Fixed(msk), MARCH_4_2021 as i64 + 1000)
+    }
+
+    #[test]
+    fn test_time_style_parse() {
+        assert_eq!("iso".parse(), Ok(TimeStyle::Iso));
+        assert_eq!("+%H".parse(), Ok(TimeStyle::Format("%H".to_owned())));
+        assert_eq!(
+            "%H".parse::<TimeStyle>(),
+            Err(TimeStyleError("%H".to_owned()))
+        );
+        assert!("+%Q".parse::<TimeStyle>().is_err());
+    }
+
+    #[test]
+    fn test_time_styles() {
+        let year_ago = MARCH_4_2021 - 365 * 24 * 3600 * 1000;
+        assert_eq!(
+            time_format(TimeStyle::LongIso).format(MARCH_4_2021),
+            "2021-03-04 15:34"
+        );
+        assert_eq!(
+            time_format(TimeStyle::FullIso).format(MARCH_4_2021),
+            "2021-03-04 15:34:56.789000000 +0300"
+        );
+        assert_eq!(
+            time_format(TimeStyle::Iso).format(MARCH_4_2021),
+            "03-04 15:34"
+        );
+        assert_eq!(time_format(TimeStyle::Iso).format(year_ago), "2020-03-04");
+        assert_eq!(
+            time_format(TimeStyle::Locale).format(MARCH_4_2021),
+            "Mar  4 15:34"
+        );
+        assert_eq!(
+            time_format(TimeStyle::Locale).format(year_ago),
+            "Mar  4  2020"
+        );
+        assert_eq!(
+            time_format(TimeStyle::Format("%s".to_owned())).format(MARCH_4_2021),
+            "1614861296"
+        );
+    }
+
+    #[test]
+    fn test_time_utc() {
+        let utc = TimeFormat::with_now(
+            TimeStyle::LongIso,
+            Zone::Fixed(chrono::FixedOffset::east(0)),
+            0,
+        );
+        assert_eq!(utc.format(MARCH_4_2021), "2021-03-04 12:34");
+    }
 }
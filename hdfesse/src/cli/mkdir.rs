@@ -13,7 +13,7 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use super::Command;
+use super::{Command, PathArgs};
 use anyhow::Result;
 use libhdfesse::{
     fs::{Hdfs, HdfsError},
@@ -31,11 +31,17 @@ pub struct MkdirArgs {
     parents: bool,
 }
 
+impl PathArgs for MkdirArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.srcs.iter().map(String::as_str).collect()
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum MkdirError {
     #[error(transparent)]
     Uri(PathError),
-    #[error("ls: {0}")]
+    #[error("mkdir: {0}")]
     Fs(#[from] HdfsError),
 }
 
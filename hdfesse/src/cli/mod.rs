@@ -13,11 +13,34 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
+pub mod blocks;
+pub mod datanode;
+pub mod datanode_probe;
+pub mod df;
+pub mod doctor;
+pub mod du;
+pub mod export;
+mod filter_args;
+pub mod fsck;
 pub mod ls;
 mod ls_output;
 pub mod mkdir;
 pub mod mv;
+pub mod namespace;
+pub mod progress;
+pub mod report;
+mod retries;
 pub mod rm;
+pub mod snapshot;
+pub mod storage_policy;
+pub mod test;
+pub mod touch;
+mod units;
+pub mod upgrade;
+
+pub(crate) use filter_args::FilterArgs;
+pub(crate) use retries::failed_call_report;
+pub(crate) use units::parse_duration;
 
 pub trait Command {
     type Args: structopt::StructOpt;
@@ -25,3 +48,9 @@ pub trait Command {
 
     fn run(&mut self, args: Self::Args) -> Result<i32, Self::Error>;
 }
+
+/// The path arguments of a command; they choose the cluster the
+/// command connects to.
+pub trait PathArgs {
+    fn paths(&self) -> Vec<&str>;
+}
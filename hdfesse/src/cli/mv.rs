@@ -13,7 +13,7 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use super::Command;
+use super::{Command, PathArgs};
 use anyhow::Result;
 use libhdfesse::{fs::Hdfs, path::Path};
 use structopt::StructOpt;
@@ -26,6 +26,16 @@ pub struct MvArgs {
     dst: String,
 }
 
+impl PathArgs for MvArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.srcs
+            .iter()
+            .chain(std::iter::once(&self.dst))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
 pub struct Mv<'a> {
     hdfs: &'a mut Hdfs,
 }
@@ -0,0 +1,168 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * dfsadmin -saveNamespace, -rollEdits and -metasave.
+ */
+use super::Command;
+use anyhow::Result;
+use libhdfesse::fs::Hdfs;
+use structopt::StructOpt;
+
+// Defaults of dfs.namenode.checkpoint.period and
+// dfs.namenode.checkpoint.txns.
+const DEFAULT_CHECKPOINT_PERIOD: u64 = 3600;
+const DEFAULT_CHECKPOINT_TXNS: u64 = 1_000_000;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "saveNamespace",
+    about = "Save current namespace into storage directories and reset edits log"
+)]
+pub struct SaveNamespaceArgs {
+    #[structopt(
+        long = "beforeShutdown",
+        help = "Save only if there was no checkpoint within the last 3 checkpoint periods"
+    )]
+    before_shutdown: bool,
+    #[structopt(
+        long = "timeWindow",
+        help = "Do not save if the last checkpoint is newer than this many seconds"
+    )]
+    time_window: Option<u64>,
+    #[structopt(
+        long = "txGap",
+        help = "Do not save if the last checkpoint is fewer than this many transactions behind"
+    )]
+    tx_gap: Option<u64>,
+}
+
+impl SaveNamespaceArgs {
+    // Same as DFSAdmin.saveNamespace for -beforeShutdown.
+    fn window_and_gap(&self) -> (u64, u64) {
+        let (time_window, tx_gap) = if self.before_shutdown {
+            (3 * DEFAULT_CHECKPOINT_PERIOD, DEFAULT_CHECKPOINT_TXNS)
+        } else {
+            (0, 0)
+        };
+        (
+            self.time_window.unwrap_or(time_window),
+            self.tx_gap.unwrap_or(tx_gap),
+        )
+    }
+}
+
+pub struct SaveNamespace<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> SaveNamespace<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for SaveNamespace<'a> {
+    type Args = SaveNamespaceArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let (time_window, tx_gap) = args.window_and_gap();
+        if self.hdfs.save_namespace(time_window, tx_gap)? {
+            println!("Save namespace successful");
+        } else {
+            println!("No extra checkpoint has been made");
+        }
+        Ok(0)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "rollEdits", about = "Roll the edit log on the active namenode")]
+pub struct RollEditsArgs {}
+
+pub struct RollEdits<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> RollEdits<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for RollEdits<'a> {
+    type Args = RollEditsArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, _args: Self::Args) -> Result<i32> {
+        let txid = self.hdfs.roll_edits()?;
+        println!("Successfully rolled edit logs.");
+        println!("New segment starts at txid {}", txid);
+        Ok(0)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "metasave",
+    about = "Save namenode's primary data structures to a file in its log directory"
+)]
+pub struct MetaSaveArgs {
+    #[structopt(name = "filename")]
+    filename: String,
+}
+
+pub struct MetaSave<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> MetaSave<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for MetaSave<'a> {
+    type Args = MetaSaveArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        self.hdfs.meta_save(&args.filename)?;
+        println!(
+            "Created metasave file {} in the log directory of namenode",
+            args.filename
+        );
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_namespace_window_and_gap() {
+        let args = SaveNamespaceArgs::from_iter(&["saveNamespace"]);
+        assert_eq!(args.window_and_gap(), (0, 0));
+
+        let args = SaveNamespaceArgs::from_iter(&["saveNamespace", "--beforeShutdown"]);
+        assert_eq!(args.window_and_gap(), (10800, 1_000_000));
+
+        let args =
+            SaveNamespaceArgs::from_iter(&["saveNamespace", "--beforeShutdown", "--txGap", "5"]);
+        assert_eq!(args.window_and_gap(), (10800, 5));
+    }
+}
@@ -0,0 +1,220 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Progress of long-running commands, reported periodically on stderr
+ * either as text for humans or, with `--progress json`, as one JSON
+ * object per line for the tools that run the commands.
+ */
+use std::fmt::Write as _;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    Text,
+    /// One JSON object per event.
+    Json,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown progress format {0:?}, expected text or json")]
+pub struct ProgressFormatError(String);
+
+impl FromStr for ProgressFormat {
+    type Err = ProgressFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ProgressFormat::Text),
+            "json" => Ok(ProgressFormat::Json),
+            _ => Err(ProgressFormatError(s.to_owned())),
+        }
+    }
+}
+
+/// A snapshot of the work done so far.  The totals are known only if
+/// the command has counted the work beforehand.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgressEvent {
+    pub files: u64,
+    pub bytes: u64,
+    pub failed: u64,
+    pub total_files: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub elapsed: Duration,
+}
+
+impl ProgressEvent {
+    /// Estimated time left, extrapolated from the rate so far, by bytes
+    /// if their total is known, by files otherwise.
+    pub fn eta(&self) -> Option<Duration> {
+        let (done, total) = match (self.total_bytes, self.total_files) {
+            (Some(total), _) if self.bytes > 0 => (self.bytes, total),
+            (_, Some(total)) if self.files > 0 => (self.files, total),
+            _ => return None,
+        };
+        let left = total.saturating_sub(done);
+        let nanos = self.elapsed.as_nanos() * u128::from(left) / u128::from(done);
+        Some(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+    }
+}
+
+pub trait ProgressObserver {
+    /// Called periodically while the command runs.
+    fn progress(&mut self, event: &ProgressEvent);
+    /// Called once when the command is done.
+    fn finish(&mut self, event: &ProgressEvent);
+}
+
+/// Progress as text lines, like "rm: deleted 10 entries, 0 failed".
+pub struct TextProgress<W> {
+    op: &'static str,
+    verb: &'static str,
+    out: W,
+}
+
+impl<W: Write> TextProgress<W> {
+    pub fn new(op: &'static str, verb: &'static str, out: W) -> Self {
+        Self { op, verb, out }
+    }
+}
+
+impl<W: Write> ProgressObserver for TextProgress<W> {
+    fn progress(&mut self, event: &ProgressEvent) {
+        let _ = writeln!(
+            self.out,
+            "{}: {} {} entries, {} failed",
+            self.op, self.verb, event.files, event.failed
+        );
+    }
+
+    // The commands print their own summary.
+    fn finish(&mut self, _event: &ProgressEvent) {}
+}
+
+/// Progress as JSON lines, including the final one with `"done":true`.
+pub struct JsonProgress<W> {
+    op: &'static str,
+    out: W,
+}
+
+impl<W: Write> JsonProgress<W> {
+    pub fn new(op: &'static str, out: W) -> Self {
+        Self { op, out }
+    }
+
+    fn emit(&mut self, event: &ProgressEvent, done: bool) {
+        let line = format_json(self.op, event, done);
+        // Reporting must not fail the command.
+        let _ = writeln!(self.out, "{}", line);
+        let _ = self.out.flush();
+    }
+}
+
+impl<W: Write> ProgressObserver for JsonProgress<W> {
+    fn progress(&mut self, event: &ProgressEvent) {
+        self.emit(event, false);
+    }
+
+    fn finish(&mut self, event: &ProgressEvent) {
+        self.emit(event, true);
+    }
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map_or_else(|| "null".to_owned(), |value| value.to_string())
+}
+
+// The fields are numbers and the command name is a fixed identifier,
+// so nothing needs escaping.
+fn format_json(op: &str, event: &ProgressEvent, done: bool) -> String {
+    let mut line = String::new();
+    let _ = write!(
+        line,
+        "{{\"event\":\"progress\",\"op\":\"{}\",\"done\":{},\"files\":{},\"bytes\":{},\
+         \"failed\":{},\"total_files\":{},\"total_bytes\":{},\"elapsed_ms\":{},\"eta_ms\":{}}}",
+        op,
+        done,
+        event.files,
+        event.bytes,
+        event.failed,
+        optional(event.total_files),
+        optional(event.total_bytes),
+        event.elapsed.as_millis(),
+        optional(event.eta().map(|eta| eta.as_millis() as u64)),
+    );
+    line
+}
+
+/// An observer writing to stderr in the format.
+pub fn stderr_observer(
+    op: &'static str,
+    verb: &'static str,
+    format: ProgressFormat,
+) -> Box<dyn ProgressObserver> {
+    match format {
+        ProgressFormat::Text => Box::new(TextProgress::new(op, verb, std::io::stderr())),
+        ProgressFormat::Json => Box::new(JsonProgress::new(op, std::io::stderr())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_events() {
+        let mut out = vec![];
+        let mut observer = JsonProgress::new("rm", &mut out);
+        let mut event = ProgressEvent {
+            files: 10,
+            bytes: 4096,
+            elapsed: Duration::from_millis(1500),
+            ..ProgressEvent::default()
+        };
+        observer.progress(&event);
+        event.failed = 1;
+        observer.finish(&event);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"event\":\"progress\",\"op\":\"rm\",\"done\":false,\"files\":10,\"bytes\":4096,\
+             \"failed\":0,\"total_files\":null,\"total_bytes\":null,\"elapsed_ms\":1500,\"eta_ms\":null}\n\
+             {\"event\":\"progress\",\"op\":\"rm\",\"done\":true,\"files\":10,\"bytes\":4096,\
+             \"failed\":1,\"total_files\":null,\"total_bytes\":null,\"elapsed_ms\":1500,\"eta_ms\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_eta() {
+        let mut event = ProgressEvent {
+            files: 1,
+            bytes: 100,
+            total_files: Some(4),
+            elapsed: Duration::from_secs(2),
+            ..ProgressEvent::default()
+        };
+        assert_eq!(event.eta(), Some(Duration::from_secs(6)));
+        // Bytes are a better measure than files.
+        event.total_bytes = Some(200);
+        assert_eq!(event.eta(), Some(Duration::from_secs(2)));
+        event.files = 0;
+        event.bytes = 0;
+        assert_eq!(event.eta(), None);
+        assert!("xml".parse::<ProgressFormat>().is_err());
+    }
+}
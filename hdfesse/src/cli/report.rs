@@ -0,0 +1,240 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * dfsadmin -report, with the layout of Java's DFSAdmin.report, so
+ * outputs can be compared line by line.
+ */
+use super::{
+    units::{byte_desc, format_percent},
+    Command,
+};
+use anyhow::Result;
+use libhdfesse::{
+    fs::{FsStatus, Hdfs},
+    status::{EcBlockGroupStats, ReplicatedBlockStats},
+};
+use std::io::{self, Write};
+use structopt::StructOpt;
+
+// TODO: datanode sections and their filters (-live, -dead, etc).
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "report",
+    about = "Report basic filesystem information and statistics"
+)]
+pub struct ReportArgs {}
+
+fn print_summary<W: Write>(out: &mut W, status: &FsStatus, safe_mode: bool) -> io::Result<()> {
+    let present_capacity = status.used + status.remaining;
+    if safe_mode {
+        writeln!(out, "Safe mode is ON")?;
+        if status.blocks_in_future > 0 {
+            writeln!(out, "\nWARNING: ")?;
+            writeln!(
+                out,
+                "Name node has detected blocks with generation stamps in future."
+            )?;
+            writeln!(
+                out,
+                "Forcing exit from safemode will cause {} byte(s) to be deleted.",
+                status.blocks_in_future
+            )?;
+            writeln!(
+                out,
+                "If you are sure that the NameNode was started with the correct metadata files then you may proceed with '-safemode forceExit'\n"
+            )?;
+        }
+    }
+    writeln!(
+        out,
+        "Configured Capacity: {} ({})",
+        status.capacity,
+        byte_desc(status.capacity)
+    )?;
+    writeln!(
+        out,
+        "Present Capacity: {} ({})",
+        present_capacity,
+        byte_desc(present_capacity)
+    )?;
+    writeln!(
+        out,
+        "DFS Remaining: {} ({})",
+        status.remaining,
+        byte_desc(status.remaining)
+    )?;
+    writeln!(
+        out,
+        "DFS Used: {} ({})",
+        status.used,
+        byte_desc(status.used)
+    )?;
+    let used_fraction = if present_capacity != 0 {
+        status.used as f64 / present_capacity as f64
+    } else {
+        0.0
+    };
+    writeln!(out, "DFS Used%: {}", format_percent(used_fraction))
+}
+
+fn print_replicated_block_stats<W: Write>(
+    out: &mut W,
+    stats: &ReplicatedBlockStats,
+) -> io::Result<()> {
+    writeln!(out, "Replicated Blocks:")?;
+    writeln!(
+        out,
+        "\tUnder replicated blocks: {}",
+        stats.low_redundancy_blocks
+    )?;
+    writeln!(
+        out,
+        "\tBlocks with corrupt replicas: {}",
+        stats.corrupt_blocks
+    )?;
+    writeln!(out, "\tMissing blocks: {}", stats.missing_blocks)?;
+    writeln!(
+        out,
+        "\tMissing blocks (with replication factor 1): {}",
+        stats.missing_replication_one_blocks
+    )?;
+    if let Some(highest) = stats.highest_priority_low_redundancy_blocks {
+        writeln!(
+            out,
+            "\tLow redundancy blocks with highest priority to recover: {}",
+            highest
+        )?;
+    }
+    writeln!(
+        out,
+        "\tPending deletion blocks: {}",
+        stats.pending_deletion_blocks
+    )
+}
+
+fn print_ec_block_group_stats<W: Write>(out: &mut W, stats: &EcBlockGroupStats) -> io::Result<()> {
+    // Sic: Java prints the trailing space.
+    writeln!(out, "Erasure Coded Block Groups: ")?;
+    writeln!(
+        out,
+        "\tLow redundancy block groups: {}",
+        stats.low_redundancy_block_groups
+    )?;
+    writeln!(
+        out,
+        "\tBlock groups with corrupt internal blocks: {}",
+        stats.corrupt_block_groups
+    )?;
+    writeln!(
+        out,
+        "\tMissing block groups: {}",
+        stats.missing_block_groups
+    )?;
+    if let Some(highest) = stats.highest_priority_low_redundancy_blocks {
+        writeln!(
+            out,
+            "\tLow redundancy blocks with highest priority to recover: {}",
+            highest
+        )?;
+    }
+    writeln!(
+        out,
+        "\tPending deletion blocks: {}",
+        stats.pending_deletion_blocks
+    )
+}
+
+pub struct Report<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for Report<'a> {
+    type Args = ReportArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, _args: Self::Args) -> Result<i32> {
+        let status = self.hdfs.get_status()?;
+        let safe_mode = self.hdfs.is_in_safe_mode()?;
+        let replicated = self.hdfs.get_replicated_block_stats()?;
+        let ec = self.hdfs.get_ec_block_group_stats()?;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        print_summary(&mut out, &status, safe_mode)?;
+        print_replicated_block_stats(&mut out, &replicated)?;
+        print_ec_block_group_stats(&mut out, &ec)?;
+        writeln!(out)?;
+        writeln!(out, "-------------------------------------------------")?;
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_stats_layout() {
+        let mut out = Vec::new();
+        print_replicated_block_stats(
+            &mut out,
+            &ReplicatedBlockStats {
+                low_redundancy_blocks: 1,
+                corrupt_blocks: 2,
+                missing_blocks: 3,
+                missing_replication_one_blocks: 4,
+                blocks_in_future: 5,
+                pending_deletion_blocks: 6,
+                highest_priority_low_redundancy_blocks: None,
+            },
+        )
+        .unwrap();
+        print_ec_block_group_stats(
+            &mut out,
+            &EcBlockGroupStats {
+                low_redundancy_block_groups: 7,
+                corrupt_block_groups: 8,
+                missing_block_groups: 9,
+                block_groups_in_future: 10,
+                pending_deletion_blocks: 11,
+                highest_priority_low_redundancy_blocks: Some(12),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Replicated Blocks:
+\tUnder replicated blocks: 1
+\tBlocks with corrupt replicas: 2
+\tMissing blocks: 3
+\tMissing blocks (with replication factor 1): 4
+\tPending deletion blocks: 6
+Erasure Coded Block Groups: 
+\tLow redundancy block groups: 7
+\tBlock groups with corrupt internal blocks: 8
+\tMissing block groups: 9
+\tLow redundancy blocks with highest priority to recover: 12
+\tPending deletion blocks: 11
+"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The retry history of a failed call.  With HA, the error of a
+ * command is the error of the last namenode tried; the history tells
+ * which namenodes were tried before it and how each of them failed.
+ */
+use libhdfesse::ha_rpc::FailedCall;
+use std::fmt::Write;
+
+/// The report of the failed call, if it has tried more than one
+/// namenode or the same one again.
+pub(crate) fn failed_call_report(failure: &FailedCall) -> Option<String> {
+    if failure.attempts.len() < 2 {
+        return None;
+    }
+    let width = failure
+        .attempts
+        .iter()
+        .map(|attempt| attempt.node.len())
+        .max()
+        .unwrap_or(0);
+    let mut report = format!(
+        "{} failed after {} attempts:",
+        failure.method,
+        failure.attempts.len()
+    );
+    for attempt in &failure.attempts {
+        write!(
+            report,
+            "\n  {:<width$} {:>6} ms  {}",
+            attempt.node,
+            attempt.elapsed.as_millis(),
+            attempt.error,
+            width = width
+        )
+        .expect("writing to a String");
+    }
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libhdfesse::ha_rpc::CallAttempt;
+    use std::time::Duration;
+
+    fn attempt(node: &str, error: &str, millis: u64) -> CallAttempt {
+        CallAttempt {
+            node: node.to_owned(),
+            error: error.to_owned(),
+            elapsed: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn test_failed_call_report() {
+        let mut failure = FailedCall {
+            method: "getFileInfo".to_owned(),
+            attempts: vec![attempt("nn1:8020", "connect failed: refused", 3)],
+        };
+        assert_eq!(failed_call_report(&failure), None);
+
+        failure
+            .attempts
+            .push(attempt("nn10:8020", "StandbyException", 1200));
+        assert_eq!(
+            failed_call_report(&failure).unwrap(),
+            "getFileInfo failed after 2 attempts:\n  \
+             nn1:8020       3 ms  connect failed: refused\n  \
+             nn10:8020   1200 ms  StandbyException"
+        );
+    }
+}
@@ -13,43 +13,507 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use super::Command;
-use anyhow::Result;
-use libhdfesse::{fs::Hdfs, path::Path};
+use super::{
+    progress::{stderr_observer, ProgressEvent, ProgressFormat, ProgressObserver},
+    Command, PathArgs,
+};
+use anyhow::{anyhow, Result};
+use hdfesse_proto::hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType};
+use libhdfesse::{
+    fs::{FsError, Hdfs, HdfsError},
+    ha_rpc::HaHdfsConnection,
+    path::Path,
+    rpc::{RpcConnection, SimpleConnector},
+    status::DirEntry,
+};
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 use structopt::StructOpt;
 
+/// Entries listed and handed to a worker at once.
+const DELETE_BATCH_SIZE: usize = 1000;
+const DEFAULT_THREADS: usize = 4;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, StructOpt)]
 pub struct RmArgs {
     #[structopt(short = "r", help = "Remove directories recursively")]
     recursive: bool,
+    #[structopt(
+        long = "client-side",
+        requires = "recursive",
+        help = "Delete directory trees entry by entry from several connections, \
+                instead of with a single namenode call that may time out"
+    )]
+    client_side: bool,
+    #[structopt(
+        long = "threads",
+        requires = "client-side",
+        help = "Connections deleting in parallel with --client-side [default: 4]"
+    )]
+    threads: Option<usize>,
+    #[structopt(
+        long = "progress",
+        requires = "client-side",
+        help = "Progress format of --client-side on stderr: text or json [default: text]"
+    )]
+    progress: Option<ProgressFormat>,
     #[structopt(name = "src", required = true)]
     srcs: Vec<String>,
 }
 
-pub struct Rm<'a> {
-    hdfs: &'a mut Hdfs,
+impl PathArgs for RmArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.srcs.iter().map(String::as_str).collect()
+    }
+}
+
+fn is_dir(status: &HdfsFileStatusProto) -> bool {
+    status.get_fileType() == HdfsFileStatusProto_FileType::IS_DIR
+}
+
+type Batch = Vec<(Path<'static>, HdfsFileStatusProto)>;
+// With the length of the deleted file.
+type Outcome = Vec<(Path<'static>, u64, Result<bool, HdfsError>)>;
+
+/// Deleted entries and bytes, and the paths that failed, reported
+/// periodically to the observer.
+struct Progress {
+    deleted: u64,
+    bytes: u64,
+    failed: Vec<String>,
+    started: Instant,
+    last_report: Instant,
+    observer: Box<dyn ProgressObserver>,
+}
+
+impl Progress {
+    fn new(observer: Box<dyn ProgressObserver>) -> Self {
+        let now = Instant::now();
+        Self {
+            deleted: 0,
+            bytes: 0,
+            failed: vec![],
+            started: now,
+            last_report: now,
+            observer,
+        }
+    }
+
+    fn record(&mut self, path: &Path<'_>, length: u64, res: Result<bool, HdfsError>) {
+        match res {
+            Ok(true) => {
+                self.deleted += 1;
+                self.bytes += length;
+            }
+            // Already gone, e.g. deleted concurrently.
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                self.failed.push(path.to_string());
+            }
+        }
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.last_report = Instant::now();
+            let event = self.event();
+            self.observer.progress(&event);
+        }
+    }
+
+    fn event(&self) -> ProgressEvent {
+        ProgressEvent {
+            files: self.deleted,
+            bytes: self.bytes,
+            failed: self.failed.len() as u64,
+            elapsed: self.started.elapsed(),
+            ..ProgressEvent::default()
+        }
+    }
+
+    fn finish(&mut self) {
+        let event = self.event();
+        self.observer.finish(&event);
+    }
+}
+
+/// Threads deleting the batches of entries, each with its own
+/// connection.
+struct Workers {
+    jobs: Option<mpsc::SyncSender<Batch>>,
+    outcomes: mpsc::Receiver<Outcome>,
+    handles: Vec<thread::JoinHandle<()>>,
+    // Batches sent, but not reported yet.
+    pending: usize,
+    progress: Progress,
+}
+
+impl Workers {
+    fn start<R: RpcConnection + Send + 'static>(
+        connections: Vec<Hdfs<R>>,
+        progress: Progress,
+    ) -> Self {
+        // A bounded queue keeps the listing from running far ahead.
+        let (jobs, job_receiver) = mpsc::sync_channel::<Batch>(connections.len());
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (outcome_sender, outcomes) = mpsc::channel();
+        let handles = connections
+            .into_iter()
+            .map(|mut hdfs| {
+                let job_receiver = job_receiver.clone();
+                let outcome_sender = outcome_sender.clone();
+                thread::spawn(move || {
+                    loop {
+                        let batch = match job_receiver.lock().unwrap().recv() {
+                            Ok(batch) => batch,
+                            Err(_) => break,
+                        };
+                        let outcome = batch
+                            .into_iter()
+                            .map(|(path, status)| {
+                                let res = hdfs.delete_entry(&path, &status);
+                                (path, status.get_length(), res)
+                            })
+                            .collect();
+                        if outcome_sender.send(outcome).is_err() {
+                            break;
+                        }
+                    }
+                    let _ = hdfs.shutdown();
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            outcomes,
+            handles,
+            pending: 0,
+            progress,
+        }
+    }
+
+    fn submit(&mut self, batch: Batch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        while let Ok(outcome) = self.outcomes.try_recv() {
+            self.collect(outcome);
+        }
+        self.jobs
+            .as_ref()
+            .expect("workers are running")
+            .send(batch)
+            .map_err(|_| workers_stopped())?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Wait until all the submitted entries are deleted.
+    fn wait(&mut self) -> Result<()> {
+        while self.pending > 0 {
+            let outcome = self.outcomes.recv().map_err(|_| workers_stopped())?;
+            self.collect(outcome);
+        }
+        Ok(())
+    }
+
+    fn collect(&mut self, outcome: Outcome) {
+        self.pending -= 1;
+        for (path, length, res) in outcome {
+            self.progress.record(&path, length, res);
+        }
+    }
+
+    fn finish(mut self) -> Result<Progress> {
+        let res = self.wait();
+        self.jobs = None;
+        let mut panicked = false;
+        for handle in self.handles.drain(..) {
+            panicked |= handle.join().is_err();
+        }
+        res?;
+        if panicked {
+            return Err(workers_stopped());
+        }
+        self.progress.finish();
+        Ok(self.progress)
+    }
+}
+
+fn workers_stopped() -> anyhow::Error {
+    anyhow!("the delete workers have stopped unexpectedly")
 }
 
-impl<'a> Rm<'a> {
-    pub fn new(hdfs: &'a mut Hdfs) -> Self {
-        Self { hdfs }
+pub struct Rm<'a, R: RpcConnection = HaHdfsConnection<SimpleConnector>> {
+    hdfs: &'a mut Hdfs<R>,
+    /// Opens the connections of a client-side delete.
+    open_worker: &'a dyn Fn() -> Result<Hdfs<R>>,
+}
+
+impl<'a, R: RpcConnection + Send + 'static> Rm<'a, R> {
+    pub fn new(hdfs: &'a mut Hdfs<R>, open_worker: &'a dyn Fn() -> Result<Hdfs<R>>) -> Self {
+        Self { hdfs, open_worker }
+    }
+
+    /// Delete the tree depth first: the workers delete the entries
+    /// of a directory in batches, and the directory itself is deleted
+    /// once they are done, unless some of them have failed.  Returns
+    /// if there were failures.
+    fn delete_client_side(
+        &mut self,
+        root: &Path<'_>,
+        threads: usize,
+        format: Option<ProgressFormat>,
+    ) -> Result<bool> {
+        let status = self.hdfs.get_file_link_info(root).map_err(HdfsError::src)?;
+        if !is_dir(&status) {
+            self.hdfs.delete_entry(root, &status)?;
+            return Ok(false);
+        }
+        let connections = (0..threads.max(1))
+            .map(|_| (self.open_worker)())
+            .collect::<Result<Vec<_>>>()?;
+        let format = format.unwrap_or(ProgressFormat::Text);
+        let progress = Progress::new(stderr_observer("rm", "deleted", format));
+        let mut workers = Workers::start(connections, progress);
+        let res = self.delete_dir(root.clone().into_owned(), &status, &mut workers);
+        let progress = workers.finish()?;
+        res?;
+
+        eprintln!("rm: deleted {} entries under {}", progress.deleted, root);
+        if progress.failed.is_empty() {
+            return Ok(false);
+        }
+        eprintln!(
+            "rm: {} entries under {} were not deleted:",
+            progress.failed.len(),
+            root
+        );
+        for path in &progress.failed {
+            eprintln!("{}", path);
+        }
+        Ok(true)
+    }
+
+    /// Failures of the entries are recorded in the progress; an error
+    /// is returned only if the workers stop.
+    fn delete_dir(
+        &mut self,
+        dir: Path<'static>,
+        status: &HdfsFileStatusProto,
+        workers: &mut Workers,
+    ) -> Result<()> {
+        // Before the contents are gone.
+        if let Err(e) = self.hdfs.check_recursive_delete(&dir, status) {
+            workers.progress.record(&dir, 0, Err(e));
+            return Ok(());
+        }
+        let failed = workers.progress.failed.len();
+        self.delete_contents(&dir, workers)?;
+        workers.wait()?;
+        // Failures of the parent's batches may be collected meanwhile.
+        let dir_str = dir.to_string();
+        let prefix = format!("{}/", dir_str.trim_end_matches('/'));
+        if !workers.progress.failed[failed..]
+            .iter()
+            .any(|path| *path == dir_str || path.starts_with(&prefix))
+        {
+            // Checked above.
+            let res = self.hdfs.delete_entry(&dir, status);
+            workers.progress.record(&dir, 0, res);
+        }
+        Ok(())
+    }
+
+    fn delete_contents(&mut self, dir: &Path<'static>, workers: &mut Workers) -> Result<()> {
+        let mut subdirs = vec![];
+        let batches = match self.hdfs.list_status_batches(dir, DELETE_BATCH_SIZE) {
+            Ok(batches) => batches,
+            Err(e) => {
+                workers.progress.record(dir, 0, Err(e));
+                return Ok(());
+            }
+        };
+        for batch in batches {
+            let batch = match batch {
+                Ok(batch) => batch,
+                Err(e) => {
+                    workers.progress.record(dir, 0, Err(e));
+                    break;
+                }
+            };
+            let mut files = vec![];
+            for entry in batch {
+                match entry.absolute_path(dir) {
+                    Ok(path) if is_dir(&entry) => subdirs.push((path, entry)),
+                    Ok(path) => files.push((path, entry)),
+                    Err(e) => {
+                        let e = HdfsError::src(FsError::Path(e));
+                        workers.progress.record(dir, 0, Err(e));
+                    }
+                }
+            }
+            workers.submit(files)?;
+        }
+        for (subdir, status) in subdirs {
+            self.delete_dir(subdir, &status, workers)?;
+        }
+        Ok(())
     }
 }
 
-impl<'a> Command for Rm<'a> {
+impl<'a, R: RpcConnection + Send + 'static> Command for Rm<'a, R> {
     type Args = RmArgs;
     type Error = anyhow::Error;
 
     fn run(&mut self, args: Self::Args) -> Result<i32> {
         let mut has_error = false;
+        let threads = args.threads.unwrap_or(DEFAULT_THREADS);
 
         for src in args.srcs {
-            if let Err(e) = self.hdfs.delete(&Path::new(&src)?, args.recursive) {
-                has_error = true;
-                eprintln!("{}", e);
+            let path = Path::new(&src)?;
+            let res = if args.client_side {
+                self.delete_client_side(&path, threads, args.progress)
+            } else {
+                self.hdfs
+                    .delete(&path, args.recursive)
+                    .map(|_| false)
+                    .map_err(Into::into)
+            };
+            match res {
+                Ok(failed) => has_error |= failed,
+                Err(e) => {
+                    has_error = true;
+                    eprintln!("{}", e);
+                }
             }
         }
 
         Ok(has_error as _)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::progress::TextProgress;
+    use super::*;
+    use hdfesse_proto::ClientNamenodeProtocol::{
+        DeleteRequestProto, DeleteResponseProto, GetFileLinkInfoRequestProto,
+        GetFileLinkInfoResponseProto,
+    };
+    use libhdfesse::testing::{file_status, mock_hdfs, remote_error, MockConnection};
+
+    const TREE: &[(&str, bool, u64)] = &[
+        ("/tree/a", false, 1),
+        ("/tree/other/d", false, 4),
+        ("/tree/sub/b", false, 2),
+        ("/tree/sub/bad", false, 3),
+        ("/tree/sub/deep/c", false, 5),
+    ];
+
+    /// Serves TREE and deletes everything but /tree/sub/bad, recording
+    /// the deleted paths.
+    fn tree_hdfs(deleted: &Arc<Mutex<Vec<String>>>) -> Hdfs<MockConnection> {
+        let deleted = deleted.clone();
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(TREE)
+            .on("getFileLinkInfo", |_: GetFileLinkInfoRequestProto| {
+                let mut resp = GetFileLinkInfoResponseProto::default();
+                resp.set_fs(file_status("", true, 0));
+                Ok(resp)
+            })
+            .on("delete", move |req: DeleteRequestProto| {
+                if req.get_src() == "/tree/sub/bad" {
+                    return Err(remote_error(
+                        "delete",
+                        "org.apache.hadoop.security.AccessControlException",
+                        "Permission denied",
+                    ));
+                }
+                deleted.lock().unwrap().push(req.get_src().to_owned());
+                let mut resp = DeleteResponseProto::default();
+                resp.set_result(true);
+                Ok(resp)
+            });
+        mock_hdfs(conn)
+    }
+
+    fn args(args: &[&str]) -> Result<RmArgs, structopt::clap::Error> {
+        RmArgs::from_iter_safe(std::iter::once("rm").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_client_side_args() {
+        let parsed = args(&["-r", "--client-side", "--threads", "8", "/a"]).unwrap();
+        assert!(parsed.client_side);
+        assert_eq!(parsed.threads, Some(8));
+        assert!(args(&["--client-side", "/a"]).is_err());
+        assert!(args(&["-r", "--threads", "8", "/a"]).is_err());
+        let parsed = args(&["-r", "--client-side", "--progress", "json", "/a"]).unwrap();
+        assert_eq!(parsed.progress, Some(ProgressFormat::Json));
+        assert!(args(&["-r", "--progress", "json", "/a"]).is_err());
+    }
+
+    #[test]
+    fn test_plain_args() {
+        let parsed = args(&["-r", "/a"]).unwrap();
+        assert!(parsed.recursive);
+        assert!(!parsed.client_side);
+        assert_eq!(parsed.progress, None);
+        let parsed = args(&["/a"]).unwrap();
+        assert!(!parsed.recursive);
+        assert_eq!(parsed.srcs, ["/a"]);
+    }
+
+    #[test]
+    fn test_progress_failures() {
+        let observer = Box::new(TextProgress::new("rm", "deleted", std::io::sink()));
+        let mut progress = Progress::new(observer);
+        progress.record(&Path::new("/a/x").unwrap(), 100, Ok(true));
+        progress.record(
+            &Path::new("/a/y").unwrap(),
+            10,
+            Err(HdfsError::src(FsError::AccessDenied("/a/y".to_owned()))),
+        );
+        // Already gone.
+        progress.record(&Path::new("/a/z").unwrap(), 1000, Ok(false));
+        assert_eq!(progress.deleted, 1);
+        assert_eq!(progress.failed, ["/a/y"]);
+        let event = progress.event();
+        assert_eq!((event.files, event.bytes, event.failed), (1, 100, 1));
+    }
+
+    #[test]
+    fn test_delete_client_side() {
+        let deleted = Arc::new(Mutex::new(vec![]));
+        let mut hdfs = tree_hdfs(&deleted);
+        let open_worker = || Ok(tree_hdfs(&deleted));
+        let mut rm = Rm::new(&mut hdfs, &open_worker);
+
+        let failed = rm
+            .delete_client_side(&Path::new("/tree").unwrap(), 2, None)
+            .unwrap();
+        assert!(failed);
+        // The failed entry keeps its ancestors.
+        let mut deleted = deleted.lock().unwrap().clone();
+        deleted.sort();
+        assert_eq!(
+            deleted,
+            [
+                "/tree/a",
+                "/tree/other",
+                "/tree/other/d",
+                "/tree/sub/b",
+                "/tree/sub/deep",
+                "/tree/sub/deep/c",
+            ]
+        );
+        // The workers delete the files, the main connection the
+        // directories.
+        let stats = hdfs.stats();
+        assert_eq!(stats.calls["delete"], 2);
+        assert_eq!(stats.calls["getListing"], 4);
+    }
+}
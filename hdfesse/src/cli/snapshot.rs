@@ -0,0 +1,199 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * `lsSnapshottableDir` and `lsSnapshot` commands, with the same
+ * output as `hdfs lsSnapshottableDir` and `hdfs lsSnapshot`.
+ */
+use super::{ls_output::format_flags, Command, PathArgs};
+use anyhow::Result;
+use chrono::TimeZone;
+use libhdfesse::{
+    fs::Hdfs,
+    path::Path,
+    status::{HdfsFileStatus, SnapshotStatus, SnapshottableDirectoryStatus},
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List directories where snapshots are allowed")]
+pub struct LsSnapshottableDirArgs {}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List snapshots of the snapshottable directory")]
+pub struct LsSnapshotArgs {
+    #[structopt(name = "dir")]
+    dir: String,
+}
+
+impl PathArgs for LsSnapshotArgs {
+    fn paths(&self) -> Vec<&str> {
+        vec![&self.dir]
+    }
+}
+
+/// Columns of a status line; the last ones are type-specific.
+fn status_columns(status: &HdfsFileStatus, extra: [String; 3]) -> Vec<String> {
+    let [first, second, path] = extra;
+    vec![
+        format!("d{}", format_flags(u32::from(status.perm.perm) & 0o777)),
+        status.replication.to_string(),
+        status.owner.to_string(),
+        status.group.to_string(),
+        status.length.to_string(),
+        chrono::Local
+            .timestamp_millis(status.mtime as i64)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        first,
+        second,
+        path,
+    ]
+}
+
+// Numbers are aligned to the right, owner and group to the left, like
+// in SnapshottableDirectoryStatus.print.
+fn format_lines(rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows.first().map(Vec::len).unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(col, val)| match col {
+                    0 => val.clone(),
+                    2 | 3 => format!("{:<1$}", val, widths[col]),
+                    _ if col + 1 == columns => val.clone(),
+                    _ => format!("{:>1$}", val, widths[col]),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+fn snapshottable_dir_row(dir: &SnapshottableDirectoryStatus) -> Vec<String> {
+    status_columns(
+        &dir.dir_status,
+        [
+            dir.snapshot_number.to_string(),
+            dir.snapshot_quota.to_string(),
+            dir.full_path(),
+        ],
+    )
+}
+
+fn snapshot_row(snapshot: &SnapshotStatus) -> Vec<String> {
+    status_columns(
+        &snapshot.dir_status,
+        [
+            snapshot.snapshot_id.to_string(),
+            if snapshot.is_deleted {
+                "DELETED"
+            } else {
+                "ACTIVE"
+            }
+            .to_owned(),
+            snapshot.full_path(),
+        ],
+    )
+}
+
+pub struct LsSnapshottableDir<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> LsSnapshottableDir<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for LsSnapshottableDir<'a> {
+    type Args = LsSnapshottableDirArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, _args: Self::Args) -> Result<i32> {
+        let mut dirs: Vec<_> = self.hdfs.list_snapshottable_dirs()?.collect();
+        // Java sorts by the full path.
+        dirs.sort_by_cached_key(SnapshottableDirectoryStatus::full_path);
+        let rows: Vec<_> = dirs.iter().map(snapshottable_dir_row).collect();
+        for line in format_lines(&rows) {
+            println!("{}", line);
+        }
+        Ok(0)
+    }
+}
+
+pub struct LsSnapshot<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> LsSnapshot<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for LsSnapshot<'a> {
+    type Args = LsSnapshotArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let dir = Path::new(&args.dir)?;
+        let mut snapshots: Vec<_> = self.hdfs.list_snapshots(&dir)?.collect();
+        snapshots.sort_by_key(|snapshot| snapshot.snapshot_id);
+        let rows: Vec<_> = snapshots.iter().map(snapshot_row).collect();
+        for line in format_lines(&rows) {
+            println!("{}", line);
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_lines() {
+        let row = |owner: &str, len: &str, path: &str| -> Vec<String> {
+            [
+                "drwxr-xr-x",
+                "0",
+                owner,
+                "hdfs",
+                len,
+                "2021-01-01 00:00",
+                "1",
+                "65536",
+                path,
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+        };
+        let rows = [row("alice", "0", "/a"), row("bob", "100", "/long/b")];
+        assert_eq!(
+            format_lines(&rows),
+            [
+                "drwxr-xr-x 0 alice hdfs   0 2021-01-01 00:00 1 65536 /a",
+                "drwxr-xr-x 0 bob   hdfs 100 2021-01-01 00:00 1 65536 /long/b",
+            ]
+        );
+    }
+}
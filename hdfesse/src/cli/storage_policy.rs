@@ -0,0 +1,59 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+use super::{Command, PathArgs};
+use anyhow::Result;
+use libhdfesse::{fs::Hdfs, path::Path};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "satisfyStoragePolicy",
+    about = "Schedule blocks to move based on the path's storage policy"
+)]
+pub struct SatisfyStoragePolicyArgs {
+    #[structopt(long = "path", help = "The path of a file or a directory")]
+    path: String,
+}
+
+impl PathArgs for SatisfyStoragePolicyArgs {
+    fn paths(&self) -> Vec<&str> {
+        vec![&self.path]
+    }
+}
+
+pub struct SatisfyStoragePolicy<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> SatisfyStoragePolicy<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for SatisfyStoragePolicy<'a> {
+    type Args = SatisfyStoragePolicyArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        self.hdfs.satisfy_storage_policy(&Path::new(&args.path)?)?;
+        println!(
+            "Scheduled blocks to move based on the current storage policy on {}",
+            args.path
+        );
+        Ok(0)
+    }
+}
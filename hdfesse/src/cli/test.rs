@@ -0,0 +1,197 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+use super::{Command, PathArgs};
+use anyhow::Result;
+use hdfesse_proto::hdfs::HdfsFileStatusProto_FileType;
+use libhdfesse::{
+    fs::{FsError, Hdfs, HdfsError},
+    path::Path,
+    status::FsAction,
+};
+use protobuf::ProtobufEnum;
+use structopt::StructOpt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TestError {
+    #[error("test: Only one test flag is allowed")]
+    Flags,
+    #[error("test: Invalid permission `{0}', expected a combination of r, w, x and -")]
+    Perm(String),
+}
+
+fn parse_fs_action(s: &str) -> Result<FsAction, TestError> {
+    let mut bits = 0;
+    for c in s.chars() {
+        bits |= match c {
+            'r' => 0o4,
+            'w' => 0o2,
+            'x' => 0o1,
+            '-' => 0,
+            _ => return Err(TestError::Perm(s.to_owned())),
+        };
+    }
+    FsAction::from_i32(bits).ok_or_else(|| TestError::Perm(s.to_owned()))
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "test",
+    about = "Check a path; exit status is 0 if the check passes, 1 otherwise"
+)]
+pub struct TestArgs {
+    #[structopt(short = "d", help = "The path is a directory")]
+    dir: bool,
+    #[structopt(short = "e", help = "The path exists")]
+    exists: bool,
+    #[structopt(short = "f", help = "The path is a file")]
+    file: bool,
+    #[structopt(short = "s", help = "The path is not empty")]
+    non_empty: bool,
+    #[structopt(short = "z", help = "The file is zero bytes in size")]
+    zero: bool,
+    #[structopt(short = "r", help = "The path exists and read permission is granted")]
+    read: bool,
+    #[structopt(short = "w", help = "The path exists and write permission is granted")]
+    write: bool,
+    #[structopt(
+        short = "a",
+        parse(try_from_str = parse_fs_action),
+        help = "The path exists and the permissions (like rw or r-x) are granted"
+    )]
+    access: Option<FsAction>,
+    #[structopt(name = "path")]
+    path: String,
+}
+
+impl PathArgs for TestArgs {
+    fn paths(&self) -> Vec<&str> {
+        vec![&self.path]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TestFlag {
+    Dir,
+    Exists,
+    File,
+    NonEmpty,
+    Zero,
+    Access(FsAction),
+}
+
+impl TestArgs {
+    fn flag(&self) -> Result<TestFlag, TestError> {
+        let flags = [
+            (self.dir, TestFlag::Dir),
+            (self.exists, TestFlag::Exists),
+            (self.file, TestFlag::File),
+            (self.non_empty, TestFlag::NonEmpty),
+            (self.zero, TestFlag::Zero),
+            (self.read, TestFlag::Access(FsAction::READ)),
+            (self.write, TestFlag::Access(FsAction::WRITE)),
+        ];
+        let selected = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| *flag)
+            .chain(self.access.map(TestFlag::Access))
+            .collect::<Vec<_>>();
+        match selected.as_slice() {
+            [flag] => Ok(*flag),
+            _ => Err(TestError::Flags),
+        }
+    }
+}
+
+pub struct Test<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Test<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+
+    fn test(&mut self, path: &Path<'_>, flag: TestFlag) -> Result<bool, HdfsError> {
+        if let TestFlag::Access(mode) = flag {
+            return match self.hdfs.check_access(path, mode) {
+                Ok(()) => Ok(true),
+                Err(HdfsError {
+                    source: FsError::AccessDenied(_) | FsError::NotFound(_),
+                    ..
+                }) => Ok(false),
+                Err(e) => Err(e),
+            };
+        }
+
+        let info = match self.hdfs.get_file_info(path) {
+            Ok(info) => info,
+            Err(FsError::NotFound(_)) => return Ok(false),
+            Err(e) => return Err(HdfsError::src(e)),
+        };
+        Ok(match flag {
+            TestFlag::Dir => info.get_fileType() == HdfsFileStatusProto_FileType::IS_DIR,
+            TestFlag::Exists => true,
+            TestFlag::File => info.get_fileType() == HdfsFileStatusProto_FileType::IS_FILE,
+            TestFlag::NonEmpty => info.get_length() > 0,
+            TestFlag::Zero => info.get_length() == 0,
+            TestFlag::Access(_) => unreachable!(),
+        })
+    }
+}
+
+impl<'a> Command for Test<'a> {
+    type Args = TestArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let flag = args.flag()?;
+        let path = Path::new(&args.path)?;
+        Ok(i32::from(!self.test(&path, flag)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fs_action() {
+        assert_eq!(parse_fs_action("rw").unwrap(), FsAction::READ_WRITE);
+        assert_eq!(parse_fs_action("r-x").unwrap(), FsAction::READ_EXECUTE);
+        assert_eq!(parse_fs_action("---").unwrap(), FsAction::NONE);
+        assert!(parse_fs_action("rwt").is_err());
+    }
+
+    #[test]
+    fn test_single_flag() {
+        let args = TestArgs::from_iter(&["test", "-d", "/tmp"]);
+        assert_eq!(args.flag().unwrap(), TestFlag::Dir);
+
+        let args = TestArgs::from_iter(&["test", "-a", "wx", "/tmp"]);
+        assert_eq!(
+            args.flag().unwrap(),
+            TestFlag::Access(FsAction::WRITE_EXECUTE)
+        );
+
+        let args = TestArgs::from_iter(&["test", "-r", "-a", "w", "/tmp"]);
+        assert!(args.flag().is_err());
+
+        let args = TestArgs::from_iter(&["test", "/tmp"]);
+        assert!(args.flag().is_err());
+    }
+}
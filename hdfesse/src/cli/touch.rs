@@ -0,0 +1,143 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+use super::{Command, PathArgs};
+use anyhow::Result;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use libhdfesse::{
+    fs::{Hdfs, HdfsError, TouchOptions},
+    path::{Path, PathError},
+};
+use std::time::SystemTime;
+use structopt::StructOpt;
+use thiserror::Error;
+
+/// Format of -t, as Java's -touch; it is in the local time zone.
+const TIMESTAMP_FORMAT: &str = "%Y%m%d:%H%M%S";
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "touch",
+    about = "Update the access and modification times, creating empty files"
+)]
+pub struct TouchArgs {
+    #[structopt(name = "path", required = true)]
+    paths: Vec<String>,
+    #[structopt(short = "a", help = "Change only the access time")]
+    access: bool,
+    #[structopt(short = "m", help = "Change only the modification time")]
+    modification: bool,
+    #[structopt(
+        short = "t",
+        parse(try_from_str = parse_timestamp),
+        help = "Use the time (yyyyMMdd:HHmmss) instead of the current one"
+    )]
+    time: Option<SystemTime>,
+    #[structopt(short = "c", help = "Do not create missing files")]
+    no_create: bool,
+}
+
+impl PathArgs for TouchArgs {
+    fn paths(&self) -> Vec<&str> {
+        self.paths.iter().map(String::as_str).collect()
+    }
+}
+
+impl TouchArgs {
+    fn options(&self) -> TouchOptions {
+        let mut opts = TouchOptions::new().with_create(!self.no_create);
+        if let Some(time) = self.time {
+            opts = opts.with_time(time);
+        }
+        // Both flags mean both times, as with none.
+        match (self.access, self.modification) {
+            (true, false) => opts.access_only(),
+            (false, true) => opts.modification_only(),
+            _ => opts,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TouchError {
+    #[error("touch: invalid timestamp {0:?}, expected yyyyMMdd:HHmmss")]
+    Timestamp(String),
+    #[error(transparent)]
+    Uri(PathError),
+    #[error("touch: {0}")]
+    Fs(#[from] HdfsError),
+}
+
+fn parse_timestamp(s: &str) -> Result<SystemTime, TouchError> {
+    let naive = NaiveDateTime::parse_from_str(s, TIMESTAMP_FORMAT)
+        .map_err(|_| TouchError::Timestamp(s.to_owned()))?;
+    // A time skipped by a DST change does not exist.
+    let local = Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| TouchError::Timestamp(s.to_owned()))?;
+    Ok(local.into())
+}
+
+pub struct Touch<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Touch<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+
+    fn touch(&mut self, path_str: &str, opts: &TouchOptions) -> Result<(), TouchError> {
+        let path = Path::new(path_str).map_err(TouchError::Uri)?;
+        Ok(self.hdfs.touch(&path, opts)?)
+    }
+}
+
+impl<'a> Command for Touch<'a> {
+    type Args = TouchArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let opts = args.options();
+        let mut has_error = false;
+
+        for path_str in &args.paths {
+            if let Err(e) = self.touch(path_str, &opts) {
+                has_error = true;
+                eprintln!("{}", e);
+            }
+        }
+
+        Ok(i32::from(has_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        let expected: SystemTime = Local.ymd(2021, 3, 4).and_hms(5, 6, 7).into();
+        assert_eq!(parse_timestamp("20210304:050607").unwrap(), expected);
+        assert!(parse_timestamp("2021-03-04 05:06:07").is_err());
+        assert!(parse_timestamp("20210304").is_err());
+
+        let args = TouchArgs::from_iter_safe(&["touch", "-t", "20210304:050607", "-a", "/f"]);
+        assert_eq!(args.unwrap().time, Some(expected));
+        assert!(TouchArgs::from_iter_safe(&["touch", "-t", "now", "/f"]).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Number formatting and parsing compatible with
+ * org.apache.hadoop.util.StringUtils.
+ */
+use number_prefix::NumberPrefix;
+use std::time::Duration;
+use thiserror::Error;
+
+const BINARY_PREFIXES: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+// Repeats org.apache.hadoop.util.StringUtils.byteDesc.
+pub(crate) fn byte_desc(n: u64) -> String {
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut idx = ((63 - n.leading_zeros()) / 10) as usize;
+    let shift = 10 * idx;
+    if n & ((1 << shift) - 1) == 0 {
+        return format!("{} {}B", n >> shift, BINARY_PREFIXES[idx - 1]);
+    }
+    let mut val = format!("{:.2}", n as f64 / (1u64 << shift) as f64);
+    // Rounding may produce 1024.00 of the smaller unit.
+    if val.starts_with("1024") {
+        idx += 1;
+        val = format!("{:.2}", n as f64 / (1u64 << (10 * idx)) as f64);
+    }
+    format!("{} {}B", val, BINARY_PREFIXES[idx - 1])
+}
+
+/// Base of the human-readable size prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SizeBase {
+    /// K = 1024, like Hadoop.
+    Binary,
+    /// k = 1000, like `ls --si`.
+    Decimal,
+}
+
+/// One decimal and the prefix letter, like `1.5 K`; sizes below the
+/// first prefix are plain bytes.
+pub(crate) fn format_human(val: u64, base: SizeBase) -> String {
+    let prefixed = match base {
+        SizeBase::Binary => NumberPrefix::binary(val as f64),
+        SizeBase::Decimal => NumberPrefix::decimal(val as f64),
+    };
+    match prefixed {
+        NumberPrefix::Standalone(bytes) => format!("{:.0}", bytes),
+        NumberPrefix::Prefixed(pref, n) => format!("{:.1} {}", n, &pref.symbol()[0..1]),
+    }
+}
+
+/// Size column of -du and -df: bytes, or the binary format_human
+/// with `human`.
+pub(crate) fn format_size(val: u64, human: bool) -> String {
+    if human {
+        format_human(val, SizeBase::Binary)
+    } else {
+        val.to_string()
+    }
+}
+
+// Repeats org.apache.hadoop.util.StringUtils.formatPercent.
+pub(crate) fn format_percent(fraction: f64) -> String {
+    format!("{:.2}%", fraction * 100.0)
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum UnitsError {
+    #[error("Invalid size prefix '{0}' in '{1}'. Allowed prefixes are k, m, g, t, p, e(case insensitive)")]
+    Prefix(char, String),
+    #[error("{0} does not fit in a Long")]
+    Overflow(String),
+    #[error("Invalid number '{0}'")]
+    Number(String),
+    #[error("Invalid time unit in '{0}'. Allowed units are ms, s, m, h, d")]
+    TimeUnit(String),
+}
+
+// Repeats StringUtils.TraditionalBinaryPrefix.string2long for
+// non-negative values.
+pub(crate) fn string2long(s: &str) -> Result<u64, UnitsError> {
+    let s = s.trim();
+    let parse = |num: &str| {
+        num.parse::<u64>()
+            .map_err(|_| UnitsError::Number(s.to_owned()))
+    };
+    match s.chars().last() {
+        Some(last) if !last.is_ascii_digit() => {
+            let idx = BINARY_PREFIXES
+                .iter()
+                .position(|p| p.eq_ignore_ascii_case(&last))
+                .ok_or_else(|| UnitsError::Prefix(last, s.to_owned()))?;
+            let num = parse(&s[..s.len() - last.len_utf8()])?;
+            num.checked_mul(1 << (10 * (idx + 1)))
+                .filter(|val| *val <= i64::MAX as u64)
+                .ok_or_else(|| UnitsError::Overflow(s.to_owned()))
+        }
+        _ => parse(s),
+    }
+}
+
+// Time duration with the suffixes of Configuration.getTimeDuration;
+// seconds without a suffix.
+pub(crate) fn parse_duration(s: &str) -> Result<Duration, UnitsError> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num = num
+        .parse::<u64>()
+        .map_err(|_| UnitsError::Number(s.to_owned()))?;
+    let millis = match unit.to_ascii_lowercase().as_str() {
+        "ms" => 1,
+        "" | "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return Err(UnitsError::TimeUnit(s.to_owned())),
+    };
+    num.checked_mul(millis)
+        .map(Duration::from_millis)
+        .ok_or_else(|| UnitsError::Overflow(s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_desc() {
+        assert_eq!(byte_desc(0), "0 B");
+        assert_eq!(byte_desc(1023), "1023 B");
+        assert_eq!(byte_desc(1024), "1 KB");
+        assert_eq!(byte_desc(1536), "1.50 KB");
+        assert_eq!(byte_desc(3 << 30), "3 GB");
+        assert_eq!(byte_desc((1 << 20) - 1), "1.00 MB");
+        assert_eq!(byte_desc(u64::MAX), "16.00 EB");
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(1536, false), "1536");
+        assert_eq!(format_size(1000, true), "1000");
+        assert_eq!(format_size(1536, true), "1.5 K");
+        assert_eq!(format_human(1500, SizeBase::Decimal), "1.5 k");
+    }
+
+    #[test]
+    fn test_format_percent() {
+        assert_eq!(format_percent(0.0), "0.00%");
+        assert_eq!(format_percent(0.12345), "12.35%");
+    }
+
+    #[test]
+    fn test_string2long() {
+        assert_eq!(string2long("100"), Ok(100));
+        assert_eq!(string2long(" 10m "), Ok(10 << 20));
+        assert_eq!(string2long("2G"), Ok(2 << 30));
+        assert_eq!(
+            string2long("1x"),
+            Err(UnitsError::Prefix('x', "1x".to_owned()))
+        );
+        assert_eq!(
+            string2long("8e"),
+            Err(UnitsError::Overflow("8e".to_owned()))
+        );
+        assert!(string2long("-1").is_err());
+        assert!(string2long("").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration(" 30s "), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5M"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(
+            parse_duration("1w"),
+            Err(UnitsError::TimeUnit("1w".to_owned()))
+        );
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+}
@@ -0,0 +1,293 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * dfsadmin -rollingUpgrade, -finalizeUpgrade and -upgrade; messages
+ * repeat Java's DFSAdmin ones, as upgrade scripts may grep them.
+ */
+use super::Command;
+use anyhow::Result;
+use chrono::TimeZone;
+use libhdfesse::{
+    fs::Hdfs,
+    status::{RollingUpgradeAction, RollingUpgradeInfo},
+};
+use std::io::{self, Write};
+use structopt::StructOpt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum UpgradeError {
+    #[error("Failed to convert \"{0}\" to RollingUpgradeAction")]
+    RollingUpgradeAction(String),
+    #[error("Failed to convert \"{0}\" to UpgradeAction")]
+    UpgradeAction(String),
+    #[error("{0}")]
+    UnexpectedState(&'static str),
+}
+
+fn parse_rolling_upgrade_action(s: &str) -> Result<RollingUpgradeAction, UpgradeError> {
+    match s.to_ascii_lowercase().as_str() {
+        "query" => Ok(RollingUpgradeAction::QUERY),
+        "prepare" => Ok(RollingUpgradeAction::START),
+        "finalize" => Ok(RollingUpgradeAction::FINALIZE),
+        _ => Err(UpgradeError::RollingUpgradeAction(s.to_owned())),
+    }
+}
+
+fn rolling_upgrade_action_name(action: RollingUpgradeAction) -> &'static str {
+    match action {
+        RollingUpgradeAction::QUERY => "QUERY",
+        RollingUpgradeAction::START => "PREPARE",
+        RollingUpgradeAction::FINALIZE => "FINALIZE",
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "rollingUpgrade",
+    about = "Query, prepare or finalize a rolling upgrade"
+)]
+pub struct RollingUpgradeArgs {
+    #[structopt(
+        name = "query|prepare|finalize",
+        parse(try_from_str = parse_rolling_upgrade_action),
+        help = "Action; query by default"
+    )]
+    action: Option<RollingUpgradeAction>,
+}
+
+fn format_timestamp(timestamp: u64, unset: &str) -> String {
+    if timestamp == 0 {
+        return unset.to_owned();
+    }
+    match chrono::Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+    {
+        Some(time) => format!(
+            "{} (={})",
+            time.format("%a %b %d %H:%M:%S %Z %Y"),
+            timestamp
+        ),
+        None => format!("(={})", timestamp),
+    }
+}
+
+// Repeats RollingUpgradeInfo.toString.
+fn print_rolling_upgrade_info<W: Write>(out: &mut W, info: &RollingUpgradeInfo) -> io::Result<()> {
+    writeln!(out, "  Block Pool ID: {}", info.block_pool_id)?;
+    writeln!(
+        out,
+        "     Start Time: {}",
+        format_timestamp(info.start_time, "<NOT STARTED>")
+    )?;
+    writeln!(
+        out,
+        "  Finalize Time: {}",
+        format_timestamp(info.finalize_time, "<NOT FINALIZED>")
+    )
+}
+
+// Repeats DFSAdmin.RollingUpgradeCommand.printMessage.
+fn print_rolling_upgrade_message<W: Write>(
+    out: &mut W,
+    info: Option<&RollingUpgradeInfo>,
+) -> io::Result<()> {
+    match info {
+        Some(info) if info.is_started() => {
+            if !info.created_rollback_images && !info.is_finalized() {
+                writeln!(
+                    out,
+                    "Preparing for upgrade. Data is being saved for rollback.\n\
+                     Run \"dfsadmin -rollingUpgrade query\" to check the status\n\
+                     for proceeding with rolling upgrade"
+                )?;
+            } else if !info.is_finalized() {
+                writeln!(out, "Proceed with rolling upgrade:")?;
+            } else {
+                writeln!(out, "Rolling upgrade is finalized.")?;
+            }
+            print_rolling_upgrade_info(out, info)
+        }
+        _ => writeln!(
+            out,
+            "There is no rolling upgrade in progress or rolling upgrade has already been finalized."
+        ),
+    }
+}
+
+pub struct RollingUpgrade<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> RollingUpgrade<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for RollingUpgrade<'a> {
+    type Args = RollingUpgradeArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        let action = args.action.unwrap_or(RollingUpgradeAction::QUERY);
+        println!(
+            "{} rolling upgrade ...",
+            rolling_upgrade_action_name(action)
+        );
+        let info = self.hdfs.rolling_upgrade(action)?;
+        match action {
+            RollingUpgradeAction::QUERY => {}
+            RollingUpgradeAction::START => {
+                if !info.as_ref().map(|i| i.is_started()).unwrap_or(false) {
+                    return Err(
+                        UpgradeError::UnexpectedState("rolling upgrade is not started").into(),
+                    );
+                }
+            }
+            RollingUpgradeAction::FINALIZE => {
+                if !info.as_ref().map(|i| i.is_finalized()).unwrap_or(true) {
+                    return Err(
+                        UpgradeError::UnexpectedState("rolling upgrade is not finalized").into(),
+                    );
+                }
+            }
+        }
+        print_rolling_upgrade_message(&mut io::stdout().lock(), info.as_ref())?;
+        Ok(0)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "finalizeUpgrade", about = "Finalize upgrade of HDFS")]
+pub struct FinalizeUpgradeArgs {}
+
+pub struct FinalizeUpgrade<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> FinalizeUpgrade<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for FinalizeUpgrade<'a> {
+    type Args = FinalizeUpgradeArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, _args: Self::Args) -> Result<i32> {
+        self.hdfs.finalize_upgrade()?;
+        println!("Finalize upgrade successful");
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradeAction {
+    Query,
+    Finalize,
+}
+
+fn parse_upgrade_action(s: &str) -> Result<UpgradeAction, UpgradeError> {
+    match s.to_ascii_lowercase().as_str() {
+        "query" => Ok(UpgradeAction::Query),
+        "finalize" => Ok(UpgradeAction::Finalize),
+        _ => Err(UpgradeError::UpgradeAction(s.to_owned())),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "upgrade", about = "Query or finalize upgrade of HDFS")]
+pub struct UpgradeArgs {
+    #[structopt(name = "query|finalize", parse(try_from_str = parse_upgrade_action))]
+    action: UpgradeAction,
+}
+
+pub struct Upgrade<'a> {
+    hdfs: &'a mut Hdfs,
+}
+
+impl<'a> Upgrade<'a> {
+    pub fn new(hdfs: &'a mut Hdfs) -> Self {
+        Self { hdfs }
+    }
+}
+
+impl<'a> Command for Upgrade<'a> {
+    type Args = UpgradeArgs;
+    type Error = anyhow::Error;
+
+    fn run(&mut self, args: Self::Args) -> Result<i32> {
+        match args.action {
+            UpgradeAction::Query => {
+                if self.hdfs.upgrade_status()? {
+                    println!("There is no upgrade in progress or upgrade has been finalized.");
+                } else {
+                    println!("Upgrade in progress. Not yet finalized.");
+                }
+                Ok(0)
+            }
+            UpgradeAction::Finalize => FinalizeUpgrade::new(self.hdfs).run(FinalizeUpgradeArgs {}),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rolling_upgrade_action() {
+        assert_eq!(
+            parse_rolling_upgrade_action("Prepare").unwrap(),
+            RollingUpgradeAction::START
+        );
+        assert_eq!(
+            parse_rolling_upgrade_action("QUERY").unwrap(),
+            RollingUpgradeAction::QUERY
+        );
+        assert!(parse_rolling_upgrade_action("start").is_err());
+    }
+
+    #[test]
+    fn test_rolling_upgrade_message_none() {
+        let mut out = Vec::new();
+        print_rolling_upgrade_message(&mut out, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "There is no rolling upgrade in progress or rolling upgrade has already been finalized.\n"
+        );
+    }
+
+    #[test]
+    fn test_rolling_upgrade_message_preparing() {
+        let info = RollingUpgradeInfo {
+            block_pool_id: "BP-1".into(),
+            start_time: 1000,
+            finalize_time: 0,
+            created_rollback_images: false,
+        };
+        let mut out = Vec::new();
+        print_rolling_upgrade_message(&mut out, Some(&info)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("Preparing for upgrade. Data is being saved for rollback.\n"));
+        assert!(out.contains("  Block Pool ID: BP-1\n"));
+        assert!(out.contains("(=1000)\n"));
+        assert!(out.ends_with("  Finalize Time: <NOT FINALIZED>\n"));
+    }
+}
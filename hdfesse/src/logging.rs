@@ -0,0 +1,191 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Logging setup.  Only warnings and errors are logged by default, so
+ * the output of cron jobs is not cluttered; -v, -q or the HDFESSE_LOG
+ * variable (with tracing's EnvFilter syntax, like "libhdfesse=debug")
+ * select the level.
+ */
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use structopt::StructOpt;
+use thiserror::Error;
+use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter};
+
+const LOG_ENV: &str = "HDFESSE_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Indented spans, for humans.
+    Tree,
+    /// One line per event.
+    Text,
+    /// One JSON object per event.
+    Json,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown log format {0:?}, expected tree, text or json")]
+pub struct LogFormatError(String);
+
+impl FromStr for LogFormat {
+    type Err = LogFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(LogFormat::Tree),
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LogFormatError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LogArgs {
+    /// Log more: -v for info, -vv for debug, -vvv for trace.
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    verbose: u8,
+    /// Log errors only.
+    #[structopt(short = "q", long = "quiet", conflicts_with = "verbose")]
+    quiet: bool,
+    /// Append the log to the file instead of stderr.
+    #[structopt(long = "log-file")]
+    log_file: Option<PathBuf>,
+    /// Log format: tree, text or json.
+    #[structopt(long = "log-format", default_value = "tree")]
+    log_format: LogFormat,
+}
+
+impl LogArgs {
+    /// The flags take precedence over the environment.
+    fn filter_directive(&self, env: Option<String>) -> String {
+        if self.quiet {
+            return "error".to_owned();
+        }
+        match self.verbose {
+            0 => env
+                .filter(|env| !env.is_empty())
+                .unwrap_or_else(|| "warn".to_owned()),
+            1 => "info".to_owned(),
+            2 => "debug".to_owned(),
+            _ => "trace".to_owned(),
+        }
+    }
+}
+
+// Each event takes a new writer; &File is Write, so the file is
+// shared.
+#[derive(Clone)]
+struct SharedFile(Arc<File>);
+
+impl Write for SharedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (&*self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        (&*self.0).flush()
+    }
+}
+
+/// Install the global subscriber according to the arguments.
+pub fn init(args: &LogArgs) -> Result<()> {
+    let directive = args.filter_directive(std::env::var(LOG_ENV).ok());
+    let filter = EnvFilter::try_new(&directive)
+        .with_context(|| format!("invalid log filter {:?}", directive))?;
+
+    match &args.log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("cannot open log file {:?}", path))?;
+            let file = Arc::new(file);
+            install(
+                args.log_format,
+                filter,
+                move || SharedFile(file.clone()),
+                false,
+            )
+        }
+        None => install(args.log_format, filter, std::io::stderr, true),
+    }
+}
+
+fn install<W>(format: LogFormat, filter: EnvFilter, writer: W, ansi: bool) -> Result<()>
+where
+    W: MakeWriter + Send + Sync + 'static,
+{
+    let registry = tracing_subscriber::Registry::default().with(filter);
+    match format {
+        LogFormat::Tree => tracing::subscriber::set_global_default(
+            registry.with(
+                tracing_tree::HierarchicalLayer::new(2)
+                    .with_writer(writer)
+                    .with_ansi(ansi),
+            ),
+        ),
+        LogFormat::Text => tracing::subscriber::set_global_default(
+            registry.with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_ansi(ansi),
+            ),
+        ),
+        LogFormat::Json => tracing::subscriber::set_global_default(
+            registry.with(tracing_subscriber::fmt::layer().json().with_writer(writer)),
+        ),
+    }?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(argv: &[&str]) -> LogArgs {
+        LogArgs::from_iter(std::iter::once("log").chain(argv.iter().copied()))
+    }
+
+    #[test]
+    fn test_filter_directive() {
+        assert_eq!(args(&[]).filter_directive(None), "warn");
+        assert_eq!(
+            args(&[]).filter_directive(Some("libhdfesse=debug".to_owned())),
+            "libhdfesse=debug"
+        );
+        assert_eq!(
+            args(&["-v"]).filter_directive(Some("off".to_owned())),
+            "info"
+        );
+        assert_eq!(args(&["-vv"]).filter_directive(None), "debug");
+        assert_eq!(args(&["-vvvv"]).filter_directive(None), "trace");
+        assert_eq!(args(&["-q"]).filter_directive(None), "error");
+    }
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!(args(&[]).log_format, LogFormat::Tree);
+        assert_eq!(args(&["--log-format", "json"]).log_format, LogFormat::Json);
+        assert!(LogFormat::from_str("xml").is_err());
+    }
+}
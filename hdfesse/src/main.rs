@@ -16,15 +16,35 @@
 #![forbid(unsafe_code)]
 #![warn(rust_2018_idioms)]
 mod cli;
+mod logging;
 use anyhow::Result;
 use cli::Command;
-use libhdfesse::hdconfig::Config;
-use libhdfesse::path::{Path, UriResolver};
+use libhdfesse::clusters::ClusterManager;
+use libhdfesse::error_code::ErrorCode;
+use libhdfesse::fs::{FsError, Hdfs, HdfsError};
+use libhdfesse::hdconfig::{get_config_dir, load_config_from_dir, parse_config, HDFS_CONFIG};
+use libhdfesse::path::{Path, PathError};
+use libhdfesse::rate_limit::RateLimiter;
+use libhdfesse::registry::{RegistryError, SchemeRegistry};
+use libhdfesse::rpc::RpcError;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
-use tracing_subscriber::layer::SubscriberExt;
 
 #[derive(StructOpt)]
 struct HdfessseApp {
+    /// Override a config property, like Hadoop's "-D key=value".
+    #[structopt(short = "D", number_of_values = 1, parse(try_from_str = parse_define))]
+    defines: Vec<(String, String)>,
+    /// Config directory to use instead of HADOOP_CONF_DIR.
+    #[structopt(long = "conf")]
+    conf: Option<PathBuf>,
+    /// Fail the command if it has not completed in the time, like
+    /// "30s" or "5m"; all its RPC calls share the deadline.
+    #[structopt(long = "timeout", parse(try_from_str = cli::parse_duration))]
+    timeout: Option<Duration>,
+    #[structopt(flatten)]
+    log: logging::LogArgs,
     #[structopt(subcommand)]
     subcmd: TopSubcmd,
 }
@@ -32,11 +52,33 @@ struct HdfessseApp {
 // The name is not visible in the command line.
 #[derive(StructOpt)]
 enum TopSubcmd {
+    Doctor(cli::doctor::DoctorArgs),
+    #[structopt(flatten)]
+    Cluster(ClusterSubcmd),
+}
+
+/// Commands that connect to a cluster.
+#[derive(StructOpt)]
+enum ClusterSubcmd {
+    Debug(Debug),
     Dfs(Dfs),
+    Dfsadmin(Dfsadmin),
+    #[structopt(name = "export-metadata")]
+    ExportMetadata(cli::export::ExportArgs),
+    Fsck(cli::fsck::FsckArgs),
+    #[structopt(name = "lsSnapshottableDir", alias = "-lsSnapshottableDir")]
+    LsSnapshottableDir(cli::snapshot::LsSnapshottableDirArgs),
+    #[structopt(name = "lsSnapshot", alias = "-lsSnapshot")]
+    LsSnapshot(cli::snapshot::LsSnapshotArgs),
+    Storagepolicies(Storagepolicies),
 }
 
 #[derive(StructOpt)]
 enum Dfs {
+    #[structopt(name = "-df")]
+    Df(cli::df::DfArgs),
+    #[structopt(name = "-du")]
+    Du(cli::du::DuArgs),
     #[structopt(name = "-ls")]
     Ls(cli::ls::LsArgs),
     #[structopt(name = "-mv")]
@@ -45,54 +87,241 @@ enum Dfs {
     Mkdir(cli::mkdir::MkdirArgs),
     #[structopt(name = "-rm")]
     Rm(cli::rm::RmArgs),
+    #[structopt(name = "-test")]
+    Test(cli::test::TestArgs),
+    #[structopt(name = "-touch")]
+    Touch(cli::touch::TouchArgs),
 }
 
-fn main() -> Result<()> {
-    tracing::subscriber::set_global_default(
-        tracing_subscriber::Registry::default().with(tracing_tree::HierarchicalLayer::new(2)),
-    )
-    .unwrap();
+#[derive(StructOpt)]
+enum Dfsadmin {
+    #[structopt(name = "-report")]
+    Report(cli::report::ReportArgs),
+    #[structopt(name = "-rollingUpgrade")]
+    RollingUpgrade(cli::upgrade::RollingUpgradeArgs),
+    #[structopt(name = "-finalizeUpgrade")]
+    FinalizeUpgrade(cli::upgrade::FinalizeUpgradeArgs),
+    #[structopt(name = "-upgrade")]
+    Upgrade(cli::upgrade::UpgradeArgs),
+    #[structopt(name = "-saveNamespace")]
+    SaveNamespace(cli::namespace::SaveNamespaceArgs),
+    #[structopt(name = "-rollEdits")]
+    RollEdits(cli::namespace::RollEditsArgs),
+    #[structopt(name = "-metasave")]
+    MetaSave(cli::namespace::MetaSaveArgs),
+    #[structopt(name = "-setBalancerBandwidth")]
+    SetBalancerBandwidth(cli::datanode::SetBalancerBandwidthArgs),
+    #[structopt(name = "-getStorageReport")]
+    StorageReport(cli::datanode::StorageReportArgs),
+    #[structopt(name = "-listDecommissioning")]
+    ListDecommissioning(cli::datanode::ListDecommissioningArgs),
+}
 
+#[derive(StructOpt)]
+enum Debug {
+    #[structopt(name = "blocks")]
+    Blocks(cli::blocks::BlocksArgs),
+    #[structopt(name = "datanode")]
+    Datanode(cli::datanode_probe::DatanodeProbeArgs),
+}
+
+#[derive(StructOpt)]
+enum Storagepolicies {
+    #[structopt(name = "-satisfyStoragePolicy")]
+    SatisfyStoragePolicy(cli::storage_policy::SatisfyStoragePolicyArgs),
+}
+
+/// The exit status of a failed command is defined by the first known
+/// error in the chain.
+fn error_exit_status(e: &anyhow::Error) -> i32 {
+    e.chain()
+        .find_map(|cause| {
+            cause
+                .downcast_ref::<HdfsError>()
+                .map(HdfsError::code)
+                .or_else(|| cause.downcast_ref::<FsError>().map(FsError::code))
+                .or_else(|| cause.downcast_ref::<RpcError>().map(RpcError::code))
+                .or_else(|| {
+                    cause
+                        .downcast_ref::<RegistryError>()
+                        .map(RegistryError::code)
+                })
+                .or_else(|| {
+                    cause
+                        .downcast_ref::<PathError>()
+                        .map(|_| ErrorCode::InvalidPath)
+                })
+        })
+        .unwrap_or(ErrorCode::Internal)
+        .exit_status()
+}
+
+impl ClusterSubcmd {
+    /// The path arguments of the command.
+    fn paths(&self) -> Vec<&str> {
+        use cli::PathArgs;
+        match self {
+            ClusterSubcmd::Dfs(dfs) => match dfs {
+                Dfs::Df(args) => args.paths(),
+                Dfs::Du(args) => args.paths(),
+                Dfs::Ls(args) => args.paths(),
+                Dfs::Mv(args) => args.paths(),
+                Dfs::Mkdir(args) => args.paths(),
+                Dfs::Rm(args) => args.paths(),
+                Dfs::Test(args) => args.paths(),
+                Dfs::Touch(args) => args.paths(),
+            },
+            ClusterSubcmd::Debug(debug) => match debug {
+                Debug::Blocks(args) => args.paths(),
+                Debug::Datanode(args) => args.paths(),
+            },
+            ClusterSubcmd::ExportMetadata(args) => args.paths(),
+            ClusterSubcmd::Fsck(args) => args.paths(),
+            ClusterSubcmd::LsSnapshot(args) => args.paths(),
+            ClusterSubcmd::Storagepolicies(Storagepolicies::SatisfyStoragePolicy(args)) => {
+                args.paths()
+            }
+            ClusterSubcmd::Dfsadmin(_) | ClusterSubcmd::LsSnapshottableDir(_) => vec![],
+        }
+    }
+}
+
+fn parse_define(define: &str) -> Result<(String, String)> {
+    match define.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(anyhow::anyhow!("expected key=value, got {:?}", define)),
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(retcode) => std::process::exit(retcode),
+        Err(e) => {
+            // The same format as returning the error from main.
+            eprintln!("Error: {:?}", e);
+            if e.chain().any(|cause| cause.is::<RegistryError>()) {
+                eprintln!("Run `hdfesse doctor` to check the configuration.");
+            }
+            std::process::exit(error_exit_status(&e));
+        }
+    }
+}
+
+fn run() -> Result<i32> {
     let opt = HdfessseApp::from_args();
+    logging::init(&opt.log)?;
+    let deadline = opt.timeout.map(|timeout| Instant::now() + timeout);
 
-    let config = Config::auto();
-
-    let default_fs = Path::new(
-        config
-            .default_fs
-            .as_ref()
-            .expect("config without defaultFS is not supported; perhaps, config is not found"),
-    )?;
-
-    let dfs = default_fs
-        .host()
-        .expect("defaultFS has to have a host, otherwise not supported");
-
-    let ns = match config
-        .services
-        .iter()
-        .find(|s| s.name.as_ref() == dfs.as_str())
-    {
-        Some(x) => x,
-        None => {
-            panic!("Service {:?} not found", dfs);
+    let conf_dir = opt.conf.clone().unwrap_or_else(get_config_dir);
+    let mut config_map = load_config_from_dir(&conf_dir, &HDFS_CONFIG);
+    for (key, value) in &opt.defines {
+        config_map.set(key.as_str(), value.as_str());
+    }
+    let config = parse_config(&config_map);
+    let subcmd = match opt.subcmd {
+        // Before connecting, as it diagnoses connection problems.
+        TopSubcmd::Doctor(args) => {
+            return cli::doctor::Doctor::new(&conf_dir, &config_map, &config).run(args);
         }
+        TopSubcmd::Cluster(subcmd) => subcmd,
     };
-    let client =
-        libhdfesse::ha_rpc::HaHdfsConnection::new(ns, libhdfesse::rpc::SimpleConnector {})?;
-
-    let service = libhdfesse::service::ClientNamenodeService::new(client);
-    let resolve = UriResolver::new("STUB", service.get_user(), None, None)?;
-    let mut hdfs = libhdfesse::fs::Hdfs::new(service, resolve);
-
-    let retcode = match opt.subcmd {
-        TopSubcmd::Dfs(dfs) => match dfs {
-            Dfs::Ls(ls_args) => cli::ls::Ls::new(&mut hdfs).run(ls_args)?,
-            Dfs::Mv(mv_args) => cli::mv::Mv::new(&mut hdfs).run(mv_args)?,
-            Dfs::Mkdir(mkdir_args) => cli::mkdir::Mkdir::new(&mut hdfs).run(mkdir_args)?,
-            Dfs::Rm(rm_args) => cli::rm::Rm::new(&mut hdfs).run(rm_args)?,
-        },
+
+    // The command connects to the cluster of its path arguments, or
+    // to the defaultFS without them.  Invalid paths are reported by
+    // the command.
+    let paths: Vec<Path<'static>> = subcmd
+        .paths()
+        .into_iter()
+        .filter_map(|path| Path::new(path).ok().map(Path::into_owned))
+        .collect();
+    let worker_config = config.clone();
+    let mut clusters = ClusterManager::new(config, SchemeRegistry::with_defaults());
+    let root = Path::new("/")?;
+    let target = clusters
+        .route(paths.first().unwrap_or(&root))?
+        .1
+        .into_owned();
+
+    // All the connections of the command share the rate.
+    let rate_limiter = worker_config.namenode_qps.map(RateLimiter::new);
+    // Commands with parallel workers open more connections to the same
+    // cluster.  The connections act as User::current, which honors
+    // HADOOP_USER_NAME and HADOOP_PROXY_USER.
+    let worker_registry = SchemeRegistry::with_defaults();
+    let worker_rate_limiter = rate_limiter.clone();
+    let open_worker = move || -> Result<Hdfs> {
+        let mut hdfs = worker_registry.open(&worker_config, &target)?;
+        hdfs.set_deadline(deadline);
+        hdfs.set_rate_limiter(worker_rate_limiter.clone());
+        Ok(hdfs)
     };
-    hdfs.shutdown()?;
-    std::process::exit(retcode);
+    let hdfs = clusters.get_common(&paths)?;
+    hdfs.set_deadline(deadline);
+    hdfs.set_rate_limiter(rate_limiter);
+
+    let res = run_subcmd(hdfs, &open_worker, subcmd);
+    if !matches!(res, Ok(0)) {
+        if let Some(report) = hdfs
+            .connection()
+            .last_failure()
+            .and_then(cli::failed_call_report)
+        {
+            eprintln!("{}", report);
+        }
+    }
+    let retcode = res?;
+    clusters.shutdown()?;
+    Ok(retcode)
+}
+
+fn run_subcmd(
+    hdfs: &mut Hdfs,
+    open_worker: &dyn Fn() -> Result<Hdfs>,
+    subcmd: ClusterSubcmd,
+) -> Result<i32> {
+    Ok(match subcmd {
+        ClusterSubcmd::Dfs(dfs) => match dfs {
+            Dfs::Df(df_args) => cli::df::Df::new(hdfs).run(df_args)?,
+            Dfs::Du(du_args) => cli::du::Du::new(hdfs).run(du_args)?,
+            Dfs::Ls(ls_args) => cli::ls::Ls::new(hdfs).run(ls_args)?,
+            Dfs::Mv(mv_args) => cli::mv::Mv::new(hdfs).run(mv_args)?,
+            Dfs::Mkdir(mkdir_args) => cli::mkdir::Mkdir::new(hdfs).run(mkdir_args)?,
+            Dfs::Rm(rm_args) => cli::rm::Rm::new(hdfs, open_worker).run(rm_args)?,
+            Dfs::Test(test_args) => cli::test::Test::new(hdfs).run(test_args)?,
+            Dfs::Touch(touch_args) => cli::touch::Touch::new(hdfs).run(touch_args)?,
+        },
+        ClusterSubcmd::Dfsadmin(dfsadmin) => match dfsadmin {
+            Dfsadmin::Report(report_args) => cli::report::Report::new(hdfs).run(report_args)?,
+            Dfsadmin::RollingUpgrade(args) => cli::upgrade::RollingUpgrade::new(hdfs).run(args)?,
+            Dfsadmin::FinalizeUpgrade(args) => {
+                cli::upgrade::FinalizeUpgrade::new(hdfs).run(args)?
+            }
+            Dfsadmin::Upgrade(args) => cli::upgrade::Upgrade::new(hdfs).run(args)?,
+            Dfsadmin::SaveNamespace(args) => cli::namespace::SaveNamespace::new(hdfs).run(args)?,
+            Dfsadmin::RollEdits(args) => cli::namespace::RollEdits::new(hdfs).run(args)?,
+            Dfsadmin::MetaSave(args) => cli::namespace::MetaSave::new(hdfs).run(args)?,
+            Dfsadmin::SetBalancerBandwidth(args) => {
+                cli::datanode::SetBalancerBandwidth::new(hdfs).run(args)?
+            }
+            Dfsadmin::StorageReport(args) => cli::datanode::StorageReport::new(hdfs).run(args)?,
+            Dfsadmin::ListDecommissioning(args) => {
+                cli::datanode::ListDecommissioning::new(hdfs).run(args)?
+            }
+        },
+        ClusterSubcmd::Debug(debug) => match debug {
+            Debug::Blocks(args) => cli::blocks::Blocks::new(hdfs).run(args)?,
+            Debug::Datanode(args) => cli::datanode_probe::DatanodeProbe::new(hdfs).run(args)?,
+        },
+        ClusterSubcmd::ExportMetadata(args) => cli::export::Export::new(hdfs).run(args)?,
+        ClusterSubcmd::Fsck(fsck_args) => cli::fsck::Fsck::new(hdfs).run(fsck_args)?,
+        ClusterSubcmd::LsSnapshottableDir(args) => {
+            cli::snapshot::LsSnapshottableDir::new(hdfs).run(args)?
+        }
+        ClusterSubcmd::LsSnapshot(args) => cli::snapshot::LsSnapshot::new(hdfs).run(args)?,
+        ClusterSubcmd::Storagepolicies(storagepolicies) => match storagepolicies {
+            Storagepolicies::SatisfyStoragePolicy(args) => {
+                cli::storage_policy::SatisfyStoragePolicy::new(hdfs).run(args)?
+            }
+        },
+    })
 }
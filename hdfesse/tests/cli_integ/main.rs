@@ -0,0 +1,2 @@
+#[cfg(feature = "integration_test")]
+mod test_dfs;
@@ -0,0 +1,42 @@
+use std::process::{Command, Output};
+
+use libhdfesse::test_cluster::{Capability, TestCluster};
+
+fn hdfesse(cluster: &TestCluster, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_hdfesse"));
+    if let Some(conf_dir) = cluster.conf_dir() {
+        command.arg("--conf").arg(conf_dir);
+    }
+    let output = command.args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "hdfesse {:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn test_ls() {
+    let cluster = TestCluster::require(&[]);
+    hdfesse(&cluster, &["dfs", "-ls", "/"]);
+}
+
+#[test]
+fn test_mkdir_touch_rm() {
+    let cluster = TestCluster::require(&[Capability::Write]);
+    let dir = format!("/tmp/hdfesse-cli-{}", std::process::id());
+    let file = format!("{}/file", dir);
+
+    hdfesse(&cluster, &["dfs", "-mkdir", "-p", &dir]);
+    hdfesse(&cluster, &["dfs", "-touch", &file]);
+    let output = hdfesse(&cluster, &["dfs", "-ls", &dir]);
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert!(listing.contains(&file), "{}", listing);
+
+    hdfesse(&cluster, &["dfs", "-rm", "-r", &dir]);
+    let output = hdfesse(&cluster, &["dfs", "-ls", "/tmp"]);
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert!(!listing.contains(&dir), "{}", listing);
+}
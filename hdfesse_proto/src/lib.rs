@@ -13,6 +13,14 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
+// Generated code is not maintained by hand; newer compilers emit lints
+// for it that are not relevant.
+#![allow(
+    unknown_lints,
+    renamed_and_removed_lints,
+    unused_parens,
+    mismatched_lifetime_syntaxes
+)]
 pub mod ClientNamenodeProtocol;
 pub mod HAServiceProtocol;
 pub mod IpcConnectionContext;
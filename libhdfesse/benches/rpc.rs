@@ -0,0 +1,113 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The RPC hot path: getFileInfo calls over loopback to a server that
+ * answers each of them with the same status.  Run with
+ * `cargo bench -p libhdfesse --bench rpc`.
+ */
+use criterion::{criterion_group, criterion_main, Criterion};
+use libhdfesse::proto::{
+    hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType},
+    ClientNamenodeProtocol::{GetFileInfoRequestProto, GetFileInfoResponseProto},
+    ProtobufRpcEngine::RequestHeaderProto,
+    RpcHeader::{
+        RpcRequestHeaderProto, RpcRequestHeaderProto_OperationProto, RpcResponseHeaderProto,
+    },
+};
+use libhdfesse::rpc::{HdfsConnection, RpcConnection, RpcStatus, SimpleConnector};
+use protobuf::{CodedInputStream, CodedOutputStream, Message};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+fn read_frame(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).ok()?;
+    let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+fn write_frame(stream: &mut TcpStream, messages: &[&dyn Message]) {
+    let mut frame = vec![];
+    {
+        let mut cos = CodedOutputStream::vec(&mut frame);
+        for msg in messages {
+            msg.write_length_delimited_to(&mut cos).unwrap();
+        }
+        cos.flush().unwrap();
+    }
+    stream
+        .write_all(&(frame.len() as u32).to_be_bytes())
+        .unwrap();
+    stream.write_all(&frame).unwrap();
+}
+
+/// Answer the calls until the client disconnects.
+fn serve(listener: TcpListener) {
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut preamble = [0u8; 7];
+    stream.read_exact(&mut preamble).unwrap();
+    // The connection context.
+    read_frame(&mut stream).unwrap();
+
+    let mut status = HdfsFileStatusProto::default();
+    status.set_fileType(HdfsFileStatusProto_FileType::IS_FILE);
+    status.set_path(b"part-00000".to_vec());
+    status.set_length(128 * 1024 * 1024);
+    status.mut_permission().set_perm(0o644);
+    status.set_owner("hdfs".to_owned());
+    status.set_group("supergroup".to_owned());
+    status.set_modification_time(1_600_000_000_000);
+    status.set_access_time(1_600_000_000_000);
+    let mut resp = GetFileInfoResponseProto::default();
+    resp.set_fs(status);
+
+    while let Some(data) = read_frame(&mut stream) {
+        let mut cis = CodedInputStream::from_bytes(&data);
+        let hh: RpcRequestHeaderProto = cis.read_message().unwrap();
+        if hh.get_rpcOp() == RpcRequestHeaderProto_OperationProto::RPC_CLOSE_CONNECTION {
+            break;
+        }
+        let _rh: RequestHeaderProto = cis.read_message().unwrap();
+        let mut resp_header = RpcResponseHeaderProto::default();
+        resp_header.set_callId(hh.get_callId() as u32);
+        resp_header.set_status(RpcStatus::SUCCESS);
+        write_frame(&mut stream, &[&resp_header, &resp]);
+    }
+}
+
+fn bench_get_file_info(c: &mut Criterion) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || serve(listener));
+
+    let mut conn = HdfsConnection::new("bench".into(), addr, &SimpleConnector {}).unwrap();
+    let mut args = GetFileInfoRequestProto::default();
+    args.set_src("/user/bench/data/part-00000".to_owned());
+    c.bench_function("getFileInfo loopback", |b| {
+        b.iter(|| {
+            conn.call::<GetFileInfoResponseProto>("getFileInfo".into(), &args)
+                .unwrap()
+        })
+    });
+
+    conn.shutdown().unwrap();
+    server.join().unwrap();
+}
+
+criterion_group!(benches, bench_get_file_info);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Follow the namespace changes with the inotify API, like Java's
+ * DFSInotifyEventInputStream.  Run with `cargo run --example
+ * inotify_tail --features testing`; the namenode is replaced with
+ * MockConnection.
+ */
+use hdfesse_proto::{
+    inotify::{EventBatchProto, EventProto, EventType},
+    ClientNamenodeProtocol::{
+        GetCurrentEditLogTxidRequestProto, GetCurrentEditLogTxidResponseProto,
+        GetEditsFromTxidRequestProto, GetEditsFromTxidResponseProto,
+    },
+};
+use libhdfesse::{service::ClientNamenodeService, testing::MockConnection};
+
+const LAST_TXID: i64 = 12;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = MockConnection::new("alice");
+    conn.on(
+        "getCurrentEditLogTxid",
+        |_req: GetCurrentEditLogTxidRequestProto| {
+            let mut resp = GetCurrentEditLogTxidResponseProto::default();
+            resp.set_txid(10);
+            Ok(resp)
+        },
+    )
+    .on("getEditsFromTxid", |req: GetEditsFromTxidRequestProto| {
+        let mut resp = GetEditsFromTxidResponseProto::default();
+        let list = resp.mut_eventsList();
+        list.set_firstTxid(req.get_txid());
+        list.set_lastTxid(LAST_TXID);
+        list.set_syncTxid(LAST_TXID);
+        for txid in req.get_txid()..=LAST_TXID {
+            let mut event = EventProto::default();
+            event.set_field_type(EventType::EVENT_CLOSE);
+            event.set_contents(vec![]);
+            let mut batch = EventBatchProto::default();
+            batch.set_txid(txid);
+            batch.mut_events().push(event);
+            list.mut_batch().push(batch);
+        }
+        Ok(resp)
+    });
+    let mut service = ClientNamenodeService::new(conn);
+
+    // Start from the current transaction, skipping the history.
+    let mut txid = service
+        .getCurrentEditLogTxid(&GetCurrentEditLogTxidRequestProto::default())?
+        .get_txid();
+    // A real tail polls forever; the mock runs out of transactions.
+    while txid < LAST_TXID {
+        let mut args = GetEditsFromTxidRequestProto::default();
+        args.set_txid(txid + 1);
+        let resp = service.getEditsFromTxid(&args)?;
+        let list = resp.get_eventsList();
+        for batch in list.get_batch() {
+            for event in batch.get_events() {
+                println!("{}\t{:?}", batch.get_txid(), event.get_field_type());
+            }
+        }
+        txid = list.get_lastTxid();
+    }
+    assert_eq!(txid, LAST_TXID);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * List a directory.  Run with `cargo run --example list --features
+ * testing`; the namenode is replaced with MockConnection.
+ */
+use hdfesse_proto::ClientNamenodeProtocol::{
+    GetFileInfoRequestProto, GetFileInfoResponseProto, GetListingRequestProto,
+    GetListingResponseProto,
+};
+use libhdfesse::{
+    fs::Hdfs,
+    path::{Path, UriResolver},
+    service::ClientNamenodeService,
+    testing::{file_status, MockConnection},
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = MockConnection::new("alice");
+    conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+        let mut resp = GetFileInfoResponseProto::default();
+        resp.set_fs(file_status(req.get_src(), true, 0));
+        Ok(resp)
+    })
+    .on("getListing", |_req: GetListingRequestProto| {
+        let mut resp = GetListingResponseProto::default();
+        let listing = resp.mut_dirList();
+        listing
+            .mut_partialListing()
+            .push(file_status("part-0000", false, 1024));
+        listing
+            .mut_partialListing()
+            .push(file_status("part-0001", false, 512));
+        listing.set_remainingEntries(0);
+        Ok(resp)
+    });
+
+    let service = ClientNamenodeService::new(conn);
+    let resolve = UriResolver::new("mock", service.get_user(), None, None)?;
+    let mut hdfs = Hdfs::new(service, resolve);
+
+    let mut names = vec![];
+    for entry in hdfs.list_status(&Path::new("/data")?)? {
+        let entry = entry?;
+        let name = String::from_utf8_lossy(entry.get_path()).into_owned();
+        println!("{}\t{}", entry.get_length(), name);
+        names.push(name);
+    }
+    assert_eq!(names, ["part-0000", "part-0001"]);
+
+    hdfs.shutdown()?;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Print the difference between two snapshots.  Run with `cargo run
+ * --example snapshot_diff --features testing`; the namenode is
+ * replaced with MockConnection.
+ */
+use hdfesse_proto::{
+    hdfs::SnapshotDiffReportEntryProto,
+    ClientNamenodeProtocol::{
+        GetSnapshotDiffReportRequestProto, GetSnapshotDiffReportResponseProto,
+    },
+};
+use libhdfesse::{service::ClientNamenodeService, testing::MockConnection};
+
+fn entry(label: &str, path: &str) -> SnapshotDiffReportEntryProto {
+    let mut entry = SnapshotDiffReportEntryProto::default();
+    entry.set_modificationLabel(label.to_owned());
+    entry.set_fullpath(path.as_bytes().to_vec());
+    entry
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = MockConnection::new("alice");
+    conn.on(
+        "getSnapshotDiffReport",
+        |req: GetSnapshotDiffReportRequestProto| {
+            let mut resp = GetSnapshotDiffReportResponseProto::default();
+            let report = resp.mut_diffReport();
+            report.set_snapshotRoot(req.get_snapshotRoot().to_owned());
+            report.set_fromSnapshot(req.get_fromSnapshot().to_owned());
+            report.set_toSnapshot(req.get_toSnapshot().to_owned());
+            report.mut_diffReportEntries().push(entry("M", ""));
+            report.mut_diffReportEntries().push(entry("+", "new.csv"));
+            report.mut_diffReportEntries().push(entry("-", "old.csv"));
+            Ok(resp)
+        },
+    );
+    let mut service = ClientNamenodeService::new(conn);
+
+    let mut args = GetSnapshotDiffReportRequestProto::default();
+    args.set_snapshotRoot("/data".to_owned());
+    args.set_fromSnapshot("s1".to_owned());
+    args.set_toSnapshot("s2".to_owned());
+    let resp = service.getSnapshotDiffReport(&args)?;
+    let report = resp.get_diffReport();
+
+    // The same format as `hdfs snapshotDiff`.
+    println!(
+        "Difference between snapshot {} and snapshot {} under directory {}:",
+        report.get_fromSnapshot(),
+        report.get_toSnapshot(),
+        report.get_snapshotRoot()
+    );
+    for entry in report.get_diffReportEntries() {
+        println!(
+            "{}\t./{}",
+            entry.get_modificationLabel(),
+            String::from_utf8_lossy(entry.get_fullpath())
+        );
+    }
+    assert_eq!(report.get_diffReportEntries().len(), 3);
+    assert_eq!(service.into_inner().calls(), ["getSnapshotDiffReport"]);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Sources of the credentials that connections authenticate with.  The
+ * RPC connection supports only SIMPLE authentication, where the
+ * namenode trusts the user name in the connection header; Kerberos
+ * and delegation tokens need SASL, which is not implemented yet, and
+ * will come as new Credentials variants.
+ */
+use std::error::Error;
+
+use crate::{user::User, util};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Credentials {
+    /// SIMPLE authentication with the user name.
+    Simple { user: String },
+}
+
+impl Credentials {
+    /// The authenticated user.
+    pub fn user(&self) -> &str {
+        match self {
+            Credentials::Simple { user } => user,
+        }
+    }
+}
+
+/**
+ * A source of credentials, e.g. of a gateway service that
+ * authenticates as different users.  The credentials are requested
+ * for each new connection to a namenode, so a provider may refresh
+ * them when they expire.
+ */
+pub trait AuthProvider: std::fmt::Debug + Send + Sync {
+    fn credentials(&self) -> Result<Credentials, Box<dyn Error + Send + Sync>>;
+}
+
+/// The user of the process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsUser;
+
+impl AuthProvider for OsUser {
+    fn credentials(&self) -> Result<Credentials, Box<dyn Error + Send + Sync>> {
+        Ok(Credentials::Simple {
+            user: util::get_username()?,
+        })
+    }
+}
+
+/// User::login, i.e. HADOOP_USER_NAME or the OS user; the default
+/// provider.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginUser;
+
+impl AuthProvider for LoginUser {
+    fn credentials(&self) -> Result<Credentials, Box<dyn Error + Send + Sync>> {
+        Ok(Credentials::Simple {
+            user: User::login()?.name().to_owned(),
+        })
+    }
+}
+
+/// A fixed user name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticUser(pub String);
+
+impl AuthProvider for StaticUser {
+    fn credentials(&self) -> Result<Credentials, Box<dyn Error + Send + Sync>> {
+        Ok(Credentials::Simple {
+            user: self.0.clone(),
+        })
+    }
+}
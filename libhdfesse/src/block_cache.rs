@@ -0,0 +1,241 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Cache of block locations for wide scans.  Locations are fetched by
+ * regions of REGION_SIZE bytes, so that only the read part of a large
+ * file is ever requested.
+ */
+use std::collections::{HashMap, VecDeque};
+
+use crate::status::LocatedBlock;
+
+/// Block locations are fetched and cached by regions of this size.
+pub const REGION_SIZE: u64 = 1 << 30;
+
+pub const DEFAULT_CACHE_FILES: usize = 1024;
+
+struct CachedFile {
+    length: u64,
+    mtime: u64,
+    regions: HashMap<u64, Vec<LocatedBlock>>,
+}
+
+/**
+ * Block locations by file id.  A file's entry is dropped when its
+ * length or modification time changes; the least recently added
+ * file is evicted when the cache is full.
+ */
+pub struct BlockLocationCache {
+    files: HashMap<u64, CachedFile>,
+    order: VecDeque<u64>,
+    capacity: usize,
+}
+
+/// Regions overlapping the byte range.
+pub(crate) fn regions(offset: u64, length: u64) -> std::ops::Range<u64> {
+    if length == 0 {
+        0..0
+    } else {
+        (offset / REGION_SIZE)..((offset + length - 1) / REGION_SIZE + 1)
+    }
+}
+
+impl BlockLocationCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            files: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// The regions of the range that are to be fetched.  The file
+    /// entry is reset if the file has changed.
+    pub(crate) fn missing_regions(
+        &mut self,
+        file_id: u64,
+        length: u64,
+        mtime: u64,
+        offset: u64,
+        range_length: u64,
+    ) -> Vec<u64> {
+        let file = self.files.get(&file_id);
+        if file.map(|file| (file.length, file.mtime) != (length, mtime)) == Some(true) {
+            self.invalidate(file_id);
+        }
+        if self.capacity == 0 {
+            return regions(offset, range_length).collect();
+        }
+        if !self.files.contains_key(&file_id) {
+            if self.files.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.files.remove(&oldest);
+                }
+            }
+            self.order.push_back(file_id);
+            self.files.insert(
+                file_id,
+                CachedFile {
+                    length,
+                    mtime,
+                    regions: HashMap::new(),
+                },
+            );
+        }
+        let file = &self.files[&file_id];
+        regions(offset, range_length)
+            .filter(|region| !file.regions.contains_key(region))
+            .collect()
+    }
+
+    pub(crate) fn insert_region(&mut self, file_id: u64, region: u64, blocks: Vec<LocatedBlock>) {
+        if let Some(file) = self.files.get_mut(&file_id) {
+            file.regions.insert(region, blocks);
+        }
+    }
+
+    /// Cached blocks overlapping the range, ordered by offset.
+    pub(crate) fn blocks(&self, file_id: u64, offset: u64, length: u64) -> Vec<LocatedBlock> {
+        let file = match self.files.get(&file_id) {
+            Some(file) => file,
+            None => return vec![],
+        };
+        let end = offset.saturating_add(length);
+        // A block may span two regions.
+        let mut blocks: Vec<LocatedBlock> = regions(offset, length)
+            .filter_map(|region| file.regions.get(&region))
+            .flatten()
+            .filter(|block| block.offset < end && block.offset + block.b.num_bytes > offset)
+            .cloned()
+            .collect();
+        blocks.sort_by_key(|block| block.offset);
+        blocks.dedup_by_key(|block| block.offset);
+        blocks
+    }
+
+    pub fn invalidate(&mut self, file_id: u64) {
+        if self.files.remove(&file_id).is_some() {
+            self.order.retain(|id| *id != file_id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.order.clear();
+    }
+
+    /// The maximum number of cached files; zero disables the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of cached files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl Default for BlockLocationCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_FILES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ExtendedBlock;
+    use hdfesse_proto::Security::TokenProto;
+
+    const BLOCK: u64 = 256 << 20;
+
+    fn block(offset: u64) -> LocatedBlock {
+        LocatedBlock {
+            b: ExtendedBlock {
+                pool_id: "pool".into(),
+                block_id: offset,
+                num_bytes: BLOCK,
+                generation_stamp: 1,
+            },
+            offset,
+            locs: vec![],
+            storage_ids: vec![],
+            storage_types: vec![],
+            corrupt: false,
+            block_token: TokenProto::default().into(),
+            cached_locs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_regions() {
+        assert_eq!(regions(0, 0), 0..0);
+        assert_eq!(regions(0, 1), 0..1);
+        assert_eq!(regions(REGION_SIZE - 1, 2), 0..2);
+        assert_eq!(regions(REGION_SIZE, REGION_SIZE), 1..2);
+    }
+
+    #[test]
+    fn test_missing_regions_and_invalidation() {
+        let mut cache = BlockLocationCache::default();
+        let length = 3 * REGION_SIZE;
+        assert_eq!(cache.missing_regions(1, length, 10, 0, length), [0, 1, 2]);
+        cache.insert_region(1, 1, vec![block(REGION_SIZE)]);
+        assert_eq!(cache.missing_regions(1, length, 10, 0, length), [0, 2]);
+        assert_eq!(
+            cache.missing_regions(1, length, 10, REGION_SIZE, REGION_SIZE),
+            Vec::<u64>::new()
+        );
+        // The file is appended.
+        assert_eq!(
+            cache.missing_regions(1, length + 1, 11, REGION_SIZE, REGION_SIZE),
+            [1]
+        );
+    }
+
+    #[test]
+    fn test_blocks_dedup() {
+        let mut cache = BlockLocationCache::default();
+        cache.missing_regions(1, 2 * REGION_SIZE, 0, 0, 2 * REGION_SIZE);
+        // The block at 7/8 GB spans both regions.
+        let spanning = block(REGION_SIZE - BLOCK / 2);
+        cache.insert_region(1, 0, vec![block(0), spanning.clone()]);
+        cache.insert_region(1, 1, vec![spanning, block(REGION_SIZE + BLOCK / 2)]);
+
+        let offsets: Vec<u64> = cache
+            .blocks(1, REGION_SIZE - 1, 2)
+            .iter()
+            .map(|block| block.offset)
+            .collect();
+        assert_eq!(offsets, [REGION_SIZE - BLOCK / 2]);
+        assert_eq!(cache.blocks(1, 0, 2 * REGION_SIZE).len(), 3);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut cache = BlockLocationCache::new(2);
+        for file_id in 1..=3 {
+            cache.missing_regions(file_id, 1, 0, 0, 1);
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.missing_regions(1, 1, 0, 0, 1), [0]);
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,63 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Cancellation of RPC calls.  The token is shared between the code
+ * that performs the calls and, for example, a signal handler or
+ * another thread; the anticipated async version will reuse it as is.
+ */
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/**
+ * A cloneable flag; all clones share the same state.  Once cancelled,
+ * the token stays cancelled.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all calls that use this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_shared() {
+        let token = CancellationToken::new();
+        let other = token.clone();
+        assert!(!token.is_cancelled());
+        other.cancel();
+        assert!(token.is_cancelled());
+        assert!(other.is_cancelled());
+    }
+}
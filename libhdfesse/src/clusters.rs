@@ -0,0 +1,264 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Connections to several clusters at once.  Each operation is routed
+ * by the scheme and the authority of its path, so that a process can
+ * work with hdfs://one/... and hdfs://two/... paths together.
+ */
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+};
+
+use crate::{
+    fs::{Hdfs, HdfsError},
+    hdconfig::Config,
+    path::Path,
+    registry::{RegistryError, SchemeRegistry},
+};
+
+/**
+ * A filesystem instance per scheme and authority, created on first
+ * use by the registry.  Paths without scheme belong to the config's
+ * defaultFS, as do the paths without authority of its scheme, like
+ * hdfs:///a; other schemes, like file:///a, go to their own registry
+ * entry.
+ */
+pub struct ClusterManager<F = Hdfs> {
+    config: Config,
+    registry: SchemeRegistry<F>,
+    // By scheme and host, both in lowercase.
+    filesystems: HashMap<(String, String), F>,
+}
+
+impl<F> ClusterManager<F> {
+    pub fn new(config: Config, registry: SchemeRegistry<F>) -> Self {
+        Self {
+            config,
+            registry,
+            filesystems: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The key (the scheme and the authority in lowercase) and the URI
+    /// of the path's filesystem.
+    pub fn route<'p>(
+        &self,
+        path: &'p Path<'p>,
+    ) -> Result<((String, String), Cow<'p, Path<'p>>), RegistryError> {
+        let default = || -> Result<Cow<'p, Path<'p>>, RegistryError> {
+            let default = self
+                .config
+                .default_fs
+                .as_deref()
+                .ok_or_else(|| RegistryError::NoScheme(path.to_string().into()))?;
+            Ok(Cow::Owned(Path::new(default)?.into_owned()))
+        };
+        let target = match (path.scheme(), path.host().filter(|host| !host.is_empty())) {
+            (Some(_), Some(_)) => Cow::Borrowed(path),
+            (Some(scheme), None) => {
+                let default = default()?;
+                if default
+                    .scheme()
+                    .map(|default| default.eq_ignore_ascii_case(&scheme))
+                    .unwrap_or(false)
+                {
+                    default
+                } else {
+                    Cow::Borrowed(path)
+                }
+            }
+            (None, _) => default()?,
+        };
+        let scheme = target
+            .scheme()
+            .ok_or_else(|| RegistryError::NoScheme(target.to_string().into()))?;
+        let host = target.host().unwrap_or_default().to_ascii_lowercase();
+        Ok(((scheme, host), target))
+    }
+
+    /// The filesystem of the path.
+    pub fn get(&mut self, path: &Path<'_>) -> Result<&mut F, RegistryError> {
+        let (key, target) = self.route(path)?;
+        Ok(match self.filesystems.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.registry.open(&self.config, &target)?),
+        })
+    }
+
+    /// The filesystem of the defaultFS.
+    pub fn get_default(&mut self) -> Result<&mut F, RegistryError> {
+        self.get(&Path::new("/")?)
+    }
+
+    /// Both paths have to belong to the same filesystem, e.g. for
+    /// rename.
+    pub fn get_same(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<&mut F, RegistryError> {
+        self.get_common(&[src.clone(), dst.clone()])
+    }
+
+    /// The filesystem of all the paths, e.g. of a command's arguments;
+    /// the defaultFS if there are none.
+    pub fn get_common(&mut self, paths: &[Path<'_>]) -> Result<&mut F, RegistryError> {
+        let first = match paths.first() {
+            Some(first) => first,
+            None => return self.get_default(),
+        };
+        let key = self.route(first)?.0;
+        for path in &paths[1..] {
+            if self.route(path)?.0 != key {
+                return Err(RegistryError::DifferentFilesystems {
+                    src: first.to_string().into(),
+                    dst: path.to_string().into(),
+                });
+            }
+        }
+        self.get(first)
+    }
+
+    /// The number of open filesystems.
+    pub fn len(&self) -> usize {
+        self.filesystems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filesystems.is_empty()
+    }
+}
+
+impl ClusterManager<Hdfs> {
+    /// Shut down all the connections; the first error is returned.
+    pub fn shutdown(self) -> Result<(), HdfsError> {
+        let mut res = Ok(());
+        for (_, hdfs) in self.filesystems {
+            let shut = hdfs.shutdown();
+            if res.is_ok() {
+                res = shut;
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn manager(opened: Arc<AtomicUsize>) -> ClusterManager<String> {
+        let mut registry = SchemeRegistry::new();
+        registry.register("hdfs", move |_config, path| {
+            opened.fetch_add(1, Ordering::SeqCst);
+            Ok(path.host().unwrap_or_default())
+        });
+        registry.register("file", |_config, _path| Ok("local".to_owned()));
+        ClusterManager::new(
+            Config {
+                default_fs: Some("hdfs://main".into()),
+                services: vec![],
+                client_name_suffix: None,
+                namenode_qps: None,
+            },
+            registry,
+        )
+    }
+
+    #[test]
+    fn test_route_by_authority() {
+        let opened = Arc::new(AtomicUsize::new(0));
+        let mut clusters = manager(opened.clone());
+        assert_eq!(
+            clusters.get(&Path::new("hdfs://other/a").unwrap()).unwrap(),
+            "other"
+        );
+        assert_eq!(clusters.get(&Path::new("/a/b").unwrap()).unwrap(), "main");
+        assert_eq!(clusters.get(&Path::new("a").unwrap()).unwrap(), "main");
+        assert_eq!(
+            clusters.get(&Path::new("hdfs://OTHER/b").unwrap()).unwrap(),
+            "other"
+        );
+        assert_eq!(clusters.get_default().unwrap(), "main");
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+        assert_eq!(clusters.len(), 2);
+    }
+
+    #[test]
+    fn test_route_without_authority() {
+        let mut clusters = manager(Arc::new(AtomicUsize::new(0)));
+        // The defaultFS, as its scheme matches.
+        assert_eq!(
+            clusters.get(&Path::new("HDFS:///a").unwrap()).unwrap(),
+            "main"
+        );
+        assert_eq!(
+            clusters.get(&Path::new("file:///tmp/a").unwrap()).unwrap(),
+            "local"
+        );
+        assert!(matches!(
+            clusters.get(&Path::new("s3a:///a").unwrap()),
+            Err(RegistryError::UnknownScheme(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_same() {
+        let mut clusters = manager(Arc::new(AtomicUsize::new(0)));
+        assert_eq!(
+            clusters
+                .get_same(
+                    &Path::new("/a").unwrap(),
+                    &Path::new("hdfs://main/b").unwrap()
+                )
+                .unwrap(),
+            "main"
+        );
+        assert!(matches!(
+            clusters.get_same(
+                &Path::new("/a").unwrap(),
+                &Path::new("hdfs://other/b").unwrap()
+            ),
+            Err(RegistryError::DifferentFilesystems { .. })
+        ));
+    }
+
+    #[test]
+    fn test_get_common() {
+        let mut clusters = manager(Arc::new(AtomicUsize::new(0)));
+        assert_eq!(clusters.get_common(&[]).unwrap(), "main");
+        let paths = [
+            Path::new("hdfs://other/a").unwrap(),
+            Path::new("hdfs://Other/b").unwrap(),
+        ];
+        assert_eq!(clusters.get_common(&paths).unwrap(), "other");
+        let paths = [
+            Path::new("hdfs://other/a").unwrap(),
+            Path::new("/b").unwrap(),
+        ];
+        match clusters.get_common(&paths) {
+            Err(RegistryError::DifferentFilesystems { src, dst }) => {
+                assert_eq!((&*src, &*dst), ("hdfs://other/a", "/b"))
+            }
+            other => panic!("unexpected result: {:?}", other.map(|fs| fs.clone())),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Messages of the datanode data transfer protocol.  Each operation
+ * carries the block access token of the block, which the datanode
+ * checks when the cluster has dfs.block.access.token.enable set; the
+ * token is taken from the block locations returned by the namenode.
+ *
+ * SASL on the data channel (dfs.data.transfer.protection) is not
+ * supported yet; such datanodes reject the operation.
+ */
+use std::io::{Read, Write};
+
+use hdfesse_proto::datatransfer::{
+    BaseHeaderProto, BlockOpResponseProto, ClientOperationHeaderProto, OpReadBlockProto, Status,
+};
+use protobuf::Message;
+use thiserror::Error;
+
+use crate::{error_code::ErrorCode, status::LocatedBlock};
+
+/// DataTransferProtocol.DATA_TRANSFER_VERSION of Hadoop 2 and 3.
+pub const DATA_TRANSFER_VERSION: u16 = 28;
+/// Longest operation response accepted, like the RPC's
+/// ipc.maximum.response.length.
+const MAX_RESPONSE_LEN: u64 = 128 * 1024 * 1024;
+
+/// Operation codes; see Java's org.apache.hadoop.hdfs.protocol.datatransfer.Op.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Op {
+    WriteBlock = 80,
+    ReadBlock = 81,
+    BlockChecksum = 85,
+}
+
+#[derive(Debug, Error)]
+pub enum DataTransferError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Protobuf(#[from] protobuf::ProtobufError),
+    /// The block token is invalid or expired; the block locations
+    /// have to be fetched again.
+    #[error("block access token rejected: {0}")]
+    AccessToken(String),
+    #[error("datanode error {status:?}: {message}")]
+    Status { status: Status, message: String },
+    #[error("response is too long: {len} bytes")]
+    ResponseTooLong { len: u64 },
+}
+
+impl DataTransferError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DataTransferError::Io(_) => ErrorCode::Io,
+            DataTransferError::Protobuf(_)
+            | DataTransferError::Status { .. }
+            | DataTransferError::ResponseTooLong { .. } => ErrorCode::Protocol,
+            DataTransferError::AccessToken(_) => ErrorCode::AccessDenied,
+        }
+    }
+}
+
+/// Header of client operations with the block and its access token.
+pub fn client_header(block: &LocatedBlock, client_name: &str) -> ClientOperationHeaderProto {
+    let mut base = BaseHeaderProto::default();
+    base.set_block((&block.b).into());
+    base.set_token((&block.block_token).into());
+
+    let mut header = ClientOperationHeaderProto::default();
+    header.set_baseHeader(base);
+    header.set_clientName(client_name.to_owned());
+    header
+}
+
+/// Request to read the part of the block; the offset is relative to
+/// the block start.
+pub fn read_block_op(
+    block: &LocatedBlock,
+    client_name: &str,
+    offset: u64,
+    len: u64,
+    send_checksums: bool,
+) -> OpReadBlockProto {
+    let mut op = OpReadBlockProto::default();
+    op.set_header(client_header(block, client_name));
+    op.set_offset(offset);
+    op.set_len(len);
+    op.set_sendChecksums(send_checksums);
+    op
+}
+
+/// Send the operation: the protocol version, the op code and the
+/// length-delimited request.
+pub fn send_op<W: Write>(
+    out: &mut W,
+    op: Op,
+    request: &dyn Message,
+) -> Result<(), DataTransferError> {
+    out.write_all(&DATA_TRANSFER_VERSION.to_be_bytes())?;
+    out.write_all(&[op as u8])?;
+    let mut cos = protobuf::CodedOutputStream::new(out);
+    request.write_length_delimited_to(&mut cos)?;
+    cos.flush()?;
+    Ok(())
+}
+
+/**
+ * Read the length-delimited response to an operation.  Unlike
+ * protobuf's CodedInputStream, nothing beyond the message is consumed:
+ * the block data follows it in the same stream.  A buffered input
+ * may read ahead, though: the block data is then in its buffer, and
+ * the caller has to keep reading from it rather than from the socket.
+ */
+pub fn read_response<R: Read>(input: &mut R) -> Result<BlockOpResponseProto, DataTransferError> {
+    let mut len: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        input.read_exact(&mut byte)?;
+        len |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            if len > MAX_RESPONSE_LEN {
+                return Err(DataTransferError::ResponseTooLong { len });
+            }
+            let mut buf = vec![0; len as usize];
+            input.read_exact(&mut buf)?;
+            return Ok(BlockOpResponseProto::parse_from_bytes(&buf)?);
+        }
+    }
+    Err(protobuf::ProtobufError::WireError(protobuf::error::WireError::IncorrectVarint).into())
+}
+
+/// Check the datanode response to the operation.
+pub fn check_response(resp: &BlockOpResponseProto) -> Result<(), DataTransferError> {
+    match resp.get_status() {
+        Status::SUCCESS => Ok(()),
+        Status::ERROR_ACCESS_TOKEN => Err(DataTransferError::AccessToken(
+            resp.get_message().to_owned(),
+        )),
+        status => Err(DataTransferError::Status {
+            status,
+            message: resp.get_message().to_owned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::{ExtendedBlock, Token};
+    use hdfesse_proto::Security::TokenProto;
+
+    fn located_block() -> LocatedBlock {
+        let mut token = TokenProto::default();
+        token.set_identifier(b"id".to_vec());
+        token.set_password(b"secret".to_vec());
+        token.set_kind("HDFS_BLOCK_TOKEN".to_owned());
+        LocatedBlock {
+            b: ExtendedBlock {
+                pool_id: "BP-1".into(),
+                block_id: 1073741825,
+                num_bytes: 1024,
+                generation_stamp: 1001,
+            },
+            offset: 0,
+            locs: vec![],
+            storage_ids: vec![],
+            storage_types: vec![],
+            corrupt: false,
+            block_token: Token::from(token),
+            cached_locs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_read_block_op_token() {
+        let op = read_block_op(&located_block(), "client", 10, 100, true);
+        let base = op.get_header().get_baseHeader();
+        assert_eq!(base.get_block().get_blockId(), 1073741825);
+        assert_eq!(base.get_block().get_generationStamp(), 1001);
+        assert_eq!(base.get_token().get_identifier(), b"id");
+        assert_eq!(base.get_token().get_password(), b"secret");
+        assert_eq!(base.get_token().get_kind(), "HDFS_BLOCK_TOKEN");
+        assert_eq!(op.get_header().get_clientName(), "client");
+        assert!(op.is_initialized());
+    }
+
+    #[test]
+    fn test_send_op_framing() {
+        let op = read_block_op(&located_block(), "client", 0, 1, false);
+        let mut out = vec![];
+        send_op(&mut out, Op::ReadBlock, &op).unwrap();
+        assert_eq!(&out[..3], &[0, 28, 81]);
+
+        let mut cis = protobuf::CodedInputStream::from_bytes(&out[3..]);
+        let parsed: OpReadBlockProto = cis.read_message().unwrap();
+        assert_eq!(parsed, op);
+    }
+
+    #[test]
+    fn test_read_response() {
+        let mut resp = BlockOpResponseProto::default();
+        resp.set_status(Status::ERROR);
+        resp.set_message("Replica not found".to_owned());
+        let mut input = resp.write_length_delimited_to_bytes().unwrap();
+        input.extend_from_slice(b"packet");
+
+        let mut reader = &input[..];
+        assert_eq!(read_response(&mut reader).unwrap(), resp);
+        assert_eq!(reader, b"packet");
+
+        let mut closed = &input[..3];
+        assert!(matches!(
+            read_response(&mut closed),
+            Err(DataTransferError::Io(_))
+        ));
+
+        // 2^32 as a varint.
+        let mut huge = &[0x80, 0x80, 0x80, 0x80, 0x10][..];
+        assert!(matches!(
+            read_response(&mut huge),
+            Err(DataTransferError::ResponseTooLong { len: 0x1_0000_0000 })
+        ));
+    }
+
+    #[test]
+    fn test_check_response() {
+        let mut resp = BlockOpResponseProto::default();
+        resp.set_status(Status::SUCCESS);
+        assert!(check_response(&resp).is_ok());
+
+        resp.set_status(Status::ERROR_ACCESS_TOKEN);
+        resp.set_message("Block token is expired".to_owned());
+        let err = check_response(&resp).unwrap_err();
+        assert!(matches!(err, DataTransferError::AccessToken(_)));
+        assert_eq!(err.code(), ErrorCode::AccessDenied);
+    }
+}
@@ -0,0 +1,589 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Client-side envelope encryption, for clusters without KMS and
+ * encryption zones.  Each file gets a random data key, encrypted by
+ * the user's KeyProvider and stored with the other parameters in the
+ * ENVELOPE_XATTR extended attribute of the file; the namenode and the
+ * datanodes never see the data key or the plaintext.
+ *
+ * The data is encrypted with AES-256-GCM in chunks of a fixed size, as
+ * in the STREAM construction: the nonce of a chunk is the file's random
+ * prefix, the chunk number and a flag of the last chunk, so chunks
+ * cannot be reordered, dropped or appended without failing the
+ * decryption.  Each chunk grows by TAG_SIZE bytes.
+ */
+use crate::{
+    fs::{FsError, Hdfs, HdfsError},
+    path::Path,
+    rpc::RpcConnection,
+    service::ClientNamenodeService,
+};
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use hdfesse_proto::xattr::{XAttrProto, XAttrProto_XAttrNamespaceProto};
+use std::{
+    borrow::BorrowMut,
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+use thiserror::Error;
+
+/// The extended attribute with the header, in the user namespace.
+pub const ENVELOPE_XATTR: &str = "hdfesse.envelope";
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+/// The authentication tag appended to each chunk.
+pub const TAG_SIZE: usize = 16;
+
+const MAGIC: &[u8; 4] = b"HDFE";
+const VERSION: u8 = 1;
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const PREFIX_SIZE: usize = 7;
+
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    #[error("key provider error: {0}")]
+    KeyProvider(String),
+    #[error("unknown key {0:?}")]
+    UnknownKey(String),
+    #[error("invalid envelope header: {0}")]
+    Header(&'static str),
+    /// A chunk of the wrong size, or one after the last one.
+    #[error("invalid chunk: {0}")]
+    Chunk(&'static str),
+    #[error("decryption failed: wrong key or corrupted data")]
+    Decrypt,
+    #[error("no random numbers: {0}")]
+    Random(getrandom::Error),
+}
+
+/// Overwrite the secret, so that it doesn't linger in freed memory.
+/// black_box keeps the writes from being optimized away as dead
+/// stores.
+fn zero(secret: &mut [u8]) {
+    secret.fill(0);
+    std::hint::black_box(secret);
+}
+
+/// A key of AES-256.  Debug does not show it, and it is zeroed on
+/// drop.
+#[derive(Clone)]
+pub struct DataKey([u8; KEY_SIZE]);
+
+impl DataKey {
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        Self(key)
+    }
+
+    pub fn generate() -> Result<Self, EnvelopeError> {
+        let mut key = [0; KEY_SIZE];
+        getrandom::getrandom(&mut key).map_err(EnvelopeError::Random)?;
+        Ok(Self(key))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+}
+
+impl Drop for DataKey {
+    fn drop(&mut self) {
+        zero(&mut self.0);
+    }
+}
+
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DataKey(..)")
+    }
+}
+
+/// A data key encrypted with the provider's key of the name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    pub key_name: String,
+    pub material: Vec<u8>,
+}
+
+/**
+ * Encrypts the data keys with the user's keys, which never leave it:
+ * a KMS client, a hardware module or a local keystore.  The key name
+ * is stored in the header, so that keys can be rotated.
+ */
+pub trait KeyProvider {
+    fn wrap_key(&self, key: &DataKey) -> Result<WrappedKey, EnvelopeError>;
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<DataKey, EnvelopeError>;
+}
+
+/**
+ * Wraps the keys with AES-256-GCM under the current one of the given
+ * keys; the older ones still unwrap the files written with them.
+ */
+#[derive(Debug, Clone)]
+pub struct StaticKeyProvider {
+    current: String,
+    keys: HashMap<String, DataKey>,
+}
+
+impl StaticKeyProvider {
+    pub fn new(name: impl Into<String>, key: DataKey) -> Self {
+        let current = name.into();
+        let mut keys = HashMap::new();
+        keys.insert(current.clone(), key);
+        Self { current, keys }
+    }
+
+    /// Add a key for unwrapping only.
+    pub fn with_key(mut self, name: impl Into<String>, key: DataKey) -> Self {
+        self.keys.insert(name.into(), key);
+        self
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn wrap_key(&self, key: &DataKey) -> Result<WrappedKey, EnvelopeError> {
+        let mut nonce = [0; NONCE_SIZE];
+        getrandom::getrandom(&mut nonce).map_err(EnvelopeError::Random)?;
+        let sealed = self.keys[&self.current]
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), &key.as_bytes()[..])
+            .map_err(|_| EnvelopeError::KeyProvider("cannot wrap the key".to_owned()))?;
+        let mut material = nonce.to_vec();
+        material.extend_from_slice(&sealed);
+        Ok(WrappedKey {
+            key_name: self.current.clone(),
+            material,
+        })
+    }
+
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<DataKey, EnvelopeError> {
+        let key = self
+            .keys
+            .get(&wrapped.key_name)
+            .ok_or_else(|| EnvelopeError::UnknownKey(wrapped.key_name.clone()))?;
+        if wrapped.material.len() < NONCE_SIZE {
+            return Err(EnvelopeError::Decrypt);
+        }
+        let (nonce, sealed) = wrapped.material.split_at(NONCE_SIZE);
+        let mut plain = key
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| EnvelopeError::Decrypt)?;
+        let res = plain[..]
+            .try_into()
+            .map(DataKey)
+            .map_err(|_| EnvelopeError::Decrypt);
+        zero(&mut plain);
+        res
+    }
+}
+
+/// The parameters of an encrypted file, stored in ENVELOPE_XATTR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeHeader {
+    pub chunk_size: u32,
+    nonce_prefix: [u8; PREFIX_SIZE],
+    pub key: WrappedKey,
+}
+
+impl EnvelopeHeader {
+    /**
+     * The layout is the magic "HDFE", the version byte, the chunk size
+     * (u32), the nonce prefix, the key name and the wrapped key, each
+     * of the latter with its length (u16); integers are big-endian.
+     * A key name or wrapped key longer than u16::MAX is an error.
+     */
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EnvelopeError> {
+        let name = self.key.key_name.as_bytes();
+        let mut out = Vec::with_capacity(
+            MAGIC.len() + 1 + 4 + PREFIX_SIZE + 4 + name.len() + self.key.material.len(),
+        );
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.chunk_size.to_be_bytes());
+        out.extend_from_slice(&self.nonce_prefix);
+        for field in &[name, &self.key.material[..]] {
+            let len = u16::try_from(field.len()).map_err(|_| EnvelopeError::Header("too long"))?;
+            out.extend_from_slice(&len.to_be_bytes());
+            out.extend_from_slice(field);
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], EnvelopeError> {
+            if bytes.len() < len {
+                return Err(EnvelopeError::Header("truncated"));
+            }
+            let (head, tail) = bytes.split_at(len);
+            *bytes = tail;
+            Ok(head)
+        }
+        fn take_field<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], EnvelopeError> {
+            let len = u16::from_be_bytes(take(bytes, 2)?.try_into().unwrap());
+            take(bytes, len as usize)
+        }
+
+        if take(&mut bytes, MAGIC.len())? != MAGIC {
+            return Err(EnvelopeError::Header("no magic"));
+        }
+        if take(&mut bytes, 1)? != [VERSION] {
+            return Err(EnvelopeError::Header("unsupported version"));
+        }
+        let chunk_size = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+        if chunk_size == 0 {
+            return Err(EnvelopeError::Header("zero chunk size"));
+        }
+        let nonce_prefix = take(&mut bytes, PREFIX_SIZE)?.try_into().unwrap();
+        let key_name = std::str::from_utf8(take_field(&mut bytes)?)
+            .map_err(|_| EnvelopeError::Header("key name is not UTF-8"))?
+            .to_owned();
+        let material = take_field(&mut bytes)?.to_vec();
+        if !bytes.is_empty() {
+            return Err(EnvelopeError::Header("trailing bytes"));
+        }
+        Ok(Self {
+            chunk_size,
+            nonce_prefix,
+            key: WrappedKey { key_name, material },
+        })
+    }
+}
+
+/// The cipher of a file and the number of the next chunk.
+struct ChunkCipher {
+    cipher: Aes256Gcm,
+    nonce_prefix: [u8; PREFIX_SIZE],
+    chunk_size: usize,
+    next: Option<u32>,
+}
+
+impl ChunkCipher {
+    /// The nonce of the next chunk, checking its size.
+    fn next_nonce(&mut self, len: usize, last: bool) -> Result<[u8; NONCE_SIZE], EnvelopeError> {
+        let index = self
+            .next
+            .ok_or(EnvelopeError::Chunk("after the last one"))?;
+        if len > self.chunk_size || (!last && len != self.chunk_size) {
+            return Err(EnvelopeError::Chunk("wrong size"));
+        }
+        // Chunks past u32::MAX have no nonce.
+        self.next = if last { None } else { index.checked_add(1) };
+        if self.next.is_none() && !last {
+            return Err(EnvelopeError::Chunk("too many chunks"));
+        }
+        let mut nonce = [0; NONCE_SIZE];
+        nonce[..PREFIX_SIZE].copy_from_slice(&self.nonce_prefix);
+        nonce[PREFIX_SIZE..NONCE_SIZE - 1].copy_from_slice(&index.to_be_bytes());
+        nonce[NONCE_SIZE - 1] = last as u8;
+        Ok(nonce)
+    }
+}
+
+/**
+ * Encrypts a file chunk by chunk.  All the chunks have the chunk size,
+ * except the last one, which is shorter and may be empty.
+ */
+pub struct Encryptor(ChunkCipher);
+
+impl Encryptor {
+    /// A new data key for a file, and the header to store with it.
+    pub fn new(
+        provider: &dyn KeyProvider,
+        chunk_size: u32,
+    ) -> Result<(Self, EnvelopeHeader), EnvelopeError> {
+        if chunk_size == 0 {
+            return Err(EnvelopeError::Chunk("zero chunk size"));
+        }
+        let key = DataKey::generate()?;
+        let mut nonce_prefix = [0; PREFIX_SIZE];
+        getrandom::getrandom(&mut nonce_prefix).map_err(EnvelopeError::Random)?;
+        let header = EnvelopeHeader {
+            chunk_size,
+            nonce_prefix,
+            key: provider.wrap_key(&key)?,
+        };
+        let cipher = ChunkCipher {
+            cipher: key.cipher(),
+            nonce_prefix,
+            chunk_size: chunk_size as usize,
+            next: Some(0),
+        };
+        Ok((Self(cipher), header))
+    }
+
+    pub fn encrypt_chunk(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>, EnvelopeError> {
+        let nonce = self.0.next_nonce(chunk.len(), last)?;
+        self.0
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), chunk)
+            .map_err(|_| EnvelopeError::Chunk("too long"))
+    }
+}
+
+/// Decrypts the chunks of a file in order.
+pub struct Decryptor(ChunkCipher);
+
+impl Decryptor {
+    pub fn new(provider: &dyn KeyProvider, header: &EnvelopeHeader) -> Result<Self, EnvelopeError> {
+        let key = provider.unwrap_key(&header.key)?;
+        Ok(Self(ChunkCipher {
+            cipher: key.cipher(),
+            nonce_prefix: header.nonce_prefix,
+            chunk_size: header.chunk_size as usize + TAG_SIZE,
+            next: Some(0),
+        }))
+    }
+
+    pub fn decrypt_chunk(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>, EnvelopeError> {
+        let nonce = self.0.next_nonce(chunk.len(), last)?;
+        self.0
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), chunk)
+            .map_err(|_| EnvelopeError::Decrypt)
+    }
+}
+
+/// Encrypt the whole data at once.
+pub fn seal(
+    provider: &dyn KeyProvider,
+    chunk_size: u32,
+    data: &[u8],
+) -> Result<(EnvelopeHeader, Vec<u8>), EnvelopeError> {
+    let (mut encryptor, header) = Encryptor::new(provider, chunk_size)?;
+    let chunk_size = chunk_size as usize;
+    let full = data.len() / chunk_size;
+    let mut out = Vec::with_capacity(data.len() + (full + 1) * TAG_SIZE);
+    for chunk in data.chunks_exact(chunk_size) {
+        out.extend(encryptor.encrypt_chunk(chunk, false)?);
+    }
+    out.extend(encryptor.encrypt_chunk(&data[full * chunk_size..], true)?);
+    Ok((header, out))
+}
+
+/// Decrypt the data of seal.
+pub fn open(
+    provider: &dyn KeyProvider,
+    header: &EnvelopeHeader,
+    data: &[u8],
+) -> Result<Vec<u8>, EnvelopeError> {
+    let mut decryptor = Decryptor::new(provider, header)?;
+    let chunk_size = header.chunk_size as usize + TAG_SIZE;
+    // The last chunk is always shorter than the others.
+    let full = data.len() / chunk_size;
+    let mut out = Vec::with_capacity(data.len());
+    for chunk in data.chunks_exact(chunk_size) {
+        out.extend(decryptor.decrypt_chunk(chunk, false)?);
+    }
+    let last = &data[full * chunk_size..];
+    if last.len() < TAG_SIZE {
+        return Err(EnvelopeError::Decrypt);
+    }
+    out.extend(decryptor.decrypt_chunk(last, true)?);
+    Ok(out)
+}
+
+impl<R, SRef> Hdfs<R, SRef>
+where
+    R: RpcConnection,
+    SRef: BorrowMut<ClientNamenodeService<R>>,
+{
+    /// Store the header of an encrypted file.
+    pub fn set_envelope_header(
+        &mut self,
+        path: &Path<'_>,
+        header: &EnvelopeHeader,
+    ) -> Result<(), HdfsError> {
+        let mut xattr = XAttrProto::default();
+        xattr.set_namespace(XAttrProto_XAttrNamespaceProto::USER);
+        xattr.set_name(ENVELOPE_XATTR.to_owned());
+        xattr.set_value(
+            header
+                .to_bytes()
+                .map_err(|e| HdfsError::src(FsError::Envelope(e)))?,
+        );
+        self.set_xattr(path, xattr)
+    }
+
+    /// The header of the file, if it is encrypted.
+    pub fn get_envelope_header(
+        &mut self,
+        path: &Path<'_>,
+    ) -> Result<Option<EnvelopeHeader>, HdfsError> {
+        let xattrs = self.get_xattrs(path)?;
+        xattrs
+            .iter()
+            .find(|xattr| {
+                xattr.get_namespace() == XAttrProto_XAttrNamespaceProto::USER
+                    && xattr.get_name() == ENVELOPE_XATTR
+            })
+            .map(|xattr| EnvelopeHeader::from_bytes(xattr.get_value()))
+            .transpose()
+            .map_err(|e| HdfsError::src(FsError::Envelope(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_hdfs, MockConnection};
+    use hdfesse_proto::xattr::{
+        GetXAttrsRequestProto, GetXAttrsResponseProto, SetXAttrRequestProto, SetXAttrResponseProto,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn provider() -> StaticKeyProvider {
+        StaticKeyProvider::new("k1", DataKey::new([1; KEY_SIZE]))
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let provider = provider();
+        for len in &[0, 1, 15, 16, 17, 48, 100] {
+            let data: Vec<u8> = (0..*len as u8).collect();
+            let (header, sealed) = seal(&provider, 16, &data).unwrap();
+            assert_eq!(sealed.len(), len + (len / 16 + 1) * TAG_SIZE);
+            let header = EnvelopeHeader::from_bytes(&header.to_bytes().unwrap()).unwrap();
+            assert_eq!(open(&provider, &header, &sealed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_tampering() {
+        let provider = provider();
+        let data = [7; 40];
+        let (header, sealed) = seal(&provider, 16, &data).unwrap();
+
+        let mut flipped = sealed.clone();
+        flipped[3] ^= 1;
+        assert!(matches!(
+            open(&provider, &header, &flipped),
+            Err(EnvelopeError::Decrypt)
+        ));
+        // Dropping the last chunk or a whole middle one.
+        let chunk = 16 + TAG_SIZE;
+        assert!(open(&provider, &header, &sealed[..2 * chunk]).is_err());
+        let mut dropped = sealed[..chunk].to_vec();
+        dropped.extend_from_slice(&sealed[2 * chunk..]);
+        assert!(open(&provider, &header, &dropped).is_err());
+
+        let other = StaticKeyProvider::new("k1", DataKey::new([2; KEY_SIZE]));
+        assert!(matches!(
+            open(&other, &header, &sealed),
+            Err(EnvelopeError::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let (header, sealed) = seal(&provider(), 16, b"data").unwrap();
+        let rotated = StaticKeyProvider::new("k2", DataKey::new([2; KEY_SIZE]))
+            .with_key("k1", DataKey::new([1; KEY_SIZE]));
+        assert_eq!(open(&rotated, &header, &sealed).unwrap(), b"data");
+        let (header, _) = seal(&rotated, 16, b"data").unwrap();
+        assert_eq!(header.key.key_name, "k2");
+        assert!(matches!(
+            Decryptor::new(&provider(), &header),
+            Err(EnvelopeError::UnknownKey(name)) if name == "k2"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let (header, _) = seal(&provider(), 16, b"").unwrap();
+        let bytes = header.to_bytes().unwrap();
+        assert!(EnvelopeHeader::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(EnvelopeHeader::from_bytes(&extra).is_err());
+        assert!(EnvelopeHeader::from_bytes(b"HDFS\x01").is_err());
+
+        let mut long = header.clone();
+        long.key.key_name = "k".repeat(u16::MAX as usize + 1);
+        assert!(matches!(long.to_bytes(), Err(EnvelopeError::Header(_))));
+        long.key.key_name = "k".repeat(u16::MAX as usize);
+        let bytes = long.to_bytes().unwrap();
+        assert_eq!(EnvelopeHeader::from_bytes(&bytes).unwrap(), long);
+    }
+
+    /// A namenode that keeps the attributes set on any path.
+    fn xattr_conn(stored: Arc<Mutex<Vec<XAttrProto>>>) -> MockConnection {
+        let handler_stored = stored.clone();
+        let mut conn = MockConnection::new("test");
+        conn.on("setXAttr", move |req: SetXAttrRequestProto| {
+            assert_eq!(req.get_src(), "/file");
+            handler_stored.lock().unwrap().push(req.get_xAttr().clone());
+            Ok(SetXAttrResponseProto::default())
+        })
+        .on("getXAttrs", move |_: GetXAttrsRequestProto| {
+            let mut resp = GetXAttrsResponseProto::default();
+            resp.set_xAttrs(stored.lock().unwrap().clone().into());
+            Ok(resp)
+        });
+        conn
+    }
+
+    #[test]
+    fn test_header_xattr() {
+        let stored = Arc::new(Mutex::new(vec![]));
+        let mut hdfs = mock_hdfs(xattr_conn(stored.clone()));
+        let path = Path::new("/file").unwrap();
+
+        assert_eq!(hdfs.get_envelope_header(&path).unwrap(), None);
+        let (header, _) = seal(&provider(), 16, b"data").unwrap();
+        hdfs.set_envelope_header(&path, &header).unwrap();
+        {
+            let stored = stored.lock().unwrap();
+            assert_eq!(
+                stored[0].get_namespace(),
+                XAttrProto_XAttrNamespaceProto::USER
+            );
+            assert_eq!(stored[0].get_name(), ENVELOPE_XATTR);
+        }
+        assert_eq!(hdfs.get_envelope_header(&path).unwrap(), Some(header));
+    }
+
+    #[test]
+    fn test_malformed_header_xattr() {
+        let mut xattr = XAttrProto::default();
+        xattr.set_namespace(XAttrProto_XAttrNamespaceProto::USER);
+        xattr.set_name(ENVELOPE_XATTR.to_owned());
+        xattr.set_value(b"HDFE\x01".to_vec());
+        let stored = Arc::new(Mutex::new(vec![xattr]));
+        let mut hdfs = mock_hdfs(xattr_conn(stored.clone()));
+        let path = Path::new("/file").unwrap();
+
+        let err = hdfs.get_envelope_header(&path).unwrap_err();
+        assert!(matches!(
+            err.source,
+            FsError::Envelope(EnvelopeError::Header("truncated"))
+        ));
+
+        // The header is checked before anything is sent.
+        let (mut header, _) = seal(&provider(), 16, b"").unwrap();
+        header.key.key_name = "k".repeat(u16::MAX as usize + 1);
+        assert!(hdfs.set_envelope_header(&path, &header).is_err());
+        assert_eq!(stored.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,144 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Stable error codes.  Every error of the library maps to a code;
+ * FFI errno values, CLI exit statuses and the names of binding
+ * exceptions are all derived from it.  The numeric values are part of
+ * the public interface: they never change, and new codes only get
+ * new values.
+ */
+
+/**
+ * Error code.  The numeric value is also the exit status of the CLI
+ * when a command fails with such an error.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum ErrorCode {
+    /// Any error that has no specific code.
+    Internal = 1,
+    /// The path cannot be parsed or resolved.
+    InvalidPath = 2,
+    /// The namenode rejected an argument.
+    InvalidArgument = 3,
+    NotFound = 4,
+    FileExists = 5,
+    IsDir = 6,
+    NotDir = 7,
+    AccessDenied = 8,
+    /// The path contains a symlink that cannot be resolved.
+    UnresolvedLink = 9,
+    /// The namenode is in safe mode and rejects modifications.
+    SafeMode = 10,
+    QuotaExceeded = 11,
+    Unsupported = 12,
+    /// The namenode is out of memory.
+    OutOfMemory = 13,
+    /// Local IO or network error.
+    Io = 14,
+    TimedOut = 15,
+    Cancelled = 16,
+    /// Malformed data or a fatal response from the namenode.
+    Protocol = 17,
+}
+
+static EXCEPTION_CODES: ::phf::Map<&'static str, ErrorCode> = ::phf::phf_map! {
+    "java.io.FileNotFoundException" => ErrorCode::NotFound,
+    "org.apache.hadoop.security.AccessControlException" => ErrorCode::AccessDenied,
+    "org.apache.hadoop.fs.UnresolvedLinkException" => ErrorCode::UnresolvedLink,
+    "org.apache.hadoop.fs.ParentNotDirectoryException" => ErrorCode::NotDir,
+    "java.lang.IllegalArgumentException" => ErrorCode::InvalidArgument,
+    "java.lang.OutOfMemoryError" => ErrorCode::OutOfMemory,
+    "org.apache.hadoop.hdfs.server.namenode.SafeModeException" => ErrorCode::SafeMode,
+    "org.apache.hadoop.fs.FileAlreadyExistsException" => ErrorCode::FileExists,
+    "org.apache.hadoop.hdfs.protocol.QuotaExceededException" => ErrorCode::QuotaExceeded,
+    "org.apache.hadoop.hdfs.protocol.NSQuotaExceededException" => ErrorCode::QuotaExceeded,
+    "org.apache.hadoop.hdfs.protocol.DSQuotaExceededException" => ErrorCode::QuotaExceeded,
+    "java.lang.UnsupportedOperationException" => ErrorCode::Unsupported,
+};
+
+impl ErrorCode {
+    /// The code of a remote Java exception.
+    pub fn from_exception_class(class_name: &str) -> Self {
+        EXCEPTION_CODES
+            .get(class_name)
+            .copied()
+            .unwrap_or(ErrorCode::Internal)
+    }
+
+    /// The stable numeric value.
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    /// The stable name, e.g. for exception classes of language
+    /// bindings.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::Internal => "Internal",
+            ErrorCode::InvalidPath => "InvalidPath",
+            ErrorCode::InvalidArgument => "InvalidArgument",
+            ErrorCode::NotFound => "NotFound",
+            ErrorCode::FileExists => "FileExists",
+            ErrorCode::IsDir => "IsDir",
+            ErrorCode::NotDir => "NotDir",
+            ErrorCode::AccessDenied => "AccessDenied",
+            ErrorCode::UnresolvedLink => "UnresolvedLink",
+            ErrorCode::SafeMode => "SafeMode",
+            ErrorCode::QuotaExceeded => "QuotaExceeded",
+            ErrorCode::Unsupported => "Unsupported",
+            ErrorCode::OutOfMemory => "OutOfMemory",
+            ErrorCode::Io => "Io",
+            ErrorCode::TimedOut => "TimedOut",
+            ErrorCode::Cancelled => "Cancelled",
+            ErrorCode::Protocol => "Protocol",
+        }
+    }
+
+    /// Exit status of the CLI.
+    pub fn exit_status(self) -> i32 {
+        self.value() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_values() {
+        // These values are public interface; never change them.
+        assert_eq!(ErrorCode::Internal.value(), 1);
+        assert_eq!(ErrorCode::NotFound.value(), 4);
+        assert_eq!(ErrorCode::AccessDenied.value(), 8);
+        assert_eq!(ErrorCode::SafeMode.value(), 10);
+        assert_eq!(ErrorCode::Io.value(), 14);
+        assert_eq!(ErrorCode::Protocol.value(), 17);
+    }
+
+    #[test]
+    fn test_from_exception_class() {
+        assert_eq!(
+            ErrorCode::from_exception_class("java.io.FileNotFoundException"),
+            ErrorCode::NotFound
+        );
+        assert_eq!(
+            ErrorCode::from_exception_class("org.example.UnknownException"),
+            ErrorCode::Internal
+        );
+    }
+}
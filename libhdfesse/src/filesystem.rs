@@ -0,0 +1,105 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The filesystem interface common for HDFS and the local filesystem,
+ * so that a command may work with any of them.  It covers metadata
+ * operations only; file streams will be added with the data
+ * transfer.
+ */
+use std::borrow::BorrowMut;
+
+use crate::{
+    fs::{Hdfs, HdfsError},
+    path::Path,
+    rpc::RpcConnection,
+    service,
+    status::HdfsFileStatus,
+};
+
+/// Directory listing, lazy if the filesystem supports it.
+pub type ListIterator<'s> = Box<dyn Iterator<Item = Result<HdfsFileStatus, HdfsError>> + 's>;
+
+/**
+ * Metadata operations with the same semantics as Hdfs methods of
+ * the same name.  The trait requires Send, so `Box<dyn FileSystem>`
+ * may be moved to another thread.
+ */
+pub trait FileSystem: Send {
+    /// Entries of the directory; their path is the entry name.
+    fn list<'s>(&'s mut self, path: &Path<'_>) -> Result<ListIterator<'s>, HdfsError>;
+
+    /// Status of the path; its path field is empty.
+    fn stat(&mut self, path: &Path<'_>) -> Result<HdfsFileStatus, HdfsError>;
+
+    fn rename(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<(), HdfsError>;
+
+    /// Returns false if nothing is deleted, e.g. the path is missing.
+    fn delete(&mut self, path: &Path<'_>, recursive: bool) -> Result<bool, HdfsError>;
+
+    fn mkdirs(&mut self, path: &Path<'_>, create_parent: bool) -> Result<bool, HdfsError>;
+}
+
+impl<R, SRef> FileSystem for Hdfs<R, SRef>
+where
+    R: RpcConnection + Send,
+    SRef: BorrowMut<service::ClientNamenodeService<R>> + Send,
+{
+    fn list<'s>(&'s mut self, path: &Path<'_>) -> Result<ListIterator<'s>, HdfsError> {
+        Ok(Box::new(
+            self.list_status(path)?.map(|entry| entry.map(Into::into)),
+        ))
+    }
+
+    fn stat(&mut self, path: &Path<'_>) -> Result<HdfsFileStatus, HdfsError> {
+        self.get_file_info(path)
+            .map(Into::into)
+            .map_err(HdfsError::src)
+    }
+
+    fn rename(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<(), HdfsError> {
+        Hdfs::rename(self, src, dst)
+    }
+
+    fn delete(&mut self, path: &Path<'_>, recursive: bool) -> Result<bool, HdfsError> {
+        Hdfs::delete(self, path, recursive)
+    }
+
+    fn mkdirs(&mut self, path: &Path<'_>, create_parent: bool) -> Result<bool, HdfsError> {
+        Hdfs::mkdirs(self, path, create_parent)
+    }
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for Box<F> {
+    fn list<'s>(&'s mut self, path: &Path<'_>) -> Result<ListIterator<'s>, HdfsError> {
+        (**self).list(path)
+    }
+
+    fn stat(&mut self, path: &Path<'_>) -> Result<HdfsFileStatus, HdfsError> {
+        (**self).stat(path)
+    }
+
+    fn rename(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<(), HdfsError> {
+        (**self).rename(src, dst)
+    }
+
+    fn delete(&mut self, path: &Path<'_>, recursive: bool) -> Result<bool, HdfsError> {
+        (**self).delete(path, recursive)
+    }
+
+    fn mkdirs(&mut self, path: &Path<'_>, create_parent: bool) -> Result<bool, HdfsError> {
+        (**self).mkdirs(path, create_parent)
+    }
+}
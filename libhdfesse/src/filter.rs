@@ -0,0 +1,226 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Filters of the entries of recursive walks, like Java's PathFilter.
+ * A filter decides both whether an entry is reported and whether a
+ * directory is descended into, so that whole subtrees are skipped
+ * without being listed.  Closures of the path and the status are
+ * filters too.
+ */
+use hdfesse_proto::hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType};
+
+use crate::{
+    glob::{GlobError, GlobPattern},
+    path::Path,
+};
+
+pub trait PathFilter: Send + Sync {
+    /// Whether the entry is reported.
+    fn accept(&self, path: &Path<'_>, status: &HdfsFileStatusProto) -> bool;
+
+    /// Whether the walk descends into the directory; all directories
+    /// are, by default, even if they are not accepted.
+    fn descend(&self, _path: &Path<'_>, _status: &HdfsFileStatusProto) -> bool {
+        true
+    }
+}
+
+impl<F> PathFilter for F
+where
+    F: Fn(&Path<'_>, &HdfsFileStatusProto) -> bool + Send + Sync,
+{
+    fn accept(&self, path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        self(path, status)
+    }
+}
+
+/// Accepts all entries.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AcceptAll;
+
+impl PathFilter for AcceptAll {
+    fn accept(&self, _path: &Path<'_>, _status: &HdfsFileStatusProto) -> bool {
+        true
+    }
+}
+
+/// Accepts the entries that all the filters accept, and descends
+/// where all of them descend.
+#[derive(Default)]
+pub struct AllOf(pub Vec<Box<dyn PathFilter>>);
+
+impl PathFilter for AllOf {
+    fn accept(&self, path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        self.0.iter().all(|filter| filter.accept(path, status))
+    }
+
+    fn descend(&self, path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        self.0.iter().all(|filter| filter.descend(path, status))
+    }
+}
+
+/// Entries whose name matches the glob pattern, like Java's
+/// GlobFilter.
+#[derive(Debug, Clone)]
+pub struct GlobFilter(GlobPattern);
+
+impl GlobFilter {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        GlobPattern::new(pattern).map(Self)
+    }
+}
+
+impl PathFilter for GlobFilter {
+    fn accept(&self, path: &Path<'_>, _status: &HdfsFileStatusProto) -> bool {
+        self.0.matches(&path.basename())
+    }
+}
+
+/// Entries whose full path (without scheme and authority) matches
+/// the regular expression somewhere; anchor it to match the whole.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct RegexFilter(pub regex::Regex);
+
+#[cfg(feature = "regex")]
+impl PathFilter for RegexFilter {
+    fn accept(&self, path: &Path<'_>, _status: &HdfsFileStatusProto) -> bool {
+        self.0.is_match(&path.to_path_string())
+    }
+}
+
+/// Files with the length in the range, inclusive; other entries are
+/// not accepted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeFilter {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl PathFilter for SizeFilter {
+    fn accept(&self, _path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        let length = status.get_length();
+        status.get_fileType() == HdfsFileStatusProto_FileType::IS_FILE
+            && self.min.map(|min| length >= min).unwrap_or(true)
+            && self.max.map(|max| length <= max).unwrap_or(true)
+    }
+}
+
+/// Entries modified in the range, in milliseconds since the epoch;
+/// after is inclusive, before is not.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MtimeFilter {
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+}
+
+impl PathFilter for MtimeFilter {
+    fn accept(&self, _path: &Path<'_>, status: &HdfsFileStatusProto) -> bool {
+        let mtime = status.get_modification_time();
+        self.after.map(|after| mtime >= after).unwrap_or(true)
+            && self.before.map(|before| mtime < before).unwrap_or(true)
+    }
+}
+
+/**
+ * Skips the entries whose name starts with "." or "_", and does not
+ * descend into such directories, like the default filter of Hadoop's
+ * FileInputFormat: they are the staging files of jobs, like
+ * _temporary and _SUCCESS, or hidden ones.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HiddenFilter;
+
+impl HiddenFilter {
+    fn is_hidden(path: &Path<'_>) -> bool {
+        let name = path.basename();
+        name.starts_with('.') || name.starts_with('_')
+    }
+}
+
+impl PathFilter for HiddenFilter {
+    fn accept(&self, path: &Path<'_>, _status: &HdfsFileStatusProto) -> bool {
+        !Self::is_hidden(path)
+    }
+
+    fn descend(&self, path: &Path<'_>, _status: &HdfsFileStatusProto) -> bool {
+        !Self::is_hidden(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::file_status;
+
+    fn accepts(filter: &dyn PathFilter, path: &str, status: &HdfsFileStatusProto) -> bool {
+        filter.accept(&Path::new(path).unwrap(), status)
+    }
+
+    #[test]
+    fn test_filters() {
+        let file = file_status("", false, 100);
+        let dir = file_status("", true, 0);
+
+        let glob = GlobFilter::new("part-*").unwrap();
+        assert!(accepts(&glob, "/out/part-0000", &file));
+        assert!(!accepts(&glob, "/part-0/_SUCCESS", &file));
+
+        let size = SizeFilter {
+            min: Some(10),
+            max: Some(100),
+        };
+        assert!(accepts(&size, "/f", &file));
+        assert!(!accepts(&size, "/d", &dir));
+        assert!(!accepts(
+            &SizeFilter {
+                min: Some(101),
+                max: None
+            },
+            "/f",
+            &file
+        ));
+
+        let mut old = file_status("", false, 0);
+        old.set_modification_time(1000);
+        let mtime = MtimeFilter {
+            after: Some(1000),
+            before: Some(2000),
+        };
+        assert!(accepts(&mtime, "/f", &old));
+        assert!(!accepts(
+            &MtimeFilter {
+                after: None,
+                before: Some(1000)
+            },
+            "/f",
+            &old
+        ));
+
+        let hidden = Path::new("/out/_temporary").unwrap();
+        assert!(!HiddenFilter.accept(&hidden, &dir));
+        assert!(!HiddenFilter.descend(&hidden, &dir));
+        assert!(accepts(&HiddenFilter, "/out/.x/part", &file));
+
+        let all = AllOf(vec![Box::new(glob), Box::new(HiddenFilter)]);
+        assert!(accepts(&all, "/out/part-0", &file));
+        assert!(!all.descend(&hidden, &dir));
+
+        let closure = |path: &Path<'_>, _: &HdfsFileStatusProto| path.basename().len() > 3;
+        assert!(accepts(&closure, "/long", &file));
+        assert!(PathFilter::descend(&closure, &hidden, &dir));
+    }
+}
@@ -0,0 +1,284 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Following a growing file, like `tail -f`.  The file length is polled
+ * with getFileInfo; the polling interval grows while the file does not
+ * change, and is jittered, so many followers do not hit the namenode
+ * at once.
+ *
+ * Namenode updates the length of a file being written only when a
+ * block is completed or the writer calls hsync with UPDATE_LENGTH, so
+ * appended data may be reported late.
+ */
+use std::{borrow::BorrowMut, ops::Range, time::Duration};
+
+use crate::{
+    fs::{ensure_not_dir, FsError, Hdfs, HdfsError, HdfsErrorKind},
+    path::Path,
+    rpc::RpcConnection,
+    service::ClientNamenodeService,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FollowOptions {
+    /// The interval after a change.
+    pub min_interval: Duration,
+    /// The interval grows up to this value while nothing changes.
+    pub max_interval: Duration,
+    /// The sleep of an interval; random_jitter by default.  Tests
+    /// replace it to get exact intervals.
+    pub jitter: fn(Duration) -> Duration,
+}
+
+impl Default for FollowOptions {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_millis(500),
+            max_interval: Duration::from_secs(5),
+            jitter: random_jitter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FollowEvent {
+    /// The bytes were appended since the last event.
+    Appended(Range<u64>),
+    /// The path now refers to another file (by file ID), or the file
+    /// was truncated; all its content is new.
+    Replaced { len: u64 },
+}
+
+/**
+ * Tracks the length and the file ID of a path.  It does not read the
+ * data: the caller reads the reported ranges.
+ */
+pub struct Follower {
+    path: Path<'static>,
+    // Zero if namenode does not report file IDs (before Hadoop 2.1).
+    file_id: u64,
+    offset: u64,
+    interval: Duration,
+    options: FollowOptions,
+}
+
+impl Follower {
+    /// Start following from the current end of the file.
+    pub fn new<R, SRef>(
+        hdfs: &mut Hdfs<R, SRef>,
+        path: &Path<'_>,
+        options: FollowOptions,
+    ) -> Result<Self, HdfsError>
+    where
+        R: RpcConnection,
+        SRef: BorrowMut<ClientNamenodeService<R>>,
+    {
+        let status = hdfs.get_file_info(path).map_err(HdfsError::src)?;
+        ensure_not_dir(&status, path.to_string().into(), HdfsErrorKind::Src)?;
+        Ok(Self {
+            path: path.clone().into_owned(),
+            file_id: status.get_fileId(),
+            offset: status.get_length(),
+            interval: options.min_interval,
+            options,
+        })
+    }
+
+    /// The length of the file reported by the last event.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Check the file once.  A missing file is not an error: it may be
+    /// being replaced.
+    pub fn poll<R, SRef>(
+        &mut self,
+        hdfs: &mut Hdfs<R, SRef>,
+    ) -> Result<Option<FollowEvent>, HdfsError>
+    where
+        R: RpcConnection,
+        SRef: BorrowMut<ClientNamenodeService<R>>,
+    {
+        let status = match hdfs.get_file_info(&self.path) {
+            Ok(status) => status,
+            Err(FsError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(HdfsError::src(e)),
+        };
+        ensure_not_dir(&status, self.path.to_string().into(), HdfsErrorKind::Src)?;
+
+        let (file_id, len) = (status.get_fileId(), status.get_length());
+        let event = if file_id != self.file_id || len < self.offset {
+            Some(FollowEvent::Replaced { len })
+        } else if len > self.offset {
+            Some(FollowEvent::Appended(self.offset..len))
+        } else {
+            None
+        };
+        self.file_id = file_id;
+        self.offset = len;
+        Ok(event)
+    }
+
+    /// Wait for the next change, polling with backoff.
+    pub fn next_event<R, SRef>(
+        &mut self,
+        hdfs: &mut Hdfs<R, SRef>,
+    ) -> Result<FollowEvent, HdfsError>
+    where
+        R: RpcConnection,
+        SRef: BorrowMut<ClientNamenodeService<R>>,
+    {
+        let clock = hdfs.clock();
+        loop {
+            if let Some(event) = self.poll(hdfs)? {
+                self.interval = self.options.min_interval;
+                return Ok(event);
+            }
+            clock.sleep((self.options.jitter)(self.interval));
+            self.interval = (self.interval * 2).min(self.options.max_interval);
+        }
+    }
+}
+
+/// A random duration within ±25% of the interval.
+pub fn random_jitter(interval: Duration) -> Duration {
+    let random = uuid::Uuid::new_v4().as_bytes()[0];
+    interval.mul_f64(0.75 + f64::from(random) / 510.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::{Clock, ManualClock},
+        testing::{file_status, mock_hdfs, MockConnection},
+    };
+    use hdfesse_proto::ClientNamenodeProtocol::{
+        GetFileInfoRequestProto, GetFileInfoResponseProto,
+    };
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    /// Serve the (file ID, length) states in order; None is a missing
+    /// file.  The last state is repeated.
+    fn hdfs_with_states(states: Vec<Option<(u64, u64)>>) -> Hdfs<MockConnection> {
+        let states = Arc::new(Mutex::new(VecDeque::from(states)));
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", move |req: GetFileInfoRequestProto| {
+            let mut states = states.lock().unwrap();
+            let state = if states.len() > 1 {
+                states.pop_front().unwrap()
+            } else {
+                states[0]
+            };
+            let mut resp = GetFileInfoResponseProto::default();
+            if let Some((file_id, len)) = state {
+                let mut status = file_status(req.get_src(), false, len);
+                status.set_fileId(file_id);
+                resp.set_fs(status);
+            }
+            Ok(resp)
+        });
+        mock_hdfs(conn)
+    }
+
+    #[test]
+    fn test_poll_events() {
+        let mut hdfs = hdfs_with_states(vec![
+            Some((1, 10)),
+            Some((1, 10)),
+            Some((1, 25)),
+            None,
+            Some((2, 30)),
+            Some((2, 5)),
+        ]);
+        let path = Path::new("/log").unwrap();
+        let mut follower = Follower::new(&mut hdfs, &path, FollowOptions::default()).unwrap();
+        assert_eq!(follower.offset(), 10);
+
+        assert_eq!(follower.poll(&mut hdfs).unwrap(), None);
+        assert_eq!(
+            follower.poll(&mut hdfs).unwrap(),
+            Some(FollowEvent::Appended(10..25))
+        );
+        assert_eq!(follower.poll(&mut hdfs).unwrap(), None);
+        // Rotated: new file, longer than the old one.
+        assert_eq!(
+            follower.poll(&mut hdfs).unwrap(),
+            Some(FollowEvent::Replaced { len: 30 })
+        );
+        // Truncated.
+        assert_eq!(
+            follower.poll(&mut hdfs).unwrap(),
+            Some(FollowEvent::Replaced { len: 5 })
+        );
+        assert_eq!(follower.offset(), 5);
+    }
+
+    #[test]
+    fn test_next_event_backoff() {
+        let mut states = vec![Some((1, 10)); 6];
+        states.push(Some((1, 11)));
+        let mut hdfs = hdfs_with_states(states);
+        let clock = ManualClock::new();
+        hdfs.set_clock(Arc::new(clock.clone()));
+        let options = FollowOptions {
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(4),
+            jitter: |interval| interval,
+        };
+        let path = Path::new("/log").unwrap();
+        let mut follower = Follower::new(&mut hdfs, &path, options).unwrap();
+
+        let start = clock.now();
+        assert_eq!(
+            follower.next_event(&mut hdfs).unwrap(),
+            FollowEvent::Appended(10..11)
+        );
+        // Five sleeps: 1, 2, 4, 4, 4 seconds.
+        assert_eq!(clock.now() - start, Duration::from_secs(15));
+        assert_eq!(follower.interval, options.min_interval);
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        for _ in 0..100 {
+            let d = random_jitter(Duration::from_secs(4));
+            assert!(d >= Duration::from_secs(3) && d <= Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn test_directory_rejected() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(file_status(req.get_src(), true, 0));
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let res = Follower::new(&mut hdfs, &Path::new("/dir").unwrap(), Default::default());
+        assert!(matches!(
+            res,
+            Err(HdfsError {
+                source: FsError::IsDir(_),
+                ..
+            })
+        ));
+    }
+}
@@ -15,29 +15,94 @@
 */
 use std::{
     borrow::{BorrowMut, Cow},
+    collections::HashMap,
     fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-pub use crate::fs_ls::LsGroupIterator;
+pub use crate::fs_corrupt::{CorruptFileBlockIterator, CorruptFileBlockPages};
+use crate::fs_ls::ListingCursor;
+pub use crate::fs_ls::{BatchIterator, LsGroupIterator};
+pub use crate::fs_walk::Walk;
 use crate::{
-    fs_ls::LsIterator,
+    block_cache::{self, BlockLocationCache},
+    clock::{Clock, SystemClock},
+    error_code::ErrorCode,
+    filter::PathFilter,
+    glob::{self, GlobError, GlobPattern},
+    ha_rpc::FailoverCounts,
+    lease::{generate_client_name_with_suffix, Lease},
+    paged::PagedIter,
     path::{Path, PathError, UriResolver},
+    preserve::Preserve,
+    rate_limit::RateLimiter,
     rpc::{self, RpcConnection},
     service,
-    status::LocatedBlock,
+    splits::{self, Split},
+    stats::SessionStats,
+    status::{
+        ContentSummary, DatanodeInfo, DatanodeReportType, DatanodeStorageReport, DirEntry,
+        EcBlockGroupStats, FsAction, LocatedBlock, QuotaHeadroom, QuotaUsage, ReplicatedBlockStats,
+        RollingUpgradeAction, RollingUpgradeInfo, SnapshotStatus, SnapshottableDirectoryStatus,
+    },
 };
 pub use hdfesse_proto::hdfs::ErasureCodingPolicyState;
 use hdfesse_proto::{
-    acl::FsPermissionProto,
-    hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType},
+    acl::{
+        AclEntryProto, AclStatusProto, FsPermissionProto, GetAclStatusRequestProto,
+        SetAclRequestProto,
+    },
+    encryption::GetEZForPathRequestProto,
+    hdfs::{
+        BlockStoragePolicyProto, CryptoProtocolVersionProto, HdfsFileStatusProto,
+        HdfsFileStatusProto_FileType, HdfsFileStatusProto_Flags, LocatedBlocksProto,
+    },
+    xattr::{
+        GetXAttrsRequestProto, SetXAttrRequestProto, XAttrProto, XAttrProto_XAttrNamespaceProto,
+        XAttrSetFlagProto,
+    },
     ClientNamenodeProtocol::{
-        DeleteRequestProto, GetBlockLocationsRequestProto, GetFsStatusRequestProto,
-        MkdirsRequestProto, SetPermissionRequestProto, SetTimesRequestProto,
+        CheckAccessRequestProto, CompleteRequestProto, CreateFlagProto, CreateRequestProto,
+        DeleteRequestProto, DeleteResponseProto, FinalizeUpgradeRequestProto,
+        GetBlockLocationsRequestProto, GetContentSummaryRequestProto,
+        GetDatanodeReportRequestProto, GetDatanodeStorageReportRequestProto,
+        GetFileLinkInfoRequestProto, GetFsECBlockGroupStatsRequestProto,
+        GetFsReplicatedBlockStatsRequestProto, GetFsStatsResponseProto, GetFsStatusRequestProto,
+        GetPreferredBlockSizeRequestProto, GetQuotaUsageRequestProto,
+        GetServerDefaultsRequestProto, GetSnapshotListingRequestProto,
+        GetSnapshottableDirListingRequestProto, GetStoragePoliciesRequestProto,
+        IsFileClosedRequestProto, MetaSaveRequestProto, MkdirsRequestProto, MsyncRequestProto,
+        RecoverLeaseRequestProto, RenameResponseProto, RenewLeaseRequestProto,
+        RollEditsRequestProto, RollingUpgradeRequestProto, SafeModeActionProto,
+        SatisfyStoragePolicyRequestProto, SaveNamespaceRequestProto,
+        SetBalancerBandwidthRequestProto, SetOwnerRequestProto, SetPermissionRequestProto,
+        SetSafeModeRequestProto, SetTimesRequestProto, UpgradeStatusRequestProto,
     },
 };
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 const DEFAULT_DIR_PERM: u32 = 0o777;
+/// 0o666 with the default umask 022, as Java's FileSystem.create.
+const DEFAULT_FILE_PERM: u32 = 0o644;
+/// setTimes leaves the time unchanged; it is -1 on the Java side.
+const TIME_UNCHANGED: u64 = u64::MAX;
+
+const ACCESS_CONTROL_EXCEPTION: &str = "org.apache.hadoop.security.AccessControlException";
+const FILE_NOT_FOUND_EXCEPTION: &str = "java.io.FileNotFoundException";
+const NO_SUCH_METHOD_EXCEPTION: &str = "org.apache.hadoop.ipc.RpcNoSuchMethodException";
+const SAFE_MODE_EXCEPTION: &str = "org.apache.hadoop.hdfs.server.namenode.SafeModeException";
+const RETRIABLE_EXCEPTION: &str = "org.apache.hadoop.ipc.RetriableException";
+const SNAPSHOT_EXCEPTION: &str = "org.apache.hadoop.hdfs.protocol.SnapshotException";
+const NS_QUOTA_EXCEEDED_EXCEPTION: &str =
+    "org.apache.hadoop.hdfs.protocol.NSQuotaExceededException";
+
+/// Default time to live of quota_headroom results.
+pub const DEFAULT_QUOTA_CACHE_TTL: Duration = Duration::from_secs(1);
+/// At most this many quota_headroom results are cached.
+const MAX_QUOTA_CACHE_ENTRIES: usize = 1024;
 
 #[derive(Debug, Error)]
 pub enum FsError {
@@ -53,6 +118,174 @@ pub enum FsError {
     IsDir(String),
     #[error("`{0}': File exists")]
     FileExists(String),
+    #[error("`{0}': Permission denied")]
+    AccessDenied(String),
+    /// The path belongs to another filesystem.
+    #[error("Wrong FS: {path}, expected: {expected}")]
+    WrongFs { path: String, expected: String },
+    /// Error of a local filesystem operation.
+    #[error(transparent)]
+    Local(std::io::Error),
+    /// The namenode is in safe mode and rejects modifications; the
+    /// value is the namenode message.
+    #[error("{0}")]
+    SafeMode(String),
+    /// The directory changed during the listing so that it cannot be
+    /// continued.  The entries returned before are valid.
+    #[error("`{path}': Listing is inconsistent: {kind}")]
+    InconsistentListing {
+        path: String,
+        kind: ListingInconsistency,
+    },
+    /// Following the symbolic link would walk a directory that
+    /// contains it again.
+    #[error("`{0}': Symbolic link loop, not descending")]
+    SymlinkLoop(String),
+    /// A snapshottable directory with snapshots cannot be deleted.
+    #[error("`{0}': Directory has snapshots; delete them first with -deleteSnapshot")]
+    HasSnapshots(String),
+    /// A non-empty directory of fs.protected.directories cannot be
+    /// deleted.
+    #[error("`{0}': Directory is protected; remove it from fs.protected.directories first")]
+    ProtectedDirectory(String),
+    /// Deleting the root of an encryption zone destroys the zone; see
+    /// Hdfs::set_protect_encryption_zone_roots.
+    #[error("`{0}': Directory is the root of an encryption zone; delete its contents instead")]
+    EncryptionZoneRoot(String),
+    /// The operation would exceed the name quota of the path's
+    /// directory or one of its ancestors.
+    #[error("`{path}': {quota}")]
+    NameQuotaExceeded {
+        path: String,
+        quota: NameQuotaExceeded,
+    },
+    #[error(transparent)]
+    Glob(#[from] GlobError),
+    /// The header of a client-side encrypted file is invalid.
+    #[cfg(feature = "envelope")]
+    #[error(transparent)]
+    Envelope(crate::envelope::EnvelopeError),
+}
+
+/**
+ * The fields of the namenode's NSQuotaExceededException message, like
+ * "The NameSpace quota (directories and files) of directory /a is
+ * exceeded: quota=2 file count=3".
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameQuotaExceeded {
+    /// The directory with the quota; None if the namenode has not
+    /// named it.
+    pub directory: Option<String>,
+    pub quota: u64,
+    /// The number of names that the operation would result in.
+    pub count: u64,
+}
+
+impl NameQuotaExceeded {
+    pub fn parse(message: &str) -> Option<Self> {
+        let message = &message[message.find("NameSpace quota")?..];
+        let (head, tail) = message.split_at(message.find(" is exceeded:")?);
+        let directory = head
+            .find(" of directory ")
+            .map(|start| head[start + " of directory ".len()..].to_owned());
+        let number_after = |key: &str| {
+            let value = &tail[tail.find(key)? + key.len()..];
+            let end = value
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(value.len());
+            value[..end].parse().ok()
+        };
+        Some(Self {
+            directory,
+            quota: number_after("quota=")?,
+            count: number_after("file count=")?,
+        })
+    }
+}
+
+impl Display for NameQuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.directory {
+            Some(directory) => write!(f, "Name quota of `{}' is exceeded", directory)?,
+            None => f.write_str("Name quota is exceeded")?,
+        }
+        write!(f, ": quota={}, file count={}", self.quota, self.count)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingInconsistency {
+    /// The directory was removed between pages.
+    DirectoryRemoved,
+    /// The namenode keeps returning empty pages while reporting
+    /// remaining entries.
+    EmptyPage,
+}
+
+impl Display for ListingInconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ListingInconsistency::DirectoryRemoved => "directory removed",
+            ListingInconsistency::EmptyPage => "empty page with remaining entries",
+        })
+    }
+}
+
+impl FsError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            FsError::Path(_) => ErrorCode::InvalidPath,
+            FsError::NotFound(_) => ErrorCode::NotFound,
+            FsError::Rpc(e) => e.code(),
+            FsError::NotDir(_) => ErrorCode::NotDir,
+            FsError::IsDir(_) => ErrorCode::IsDir,
+            FsError::FileExists(_) => ErrorCode::FileExists,
+            FsError::AccessDenied(_) => ErrorCode::AccessDenied,
+            FsError::WrongFs { .. } => ErrorCode::InvalidPath,
+            FsError::Local(_) => ErrorCode::Io,
+            FsError::SafeMode(_) => ErrorCode::SafeMode,
+            FsError::InconsistentListing { kind, .. } => match kind {
+                ListingInconsistency::DirectoryRemoved => ErrorCode::NotFound,
+                ListingInconsistency::EmptyPage => ErrorCode::Protocol,
+            },
+            FsError::SymlinkLoop(_) => ErrorCode::UnresolvedLink,
+            FsError::HasSnapshots(_) | FsError::EncryptionZoneRoot(_) => ErrorCode::InvalidArgument,
+            FsError::ProtectedDirectory(_) => ErrorCode::AccessDenied,
+            FsError::NameQuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+            FsError::Glob(_) => ErrorCode::InvalidPath,
+            #[cfg(feature = "envelope")]
+            FsError::Envelope(_) => ErrorCode::InvalidArgument,
+        }
+    }
+}
+
+/// What modifications do when the namenode is in safe mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SafeModePolicy {
+    /// Fail with FsError::SafeMode.
+    #[default]
+    Fail,
+    /// Poll the namenode until it leaves safe mode and retry the
+    /// operation once; fail with FsError::SafeMode after the timeout.
+    Wait { timeout: Duration, poll: Duration },
+}
+
+/// The namenode message if the error is caused by safe mode.
+fn safe_mode_message(e: &rpc::RpcError) -> Option<&str> {
+    match e {
+        rpc::RpcError::ErrorResponse {
+            exception,
+            error_msg,
+            ..
+        } if exception == SAFE_MODE_EXCEPTION
+            // HA namenodes wrap it while starting up.
+            || (exception == RETRIABLE_EXCEPTION && error_msg.contains("safe mode")) =>
+        {
+            Some(error_msg)
+        }
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
@@ -80,6 +313,11 @@ pub struct HdfsError {
 }
 
 impl HdfsError {
+    #[inline]
+    pub fn code(&self) -> ErrorCode {
+        self.source.code()
+    }
+
     pub fn src<E: Into<FsError>>(source: E) -> Self {
         Self {
             kind: HdfsErrorKind::Src,
@@ -100,6 +338,56 @@ impl HdfsError {
     }
 }
 
+/// Convert well-known exceptions of path-based operations to
+/// specific variants.
+fn path_rpc_error(e: rpc::RpcError, path: &Path<'_>) -> FsError {
+    match e.get_class_name() {
+        Some(ACCESS_CONTROL_EXCEPTION) => FsError::AccessDenied(path.to_path_string()),
+        Some(FILE_NOT_FOUND_EXCEPTION) => FsError::NotFound(path.to_path_string()),
+        Some(NS_QUOTA_EXCEEDED_EXCEPTION) => match NameQuotaExceeded::parse(error_message(&e)) {
+            Some(quota) => FsError::NameQuotaExceeded {
+                path: path.to_path_string(),
+                quota,
+            },
+            None => FsError::Rpc(e),
+        },
+        _ => FsError::Rpc(e),
+    }
+}
+
+fn error_message(e: &rpc::RpcError) -> &str {
+    match e {
+        rpc::RpcError::ErrorResponse { error_msg, .. } => error_msg.as_str(),
+        _ => "",
+    }
+}
+
+/// The path of the message between the prefix and the suffix, or the
+/// path itself.  The namenode names the offending subdirectory.
+fn message_path(message: &str, prefix: &str, suffix: &str, path: &Path<'_>) -> String {
+    message
+        .find(prefix)
+        .map(|idx| &message[idx + prefix.len()..])
+        .and_then(|rest| rest.split(suffix).next())
+        .filter(|found| found.starts_with('/'))
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| path.to_path_string())
+}
+
+/// Typed errors of the namenode's delete checks.
+fn delete_rpc_error(e: rpc::RpcError, path: &Path<'_>) -> FsError {
+    let message = error_message(&e);
+    match e.get_class_name() {
+        Some(SNAPSHOT_EXCEPTION) if message.contains("has snapshots") => FsError::HasSnapshots(
+            message_path(message, "The directory ", " cannot be deleted", path),
+        ),
+        Some(ACCESS_CONTROL_EXCEPTION) if message.contains("protected directory") => {
+            FsError::ProtectedDirectory(message_path(message, "protected directory ", " ", path))
+        }
+        _ => path_rpc_error(e, path),
+    }
+}
+
 pub fn ensure_dir(
     file_info: &HdfsFileStatusProto,
     path: Cow<'_, str>,
@@ -145,6 +433,9 @@ pub fn ensure_not_exists(
     }
 }
 
+/// Filesystem-wide statistics of getFsStats; the space is in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct FsStatus {
     pub capacity: u64,
     pub used: u64,
@@ -157,6 +448,27 @@ pub struct FsStatus {
     pub pending_deletion_blocks: u64,
 }
 
+impl From<&GetFsStatsResponseProto> for FsStatus {
+    fn from(stats: &GetFsStatsResponseProto) -> Self {
+        Self {
+            capacity: stats.get_capacity(),
+            used: stats.get_used(),
+            remaining: stats.get_remaining(),
+            under_replicated: stats.get_under_replicated(),
+            corrupt_blocks: stats.get_corrupt_blocks(),
+            missing_blocks: stats.get_missing_blocks(),
+            missing_repl_one_blocks: stats.get_missing_repl_one_blocks(),
+            blocks_in_future: stats.get_blocks_in_future(),
+            pending_deletion_blocks: stats.get_pending_deletion_blocks(),
+        }
+    }
+}
+
+/**
+ * HDFS client.  It is Send when the connection is, so it may be moved
+ * between threads, but it is not Sync: the calls take `&mut self`,
+ * and one client serves one thread at a time.
+ */
 pub struct Hdfs<
     R = crate::ha_rpc::HaHdfsConnection<crate::rpc::SimpleConnector>,
     SRef = service::ClientNamenodeService<R>,
@@ -166,6 +478,18 @@ pub struct Hdfs<
 {
     service: SRef,
     resolve: UriResolver,
+    auto_msync_period: Option<Duration>,
+    last_msync: Option<Instant>,
+    clock: Arc<dyn Clock>,
+    lease: Lease,
+    quota_cache: HashMap<String, (Instant, QuotaHeadroom)>,
+    quota_cache_ttl: Duration,
+    block_cache: BlockLocationCache,
+    safe_mode_policy: SafeModePolicy,
+    protect_encryption_zone_roots: bool,
+    // Failovers of the connection before the stats were reset.
+    failover_baseline: FailoverCounts,
+    verify_after_failover: bool,
     _phantom: std::marker::PhantomData<R>,
 }
 
@@ -175,22 +499,271 @@ where
     SRef: BorrowMut<service::ClientNamenodeService<R>>,
 {
     pub fn new(service: SRef, resolve: UriResolver) -> Self {
+        let failover_baseline = service.borrow().connection().failover_counts();
         Self {
             service,
             resolve,
+            auto_msync_period: None,
+            last_msync: None,
+            clock: Arc::new(SystemClock),
+            lease: Lease::new(),
+            quota_cache: HashMap::new(),
+            quota_cache_ttl: DEFAULT_QUOTA_CACHE_TTL,
+            block_cache: BlockLocationCache::default(),
+            safe_mode_policy: SafeModePolicy::default(),
+            protect_encryption_zone_roots: false,
+            failover_baseline,
+            verify_after_failover: false,
             _phantom: std::marker::PhantomData,
         }
     }
 
+    /// A client that has answered a ping, so a broken setup fails at
+    /// startup instead of at the first operation.
+    pub fn new_checked(service: SRef, resolve: UriResolver) -> Result<Self, HdfsError> {
+        let mut hdfs = Self::new(service, resolve);
+        hdfs.ping()?;
+        Ok(hdfs)
+    }
+
     pub fn get_user(&self) -> &str {
         self.service.borrow().get_user()
     }
 
+    /// The nameservice (or the namenode host) of the filesystem.
+    pub fn authority(&self) -> String {
+        self.resolve.default_host()
+    }
+
+    /// Resolve the path part; a full URL of another scheme or
+    /// nameservice is rejected, as the namenode would take it for its
+    /// own path.  Such paths are routed to their filesystem by
+    /// clusters::ClusterManager and the scheme registry.
+    fn resolve_path<'a>(&self, path: &'a Path<'a>) -> Result<Cow<'a, Path<'a>>, FsError> {
+        let foreign_scheme = path
+            .scheme()
+            .filter(|scheme| !scheme.eq_ignore_ascii_case("hdfs"))
+            .is_some();
+        let foreign_host = path
+            .host()
+            .filter(|host| !host.is_empty() && !host.eq_ignore_ascii_case(&self.authority()))
+            .is_some();
+        if foreign_scheme || foreign_host {
+            return Err(FsError::WrongFs {
+                path: path.to_string(),
+                expected: format!("hdfs://{}", self.authority()),
+            });
+        }
+        Ok(self.resolve.resolve_path(path)?)
+    }
+
+    /// Timeout, cancellation and the caller context for each
+    /// subsequent RPC call.  A timed out or cancelled call fails with
+    /// RpcError::TimedOut or RpcError::Cancelled.
+    pub fn set_call_options(&mut self, options: rpc::CallOptions) {
+        self.service.borrow_mut().set_call_options(options)
+    }
+
+    /// Limit the rate of subsequent namenode calls; a limiter shared
+    /// with other clients limits their calls together.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) {
+        self.service.borrow_mut().set_rate_limiter(rate_limiter)
+    }
+
+    /**
+     * A rename or delete that fails after a namenode failover may
+     * have been applied by the old active namenode anyway, and a
+     * retry, by the namenode or by the caller, would fail or act on
+     * the new state.  With verification, such a failure is checked
+     * with getFileLinkInfo of the paths: an applied operation
+     * succeeds, and one that has not taken effect is retried once.
+     * Off by default, as the namenode's retry cache usually
+     * deduplicates the calls.
+     */
+    pub fn set_verify_after_failover(&mut self, verify: bool) {
+        self.verify_after_failover = verify;
+    }
+
+    /// Counters of the calls made with the handle since its creation
+    /// or the last reset_stats.
+    pub fn stats(&self) -> SessionStats {
+        let service = self.service.borrow();
+        SessionStats::new(
+            service.call_counts(),
+            service.connection().failover_counts(),
+            self.failover_baseline,
+        )
+    }
+
+    pub fn reset_stats(&mut self) {
+        let service = self.service.borrow_mut();
+        service.reset_call_counts();
+        self.failover_baseline = service.connection().failover_counts();
+    }
+
+    /// The caller context of subsequent calls; other call options are
+    /// kept.
+    pub fn set_caller_context(&mut self, caller_context: Option<rpc::CallerContext>) {
+        let mut options = self.service.borrow().call_options().clone();
+        options.caller_context = caller_context;
+        self.set_call_options(options);
+    }
+
+    /// The deadline of subsequent calls, e.g. of a whole command;
+    /// other call options are kept.
+    pub fn set_deadline(&mut self, deadline: Option<std::time::Instant>) {
+        let mut options = self.service.borrow().call_options().clone();
+        options.deadline = deadline;
+        self.set_call_options(options);
+    }
+
+    /// Run the operation with the caller context, restoring the
+    /// previous one afterwards.
+    pub fn with_caller_context<T, F: FnOnce(&mut Self) -> T>(
+        &mut self,
+        caller_context: rpc::CallerContext,
+        op: F,
+    ) -> T {
+        let previous = self.service.borrow().call_options().caller_context.clone();
+        self.set_caller_context(Some(caller_context));
+        let res = op(self);
+        self.set_caller_context(previous);
+        res
+    }
+
+    /// Wait until the namenode state seen by subsequent calls is not
+    /// older than the active namenode's one.  It matters only for
+    /// reads served by observer namenodes.
+    pub fn msync(&mut self) -> Result<(), HdfsError> {
+        let args = MsyncRequestProto::default();
+        self.service
+            .borrow_mut()
+            .msync(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        self.last_msync = Some(self.clock.now());
+        Ok(())
+    }
+
+    /**
+     * Check that the namenode serves calls, with getServerDefaults: it
+     * is cheap and needs no permissions.  The round-trip time is
+     * returned.  For readiness probes, ErrorCode::Io and TimedOut mean
+     * the cluster is unreachable, while other codes point to the
+     * setup, like a wrong address that is not a namenode.
+     */
+    pub fn ping(&mut self) -> Result<Duration, HdfsError> {
+        let start = self.clock.now();
+        self.service
+            .borrow_mut()
+            .getServerDefaults(&GetServerDefaultsRequestProto::default())
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        Ok(self.clock.now().saturating_duration_since(start))
+    }
+
+    /// Call msync before a read if the last msync is older than the
+    /// period; zero period means msync before every read, and None
+    /// disables it (default).  Same as Java's
+    /// dfs.client.failover.observer.auto-msync-period.
+    pub fn set_auto_msync_period(&mut self, period: Option<Duration>) {
+        self.auto_msync_period = period;
+    }
+
+    /// Replace the clock used for the auto-msync period.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub(crate) fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    fn auto_msync(&mut self) -> Result<(), FsError> {
+        if let Some(period) = self.auto_msync_period {
+            let due = self
+                .last_msync
+                .map(|last| self.clock.now().saturating_duration_since(last) >= period)
+                .unwrap_or(true);
+            if due {
+                self.msync().map_err(|e| e.source)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The namenode connection, e.g. for its failover statistics.
+    pub fn connection(&self) -> &R {
+        self.service.borrow().connection()
+    }
+
+    /// The client name the namenode leases files to.
+    pub fn client_name(&self) -> &str {
+        self.lease.client_name()
+    }
+
+    /// Append the suffix to the client name, e.g. to tell the
+    /// application in the namenode logs; see
+    /// lease::generate_client_name_with_suffix.  It should be called
+    /// before opening files for writing.
+    pub fn set_client_name_suffix(&mut self, suffix: &str) {
+        self.lease
+            .set_client_name(generate_client_name_with_suffix(suffix));
+    }
+
+    pub fn lease(&self) -> &Lease {
+        &self.lease
+    }
+
+    pub fn lease_mut(&mut self) -> &mut Lease {
+        &mut self.lease
+    }
+
+    pub fn renew_lease(&mut self) -> Result<(), HdfsError> {
+        let mut args = RenewLeaseRequestProto::default();
+        args.set_clientName(self.lease.client_name().to_owned());
+        self.service
+            .borrow_mut()
+            .renewLease(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        self.lease.renewed(self.clock.now());
+        Ok(())
+    }
+
+    /// Renew the lease if there are files open for writing and the
+    /// renewal period has passed.  Writers call it as they go; the
+    /// library starts no background thread, as the connection is not
+    /// shared.
+    pub fn renew_lease_if_due(&mut self) -> Result<(), HdfsError> {
+        if self.lease.is_due(self.clock.now()) {
+            self.renew_lease()?;
+        }
+        Ok(())
+    }
+
+    /// Start the recovery of the file's lease held by another
+    /// client; true if the file is already closed.
+    pub fn recover_lease(&mut self, path: &Path<'_>) -> Result<bool, HdfsError> {
+        let path = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = RecoverLeaseRequestProto::default();
+        args.set_src(path.to_path_string());
+        args.set_clientName(self.lease.client_name().to_owned());
+        let res = self
+            .service
+            .borrow_mut()
+            .recoverLease(&args)
+            .map_err(|e| path_rpc_error(e, &path))
+            .map_err(HdfsError::src)?;
+        Ok(res.get_result())
+    }
+
     pub fn list_status<'s>(
         &'s mut self,
         src: &Path<'_>,
     ) -> Result<impl Iterator<Item = Result<HdfsFileStatusProto, HdfsError>> + 's, HdfsError> {
-        let src = self.resolve.resolve_path(src).map_err(HdfsError::src)?;
+        let src = self.resolve_path(src).map_err(HdfsError::src)?;
 
         ensure_dir(
             &self.get_file_info(&src).map_err(HdfsError::src)?,
@@ -199,13 +772,151 @@ where
         )?;
 
         Ok(
-            LsIterator::new(LsGroupIterator::new(self.service.borrow_mut(), &src))
+            PagedIter::new(LsGroupIterator::new(self.service.borrow_mut(), &src))
+                .map(|r| r.map_err(HdfsError::op)),
+        )
+    }
+
+    /**
+     * Paths that match the pattern (see the glob module), sorted, with
+     * their statuses.  The paths have the form of the pattern: they
+     * are relative if it is, and keep its scheme and authority.  A
+     * pattern without wildcards is looked up like get_file_info, and
+     * NotFound is returned if there is no such path; otherwise the
+     * result may be empty.  Like Java's Globber, entries matched by
+     * the last component are not resolved if they are symbolic links.
+     */
+    pub fn glob_status(
+        &mut self,
+        pattern: &str,
+    ) -> Result<Vec<(String, HdfsFileStatusProto)>, HdfsError> {
+        let (authority, path) = glob::split_authority(pattern);
+        let root = if path.starts_with('/') {
+            format!("{}/", authority)
+        } else {
+            authority.to_owned()
+        };
+
+        let mut found = vec![];
+        for alternative in glob::expand_braces(path).map_err(HdfsError::src)? {
+            let mut candidates = vec![(root.clone(), None)];
+            for component in alternative.split('/').filter(|c| !c.is_empty()) {
+                let component = GlobPattern::new(component).map_err(HdfsError::src)?;
+                if let Some(name) = component.literal() {
+                    for candidate in &mut candidates {
+                        *candidate = (glob::child(&candidate.0, &name), None);
+                    }
+                    continue;
+                }
+                let mut matched = vec![];
+                for (dir, _) in candidates {
+                    let dir_path = Path::new(if dir.is_empty() { "." } else { &dir })
+                        .map_err(HdfsError::src)?;
+                    let entries = match self.list_status(&dir_path) {
+                        Ok(entries) => entries,
+                        // Nothing to match in a file or a missing path.
+                        Err(HdfsError {
+                            source: FsError::NotFound(_) | FsError::NotDir(_),
+                            ..
+                        }) => continue,
+                        Err(e) => return Err(e),
+                    };
+                    for entry in entries {
+                        let entry = entry?;
+                        let name = entry.name().into_owned();
+                        if component.matches(&name) {
+                            matched.push((glob::child(&dir, &name), Some(entry)));
+                        }
+                    }
+                }
+                candidates = matched;
+            }
+
+            for (path, status) in candidates {
+                let status = match status {
+                    Some(status) => status,
+                    None => match self.get_file_info(&Path::new(&path).map_err(HdfsError::src)?) {
+                        Ok(status) => status,
+                        Err(FsError::NotFound(_)) => continue,
+                        Err(e) => return Err(HdfsError::src(e)),
+                    },
+                };
+                found.push((path, status));
+            }
+        }
+
+        if found.is_empty() && !glob::has_wildcard(pattern) {
+            return Err(HdfsError::src(FsError::NotFound(pattern.to_owned())));
+        }
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found.dedup_by(|a, b| a.0 == b.0);
+        Ok(found)
+    }
+
+    /**
+     * Entries of the tree under the directory, recursively, that the
+     * filter accepts; see Walk for the order and the options.  The
+     * filter may also prune subtrees, which are not listed then.
+     * The directories are listed page by page, as the entries are
+     * consumed.
+     */
+    pub fn walk<'h>(
+        &'h mut self,
+        root: &Path<'_>,
+        filter: &'h dyn PathFilter,
+    ) -> Result<Walk<'h, R, SRef>, HdfsError> {
+        // The resolved path may borrow the argument.
+        let root = Path::into_owned(Cow::into_owned(
+            self.resolve_path(root).map_err(HdfsError::src)?,
+        ));
+        let status = self.get_file_info(&root).map_err(HdfsError::src)?;
+        ensure_dir(&status, root.to_string().into(), HdfsErrorKind::Src)?;
+        Ok(Walk::new(self, root, status.get_fileId(), filter))
+    }
+
+    /// The next page of the listing, or None after the last one.
+    pub(crate) fn list_page(
+        &mut self,
+        cursor: &mut ListingCursor,
+    ) -> Result<Option<Vec<HdfsFileStatusProto>>, HdfsError> {
+        if cursor.is_done() {
+            return Ok(None);
+        }
+        let (_, page) = cursor
+            .next_group(self.service.borrow_mut())
+            .map_err(HdfsError::op)?;
+        Ok(Some(page.into_vec()))
+    }
+
+    /// Same as list_status, but entries are grouped into batches of
+    /// at most batch_size.  Nothing is fetched ahead of the requested
+    /// batch, so memory use does not depend on the directory size.
+    pub fn list_status_batches<'s>(
+        &'s mut self,
+        src: &Path<'_>,
+        batch_size: usize,
+    ) -> Result<impl Iterator<Item = Result<Vec<HdfsFileStatusProto>, HdfsError>> + 's, HdfsError>
+    {
+        Ok(BatchIterator::new(self.list_status(src)?, batch_size))
+    }
+
+    /// Files with corrupt blocks under the path.  Namenode returns
+    /// them by chunks that are fetched lazily.
+    pub fn list_corrupt_file_blocks<'s>(
+        &'s mut self,
+        path: &Path<'_>,
+    ) -> Result<impl Iterator<Item = Result<String, HdfsError>> + 's, HdfsError> {
+        let path = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        Ok(
+            PagedIter::new(CorruptFileBlockPages::new(self.service.borrow_mut(), &path))
                 .map(|r| r.map_err(HdfsError::op)),
         )
     }
 
     pub fn get_file_info(&mut self, src: &Path<'_>) -> Result<HdfsFileStatusProto, FsError> {
-        let src = self.resolve.resolve_path(src)?;
+        let src = self.resolve_path(src)?;
+        self.auto_msync()?;
 
         self.service
             .borrow_mut()
@@ -214,23 +925,300 @@ where
             .ok_or_else(|| FsError::NotFound(src.to_path_string()))
     }
 
-    // TODO a sketch; one should check that dst exists or doesn't
-    // exist and srcs do exist, etc.
-    pub fn rename(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<(), HdfsError> {
-        let src = self.resolve.resolve_path(src).map_err(HdfsError::src)?;
-        let dst = self.resolve.resolve_path(dst).map_err(HdfsError::dst)?;
+    /// The ID of the file, for Path::from_file_id.  None if the
+    /// namenode does not report IDs (before Hadoop 2.1).
+    pub fn get_file_id(&mut self, src: &Path<'_>) -> Result<Option<u64>, FsError> {
+        let status = self.get_file_info(src)?;
+        Ok(Some(status.get_fileId()).filter(|file_id| *file_id != 0))
+    }
+
+    /// Same as get_file_info, but a symlink is not followed: the
+    /// status of the link itself is returned.  Links in the parent
+    /// directories are still resolved by the namenode.
+    pub fn get_file_link_info(&mut self, src: &Path<'_>) -> Result<HdfsFileStatusProto, FsError> {
+        let src = self.resolve_path(src)?;
+        self.auto_msync()?;
+
+        let mut args = GetFileLinkInfoRequestProto::default();
+        args.set_src(src.to_path_string());
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getFileLinkInfo(&args)
+            .map_err(FsError::Rpc)?;
+        if resp.has_fs() {
+            Ok(resp.take_fs())
+        } else {
+            Err(FsError::NotFound(src.to_path_string()))
+        }
+    }
+
+    /**
+     * Call a namenode method that has no wrapper yet, e.g. one added in
+     * a newer Hadoop version.  The request and response types are
+     * the protobuf messages of the method; they are re-exported as
+     * `libhdfesse::proto`.
+     *
+     * The request is sent as is: paths in it are not resolved against
+     * the default filesystem, and nothing is validated.  A method
+     * unknown to `service::Idempotency` is never re-sent on failover.
+     * A wrong response type fails to parse or, worse, parses into
+     * garbage, as protobuf messages are not self-describing.
+     */
+    #[cfg(feature = "raw-rpc")]
+    pub fn call<Req: protobuf::Message, Resp: protobuf::Message>(
+        &mut self,
+        method: &str,
+        request: &Req,
+    ) -> Result<Resp, HdfsError> {
+        self.auto_msync().map_err(HdfsError::op)?;
+        self.service
+            .borrow_mut()
+            .call(method, request)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::src)
+    }
+
+    /// Check if the path exists.  Unlike get_file_info, a missing path
+    /// is not an error.
+    pub fn exists(&mut self, path: &Path<'_>) -> Result<bool, HdfsError> {
+        match self.get_file_info(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::NotFound(_)) => Ok(false),
+            Err(e) => Err(HdfsError::src(e)),
+        }
+    }
+
+    /// Check if the current user may access the path in the mode.
+    /// Fails with FsError::AccessDenied or FsError::NotFound.
+    pub fn check_access(&mut self, path: &Path<'_>, mode: FsAction) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = CheckAccessRequestProto::default();
+        args.set_path(path_res.to_path_string());
+        args.set_mode(mode);
+
+        self.service
+            .borrow_mut()
+            .checkAccess(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)?;
+        Ok(())
+    }
+
+    /// Check if the file is closed, i.e. it is not being written and
+    /// its length is final.
+    pub fn is_file_closed(&mut self, path: &Path<'_>) -> Result<bool, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = IsFileClosedRequestProto::default();
+        args.set_src(path_res.to_path_string());
+
+        self.service
+            .borrow_mut()
+            .isFileClosed(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)
+            .map(|resp| resp.get_result())
+    }
+
+    /// Schedule movement of the path's blocks to satisfy its storage
+    /// policy; it requires the storage policy satisfier to be enabled
+    /// on the namenode.
+    pub fn satisfy_storage_policy(&mut self, path: &Path<'_>) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = SatisfyStoragePolicyRequestProto::default();
+        args.set_src(path_res.to_path_string());
+
+        self.service
+            .borrow_mut()
+            .satisfyStoragePolicy(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)?;
+        Ok(())
+    }
 
+    /// Storage policies of the cluster.  The storagePolicy field of
+    /// a file status is the id of one of them, or zero if the policy
+    /// is not set and is inherited from the parent directory.
+    pub fn get_storage_policies(&mut self) -> Result<Vec<BlockStoragePolicyProto>, HdfsError> {
         self.service
             .borrow_mut()
-            .rename(src.to_path_string(), dst.to_path_string())
+            .getStoragePolicies(&GetStoragePoliciesRequestProto::default())
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(|mut resp| resp.take_policies().into_vec())
+    }
+
+    /// Directories where snapshots are allowed and the current user
+    /// may list them (all of them for a superuser).
+    pub fn list_snapshottable_dirs(
+        &mut self,
+    ) -> Result<impl Iterator<Item = SnapshottableDirectoryStatus>, HdfsError> {
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getSnapshottableDirListing(&GetSnapshottableDirListingRequestProto::default())
             .map_err(FsError::Rpc)
             .map_err(HdfsError::op)?;
+        Ok(resp
+            .take_snapshottableDirList()
+            .take_snapshottableDirListing()
+            .into_iter()
+            .map(Into::into))
+    }
+
+    /// Snapshots of the snapshottable directory.
+    pub fn list_snapshots(
+        &mut self,
+        dir: &Path<'_>,
+    ) -> Result<impl Iterator<Item = SnapshotStatus>, HdfsError> {
+        let dir_res = self.resolve_path(dir).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = GetSnapshotListingRequestProto::default();
+        args.set_snapshotRoot(dir_res.to_path_string());
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getSnapshotListing(&args)
+            .map_err(|e| path_rpc_error(e, &dir_res))
+            .map_err(HdfsError::src)?;
+        Ok(resp
+            .take_snapshotList()
+            .take_snapshotListing()
+            .into_iter()
+            .map(Into::into))
+    }
+
+    /// Sizes and counts of the whole subtree.  The namenode walks the
+    /// subtree, which may be slow on huge trees.
+    pub fn get_content_summary(&mut self, path: &Path<'_>) -> Result<ContentSummary, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = GetContentSummaryRequestProto::default();
+        args.set_path(path_res.to_path_string());
+        self.service
+            .borrow_mut()
+            .getContentSummary(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)
+            .map(|resp| resp.get_summary().into())
+    }
+
+    /// Quota and usage of the directory.  Namenodes without
+    /// getQuotaUsage (before Hadoop 3.0) are asked for the content
+    /// summary, which is much more expensive.
+    pub fn get_quota_usage(&mut self, path: &Path<'_>) -> Result<QuotaUsage, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = GetQuotaUsageRequestProto::default();
+        args.set_path(path_res.to_path_string());
+        match self.service.borrow_mut().getQuotaUsage(&args) {
+            Ok(resp) => Ok(resp.get_usage().into()),
+            Err(e) if e.get_class_name() == Some(NO_SUCH_METHOD_EXCEPTION) => {
+                let mut args = GetContentSummaryRequestProto::default();
+                args.set_path(path_res.to_path_string());
+                self.service
+                    .borrow_mut()
+                    .getContentSummary(&args)
+                    .map_err(|e| path_rpc_error(e, &path_res))
+                    .map_err(HdfsError::src)
+                    .map(|resp| resp.get_summary().into())
+            }
+            Err(e) => Err(HdfsError::src(path_rpc_error(e, &path_res))),
+        }
+    }
+
+    /// What may be written into the directory before its quotas are
+    /// exceeded, to fail fast before writing.  The result is cached
+    /// for the cache TTL, so it may be that stale.
+    pub fn quota_headroom(&mut self, path: &Path<'_>) -> Result<QuotaHeadroom, HdfsError> {
+        let key = self
+            .resolve
+            .resolve_path(path)
+            .map_err(HdfsError::src)?
+            .to_path_string();
+        let now = self.clock.now();
+        if let Some((at, headroom)) = self.quota_cache.get(&key) {
+            if now.saturating_duration_since(*at) < self.quota_cache_ttl {
+                return Ok(*headroom);
+            }
+        }
+        let headroom = self.get_quota_usage(path)?.headroom();
+        // Sweep the expired entries, and the oldest one if the cache
+        // is still full, so that it doesn't grow with every path
+        // ever asked.
+        let ttl = self.quota_cache_ttl;
+        self.quota_cache
+            .retain(|_, (at, _)| now.saturating_duration_since(*at) < ttl);
+        if self.quota_cache.len() >= MAX_QUOTA_CACHE_ENTRIES {
+            let oldest = self
+                .quota_cache
+                .iter()
+                .min_by_key(|(_, (at, _))| *at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.quota_cache.remove(&oldest);
+            }
+        }
+        self.quota_cache.insert(key, (now, headroom));
+        Ok(headroom)
+    }
+
+    /// Time to live of quota_headroom results; zero disables the cache.
+    pub fn set_quota_cache_ttl(&mut self, ttl: Duration) {
+        self.quota_cache_ttl = ttl;
+        if ttl == Duration::ZERO {
+            self.quota_cache.clear();
+        }
+    }
+
+    pub fn clear_quota_cache(&mut self) {
+        self.quota_cache.clear();
+    }
+
+    // TODO a sketch; one should check that dst exists or doesn't
+    // exist and srcs do exist, etc.
+    pub fn rename(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<(), HdfsError> {
+        let src = self.resolve_path(src).map_err(HdfsError::src)?;
+        let dst = self.resolve_path(dst).map_err(HdfsError::dst)?;
+
+        let (src_str, dst_str) = (src.to_path_string(), dst.to_path_string());
+        self.call_verified(
+            |service| service.rename(src_str.clone(), dst_str.clone()),
+            |resp| !resp.get_result(),
+            |hdfs| {
+                let src_exists = hdfs.link_exists(&src)?;
+                let dst_exists = hdfs.link_exists(&dst)?;
+                Ok(match (src_exists, dst_exists) {
+                    (false, true) => Some(true),
+                    (true, false) => Some(false),
+                    // A rename into an existing directory, or the
+                    // paths have been changed by others.
+                    _ => None,
+                })
+            },
+            || {
+                let mut resp = RenameResponseProto::new();
+                resp.set_result(true);
+                resp
+            },
+        )
+        .map_err(HdfsError::op)?;
         Ok(())
     }
 
     // Almost functional implementation, requires some polishing.
     pub fn mkdirs(&mut self, src: &Path<'_>, create_parent: bool) -> Result<bool, HdfsError> {
-        let src_res = self.resolve.resolve_path(src).map_err(HdfsError::src)?;
+        let src_res = self.resolve_path(src).map_err(HdfsError::src)?;
 
         if !create_parent {
             // create_parent also assumes that it is ok if path exists
@@ -247,51 +1235,533 @@ where
         args.set_src(src_res.to_path_string());
         args.set_createParent(create_parent);
         args.set_masked(fs_perm);
-        self.service
-            .borrow_mut()
-            .mkdirs(&args)
-            .map_err(FsError::Rpc)
-            .map_err(HdfsError::op)
+        self.call_mutating(|service| service.mkdirs(&args))
+            .map_err(|e| match e {
+                FsError::Rpc(e) => path_rpc_error(e, &src_res),
+                e => e,
+            })
+            .map_err(HdfsError::src)
             .map(|resp| resp.get_result())
     }
 
-    /// Delete path
+    /**
+     * Delete the path.  The namenode refuses a recursive delete of a
+     * directory with snapshots or of a protected directory; its errors
+     * are translated to FsError::HasSnapshots and
+     * FsError::ProtectedDirectory naming the offending directory.
+     * With set_protect_encryption_zone_roots, a recursive delete of
+     * an encryption zone root is refused as well, at the cost of an
+     * extra call.
+     */
     pub fn delete(&mut self, path: &Path<'_>, recursive: bool) -> Result<bool, HdfsError> {
-        let path_res = self.resolve.resolve_path(path).map_err(HdfsError::src)?;
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        // The namenode deletes a symlink itself, not its target, so
+        // the target is not checked either.
         if !recursive {
             ensure_not_dir(
-                &self.get_file_info(path).map_err(HdfsError::src)?,
+                &self.get_file_link_info(path).map_err(HdfsError::src)?,
                 path.to_string().into(),
                 HdfsErrorKind::Src,
             )?;
+        } else if self.protect_encryption_zone_roots {
+            self.check_encryption_zone_root(&path_res)?;
         }
+        self.send_delete(&path_res, recursive)
+    }
+
+    /// Delete an entry of a listing, e.g. of a client-side recursive
+    /// delete, without looking up its status again.  A directory is
+    /// deleted with its contents; the caller runs
+    /// check_recursive_delete first.
+    pub fn delete_entry(
+        &mut self,
+        path: &Path<'_>,
+        status: &HdfsFileStatusProto,
+    ) -> Result<bool, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        let is_dir = status.get_fileType() == HdfsFileStatusProto_FileType::IS_DIR;
+        self.send_delete(&path_res, is_dir)
+    }
+
+    fn send_delete(&mut self, path_res: &Path<'_>, recursive: bool) -> Result<bool, HdfsError> {
         let mut args = DeleteRequestProto::default();
         args.set_src(path_res.to_path_string());
         args.borrow_mut().set_recursive(recursive);
+        self.call_verified(
+            |service| service.delete(&args),
+            |resp| !resp.get_result(),
+            |hdfs| Ok(Some(!hdfs.link_exists(path_res)?)),
+            || {
+                let mut resp = DeleteResponseProto::new();
+                resp.set_result(true);
+                resp
+            },
+        )
+        .map_err(|e| match e {
+            FsError::Rpc(e) => delete_rpc_error(e, path_res),
+            e => e,
+        })
+        .map_err(HdfsError::src)
+        .map(|resp| resp.get_result())
+    }
+
+    /// The checks of a recursive delete of the path with the status:
+    /// a directory must have no snapshots and, with
+    /// set_protect_encryption_zone_roots, must not be the root of an
+    /// encryption zone.  A client-side recursive delete runs them
+    /// before deleting the contents, as the namenode checks only the
+    /// directories it deletes itself.  They take an extra call only
+    /// for directories flagged as snapshottable or encrypted.
+    pub fn check_recursive_delete(
+        &mut self,
+        path: &Path<'_>,
+        status: &HdfsFileStatusProto,
+    ) -> Result<(), HdfsError> {
+        if status.get_fileType() != HdfsFileStatusProto_FileType::IS_DIR {
+            return Ok(());
+        }
+        let path = self.resolve_path(path).map_err(HdfsError::src)?;
+        let path = path.as_ref();
+        let flags = status.get_flags();
+        if flags & (HdfsFileStatusProto_Flags::SNAPSHOT_ENABLED as u32) != 0
+            && self.has_snapshots(path)?.unwrap_or(false)
+        {
+            return Err(HdfsError::src(FsError::HasSnapshots(path.to_path_string())));
+        }
+        if self.protect_encryption_zone_roots
+            && flags & (HdfsFileStatusProto_Flags::HAS_CRYPT as u32) != 0
+        {
+            self.check_encryption_zone_root(path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the snapshottable directory has snapshots; None if the
+    /// namenode cannot list them (before Hadoop 3.3).
+    fn has_snapshots(&mut self, dir: &Path<'_>) -> Result<Option<bool>, HdfsError> {
+        match self.list_snapshots(dir) {
+            Ok(mut snapshots) => Ok(Some(snapshots.next().is_some())),
+            Err(HdfsError {
+                source: FsError::Rpc(e),
+                ..
+            }) if e.get_class_name() == Some(NO_SUCH_METHOD_EXCEPTION) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn check_encryption_zone_root(&mut self, path: &Path<'_>) -> Result<(), HdfsError> {
+        let path_str = path.to_path_string();
+        let mut args = GetEZForPathRequestProto::default();
+        args.set_src(path_str.clone());
+        let resp = match self.service.borrow_mut().getEZForPath(&args) {
+            Ok(resp) => resp,
+            Err(e) => match path_rpc_error(e, path) {
+                // The namenode reports it.
+                FsError::NotFound(_) => return Ok(()),
+                e => return Err(HdfsError::src(e)),
+            },
+        };
+        if resp.has_zone() && resp.get_zone().get_path() == path_str {
+            return Err(HdfsError::src(FsError::EncryptionZoneRoot(path_str)));
+        }
+        Ok(())
+    }
+
+    /// Refuse recursive deletes of encryption zone roots (off by
+    /// default, like the namenode).
+    pub fn set_protect_encryption_zone_roots(&mut self, protect: bool) {
+        self.protect_encryption_zone_roots = protect;
+    }
+
+    pub fn get_status(&mut self) -> Result<FsStatus, HdfsError> {
+        let args = GetFsStatusRequestProto::default();
         self.service
             .borrow_mut()
-            .delete(&args)
+            .getFsStats(&args)
+            .map(|stats| FsStatus::from(&stats))
             .map_err(FsError::Rpc)
-            .map_err(HdfsError::src)
+            .map_err(HdfsError::op)
+    }
+
+    pub fn get_replicated_block_stats(&mut self) -> Result<ReplicatedBlockStats, HdfsError> {
+        let args = GetFsReplicatedBlockStatsRequestProto::default();
+        self.service
+            .borrow_mut()
+            .getFsReplicatedBlockStats(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(Into::into)
+    }
+
+    pub fn get_ec_block_group_stats(&mut self) -> Result<EcBlockGroupStats, HdfsError> {
+        let args = GetFsECBlockGroupStatsRequestProto::default();
+        self.service
+            .borrow_mut()
+            .getFsECBlockGroupStats(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(Into::into)
+    }
+
+    /// How rename, mkdirs, delete, chmod and set_time handle safe mode.
+    pub fn set_safe_mode_policy(&mut self, policy: SafeModePolicy) {
+        self.safe_mode_policy = policy;
+    }
+
+    /// Poll the namenode until it leaves safe mode; fails with
+    /// FsError::SafeMode after the timeout.  Batch jobs started right
+    /// after a namenode restart may call it before writing.
+    pub fn wait_safe_mode_off(
+        &mut self,
+        timeout: Duration,
+        poll: Duration,
+    ) -> Result<(), HdfsError> {
+        let deadline = self.clock.now() + timeout;
+        while self.is_in_safe_mode()? {
+            let now = self.clock.now();
+            if now >= deadline {
+                return Err(HdfsError::op(FsError::SafeMode(format!(
+                    "Name node is still in safe mode after {:?}",
+                    timeout
+                ))));
+            }
+            self.clock.sleep(poll.min(deadline - now));
+        }
+        Ok(())
+    }
+
+    /// Perform the modification according to the safe mode policy.
+    fn call_mutating<T>(
+        &mut self,
+        mut call: impl FnMut(&mut service::ClientNamenodeService<R>) -> Result<T, rpc::RpcError>,
+    ) -> Result<T, FsError> {
+        let mut waited = false;
+        loop {
+            let err = match call(self.service.borrow_mut()) {
+                Ok(val) => return Ok(val),
+                Err(e) => e,
+            };
+            let msg = match safe_mode_message(&err) {
+                Some(msg) => msg.to_owned(),
+                None => return Err(FsError::Rpc(err)),
+            };
+            match self.safe_mode_policy {
+                SafeModePolicy::Wait { timeout, poll } if !waited => {
+                    self.wait_safe_mode_off(timeout, poll)
+                        .map_err(|e| e.source)?;
+                    waited = true;
+                }
+                _ => return Err(FsError::SafeMode(msg)),
+            }
+        }
+    }
+
+    /**
+     * Perform a non-idempotent modification; see
+     * set_verify_after_failover.  A call is suspect if it has failed
+     * with an RPC error or returned a result that `failed` rejects
+     * while the connection failed over.  `applied` then checks the
+     * namespace: Some(true) means the operation has taken effect and
+     * `done` is returned, Some(false) means it has not and the call
+     * is retried once, and None keeps the original result.
+     */
+    fn call_verified<T>(
+        &mut self,
+        mut call: impl FnMut(&mut service::ClientNamenodeService<R>) -> Result<T, rpc::RpcError>,
+        failed: impl FnOnce(&T) -> bool,
+        applied: impl FnOnce(&mut Self) -> Result<Option<bool>, FsError>,
+        done: impl FnOnce() -> T,
+    ) -> Result<T, FsError> {
+        let before = self.failover_counts();
+        let res = self.call_mutating(&mut call);
+        let suspect = match &res {
+            Ok(val) => failed(val),
+            Err(FsError::Rpc(_)) => true,
+            Err(_) => false,
+        };
+        if !self.verify_after_failover || !suspect || self.failover_counts() == before {
+            return res;
+        }
+        match applied(self) {
+            Ok(Some(true)) => Ok(done()),
+            Ok(Some(false)) => self.call_mutating(call),
+            // The namenode may be still unavailable.
+            Ok(None) | Err(_) => res,
+        }
+    }
+
+    fn failover_counts(&self) -> FailoverCounts {
+        self.service.borrow().connection().failover_counts()
+    }
+
+    /// Whether the path exists; a symlink is not followed.
+    fn link_exists(&mut self, path: &Path<'_>) -> Result<bool, FsError> {
+        match self.get_file_link_info(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check if the namenode is in safe mode without changing it.
+    pub fn is_in_safe_mode(&mut self) -> Result<bool, HdfsError> {
+        let mut args = SetSafeModeRequestProto::default();
+        args.set_action(SafeModeActionProto::SAFEMODE_GET);
+        self.service
+            .borrow_mut()
+            .setSafeMode(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
             .map(|resp| resp.get_result())
     }
 
-    pub fn get_status(&mut self) -> Result<FsStatus, HdfsError> {
-        let args = GetFsStatusRequestProto::default();
-        match self.service.borrow_mut().getFsStats(&args) {
-            Ok(stats) => Ok(FsStatus {
-                capacity: stats.get_capacity(),
-                used: stats.get_used(),
-                remaining: stats.get_used(),
-                under_replicated: stats.get_under_replicated(),
-                corrupt_blocks: stats.get_corrupt_blocks(),
-                missing_blocks: stats.get_missing_blocks(),
-                missing_repl_one_blocks: stats.get_missing_repl_one_blocks(),
-                blocks_in_future: stats.get_blocks_in_future(),
-                pending_deletion_blocks: stats.get_pending_deletion_blocks(),
-            }),
-            Err(e) => Err(HdfsError::op(FsError::Rpc(e))),
+    /// Query, start or finalize a rolling upgrade.  None means that
+    /// there is no rolling upgrade in progress.
+    pub fn rolling_upgrade(
+        &mut self,
+        action: RollingUpgradeAction,
+    ) -> Result<Option<RollingUpgradeInfo>, HdfsError> {
+        let mut args = RollingUpgradeRequestProto::default();
+        args.set_action(action);
+        self.service
+            .borrow_mut()
+            .rollingUpgrade(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(|resp| resp.rollingUpgradeInfo.into_option().map(Into::into))
+    }
+
+    pub fn finalize_upgrade(&mut self) -> Result<(), HdfsError> {
+        let args = FinalizeUpgradeRequestProto::default();
+        self.service
+            .borrow_mut()
+            .finalizeUpgrade(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        Ok(())
+    }
+
+    /// Returns true if there is no upgrade in progress or it is
+    /// already finalized.
+    pub fn upgrade_status(&mut self) -> Result<bool, HdfsError> {
+        let args = UpgradeStatusRequestProto::default();
+        self.service
+            .borrow_mut()
+            .upgradeStatus(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(|resp| resp.get_upgradeFinalized())
+    }
+
+    /// Save the namespace image; the namenode has to be in safe
+    /// mode.  The image is not saved if the last checkpoint is newer
+    /// than time_window seconds and fewer than tx_gap transactions
+    /// behind; zero disables the corresponding check.  Returns true
+    /// if the image was saved.
+    pub fn save_namespace(&mut self, time_window: u64, tx_gap: u64) -> Result<bool, HdfsError> {
+        let mut args = SaveNamespaceRequestProto::default();
+        args.set_timeWindow(time_window);
+        args.set_txGap(tx_gap);
+        self.service
+            .borrow_mut()
+            .saveNamespace(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(|resp| resp.get_saved())
+    }
+
+    /// Roll the edit log; returns the transaction ID of the new
+    /// segment.
+    pub fn roll_edits(&mut self) -> Result<u64, HdfsError> {
+        let args = RollEditsRequestProto::default();
+        self.service
+            .borrow_mut()
+            .rollEdits(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)
+            .map(|resp| resp.get_newSegmentTxId())
+    }
+
+    /// Dump namenode's data structures into the filename in the
+    /// namenode's log directory.
+    pub fn meta_save(&mut self, filename: &str) -> Result<(), HdfsError> {
+        let mut args = MetaSaveRequestProto::default();
+        args.set_filename(filename.to_owned());
+        self.service
+            .borrow_mut()
+            .metaSave(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        Ok(())
+    }
+
+    /// Set the bandwidth each datanode may use for balancing, in
+    /// bytes per second.
+    pub fn set_balancer_bandwidth(&mut self, bandwidth: u64) -> Result<(), HdfsError> {
+        let mut args = SetBalancerBandwidthRequestProto::default();
+        // The protocol field is signed; larger values make no sense anyway.
+        args.set_bandwidth(bandwidth.min(i64::MAX as u64) as i64);
+        self.service
+            .borrow_mut()
+            .setBalancerBandwidth(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        Ok(())
+    }
+
+    /// Datanodes of the type, like DatanodeReportType::DECOMMISSIONING.
+    pub fn get_datanode_report(
+        &mut self,
+        report_type: DatanodeReportType,
+    ) -> Result<Vec<DatanodeInfo>, HdfsError> {
+        let mut args = GetDatanodeReportRequestProto::default();
+        args.set_field_type(report_type);
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getDatanodeReport(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        Ok(resp.take_di().into_iter().map(Into::into).collect())
+    }
+
+    pub fn get_datanode_storage_report(
+        &mut self,
+        report_type: DatanodeReportType,
+    ) -> Result<Vec<DatanodeStorageReport>, HdfsError> {
+        let mut args = GetDatanodeStorageReportRequestProto::default();
+        args.set_field_type(report_type);
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getDatanodeStorageReport(&args)
+            .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        Ok(resp
+            .take_datanodeStorageReports()
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Same as get_file_block_locations, but locations are cached by
+    /// the file id and fetched by block_cache::REGION_SIZE regions.
+    /// The cached locations of a file are dropped when the length or
+    /// the modification time in the status change; files under
+    /// construction or without a file id are not cached, nor is
+    /// anything with a zero cache capacity.
+    pub fn get_file_block_locations_cached(
+        &mut self,
+        path: &Path<'_>,
+        file_status: &HdfsFileStatusProto,
+        offset: u64,
+        length: u64,
+    ) -> Result<Vec<LocatedBlock>, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        let file_id = file_status.get_fileId();
+        let length = length.min(file_status.get_length().saturating_sub(offset));
+        // Statuses without the file id would share the entry of id 0.
+        if file_id == 0 || self.block_cache.capacity() == 0 {
+            return Ok(self
+                .fetch_block_locations(&path_res, offset, length)?
+                .take_blocks()
+                .into_iter()
+                .map(Into::into)
+                .collect());
+        }
+
+        let missing = self.block_cache.missing_regions(
+            file_id,
+            file_status.get_length(),
+            file_status.get_modification_time(),
+            offset,
+            length,
+        );
+        for region in missing {
+            let mut locations = self.fetch_block_locations(
+                &path_res,
+                region * block_cache::REGION_SIZE,
+                block_cache::REGION_SIZE,
+            )?;
+            if locations.get_underConstruction() {
+                self.block_cache.invalidate(file_id);
+                return Ok(self
+                    .fetch_block_locations(&path_res, offset, length)?
+                    .take_blocks()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect());
+            }
+            self.block_cache.insert_region(
+                file_id,
+                region,
+                locations
+                    .take_blocks()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            );
         }
+        Ok(self.block_cache.blocks(file_id, offset, length))
+    }
+
+    pub fn block_cache_mut(&mut self) -> &mut BlockLocationCache {
+        &mut self.block_cache
+    }
+
+    fn fetch_block_locations(
+        &mut self,
+        path_res: &Path<'_>,
+        offset: u64,
+        length: u64,
+    ) -> Result<LocatedBlocksProto, HdfsError> {
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = GetBlockLocationsRequestProto::default();
+        args.set_src(path_res.to_path_string());
+        args.set_offset(offset);
+        args.set_length(length);
+        self.service
+            .borrow_mut()
+            .getBlockLocations(&args)
+            .map_err(|e| path_rpc_error(e, path_res))
+            .map_err(HdfsError::src)
+            .map(|mut resp| resp.take_locations())
+    }
+
+    /// The block size the file was created with.
+    pub fn preferred_block_size(&mut self, path: &Path<'_>) -> Result<u64, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
+
+        let mut args = GetPreferredBlockSizeRequestProto::default();
+        args.set_filename(path_res.to_path_string());
+        self.service
+            .borrow_mut()
+            .getPreferredBlockSize(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)
+            .map(|resp| resp.get_bsize())
+    }
+
+    /// Block-aligned splits of the whole file; see
+    /// splits::plan_splits.
+    pub fn plan_splits(
+        &mut self,
+        path: &Path<'_>,
+        split_size: u64,
+    ) -> Result<Vec<Split>, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        let file_info = self.get_file_info(&path_res).map_err(HdfsError::src)?;
+        ensure_not_dir(&file_info, path.to_string().into(), HdfsErrorKind::Src)?;
+
+        let blocks: Vec<LocatedBlock> = self
+            .fetch_block_locations(&path_res, 0, file_info.get_length())?
+            .take_blocks()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(splits::plan_splits(&blocks, split_size))
     }
 
     // The method returns protobuf record, and it can be considered as
@@ -314,7 +1784,8 @@ where
             .map_err(HdfsError::src)?;
 
         // TODO is re-resolving really required?
-        let path_res = self.resolve.resolve_path(&path1).map_err(HdfsError::src)?;
+        let path_res = self.resolve_path(&path1).map_err(HdfsError::src)?;
+        self.auto_msync().map_err(HdfsError::op)?;
 
         let mut args = GetBlockLocationsRequestProto::default();
         args.set_src(path_res.to_path_string());
@@ -336,7 +1807,7 @@ where
     }
 
     pub fn chmod(&mut self, path: &Path<'_>, chmod: u32) -> Result<(), HdfsError> {
-        let path_res = self.resolve.resolve_path(path).map_err(HdfsError::src)?;
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
 
         let mut perm = FsPermissionProto::default();
         perm.set_perm(chmod);
@@ -345,41 +1816,352 @@ where
         args.set_src(path_res.to_path_string());
         args.set_permission(perm);
 
-        self.service
-            .borrow_mut()
-            .setPermission(&args)
-            .map_err(FsError::Rpc)
+        self.call_mutating(|service| service.setPermission(&args))
             .map_err(HdfsError::src)?;
         Ok(())
     }
 
+    /// Set the modification and access times, in seconds since the
+    /// epoch; None leaves the time unchanged.
+    #[deprecated(note = "the namenode keeps milliseconds; use set_times")]
     pub fn set_time(
         &mut self,
         path: &Path<'_>,
         mtime: Option<u64>,
         atime: Option<u64>,
     ) -> Result<(), HdfsError> {
-        let path_res = self.resolve.resolve_path(path).map_err(HdfsError::src)?;
+        self.set_times_millis(
+            path,
+            mtime.map(|mtime| mtime.saturating_mul(1000)),
+            atime.map(|atime| atime.saturating_mul(1000)),
+        )
+    }
+
+    /// Set the modification and access times with the namenode's
+    /// millisecond precision; None leaves the time unchanged.  Times
+    /// before the epoch are negative, like Java's.
+    pub fn set_times(
+        &mut self,
+        path: &Path<'_>,
+        mtime: Option<SystemTime>,
+        atime: Option<SystemTime>,
+    ) -> Result<(), HdfsError> {
+        self.set_times_millis(path, mtime.map(java_millis), atime.map(java_millis))
+    }
+
+    fn set_times_millis(
+        &mut self,
+        path: &Path<'_>,
+        mtime: Option<u64>,
+        atime: Option<u64>,
+    ) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
 
         let mut args = SetTimesRequestProto::default();
         args.set_src(path_res.to_path_string());
-        if let Some(mtime) = mtime {
-            // Convert to milliseconds; see hdfs.c.
-            args.set_mtime(mtime * 1000);
+        // Both fields are required.
+        args.set_mtime(mtime.unwrap_or(TIME_UNCHANGED));
+        args.set_atime(atime.unwrap_or(TIME_UNCHANGED));
+
+        self.call_mutating(|service| service.setTimes(&args))
+            .map_err(HdfsError::src)?;
+        Ok(())
+    }
+
+    /// Set the owner, the group or both; None leaves it unchanged.
+    pub fn set_owner(
+        &mut self,
+        path: &Path<'_>,
+        user: Option<&str>,
+        group: Option<&str>,
+    ) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = SetOwnerRequestProto::default();
+        args.set_src(path_res.to_path_string());
+        if let Some(user) = user {
+            args.set_username(user.to_owned());
         }
-        if let Some(atime) = atime {
-            args.set_atime(atime * 1000);
+        if let Some(group) = group {
+            args.set_groupname(group.to_owned());
         }
 
-        self.service
+        self.call_mutating(|service| service.setOwner(&args))
+            .map_err(HdfsError::src)?;
+        Ok(())
+    }
+
+    /// The ACL of the path; its entries do not include the ones of
+    /// the permission bits.
+    pub fn get_acl_status(&mut self, path: &Path<'_>) -> Result<AclStatusProto, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = GetAclStatusRequestProto::default();
+        args.set_src(path_res.to_path_string());
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getAclStatus(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)?;
+        Ok(resp.take_result())
+    }
+
+    /// Replace the ACL of the path.  The entries of the permission
+    /// bits that the spec lacks are kept.
+    pub fn set_acl(&mut self, path: &Path<'_>, spec: Vec<AclEntryProto>) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = SetAclRequestProto::default();
+        args.set_src(path_res.to_path_string());
+        args.set_aclSpec(spec.into());
+
+        self.call_mutating(|service| service.setAcl(&args))
+            .map_err(HdfsError::src)?;
+        Ok(())
+    }
+
+    /// The extended attributes of the path that the user may read,
+    /// with their values.
+    pub fn get_xattrs(&mut self, path: &Path<'_>) -> Result<Vec<XAttrProto>, HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = GetXAttrsRequestProto::default();
+        args.set_src(path_res.to_path_string());
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .getXAttrs(&args)
+            .map_err(|e| path_rpc_error(e, &path_res))
+            .map_err(HdfsError::src)?;
+        Ok(resp.take_xAttrs().into_vec())
+    }
+
+    /// Set the extended attribute, whether it exists or not.
+    pub fn set_xattr(&mut self, path: &Path<'_>, xattr: XAttrProto) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+
+        let mut args = SetXAttrRequestProto::default();
+        args.set_src(path_res.to_path_string());
+        args.set_xAttr(xattr);
+        args.set_flag(
+            XAttrSetFlagProto::XATTR_CREATE as u32 | XAttrSetFlagProto::XATTR_REPLACE as u32,
+        );
+
+        self.call_mutating(|service| service.setXAttr(&args))
+            .map_err(HdfsError::src)?;
+        Ok(())
+    }
+
+    /**
+     * Copy the attributes of the source to the destination, as a copy
+     * does after the data.  The status is the source's one.  ACLs are
+     * set only if the source has them; the destination keeps its own
+     * otherwise.  Raw extended attributes, like the encryption info
+     * of a file, are copied only if both paths are under RAW_PREFIX,
+     * as distcp does: the info is valid only for the same encrypted
+     * bytes, which only a copy of raw data has.  The times are set
+     * last.
+     */
+    pub fn preserve_attributes(
+        &mut self,
+        src: &Path<'_>,
+        src_status: &HdfsFileStatusProto,
+        dst: &Path<'_>,
+        preserve: &Preserve,
+    ) -> Result<(), HdfsError> {
+        let src = self.resolve_path(src).map_err(HdfsError::src)?;
+        let src = src.as_ref();
+        let dst = self.resolve_path(dst).map_err(HdfsError::dst)?;
+        let dst = dst.as_ref();
+        let to_dst = |e: HdfsError| HdfsError::dst(e.source);
+
+        if preserve.permission {
+            self.chmod(dst, src_status.get_permission().get_perm())
+                .map_err(to_dst)?;
+        }
+        let has_acl = src_status.get_flags() & (HdfsFileStatusProto_Flags::HAS_ACL as u32) != 0;
+        if preserve.acls && has_acl {
+            let mut acl = self.get_acl_status(src)?;
+            self.set_acl(dst, acl.take_entries().into_vec())
+                .map_err(to_dst)?;
+        }
+        if preserve.user || preserve.group {
+            self.set_owner(
+                dst,
+                Some(src_status.get_owner()).filter(|_| preserve.user),
+                Some(src_status.get_group()).filter(|_| preserve.group),
+            )
+            .map_err(to_dst)?;
+        }
+        if preserve.xattrs {
+            let raw = src.is_raw() && dst.is_raw();
+            self.copy_xattrs(src, dst, |namespace| {
+                raw || namespace != XAttrProto_XAttrNamespaceProto::RAW
+            })?;
+        }
+        if preserve.times {
+            self.set_times_millis(
+                dst,
+                Some(src_status.get_modification_time()),
+                Some(src_status.get_access_time()),
+            )
+            .map_err(to_dst)?;
+        }
+        Ok(())
+    }
+
+    fn copy_xattrs(
+        &mut self,
+        src: &Path<'_>,
+        dst: &Path<'_>,
+        filter: impl Fn(XAttrProto_XAttrNamespaceProto) -> bool,
+    ) -> Result<(), HdfsError> {
+        for xattr in self.get_xattrs(src)? {
+            if filter(xattr.get_namespace()) {
+                self.set_xattr(dst, xattr)
+                    .map_err(|e| HdfsError::dst(e.source))?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Update the times of the path, creating an empty file if it
+     * doesn't exist, like Java's -touch command.  The times of a new
+     * file are set only if TouchOptions has the time; otherwise the
+     * namenode's creation time stays.  The parent directory must
+     * exist.
+     */
+    pub fn touch(&mut self, path: &Path<'_>, opts: &TouchOptions) -> Result<(), HdfsError> {
+        match self.get_file_info(path) {
+            Ok(_) => {}
+            Err(FsError::NotFound(_)) if !opts.create => return Ok(()),
+            Err(FsError::NotFound(_)) => {
+                self.create_empty(path)?;
+                if opts.time.is_none() {
+                    return Ok(());
+                }
+            }
+            Err(e) => return Err(HdfsError::src(e)),
+        }
+
+        let time = opts.time.unwrap_or_else(SystemTime::now);
+        let (mtime, atime) = match (opts.modification, opts.access) {
+            (true, false) => (Some(time), None),
+            (false, true) => (None, Some(time)),
+            _ => (Some(time), Some(time)),
+        };
+        self.set_times(path, mtime, atime)
+    }
+
+    /// Create an empty file and close it at once, like Java's
+    /// fs.create(path).close().  Existing files are not overwritten.
+    fn create_empty(&mut self, path: &Path<'_>) -> Result<(), HdfsError> {
+        let path_res = self.resolve_path(path).map_err(HdfsError::src)?;
+        let defaults = self
+            .service
             .borrow_mut()
-            .setTimes(&args)
+            .getServerDefaults(&GetServerDefaultsRequestProto::default())
             .map_err(FsError::Rpc)
+            .map_err(HdfsError::op)?;
+        let defaults = defaults.get_serverDefaults();
+
+        let src = path_res.to_path_string();
+        let client_name = self.lease.client_name().to_owned();
+        let mut perm = FsPermissionProto::new();
+        perm.set_perm(DEFAULT_FILE_PERM);
+
+        let mut args = CreateRequestProto::new();
+        args.set_src(src.clone());
+        args.set_masked(perm);
+        args.set_clientName(client_name.clone());
+        args.set_createFlag(CreateFlagProto::CREATE as u32);
+        args.set_createParent(false);
+        args.set_replication(defaults.get_replication());
+        args.set_blockSize(defaults.get_blockSize());
+        args.set_cryptoProtocolVersion(vec![CryptoProtocolVersionProto::ENCRYPTION_ZONES]);
+        let created = self
+            .call_mutating(|service| service.create(&args))
+            .map_err(HdfsError::src)?;
+
+        // The file has no blocks, so the namenode completes it at
+        // once and the result is always true.
+        let mut args = CompleteRequestProto::new();
+        args.set_src(src);
+        args.set_clientName(client_name);
+        args.set_fileId(created.get_fs().get_fileId());
+        self.call_mutating(|service| service.complete(&args))
             .map_err(HdfsError::src)?;
         Ok(())
     }
 }
 
+/**
+ * What Hdfs::touch changes.  By default, both times are set to the
+ * current time, and a missing file is created.
+ */
+#[derive(Debug, Clone)]
+pub struct TouchOptions {
+    time: Option<SystemTime>,
+    access: bool,
+    modification: bool,
+    create: bool,
+}
+
+impl Default for TouchOptions {
+    fn default() -> Self {
+        Self {
+            time: None,
+            access: true,
+            modification: true,
+            create: true,
+        }
+    }
+}
+
+impl TouchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The time to set instead of the current one.  The namenode
+    /// stores milliseconds; the rest is truncated.
+    pub fn with_time(mut self, time: SystemTime) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Change only the access time.
+    pub fn access_only(mut self) -> Self {
+        self.access = true;
+        self.modification = false;
+        self
+    }
+
+    /// Change only the modification time.
+    pub fn modification_only(mut self) -> Self {
+        self.access = false;
+        self.modification = true;
+        self
+    }
+
+    /// Whether a missing file is created.
+    pub fn with_create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+}
+
+/// Milliseconds since the epoch as Java's long in the unsigned
+/// protobuf field: times before the epoch are negative.
+fn java_millis(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as u64,
+        Err(e) => (e.duration().as_millis() as i64).wrapping_neg() as u64,
+    }
+}
+
 impl<R: RpcConnection> Hdfs<R, service::ClientNamenodeService<R>> {
     #[inline]
     pub fn shutdown(self) -> Result<(), HdfsError> {
@@ -389,3 +2171,1346 @@ impl<R: RpcConnection> Hdfs<R, service::ClientNamenodeService<R>> {
             .map_err(HdfsError::op)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        testing::{file_status, mock_hdfs, MockConnection},
+    };
+    use hdfesse_proto::ClientNamenodeProtocol::{
+        CompleteResponseProto, CreateResponseProto, GetBlockLocationsResponseProto,
+        GetContentSummaryResponseProto, GetFileInfoRequestProto, GetFileInfoResponseProto,
+        GetFileLinkInfoResponseProto, GetQuotaUsageResponseProto, GetServerDefaultsResponseProto,
+        GetStoragePoliciesResponseProto, MkdirsResponseProto, MsyncResponseProto,
+        RenameRequestProto, RenewLeaseResponseProto, SetTimesResponseProto,
+    };
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_auto_msync_period() {
+        let mut conn = MockConnection::new("test");
+        conn.on("msync", |_: MsyncRequestProto| {
+            Ok(MsyncResponseProto::default())
+        })
+        .on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(file_status(req.get_src(), false, 0));
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let clock = ManualClock::new();
+        hdfs.set_clock(Arc::new(clock.clone()));
+        hdfs.set_auto_msync_period(Some(Duration::from_secs(60)));
+
+        let path = Path::new("/file").unwrap();
+        hdfs.get_file_info(&path).unwrap();
+        clock.advance(Duration::from_secs(59));
+        hdfs.get_file_info(&path).unwrap();
+        clock.advance(Duration::from_secs(1));
+        hdfs.get_file_info(&path).unwrap();
+
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            [
+                "msync",
+                "getFileInfo",
+                "getFileInfo",
+                "msync",
+                "getFileInfo"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renew_lease_if_due() {
+        let mut conn = MockConnection::new("test");
+        let client_name = Arc::new(std::sync::Mutex::new(String::new()));
+        let seen = client_name.clone();
+        conn.on("renewLease", move |req: RenewLeaseRequestProto| {
+            *seen.lock().unwrap() = req.get_clientName().to_owned();
+            Ok(RenewLeaseResponseProto::default())
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let clock = ManualClock::new();
+        hdfs.set_clock(Arc::new(clock.clone()));
+
+        // Nothing to renew without open files.
+        clock.advance(Duration::from_secs(3600));
+        hdfs.renew_lease_if_due().unwrap();
+
+        hdfs.lease_mut().file_opened(clock.now());
+        hdfs.renew_lease_if_due().unwrap();
+        clock.advance(crate::lease::DEFAULT_RENEWAL_PERIOD);
+        hdfs.renew_lease_if_due().unwrap();
+        hdfs.renew_lease_if_due().unwrap();
+
+        assert_eq!(*client_name.lock().unwrap(), hdfs.client_name());
+        assert_eq!(hdfs.service.into_inner().calls(), ["renewLease"]);
+    }
+
+    #[test]
+    fn test_quota_headroom_cached() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getQuotaUsage", |_: GetQuotaUsageRequestProto| {
+            let mut resp = GetQuotaUsageResponseProto::default();
+            let usage = resp.mut_usage();
+            usage.set_fileAndDirectoryCount(3);
+            usage.set_quota(10);
+            usage.set_spaceConsumed(100);
+            usage.set_spaceQuota(u64::MAX);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let clock = ManualClock::new();
+        hdfs.set_clock(Arc::new(clock.clone()));
+
+        let path = Path::new("/dir").unwrap();
+        let headroom = hdfs.quota_headroom(&path).unwrap();
+        assert_eq!(
+            headroom,
+            QuotaHeadroom {
+                names: Some(7),
+                space: None
+            }
+        );
+        assert!(headroom.allows(7, u64::MAX));
+        assert!(!headroom.allows(8, 0));
+        hdfs.quota_headroom(&path).unwrap();
+        clock.advance(DEFAULT_QUOTA_CACHE_TTL);
+        hdfs.quota_headroom(&path).unwrap();
+
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getQuotaUsage", "getQuotaUsage"]
+        );
+    }
+
+    #[test]
+    fn test_quota_cache_eviction() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getQuotaUsage", |_: GetQuotaUsageRequestProto| {
+            let mut resp = GetQuotaUsageResponseProto::default();
+            let usage = resp.mut_usage();
+            usage.set_fileAndDirectoryCount(0);
+            usage.set_quota(u64::MAX);
+            usage.set_spaceConsumed(0);
+            usage.set_spaceQuota(u64::MAX);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let clock = ManualClock::new();
+        hdfs.set_clock(Arc::new(clock.clone()));
+
+        for i in 0..MAX_QUOTA_CACHE_ENTRIES + 10 {
+            let name = format!("/dir{}", i);
+            hdfs.quota_headroom(&Path::new(&name).unwrap()).unwrap();
+            assert!(hdfs.quota_cache.len() <= MAX_QUOTA_CACHE_ENTRIES);
+        }
+        // The newest entries are kept.
+        assert!(hdfs
+            .quota_cache
+            .contains_key(&format!("/dir{}", MAX_QUOTA_CACHE_ENTRIES + 9)));
+
+        clock.advance(DEFAULT_QUOTA_CACHE_TTL);
+        hdfs.quota_headroom(&Path::new("/other").unwrap()).unwrap();
+        assert_eq!(hdfs.quota_cache.len(), 1);
+    }
+
+    #[test]
+    fn test_quota_usage_content_summary_fallback() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getContentSummary", |_: GetContentSummaryRequestProto| {
+            let mut resp = GetContentSummaryResponseProto::default();
+            let summary = resp.mut_summary();
+            summary.set_length(50);
+            summary.set_fileCount(2);
+            summary.set_directoryCount(1);
+            summary.set_quota(u64::MAX);
+            summary.set_spaceConsumed(100);
+            summary.set_spaceQuota(300);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        let usage = hdfs.get_quota_usage(&Path::new("/dir").unwrap()).unwrap();
+        assert_eq!(
+            usage,
+            QuotaUsage {
+                file_and_directory_count: 3,
+                quota: None,
+                space_consumed: 100,
+                space_quota: Some(300),
+                type_quotas: vec![],
+            }
+        );
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getQuotaUsage", "getContentSummary"]
+        );
+    }
+
+    #[test]
+    fn test_file_id_path() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut status = file_status("data", false, 0);
+            match req.get_src() {
+                "/user/test/data" | "/.reserved/.inodes/16386" => status.set_fileId(16386),
+                "/old" => {}
+                other => panic!("unexpected path {}", other),
+            }
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(status);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        let file_id = hdfs
+            .get_file_id(&Path::new("data").unwrap())
+            .unwrap()
+            .unwrap();
+        let by_id = hdfs.get_file_info(&Path::from_file_id(file_id)).unwrap();
+        assert_eq!(by_id.get_fileId(), 16386);
+        assert_eq!(hdfs.get_file_id(&Path::new("/old").unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_foreign_authority_rejected() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(file_status(req.get_src(), false, 0));
+            Ok(resp)
+        });
+        let service = service::ClientNamenodeService::new(conn);
+        let resolve = UriResolver::new("mycluster", "test", None, None).unwrap();
+        let mut hdfs = Hdfs::new(service, resolve);
+
+        hdfs.get_file_info(&Path::new("hdfs://MyCluster/a").unwrap())
+            .unwrap();
+        hdfs.get_file_info(&Path::new("hdfs:///a").unwrap())
+            .unwrap();
+        hdfs.get_file_info(&Path::new("HDFS://mycluster/a").unwrap())
+            .unwrap();
+        match hdfs.get_file_info(&Path::new("hdfs://other/a").unwrap()) {
+            Err(FsError::WrongFs { expected, .. }) => assert_eq!(expected, "hdfs://mycluster"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        for foreign in &["file:/tmp/a", "s3a://bucket/a", "webhdfs://mycluster/a"] {
+            match hdfs.get_file_info(&Path::new(foreign).unwrap()) {
+                Err(FsError::WrongFs { path, .. }) => assert_eq!(&path, foreign),
+                other => panic!("unexpected {:?}", other.map(|_| ())),
+            }
+        }
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getFileInfo", "getFileInfo", "getFileInfo"]
+        );
+    }
+
+    #[test]
+    fn test_get_storage_policies() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getStoragePolicies", |_: GetStoragePoliciesRequestProto| {
+            let mut resp = GetStoragePoliciesResponseProto::default();
+            for (id, name) in [(7, "HOT"), (2, "COLD")] {
+                let mut policy = BlockStoragePolicyProto::default();
+                policy.set_policyId(id);
+                policy.set_name(name.to_owned());
+                policy.mut_creationPolicy();
+                resp.mut_policies().push(policy);
+            }
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        let policies = hdfs.get_storage_policies().unwrap();
+        let names: Vec<_> = policies
+            .iter()
+            .map(|policy| (policy.get_policyId(), policy.get_name()))
+            .collect();
+        assert_eq!(names, [(7, "HOT"), (2, "COLD")]);
+    }
+
+    #[test]
+    fn test_ping() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getServerDefaults", |_: GetServerDefaultsRequestProto| {
+            let mut resp = GetServerDefaultsResponseProto::default();
+            let defaults = resp.mut_serverDefaults();
+            defaults.set_blockSize(128 << 20);
+            defaults.set_bytesPerChecksum(512);
+            defaults.set_writePacketSize(64 << 10);
+            defaults.set_replication(3);
+            defaults.set_fileBufferSize(4096);
+            Ok(resp)
+        });
+        let service = service::ClientNamenodeService::new(conn);
+        let resolve = UriResolver::new("test", "test", None, None).unwrap();
+        let mut hdfs = Hdfs::new_checked(service, resolve).unwrap();
+        hdfs.ping().unwrap();
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getServerDefaults", "getServerDefaults"]
+        );
+
+        let mut conn = MockConnection::new("test");
+        conn.on("getServerDefaults", |_: GetServerDefaultsRequestProto| {
+            Err::<GetServerDefaultsResponseProto, _>(rpc::RpcError::Io(
+                std::io::ErrorKind::ConnectionRefused.into(),
+            ))
+        });
+        let service = service::ClientNamenodeService::new(conn);
+        let resolve = UriResolver::new("test", "test", None, None).unwrap();
+        match Hdfs::new_checked(service, resolve) {
+            Err(e) => assert_eq!(e.code(), ErrorCode::Io),
+            Ok(_) => panic!("ping succeeded"),
+        }
+    }
+
+    /// A client whose "/dir" has the flags, one snapshot and the
+    /// encryption zone "/dir".
+    fn delete_hdfs(flags: HdfsFileStatusProto_Flags) -> Hdfs<MockConnection> {
+        use hdfesse_proto::{
+            encryption::GetEZForPathResponseProto,
+            hdfs::{CipherSuiteProto, CryptoProtocolVersionProto, SnapshotStatusProto},
+            ClientNamenodeProtocol::{
+                GetSnapshotListingRequestProto, GetSnapshotListingResponseProto,
+            },
+        };
+
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileLinkInfo", move |_: GetFileLinkInfoRequestProto| {
+            let mut status = file_status("", true, 0);
+            status.set_flags(flags as u32);
+            let mut resp = GetFileLinkInfoResponseProto::default();
+            resp.set_fs(status);
+            Ok(resp)
+        })
+        .on("getSnapshotListing", |_: GetSnapshotListingRequestProto| {
+            let mut snapshot = SnapshotStatusProto::default();
+            snapshot.set_dirStatus(file_status("s0", true, 0));
+            snapshot.set_snapshotID(0);
+            snapshot.set_parent_fullpath(b"/dir".to_vec());
+            snapshot.set_isDeleted(false);
+            let mut resp = GetSnapshotListingResponseProto::default();
+            resp.mut_snapshotList().mut_snapshotListing().push(snapshot);
+            Ok(resp)
+        })
+        .on("getEZForPath", |_: GetEZForPathRequestProto| {
+            let mut resp = GetEZForPathResponseProto::default();
+            let zone = resp.mut_zone();
+            zone.set_id(1);
+            zone.set_path("/dir".to_owned());
+            zone.set_suite(CipherSuiteProto::AES_CTR_NOPADDING);
+            zone.set_cryptoProtocolVersion(CryptoProtocolVersionProto::ENCRYPTION_ZONES);
+            zone.set_keyName("key".to_owned());
+            Ok(resp)
+        })
+        .on("delete", |_: DeleteRequestProto| {
+            let mut resp = DeleteResponseProto::default();
+            resp.set_result(true);
+            Ok(resp)
+        });
+        mock_hdfs(conn)
+    }
+
+    #[test]
+    fn test_delete_guards() {
+        let dir = Path::new("/dir").unwrap();
+
+        // The namenode checks the snapshots itself.
+        let mut hdfs = delete_hdfs(HdfsFileStatusProto_Flags::SNAPSHOT_ENABLED);
+        assert!(hdfs.delete(&dir, true).unwrap());
+        assert_eq!(hdfs.service.into_inner().calls(), ["delete"]);
+
+        let mut hdfs = delete_hdfs(HdfsFileStatusProto_Flags::HAS_CRYPT);
+        hdfs.set_protect_encryption_zone_roots(true);
+        let err = hdfs.delete(&dir, true).unwrap_err();
+        assert!(
+            matches!(err.source, FsError::EncryptionZoneRoot(_)),
+            "{:?}",
+            err
+        );
+        assert!(hdfs.delete(&Path::new("/dir/sub").unwrap(), true).unwrap());
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getEZForPath", "getEZForPath", "delete"]
+        );
+    }
+
+    #[test]
+    fn test_delete_entry() {
+        let file = file_status("f", false, 1);
+        let mut dir = file_status("d", true, 0);
+
+        // The listed status is not looked up again.
+        let mut hdfs = delete_hdfs(HdfsFileStatusProto_Flags::HAS_ACL);
+        assert!(hdfs
+            .delete_entry(&Path::new("/dir/f").unwrap(), &file)
+            .unwrap());
+        assert!(hdfs
+            .delete_entry(&Path::new("/dir/d").unwrap(), &dir)
+            .unwrap());
+        assert_eq!(hdfs.service.into_inner().calls(), ["delete", "delete"]);
+
+        dir.set_flags(HdfsFileStatusProto_Flags::SNAPSHOT_ENABLED as u32);
+        let mut hdfs = delete_hdfs(HdfsFileStatusProto_Flags::HAS_ACL);
+        let err = hdfs
+            .check_recursive_delete(&Path::new("/dir/d").unwrap(), &dir)
+            .unwrap_err();
+        assert!(matches!(err.source, FsError::HasSnapshots(_)), "{:?}", err);
+
+        // Namenodes before Hadoop 3.3 cannot list the snapshots; the
+        // delete is left to their own checks.
+        let mut conn = MockConnection::new("test");
+        conn.on("delete", |_: DeleteRequestProto| {
+            let mut resp = DeleteResponseProto::default();
+            resp.set_result(true);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let path = Path::new("/dir/d").unwrap();
+        hdfs.check_recursive_delete(&path, &dir).unwrap();
+        assert!(hdfs.delete_entry(&path, &dir).unwrap());
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getSnapshotListing", "delete"]
+        );
+    }
+
+    #[test]
+    fn test_delete_symlink() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileLinkInfo", |_: GetFileLinkInfoRequestProto| {
+            let mut link = file_status("", false, 0);
+            link.set_fileType(HdfsFileStatusProto_FileType::IS_SYMLINK);
+            link.set_symlink(b"/dir".to_vec());
+            let mut resp = GetFileLinkInfoResponseProto::default();
+            resp.set_fs(link);
+            Ok(resp)
+        })
+        .on("delete", |req: DeleteRequestProto| {
+            assert!(!req.get_recursive());
+            let mut resp = DeleteResponseProto::default();
+            resp.set_result(true);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        // A link to a directory is removed without -r.
+        assert!(hdfs.delete(&Path::new("/link").unwrap(), false).unwrap());
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getFileLinkInfo", "delete"]
+        );
+    }
+
+    #[test]
+    fn test_delete_server_errors() {
+        let mut conn = MockConnection::new("test");
+        conn.on("delete", |req: DeleteRequestProto| {
+            Err::<GetFileInfoResponseProto, _>(if req.get_src() == "/snap" {
+                crate::testing::remote_error(
+                    "delete",
+                    SNAPSHOT_EXCEPTION,
+                    "The directory /snap/a cannot be deleted since /snap/a is snapshottable and already has snapshots",
+                )
+            } else {
+                crate::testing::remote_error(
+                    "delete",
+                    ACCESS_CONTROL_EXCEPTION,
+                    "Cannot delete/rename non-empty protected directory /protected",
+                )
+            })
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        // The offending subdirectory is named.
+        let err = hdfs.delete(&Path::new("/snap").unwrap(), true).unwrap_err();
+        assert!(
+            matches!(&err.source, FsError::HasSnapshots(path) if path == "/snap/a"),
+            "{:?}",
+            err
+        );
+        let err = hdfs
+            .delete(&Path::new("/protected").unwrap(), true)
+            .unwrap_err();
+        assert!(
+            matches!(&err.source, FsError::ProtectedDirectory(path) if path == "/protected"),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.code(), ErrorCode::AccessDenied);
+    }
+
+    #[test]
+    fn test_with_caller_context() {
+        let mut hdfs = mock_hdfs(MockConnection::new("test"));
+        let timeout = Duration::from_secs(5);
+        hdfs.set_call_options(rpc::CallOptions::default().with_timeout(timeout));
+        hdfs.set_caller_context(Some(rpc::CallerContext::new("service")));
+
+        let context = |hdfs: &Hdfs<MockConnection>| {
+            hdfs.service
+                .call_options()
+                .caller_context
+                .as_ref()
+                .map(|c| c.context.clone())
+        };
+        let inner =
+            hdfs.with_caller_context(rpc::CallerContext::new("job_1"), |hdfs| context(hdfs));
+        assert_eq!(inner.as_deref(), Some("job_1"));
+        assert_eq!(context(&hdfs).as_deref(), Some("service"));
+        assert_eq!(hdfs.service.call_options().timeout, Some(timeout));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(file_status(req.get_src(), false, 0));
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let clock = ManualClock::new();
+        hdfs.set_rate_limiter(Some(RateLimiter::with_clock(5, Arc::new(clock.clone()))));
+
+        let start = clock.now();
+        for _ in 0..3 {
+            hdfs.get_file_info(&Path::new("/a").unwrap()).unwrap();
+        }
+        assert_eq!(clock.now() - start, Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_get_status() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFsStats", |_: GetFsStatusRequestProto| {
+            // Distinct values catch swapped fields.
+            let mut resp = GetFsStatsResponseProto::default();
+            resp.set_capacity(1000);
+            resp.set_used(300);
+            resp.set_remaining(600);
+            resp.set_under_replicated(1);
+            resp.set_corrupt_blocks(2);
+            resp.set_missing_blocks(3);
+            resp.set_missing_repl_one_blocks(4);
+            resp.set_blocks_in_future(5);
+            resp.set_pending_deletion_blocks(6);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        assert_eq!(
+            hdfs.get_status().unwrap(),
+            FsStatus {
+                capacity: 1000,
+                used: 300,
+                remaining: 600,
+                under_replicated: 1,
+                corrupt_blocks: 2,
+                missing_blocks: 3,
+                missing_repl_one_blocks: 4,
+                blocks_in_future: 5,
+                pending_deletion_blocks: 6,
+            }
+        );
+    }
+
+    #[cfg(feature = "raw-rpc")]
+    #[test]
+    fn test_raw_call() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getServerDefaults", |_: GetServerDefaultsRequestProto| {
+            let mut resp = GetServerDefaultsResponseProto::default();
+            let defaults = resp.mut_serverDefaults();
+            defaults.set_blockSize(128 << 20);
+            defaults.set_bytesPerChecksum(512);
+            defaults.set_writePacketSize(64 << 10);
+            defaults.set_replication(3);
+            defaults.set_fileBufferSize(4096);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        let resp: GetServerDefaultsResponseProto = hdfs
+            .call(
+                "getServerDefaults",
+                &GetServerDefaultsRequestProto::default(),
+            )
+            .unwrap();
+        assert_eq!(resp.get_serverDefaults().get_blockSize(), 128 << 20);
+        assert!(hdfs
+            .call::<_, GetServerDefaultsResponseProto>(
+                "noSuchMethod",
+                &GetServerDefaultsRequestProto::default()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_list_snapshots() {
+        use hdfesse_proto::{
+            hdfs::SnapshotStatusProto, ClientNamenodeProtocol::GetSnapshotListingResponseProto,
+        };
+
+        let mut conn = MockConnection::new("test");
+        conn.on(
+            "getSnapshotListing",
+            |req: GetSnapshotListingRequestProto| {
+                let mut resp = GetSnapshotListingResponseProto::default();
+                for (id, name) in ["s0", "s1"].iter().enumerate() {
+                    let mut snapshot = SnapshotStatusProto::default();
+                    snapshot.set_dirStatus(file_status(name, true, 0));
+                    snapshot.set_snapshotID(id as u32);
+                    snapshot.set_isDeleted(false);
+                    snapshot.set_parent_fullpath(req.get_snapshotRoot().as_bytes().to_vec());
+                    resp.mut_snapshotList().mut_snapshotListing().push(snapshot);
+                }
+                Ok(resp)
+            },
+        );
+        let mut hdfs = mock_hdfs(conn);
+
+        let paths: Vec<_> = hdfs
+            .list_snapshots(&Path::new("/data").unwrap())
+            .unwrap()
+            .map(|snapshot| snapshot.full_path())
+            .collect();
+        assert_eq!(paths, ["/data/.snapshot/s0", "/data/.snapshot/s1"]);
+    }
+
+    #[test]
+    fn test_safe_mode_policy() {
+        use crate::testing::remote_error;
+        use hdfesse_proto::ClientNamenodeProtocol::{
+            MkdirsRequestProto, MkdirsResponseProto, SetSafeModeResponseProto,
+        };
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let safe_mode_polls = Arc::new(AtomicUsize::new(0));
+        let polls = safe_mode_polls.clone();
+        let mut conn = MockConnection::new("test");
+        conn.on("setSafeMode", move |_: SetSafeModeRequestProto| {
+            let mut resp = SetSafeModeResponseProto::default();
+            // The namenode leaves safe mode on the third poll.
+            resp.set_result(polls.fetch_add(1, Ordering::SeqCst) < 2);
+            Ok(resp)
+        })
+        .on("mkdirs", {
+            let polls = safe_mode_polls.clone();
+            move |_: MkdirsRequestProto| {
+                if polls.load(Ordering::SeqCst) < 3 {
+                    return Err(remote_error(
+                        "mkdirs",
+                        SAFE_MODE_EXCEPTION,
+                        "Cannot create directory /a. Name node is in safe mode.",
+                    ));
+                }
+                let mut resp = MkdirsResponseProto::default();
+                resp.set_result(true);
+                Ok(resp)
+            }
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let clock = ManualClock::new();
+        hdfs.set_clock(Arc::new(clock.clone()));
+        let path = Path::new("/a").unwrap();
+
+        let err = hdfs.mkdirs(&path, true).unwrap_err();
+        assert!(matches!(err.source, FsError::SafeMode(_)));
+        assert_eq!(err.code(), ErrorCode::SafeMode);
+
+        hdfs.set_safe_mode_policy(SafeModePolicy::Wait {
+            timeout: Duration::from_secs(1),
+            poll: Duration::from_secs(1),
+        });
+        let err = hdfs.mkdirs(&path, true).unwrap_err();
+        assert!(matches!(err.source, FsError::SafeMode(_)));
+
+        hdfs.set_safe_mode_policy(SafeModePolicy::Wait {
+            timeout: Duration::from_secs(60),
+            poll: Duration::from_secs(1),
+        });
+        assert!(hdfs.mkdirs(&path, true).unwrap());
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            [
+                "mkdirs",
+                "mkdirs",
+                "setSafeMode",
+                "setSafeMode",
+                "mkdirs",
+                "setSafeMode",
+                "mkdirs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exists() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            if req.get_src() == "/file" {
+                resp.set_fs(file_status(req.get_src(), false, 0));
+            }
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        assert!(hdfs.exists(&Path::new("/file").unwrap()).unwrap());
+        assert!(!hdfs.exists(&Path::new("/missing").unwrap()).unwrap());
+        assert!(hdfs
+            .exists(&Path::new("hdfs://other/file").unwrap())
+            .is_err());
+    }
+
+    /// Serves a file of 100 bytes in a single block.
+    fn block_locations_conn() -> MockConnection {
+        let mut conn = MockConnection::new("test");
+        conn.on("getBlockLocations", |req: GetBlockLocationsRequestProto| {
+            let mut resp = GetBlockLocationsResponseProto::default();
+            let locations = resp.mut_locations();
+            locations.set_fileLength(100);
+            locations.set_underConstruction(false);
+            locations.set_isLastBlockComplete(true);
+            if req.get_offset() == 0 {
+                let block = locations.mut_blocks().push_default();
+                block.set_offset(0);
+                block.set_corrupt(false);
+                let b = block.mut_b();
+                b.set_poolId("pool".to_owned());
+                b.set_blockId(1);
+                b.set_generationStamp(1);
+                b.set_numBytes(100);
+                block.mut_blockToken().set_identifier(vec![]);
+                block.mut_blockToken().set_password(vec![]);
+                block.mut_blockToken().set_kind(String::new());
+                block.mut_blockToken().set_service(String::new());
+            }
+            Ok(resp)
+        });
+        conn
+    }
+
+    #[test]
+    fn test_block_locations_cached() {
+        let mut hdfs = mock_hdfs(block_locations_conn());
+
+        let path = Path::new("/file").unwrap();
+        let mut status = file_status("file", false, 100);
+        status.set_fileId(42);
+        let blocks = hdfs
+            .get_file_block_locations_cached(&path, &status, 0, 50)
+            .unwrap();
+        assert_eq!(blocks.len(), 1);
+        let blocks = hdfs
+            .get_file_block_locations_cached(&path, &status, 50, 1000)
+            .unwrap();
+        assert_eq!(blocks.len(), 1);
+        // Appended.
+        status.set_length(200);
+        hdfs.get_file_block_locations_cached(&path, &status, 0, 50)
+            .unwrap();
+
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getBlockLocations", "getBlockLocations"]
+        );
+    }
+
+    #[test]
+    fn test_block_locations_not_cached() {
+        let path = Path::new("/file").unwrap();
+        let mut status = file_status("file", false, 100);
+
+        // No file id.
+        let mut hdfs = mock_hdfs(block_locations_conn());
+        for _ in 0..2 {
+            let blocks = hdfs
+                .get_file_block_locations_cached(&path, &status, 0, 50)
+                .unwrap();
+            assert_eq!(blocks.len(), 1);
+        }
+        assert!(hdfs.block_cache_mut().is_empty());
+        assert_eq!(hdfs.stats().calls["getBlockLocations"], 2);
+
+        // The cache is disabled.
+        status.set_fileId(42);
+        let mut hdfs = mock_hdfs(block_locations_conn());
+        *hdfs.block_cache_mut() = BlockLocationCache::new(0);
+        for _ in 0..2 {
+            let blocks = hdfs
+                .get_file_block_locations_cached(&path, &status, 0, 50)
+                .unwrap();
+            assert_eq!(blocks.len(), 1);
+        }
+        assert_eq!(hdfs.stats().calls["getBlockLocations"], 2);
+    }
+
+    #[test]
+    fn test_set_time_round_trip() {
+        // (mtime, atime) stored by the "namenode".
+        let times = Arc::new(Mutex::new((0u64, 0u64)));
+        let mut conn = MockConnection::new("test");
+        let stored = times.clone();
+        conn.on("setTimes", move |req: SetTimesRequestProto| {
+            let mut stored = stored.lock().unwrap();
+            if req.get_mtime() != TIME_UNCHANGED {
+                stored.0 = req.get_mtime();
+            }
+            if req.get_atime() != TIME_UNCHANGED {
+                stored.1 = req.get_atime();
+            }
+            Ok(SetTimesResponseProto::default())
+        });
+        let stored = times;
+        conn.on("getFileInfo", move |req: GetFileInfoRequestProto| {
+            let (mtime, atime) = *stored.lock().unwrap();
+            let mut status = file_status(req.get_src(), false, 0);
+            status.set_modification_time(mtime);
+            status.set_access_time(atime);
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(status);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let path = Path::new("/file").unwrap();
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_600_000_000_123);
+        hdfs.set_times(&path, Some(time), None).unwrap();
+        let status = hdfs.get_file_info(&path).unwrap();
+        assert_eq!(status.get_modification_time(), 1_600_000_000_123);
+        assert_eq!(status.get_access_time(), 0);
+
+        let time = UNIX_EPOCH + Duration::from_nanos(1_600_000_000_456_789_000);
+        hdfs.touch(&path, &TouchOptions::new().with_time(time).access_only())
+            .unwrap();
+        let status = hdfs.get_file_info(&path).unwrap();
+        assert_eq!(status.get_modification_time(), 1_600_000_000_123);
+        assert_eq!(status.get_access_time(), 1_600_000_000_456);
+
+        hdfs.touch(&path, &TouchOptions::new().with_time(time))
+            .unwrap();
+        let status = hdfs.get_file_info(&path).unwrap();
+        assert_eq!(status.get_modification_time(), 1_600_000_000_456);
+
+        // Before the epoch, like Java's negative times.
+        let time = UNIX_EPOCH - Duration::from_millis(86_400_000);
+        hdfs.touch(&path, &TouchOptions::new().with_time(time))
+            .unwrap();
+        let status = hdfs.get_file_info(&path).unwrap();
+        assert_eq!(status.get_modification_time(), -86_400_000i64 as u64);
+        assert_eq!(status.get_access_time(), -86_400_000i64 as u64);
+
+        // The deprecated method keeps taking seconds.
+        #[allow(deprecated)]
+        hdfs.set_time(&path, None, Some(1_500_000_000)).unwrap();
+        let status = hdfs.get_file_info(&path).unwrap();
+        assert_eq!(status.get_access_time(), 1_500_000_000_000);
+    }
+
+    #[test]
+    fn test_java_millis() {
+        assert_eq!(java_millis(UNIX_EPOCH + Duration::from_millis(5)), 5);
+        assert_eq!(
+            java_millis(UNIX_EPOCH - Duration::from_millis(5)),
+            -5i64 as u64
+        );
+    }
+
+    #[test]
+    fn test_touch_create() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |_: GetFileInfoRequestProto| {
+            Ok(GetFileInfoResponseProto::default())
+        })
+        .on("getServerDefaults", |_: GetServerDefaultsRequestProto| {
+            let mut resp = GetServerDefaultsResponseProto::default();
+            let defaults = resp.mut_serverDefaults();
+            defaults.set_blockSize(128 << 20);
+            defaults.set_bytesPerChecksum(512);
+            defaults.set_writePacketSize(64 << 10);
+            defaults.set_replication(3);
+            defaults.set_fileBufferSize(4096);
+            Ok(resp)
+        })
+        .on("create", |req: CreateRequestProto| {
+            assert_eq!(req.get_src(), "/new");
+            assert_eq!(req.get_createFlag(), CreateFlagProto::CREATE as u32);
+            assert!(!req.get_createParent());
+            assert_eq!(req.get_replication(), 3);
+            let mut status = file_status(req.get_src(), false, 0);
+            status.set_fileId(16390);
+            let mut resp = CreateResponseProto::default();
+            resp.set_fs(status);
+            Ok(resp)
+        })
+        .on("complete", |req: CompleteRequestProto| {
+            assert_eq!(req.get_fileId(), 16390);
+            let mut resp = CompleteResponseProto::default();
+            resp.set_result(true);
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+        let path = Path::new("/new").unwrap();
+
+        hdfs.touch(&path, &TouchOptions::new().with_create(false))
+            .unwrap();
+        hdfs.touch(&path, &TouchOptions::new()).unwrap();
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            [
+                "getFileInfo",
+                "getFileInfo",
+                "getServerDefaults",
+                "create",
+                "complete"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preserve_attributes() {
+        use hdfesse_proto::{
+            acl::{
+                AclEntryProto_AclEntryScopeProto, AclEntryProto_AclEntryTypeProto,
+                AclEntryProto_FsActionProto, GetAclStatusResponseProto, SetAclResponseProto,
+            },
+            xattr::{GetXAttrsResponseProto, SetXAttrResponseProto},
+            ClientNamenodeProtocol::{
+                SetOwnerRequestProto, SetOwnerResponseProto, SetPermissionResponseProto,
+            },
+        };
+
+        fn xattr(namespace: XAttrProto_XAttrNamespaceProto, name: &str) -> XAttrProto {
+            let mut xattr = XAttrProto::default();
+            xattr.set_namespace(namespace);
+            xattr.set_name(name.to_owned());
+            xattr.set_value(b"value".to_vec());
+            xattr
+        }
+
+        let set_xattrs = Arc::new(Mutex::new(vec![]));
+        let handler_xattrs = set_xattrs.clone();
+        let mut conn = MockConnection::new("test");
+        conn.on("setPermission", |req: SetPermissionRequestProto| {
+            assert_eq!(req.get_permission().get_perm(), 0o640);
+            Ok(SetPermissionResponseProto::default())
+        })
+        .on("getAclStatus", |req: GetAclStatusRequestProto| {
+            assert_eq!(req.get_src(), "/src");
+            let mut entry = AclEntryProto::default();
+            entry.set_field_type(AclEntryProto_AclEntryTypeProto::USER);
+            entry.set_scope(AclEntryProto_AclEntryScopeProto::ACCESS);
+            entry.set_permissions(AclEntryProto_FsActionProto::READ);
+            entry.set_name("bob".to_owned());
+            let mut resp = GetAclStatusResponseProto::default();
+            let result = resp.mut_result();
+            result.set_owner("alice".to_owned());
+            result.set_group("staff".to_owned());
+            result.set_sticky(false);
+            result.mut_entries().push(entry);
+            Ok(resp)
+        })
+        .on("setAcl", |req: SetAclRequestProto| {
+            assert_eq!(req.get_src(), "/dst");
+            assert_eq!(req.get_aclSpec()[0].get_name(), "bob");
+            Ok(SetAclResponseProto::default())
+        })
+        .on("setOwner", |req: SetOwnerRequestProto| {
+            assert_eq!(req.get_username(), "alice");
+            assert!(!req.has_groupname());
+            Ok(SetOwnerResponseProto::default())
+        })
+        .on("getXAttrs", |_: GetXAttrsRequestProto| {
+            // The namenode returns raw attributes for raw paths only;
+            // a normal copy must skip them anyway.
+            let mut resp = GetXAttrsResponseProto::default();
+            let xattrs = resp.mut_xAttrs();
+            xattrs.push(xattr(XAttrProto_XAttrNamespaceProto::USER, "tag"));
+            xattrs.push(xattr(XAttrProto_XAttrNamespaceProto::RAW, "hdfs.crypto"));
+            Ok(resp)
+        })
+        .on("setXAttr", move |req: SetXAttrRequestProto| {
+            handler_xattrs.lock().unwrap().push(format!(
+                "{}:{}",
+                req.get_src(),
+                req.get_xAttr().get_name()
+            ));
+            Ok(SetXAttrResponseProto::default())
+        })
+        .on("setTimes", |req: SetTimesRequestProto| {
+            assert_eq!((req.get_mtime(), req.get_atime()), (2000, 1000));
+            Ok(SetTimesResponseProto::default())
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        let mut status = file_status("", false, 0);
+        status.mut_permission().set_perm(0o640);
+        status.set_owner("alice".to_owned());
+        status.set_modification_time(2000);
+        status.set_access_time(1000);
+        status.set_flags(
+            HdfsFileStatusProto_Flags::HAS_ACL as u32 | HdfsFileStatusProto_Flags::HAS_CRYPT as u32,
+        );
+        let preserve = "uatx".parse().unwrap();
+        hdfs.preserve_attributes(
+            &Path::new("/src").unwrap(),
+            &status,
+            &Path::new("/dst").unwrap(),
+            &preserve,
+        )
+        .unwrap();
+
+        assert_eq!(*set_xattrs.lock().unwrap(), ["/dst:tag"]);
+
+        // A copy of raw data keeps the encryption info.
+        hdfs.preserve_attributes(
+            &Path::new("/.reserved/raw/src").unwrap(),
+            &status,
+            &Path::new("/.reserved/raw/dst").unwrap(),
+            &"x".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            set_xattrs.lock().unwrap()[1..],
+            ["/.reserved/raw/dst:tag", "/.reserved/raw/dst:hdfs.crypto"]
+        );
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            [
+                "setPermission",
+                "getAclStatus",
+                "setAcl",
+                "setOwner",
+                "getXAttrs",
+                "setXAttr",
+                "setTimes",
+                "getXAttrs",
+                "setXAttr",
+                "setXAttr"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk() {
+        use crate::filter::{AcceptAll, AllOf, GlobFilter, HiddenFilter};
+
+        const TREE: &[(&str, bool, u64)] = &[
+            ("/out/_temporary/part-0", false, 1),
+            ("/out/a/part-1", false, 1),
+            ("/out/a/b/part-2", false, 1),
+            ("/out/part-0", false, 1),
+            ("/out/_SUCCESS", false, 0),
+        ];
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(TREE);
+        let mut hdfs = mock_hdfs(conn);
+        let root = Path::new("/out").unwrap();
+        let mut walk = |filter: &dyn PathFilter| {
+            hdfs.walk(&root, filter)
+                .unwrap()
+                .map(|entry| entry.unwrap().0.to_path_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            walk(&AcceptAll),
+            [
+                "/out/_SUCCESS",
+                "/out/_temporary",
+                "/out/a",
+                "/out/part-0",
+                "/out/_temporary/part-0",
+                "/out/a/b",
+                "/out/a/part-1",
+                "/out/a/b/part-2"
+            ]
+        );
+        let parts = AllOf(vec![
+            Box::new(HiddenFilter),
+            Box::new(GlobFilter::new("part-*").unwrap()),
+        ]);
+        assert_eq!(
+            walk(&parts),
+            ["/out/part-0", "/out/a/part-1", "/out/a/b/part-2"]
+        );
+
+        // Subdirectories in the reverse order, one level down.
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(TREE);
+        let mut hdfs = mock_hdfs(conn);
+        let mut walk = hdfs
+            .walk(&root, &AcceptAll)
+            .unwrap()
+            .max_depth(2)
+            .sort_subdirs_by(|a, b| b.get_path().cmp(a.get_path()));
+        let mut found = vec![];
+        while let Some(entry) = walk.next() {
+            let (_, status) = entry.unwrap();
+            found.push((
+                walk.dir().to_path_string(),
+                walk.depth(),
+                status.name().into_owned(),
+            ));
+        }
+        let found = found
+            .iter()
+            .map(|(dir, depth, name)| (dir.as_str(), *depth, name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("/out", 1, "_SUCCESS"),
+                ("/out", 1, "_temporary"),
+                ("/out", 1, "a"),
+                ("/out", 1, "part-0"),
+                ("/out/a", 2, "b"),
+                ("/out/a", 2, "part-1"),
+                ("/out/_temporary", 2, "part-0"),
+            ]
+        );
+        // The root is looked up once, and each directory is only listed.
+        drop(walk);
+        let conn = hdfs.service.into_inner();
+        let calls = conn.calls();
+        assert_eq!(calls.iter().filter(|m| *m == "getFileInfo").count(), 1);
+        assert_eq!(calls.iter().filter(|m| *m == "getListing").count(), 3);
+
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(&[("/out/part-0", false, 1)]);
+        let mut hdfs = mock_hdfs(conn);
+        let err = hdfs
+            .walk(&Path::new("/out/part-0").unwrap(), &AcceptAll)
+            .err()
+            .unwrap();
+        assert!(matches!(err.source, FsError::NotDir(_)));
+    }
+
+    #[test]
+    fn test_glob_status() {
+        let mut conn = MockConnection::new("test");
+        conn.serve_tree(&[
+            ("/data/a1/part", false, 1),
+            ("/data/a2", false, 2),
+            ("/data/b1/part", false, 3),
+            ("/user/test/x", true, 0),
+        ]);
+        let mut hdfs = mock_hdfs(conn);
+        let mut glob = |pattern: &str| {
+            hdfs.glob_status(pattern)
+                .map(|found| found.into_iter().map(|(path, _)| path).collect::<Vec<_>>())
+        };
+
+        assert_eq!(glob("/data/a*").unwrap(), ["/data/a1", "/data/a2"]);
+        assert_eq!(
+            glob("/data/*/part").unwrap(),
+            ["/data/a1/part", "/data/b1/part"]
+        );
+        assert_eq!(glob("/data/{b1,a1}").unwrap(), ["/data/a1", "/data/b1"]);
+        assert_eq!(
+            glob("hdfs://test/data/?2").unwrap(),
+            ["hdfs://test/data/a2"]
+        );
+        assert_eq!(glob("[wx]").unwrap(), ["x"]);
+        assert_eq!(glob("/data").unwrap(), ["/data"]);
+        assert!(glob("/data/c*").unwrap().is_empty());
+        assert!(matches!(
+            glob("/data/c1"),
+            Err(HdfsError {
+                source: FsError::NotFound(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            glob("/data/[a"),
+            Err(HdfsError {
+                source: FsError::Glob(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            if req.get_src() == "/file" {
+                resp.set_fs(file_status(req.get_src(), false, 0));
+            }
+            Ok(resp)
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        hdfs.get_file_info(&Path::new("/file").unwrap()).unwrap();
+        hdfs.get_file_info(&Path::new("/missing").unwrap())
+            .unwrap_err();
+        // No handler.
+        hdfs.set_times(&Path::new("/file").unwrap(), Some(UNIX_EPOCH), None)
+            .unwrap_err();
+        let stats = hdfs.stats();
+        assert_eq!(stats.calls["getFileInfo"], 2);
+        assert_eq!(stats.calls["setTimes"], 1);
+        assert_eq!(stats.total_errors(), 1);
+        assert_eq!(stats.failovers, 0);
+
+        hdfs.reset_stats();
+        assert_eq!(hdfs.stats(), SessionStats::default());
+    }
+
+    #[test]
+    fn test_mkdirs_name_quota() {
+        let mut conn = MockConnection::new("test");
+        conn.on("mkdirs", |_: MkdirsRequestProto| {
+            Err::<MkdirsResponseProto, _>(rpc::RpcError::ErrorResponse {
+                status: rpc::RpcStatus::ERROR,
+                error_msg: "The NameSpace quota (directories and files) of directory /user/a \
+                            is exceeded: quota=2 file count=3"
+                    .to_owned(),
+                error_detail: rpc::RpcErrorCode::ERROR_APPLICATION,
+                exception: NS_QUOTA_EXCEEDED_EXCEPTION.to_owned(),
+                method: "mkdirs".to_owned(),
+            })
+        });
+        let mut hdfs = mock_hdfs(conn);
+
+        let err = hdfs
+            .mkdirs(&Path::new("/user/a/b/c").unwrap(), true)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::QuotaExceeded);
+        match err.source {
+            FsError::NameQuotaExceeded { path, quota } => {
+                assert_eq!(path, "/user/a/b/c");
+                assert_eq!(
+                    quota,
+                    NameQuotaExceeded {
+                        directory: Some("/user/a".to_owned()),
+                        quota: 2,
+                        count: 3,
+                    }
+                );
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_parse_name_quota() {
+        // The message may come with the class name and the stack trace.
+        let quota = NameQuotaExceeded::parse(
+            "org.apache.hadoop.hdfs.protocol.NSQuotaExceededException: The NameSpace \
+             quota (directories and files) is exceeded: quota=10 file count=11\n\tat ...",
+        )
+        .unwrap();
+        assert_eq!(quota.directory, None);
+        assert_eq!((quota.quota, quota.count), (10, 11));
+        assert_eq!(
+            quota.to_string(),
+            "Name quota is exceeded: quota=10, file count=11"
+        );
+        assert_eq!(NameQuotaExceeded::parse("Disk quota exceeded"), None);
+    }
+
+    #[test]
+    fn test_rename_verified_after_failover() {
+        let make_hdfs = |src_exists: bool| {
+            let mut conn = MockConnection::new("test");
+            let mut renames = 0;
+            conn.fail_over_on("rename")
+                .on("rename", move |_: RenameRequestProto| {
+                    renames += 1;
+                    if renames == 1 {
+                        Err(rpc::RpcError::Io(
+                            std::io::ErrorKind::ConnectionReset.into(),
+                        ))
+                    } else {
+                        let mut resp = RenameResponseProto::default();
+                        resp.set_result(true);
+                        Ok(resp)
+                    }
+                })
+                .on(
+                    "getFileLinkInfo",
+                    move |req: GetFileLinkInfoRequestProto| {
+                        let mut resp = GetFileLinkInfoResponseProto::default();
+                        if (req.get_src() == "/src") == src_exists {
+                            resp.set_fs(file_status(req.get_src(), false, 0));
+                        }
+                        Ok(resp)
+                    },
+                );
+            mock_hdfs(conn)
+        };
+        let (src, dst) = (Path::new("/src").unwrap(), Path::new("/dst").unwrap());
+
+        let mut hdfs = make_hdfs(false);
+        hdfs.rename(&src, &dst).unwrap_err();
+
+        // Applied by the old active namenode.
+        let mut hdfs = make_hdfs(false);
+        hdfs.set_verify_after_failover(true);
+        hdfs.rename(&src, &dst).unwrap();
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["rename", "getFileLinkInfo", "getFileLinkInfo"]
+        );
+
+        // Not applied: retried.
+        let mut hdfs = make_hdfs(true);
+        hdfs.set_verify_after_failover(true);
+        hdfs.rename(&src, &dst).unwrap();
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["rename", "getFileLinkInfo", "getFileLinkInfo", "rename"]
+        );
+    }
+
+    #[test]
+    fn test_delete_verified_after_failover() {
+        let make_hdfs = || {
+            let exists = Arc::new(Mutex::new(true));
+            let mut conn = MockConnection::new("test");
+            let deleted = exists.clone();
+            conn.fail_over_on("delete")
+                .on("delete", move |_: DeleteRequestProto| {
+                    // The old active has deleted the file, and the
+                    // re-sent call finds nothing to delete.
+                    *deleted.lock().unwrap() = false;
+                    let mut resp = DeleteResponseProto::default();
+                    resp.set_result(false);
+                    Ok(resp)
+                })
+                .on(
+                    "getFileLinkInfo",
+                    move |req: GetFileLinkInfoRequestProto| {
+                        let mut resp = GetFileLinkInfoResponseProto::default();
+                        if *exists.lock().unwrap() {
+                            resp.set_fs(file_status(req.get_src(), false, 0));
+                        }
+                        Ok(resp)
+                    },
+                );
+            mock_hdfs(conn)
+        };
+        let path = Path::new("/file").unwrap();
+
+        let mut hdfs = make_hdfs();
+        assert!(!hdfs.delete(&path, false).unwrap());
+
+        let mut hdfs = make_hdfs();
+        hdfs.set_verify_after_failover(true);
+        assert!(hdfs.delete(&path, false).unwrap());
+        assert_eq!(
+            hdfs.service.into_inner().calls(),
+            ["getFileLinkInfo", "delete", "getFileLinkInfo"]
+        );
+    }
+}
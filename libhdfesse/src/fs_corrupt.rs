@@ -0,0 +1,117 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+use std::borrow::BorrowMut;
+
+use crate::{
+    fs::FsError,
+    paged::{FetchPage, Page, PagedIter},
+    path::Path,
+    rpc::RpcConnection,
+    service::ClientNamenodeService,
+};
+use hdfesse_proto::ClientNamenodeProtocol::ListCorruptFileBlocksRequestProto;
+
+/**
+Iterates over files with corrupt blocks, fetching them by chunks.
+Namenode returns a limited number of files for a call, and a cookie
+for the next call; an empty chunk means the end.  See Java's
+CorruptFileBlockIterator.
+*/
+pub type CorruptFileBlockIterator<R, SRef> = PagedIter<CorruptFileBlockPages<R, SRef>>;
+
+pub struct CorruptFileBlockPages<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> {
+    path_string: String,
+    cookie: Option<String>,
+
+    service: SRef,
+    _phantom: std::marker::PhantomData<R>,
+}
+
+impl<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> CorruptFileBlockPages<R, SRef> {
+    pub fn new(service: SRef, path: &Path<'_>) -> Self {
+        Self {
+            path_string: path.to_path_string(),
+            cookie: None,
+            service,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> FetchPage
+    for CorruptFileBlockPages<R, SRef>
+{
+    type Item = String;
+    type Error = FsError;
+
+    fn fetch_page(&mut self) -> Result<Page<String>, FsError> {
+        let mut args = ListCorruptFileBlocksRequestProto::default();
+        args.set_path(self.path_string.clone());
+        if let Some(cookie) = self.cookie.take() {
+            args.set_cookie(cookie);
+        }
+        let mut resp = self
+            .service
+            .borrow_mut()
+            .listCorruptFileBlocks(&args)
+            .map_err(FsError::Rpc)?;
+        let corrupt = resp.mut_corrupt();
+        self.cookie = Some(corrupt.take_cookie());
+        let files = corrupt.take_files().into_vec();
+        Ok(if files.is_empty() {
+            Page::last(files)
+        } else {
+            Page::more(files)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockConnection;
+    use hdfesse_proto::ClientNamenodeProtocol::ListCorruptFileBlocksResponseProto;
+
+    #[test]
+    fn test_cookies() {
+        let mut conn = MockConnection::new("test");
+        conn.on(
+            "listCorruptFileBlocks",
+            |req: ListCorruptFileBlocksRequestProto| {
+                let mut resp = ListCorruptFileBlocksResponseProto::default();
+                let corrupt = resp.mut_corrupt();
+                match req.get_cookie() {
+                    "" => {
+                        corrupt.mut_files().push("/a".to_owned());
+                        corrupt.set_cookie("1".to_owned());
+                    }
+                    "1" => {
+                        corrupt.mut_files().push("/b".to_owned());
+                        corrupt.set_cookie("2".to_owned());
+                    }
+                    _ => corrupt.set_cookie("2".to_owned()),
+                }
+                Ok(resp)
+            },
+        );
+        let service = ClientNamenodeService::new(conn);
+        let path = Path::new("/").unwrap();
+        let files: Vec<_> = PagedIter::new(CorruptFileBlockPages::new(service, &path))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(files, ["/a", "/b"]);
+    }
+}
@@ -13,27 +13,112 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use std::{
-    borrow::BorrowMut,
-    iter::{ExactSizeIterator, FusedIterator},
-};
+use std::borrow::BorrowMut;
 
+use crate::trace::warn;
 use crate::{
-    fs::FsError,
+    fs::{FsError, ListingInconsistency},
+    paged::{FetchPage, Page},
     path::Path,
-    rpc::{RpcConnection, RpcError},
+    rpc::RpcConnection,
     service::ClientNamenodeService,
 };
 use protobuf::RepeatedField;
 
 use hdfesse_proto::hdfs::HdfsFileStatusProto;
 
-pub struct LsGroupIterator<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> {
+/// Empty pages with remaining entries are requested again this many
+/// times before the listing is reported as inconsistent.
+const EMPTY_PAGE_RETRIES: usize = 3;
+
+/**
+ * Position in a directory listing: the pages are fetched one by one
+ * with any service, so that the cursor can be kept while the service
+ * is used for something else.
+ */
+pub(crate) struct ListingCursor {
     path_string: String,
     prev_name: Option<Vec<u8>>,
     len: Option<usize>,
     count: usize,
+}
+
+impl ListingCursor {
+    pub(crate) fn new(path: &Path<'_>) -> Self {
+        Self {
+            path_string: path.to_path_string(),
+            prev_name: Default::default(),
+            len: None,
+            count: 0,
+        }
+    }
+
+    /// Whether all the entries are fetched.
+    pub(crate) fn is_done(&self) -> bool {
+        self.len.map(|len| self.count >= len).unwrap_or(false)
+    }
+
+    fn inconsistent(&self, kind: ListingInconsistency) -> FsError {
+        FsError::InconsistentListing {
+            path: self.path_string.clone(),
+            kind,
+        }
+    }
+
+    pub(crate) fn next_group<R: RpcConnection>(
+        &mut self,
+        service: &mut ClientNamenodeService<R>,
+    ) -> Result<(usize, RepeatedField<HdfsFileStatusProto>), FsError> {
+        let mut retries = 0;
+        loop {
+            // The key is kept until a page is received: an empty page
+            // is requested again from the same key.
+            let list_from = self.prev_name.clone().unwrap_or_default();
+            let mut listing = service
+                .getListing(self.path_string.clone(), list_from, false)
+                .map_err(FsError::Rpc)?;
+            // Namenode returns no listing for a nonexistent directory.
+            if !listing.has_dirList() {
+                return Err(self.inconsistent(ListingInconsistency::DirectoryRemoved));
+            }
+            let partial_list = listing.mut_dirList().take_partialListing();
+            let remaining_len = listing.get_dirList().get_remainingEntries() as usize;
+
+            if partial_list.is_empty() && remaining_len != 0 {
+                // Continuing from the start would loop, and stopping
+                // would silently truncate the listing.
+                if retries == EMPTY_PAGE_RETRIES {
+                    return Err(self.inconsistent(ListingInconsistency::EmptyPage));
+                }
+                retries += 1;
+                warn!(
+                    path = %self.path_string,
+                    remaining = remaining_len,
+                    "empty listing page, retrying"
+                );
+                continue;
+            }
+
+            self.count += partial_list.len();
+            self.len = Some(self.count + remaining_len);
+
+            // Search further from the last value.  Entries removed
+            // concurrently are fine as a key: namenode continues
+            // from the name's position.
+            if let Some(entry) = partial_list.last() {
+                self.prev_name = Some(entry.get_path().to_vec());
+            }
+
+            // The remaining_len returns number of items after the last
+            // element of the partial_list.  We return here remaining
+            // items including the partial_list.
+            return Ok((remaining_len + partial_list.len(), partial_list));
+        }
+    }
+}
 
+pub struct LsGroupIterator<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> {
+    cursor: ListingCursor,
     service: SRef,
     _phantom: std::marker::PhantomData<R>,
 }
@@ -41,38 +126,14 @@ pub struct LsGroupIterator<R: RpcConnection, SRef: BorrowMut<ClientNamenodeServi
 impl<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> LsGroupIterator<R, SRef> {
     pub fn new(service: SRef, path: &Path<'_>) -> Self {
         Self {
-            path_string: path.to_path_string(),
-            prev_name: Default::default(),
-            len: None,
-            count: 0,
+            cursor: ListingCursor::new(path),
             service,
             _phantom: std::marker::PhantomData,
         }
     }
 
-    fn next_group(&mut self) -> Result<(usize, RepeatedField<HdfsFileStatusProto>), RpcError> {
-        let list_from = self.prev_name.take().unwrap_or_default();
-        let mut listing =
-            self.service
-                .borrow_mut()
-                .getListing(self.path_string.clone(), list_from, false)?;
-        let partial_list = listing.mut_dirList().take_partialListing();
-
-        self.count += partial_list.len();
-        let remaining_len = listing.get_dirList().get_remainingEntries() as usize;
-        self.len = Some(self.count + remaining_len);
-
-        // Search further from the last value
-        // It is very unlikely that partial_list is empty and
-        // prev_name is None while remainingEntries is not zero.
-        // Perhaps, it should be reported as a server's invalid
-        // data.
-        self.prev_name = partial_list.last().map(|entry| entry.get_path().to_vec());
-
-        // The remaining_len returns number of items after the last
-        // element of the partial_list.  We return here remaining
-        // items including the partial_list.
-        Ok((remaining_len + partial_list.len(), partial_list))
+    fn next_group(&mut self) -> Result<(usize, RepeatedField<HdfsFileStatusProto>), FsError> {
+        self.cursor.next_group(self.service.borrow_mut())
     }
 }
 
@@ -82,116 +143,93 @@ impl<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> Iterator
     type Item = Result<(usize, RepeatedField<HdfsFileStatusProto>), FsError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.len.map(|len| self.count >= len).unwrap_or(false) {
+        if self.cursor.is_done() {
             None
         } else {
-            Some(self.next_group().map_err(FsError::Rpc))
+            Some(self.next_group())
         }
     }
 }
 
-pub(crate) struct LsIterator<CI, I, E> {
-    gi: Option<CI>,
-    current: Result<std::vec::IntoIter<I>, E>,
-    expected: usize,
+impl<R: RpcConnection, SRef: BorrowMut<ClientNamenodeService<R>>> FetchPage
+    for LsGroupIterator<R, SRef>
+{
+    type Item = HdfsFileStatusProto;
+    type Error = FsError;
+
+    fn fetch_page(&mut self) -> Result<Page<HdfsFileStatusProto>, FsError> {
+        let (remaining, group) = self.next_group()?;
+        let group = group.into_vec();
+        let after = remaining - group.len();
+        Ok(Page::with_remaining(group, after))
+    }
+}
+
+/**
+ * Groups items of a fallible iterator into batches of at most `size`
+ * items.  The inner iterator is advanced only when the next batch is
+ * requested, so the caller controls the pace: with a listing inside,
+ * the memory is bounded by one namenode chunk (`dfs.ls.limit`, 1000
+ * entries by default) plus one batch, whatever the directory size.
+ *
+ * An error ends the current batch: the items read before it are
+ * returned as a batch first, and the error is returned by the next
+ * call.
+ */
+pub struct BatchIterator<I, T, E> {
+    inner: I,
+    size: usize,
+    error: Option<E>,
+    _phantom: std::marker::PhantomData<T>,
 }
 
-impl<CI, I, E, V> LsIterator<CI, I, E>
+impl<I, T, E> BatchIterator<I, T, E>
 where
-    CI: Iterator<Item = Result<(usize, V), E>>,
-    V: IntoIterator<IntoIter = std::vec::IntoIter<I>>,
+    I: Iterator<Item = Result<T, E>>,
 {
-    /// Fetches new chunk from the group iterator, if current chunk is empty.
-    fn ensure_new_data(&mut self) {
-        if let Some(ref mut gi) = self.gi {
-            // it is false for Err(_) because we never read after the error.
-            let should_fetch = self
-                .current
-                .as_ref()
-                .map(|it| it.len() == 0)
-                .unwrap_or(false);
-            if should_fetch {
-                if self.expected == 0 {
-                    // We have reached the end of the last chunk.
-                    // Nothing is remained.
-                    self.gi = None;
-                } else {
-                    match gi.next() {
-                        None => {
-                            self.gi = None;
-                        }
-                        Some(Ok((expected, new_data))) => {
-                            self.expected = expected;
-                            self.current = Ok(new_data.into_iter());
-                        }
-                        Some(Err(e)) => {
-                            self.expected = 1;
-                            self.current = Err(e);
-                        }
-                    }
-                }
-            }
+    pub fn new(inner: I, size: usize) -> Self {
+        assert!(size > 0, "batch size has to be positive");
+        Self {
+            inner,
+            size,
+            error: None,
+            _phantom: std::marker::PhantomData,
         }
     }
-
-    pub(crate) fn new(ci: CI) -> Self {
-        let mut it = LsIterator {
-            gi: Some(ci),
-            current: Ok(vec![].into_iter()),
-            expected: 1, // Fake value to fetch something
-        };
-        it.ensure_new_data();
-        it
-    }
 }
 
-impl<CI, I, E, V> Iterator for LsIterator<CI, I, E>
+impl<I, T, E> Iterator for BatchIterator<I, T, E>
 where
-    CI: Iterator<Item = Result<(usize, V), E>>,
-    V: IntoIterator<IntoIter = std::vec::IntoIter<I>>,
+    I: Iterator<Item = Result<T, E>>,
 {
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let remain_len = match self.gi {
-            Some(_) => self.expected + self.current.as_ref().map(|it| it.len()).unwrap_or(0),
-            None => 0,
-        };
-        // Vec adds one, so we sub. :D
-        (remain_len.saturating_sub(1), Some(remain_len))
-    }
-
-    type Item = Result<I, E>;
+    type Item = Result<Vec<T>, E>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.ensure_new_data();
-        match self.gi {
-            None => None,
-            Some(_) => {
-                match &mut self.current {
-                    Ok(it) => it.next().map(Ok),
-                    Err(_) => {
-                        let mut val = Ok(vec![].into_iter()); // Should not allocate!
-                        std::mem::swap(&mut self.current, &mut val);
-                        self.gi.take();
-                        Some(val.map(|_| unreachable!()))
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let mut batch = Vec::with_capacity(self.size);
+        while batch.len() < self.size {
+            match self.inner.next() {
+                Some(Ok(item)) => batch.push(item),
+                Some(Err(e)) => {
+                    if batch.is_empty() {
+                        return Some(Err(e));
                     }
+                    self.error = Some(e);
+                    break;
                 }
+                None => break,
             }
         }
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
     }
 }
 
-impl<I, E, CI, V> FusedIterator for LsIterator<CI, I, E>
-where
-    CI: Iterator<Item = Result<(usize, V), E>>,
-    V: IntoIterator<IntoIter = std::vec::IntoIter<I>>,
-{
-}
-
-// It is very enticing to implement ExactSizeIterator for the
-// LsIterator, however, we cannot guarantee that we will return exact
-// number of elments, as 1. error may happen, 2. new files may be
-// created in process.
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -200,113 +238,138 @@ mod test {
     struct Error {}
 
     #[test]
-    fn test_empty() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> = vec![Ok((0, vec![]))];
-        assert_eq!(LsIterator::new(gi.into_iter()).collect::<Vec<_>>(), vec![]);
-    }
-
-    #[test]
-    fn test_empty_size_hint() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> = vec![Ok((0, vec![]))];
-        assert_eq!(LsIterator::new(gi.into_iter()).size_hint(), (0, Some(0)));
-    }
-
-    #[test]
-    fn test_really_empty() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> = vec![];
-        assert_eq!(LsIterator::new(gi.into_iter()).collect::<Vec<_>>(), vec![]);
-    }
-
-    #[test]
-    fn test_really_empty_size_hint() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> = vec![];
-        assert_eq!(LsIterator::new(gi.into_iter()).size_hint(), (0, Some(0)));
-    }
-
-    #[test]
-    fn test_single() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> = vec![Ok((0, vec![1, 2]))];
+    fn test_batches() {
+        let items: Vec<Result<i32, Error>> = (1..=5).map(Ok).collect();
         assert_eq!(
-            LsIterator::new(gi.into_iter()).collect::<Vec<_>>(),
-            vec![Ok(1), Ok(2)]
+            BatchIterator::new(items.into_iter(), 2).collect::<Vec<_>>(),
+            vec![Ok(vec![1, 2]), Ok(vec![3, 4]), Ok(vec![5])]
         );
     }
 
     #[test]
-    fn test_single_size_hint() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> = vec![Ok((0, vec![1, 2]))];
-        assert_eq!(LsIterator::new(gi.into_iter()).size_hint(), (1, Some(2)));
-    }
-
-    #[test]
-    fn test_chunks() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> =
-            vec![Ok((2, vec![1, 2])), Ok((0, vec![3, 4]))];
+    fn test_batches_error() {
+        let items: Vec<Result<i32, Error>> = vec![Ok(1), Ok(2), Ok(3), Err(Error {})];
         assert_eq!(
-            LsIterator::new(gi.into_iter()).collect::<Vec<_>>(),
-            vec![Ok(1), Ok(2), Ok(3), Ok(4)]
+            BatchIterator::new(items.into_iter(), 2).collect::<Vec<_>>(),
+            vec![Ok(vec![1, 2]), Ok(vec![3]), Err(Error {})]
         );
     }
 
     #[test]
-    fn test_chunks_size_hint() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> =
-            vec![Ok((2, vec![1, 2])), Ok((0, vec![3, 4]))];
-        let mut it = LsIterator::new(gi.into_iter());
-        assert_eq!(it.size_hint(), (3, Some(4)));
-        it.next();
-        assert_eq!(it.size_hint(), (2, Some(3)));
-        it.next();
-        assert_eq!(it.size_hint(), (1, Some(2)));
-        it.next();
-        assert_eq!(it.size_hint(), (0, Some(1)));
-        it.next();
-        assert_eq!(it.size_hint(), (0, Some(0)));
+    fn test_batches_pull() {
+        // A huge listing: the iterator is advanced only as far as the
+        // consumed batches require, and the batches never exceed the
+        // size.
+        let pulled = std::cell::Cell::new(0usize);
+        let items = (0..100_000_000).map(|i| {
+            pulled.set(pulled.get() + 1);
+            Ok::<_, Error>(i)
+        });
+        let mut batches = BatchIterator::new(items, 1000);
+        for _ in 0..3 {
+            let batch = batches.next().unwrap().unwrap();
+            assert_eq!(batch.len(), 1000);
+            assert_eq!(batch.capacity(), 1000);
+        }
+        assert_eq!(pulled.get(), 3000);
     }
 
-    #[test]
-    fn test_chunk_error() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> =
-            vec![Ok((2, vec![1, 2])), Err(Error {}), Ok((0, vec![3, 4]))];
-        assert_eq!(
-            LsIterator::new(gi.into_iter()).collect::<Vec<_>>(),
-            vec![Ok(1), Ok(2), Err(Error {})]
-        );
-    }
+    mod group {
+        use super::super::*;
+        use crate::paged::PagedIter;
+        use crate::testing::{file_status, MockConnection};
+        use hdfesse_proto::ClientNamenodeProtocol::{
+            GetListingRequestProto, GetListingResponseProto,
+        };
+        use std::{
+            collections::VecDeque,
+            sync::{Arc, Mutex},
+        };
 
-    #[test]
-    fn test_chunk_error_size_hint() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> =
-            vec![Ok((2, vec![1, 2])), Err(Error {}), Ok((0, vec![3, 4]))];
-        let mut it = LsIterator::new(gi.into_iter());
-        assert_eq!(it.size_hint(), (3, Some(4)));
-        it.next().unwrap().unwrap();
-        assert_eq!(it.size_hint(), (2, Some(3)));
-        it.next().unwrap().unwrap();
-        assert_eq!(it.size_hint(), (1, Some(2)));
-        it.next().unwrap().unwrap_err();
-        assert_eq!(it.size_hint(), (0, Some(0)));
-        it.next();
-        assert_eq!(it.size_hint(), (0, Some(0)));
-    }
+        fn page(names: &[&str], remaining: u32) -> Option<GetListingResponseProto> {
+            let mut resp = GetListingResponseProto::default();
+            let list = resp.mut_dirList();
+            for name in names {
+                list.mut_partialListing().push(file_status(name, false, 0));
+            }
+            list.set_remainingEntries(remaining);
+            Some(resp)
+        }
 
-    #[test]
-    fn test_first_error() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> =
-            vec![Err(Error {}), Ok((2, vec![1, 2])), Ok((0, vec![3, 4]))];
-        assert_eq!(
-            LsIterator::new(gi.into_iter()).collect::<Vec<_>>(),
-            vec![Err(Error {})]
-        );
-    }
+        /// Listing that serves the pages in order (None is a removed
+        /// directory) and records the startAfter keys.
+        type MockGroupIterator =
+            LsGroupIterator<MockConnection, ClientNamenodeService<MockConnection>>;
+
+        fn listing(
+            pages: Vec<Option<GetListingResponseProto>>,
+        ) -> (MockGroupIterator, Arc<Mutex<Vec<String>>>) {
+            let keys = Arc::new(Mutex::new(vec![]));
+            let handler_keys = keys.clone();
+            let mut pages = VecDeque::from(pages);
+            let mut conn = MockConnection::new("test");
+            conn.on("getListing", move |req: GetListingRequestProto| {
+                handler_keys
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8(req.get_startAfter().to_vec()).unwrap());
+                Ok(pages
+                    .pop_front()
+                    .expect("unexpected getListing")
+                    .unwrap_or_default())
+            });
+            let service = ClientNamenodeService::new(conn);
+            let path = Path::new("/dir").unwrap();
+            (LsGroupIterator::new(service, &path), keys)
+        }
 
-    #[test]
-    fn test_first_error_size_hint() {
-        let gi: Vec<Result<(usize, Vec<i32>), Error>> =
-            vec![Err(Error {}), Ok((2, vec![1, 2])), Ok((0, vec![3, 4]))];
-        let mut it = LsIterator::new(gi.into_iter());
-        assert_eq!(it.size_hint(), (0, Some(1)));
-        it.next();
-        assert_eq!(it.size_hint(), (0, Some(0)));
+        fn names(it: impl Iterator<Item = Result<HdfsFileStatusProto, FsError>>) -> Vec<String> {
+            it.map(|r| String::from_utf8(r.unwrap().get_path().to_vec()).unwrap())
+                .collect()
+        }
+
+        #[test]
+        fn test_pages() {
+            let (it, keys) = listing(vec![page(&["a", "b"], 1), page(&["c"], 0)]);
+            assert_eq!(names(PagedIter::new(it)), ["a", "b", "c"]);
+            assert_eq!(*keys.lock().unwrap(), ["", "b"]);
+        }
+
+        #[test]
+        fn test_empty_page_retried() {
+            let (it, keys) = listing(vec![page(&["a"], 1), page(&[], 1), page(&["b"], 0)]);
+            assert_eq!(names(PagedIter::new(it)), ["a", "b"]);
+            assert_eq!(*keys.lock().unwrap(), ["", "a", "a"]);
+        }
+
+        #[test]
+        fn test_empty_pages_inconsistent() {
+            let mut pages = vec![page(&["a"], 1)];
+            pages.extend((0..=EMPTY_PAGE_RETRIES).map(|_| page(&[], 1)));
+            let (it, _keys) = listing(pages);
+            let res: Vec<_> = PagedIter::new(it).collect();
+            assert_eq!(res.len(), 2);
+            assert!(matches!(
+                res[1],
+                Err(FsError::InconsistentListing {
+                    kind: ListingInconsistency::EmptyPage,
+                    ..
+                })
+            ));
+        }
+
+        #[test]
+        fn test_directory_removed() {
+            let (it, _keys) = listing(vec![page(&["a"], 1), None]);
+            let res: Vec<_> = PagedIter::new(it).collect();
+            assert_eq!(res.len(), 2);
+            assert!(matches!(
+                res[1],
+                Err(FsError::InconsistentListing {
+                    kind: ListingInconsistency::DirectoryRemoved,
+                    ..
+                })
+            ));
+        }
     }
 }
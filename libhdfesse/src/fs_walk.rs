@@ -0,0 +1,263 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+use std::{borrow::BorrowMut, cmp::Ordering};
+
+use crate::{
+    filter::PathFilter,
+    fs::{FsError, Hdfs, HdfsError},
+    fs_ls::ListingCursor,
+    path::Path,
+    rpc::RpcConnection,
+    service::ClientNamenodeService,
+    status::DirEntry,
+};
+use hdfesse_proto::hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType};
+
+type Order<'h> = Box<dyn FnMut(&HdfsFileStatusProto, &HdfsFileStatusProto) -> Ordering + 'h>;
+
+/// A directory to be listed, as it is found in its parent's listing.
+struct Subdir {
+    path: Path<'static>,
+    status: HdfsFileStatusProto,
+    depth: usize,
+    /// File IDs of the directories above it; kept only when links are
+    /// followed.
+    ancestors: Vec<u64>,
+}
+
+/// The directory being listed.
+struct Listing {
+    cursor: ListingCursor,
+    /// The current page.
+    entries: std::vec::IntoIter<HdfsFileStatusProto>,
+    /// The listing ended, by the last page or by an error.
+    done: bool,
+    /// File IDs of the directory and of the ones above it; kept only
+    /// when links are followed.
+    ancestors: Vec<u64>,
+}
+
+/**
+Entries of a tree, see Hdfs::walk.  The entries of a directory are
+returned first, page by page; then its subdirectories are walked in
+the listing order, or in the order of sort_subdirs_by.  The root's
+entries have depth 1.
+
+An error of a directory's listing is returned in place of its
+remaining entries, and the walk goes on; dir tells which directory it
+is.  With follow_links, links to directories are walked like
+subdirectories: a dangling link is skipped, and a link to a directory
+above it is reported as FsError::SymlinkLoop.
+*/
+pub struct Walk<'h, R, SRef>
+where
+    R: RpcConnection,
+    SRef: BorrowMut<ClientNamenodeService<R>>,
+{
+    hdfs: &'h mut Hdfs<R, SRef>,
+    filter: &'h dyn PathFilter,
+    follow_links: bool,
+    max_depth: Option<usize>,
+    order: Option<Order<'h>>,
+    /// Directories still to be listed, the next one last.
+    pending: Vec<Subdir>,
+    /// The directory whose entries are returned and its depth.
+    dir: Path<'static>,
+    depth: usize,
+    listing: Option<Listing>,
+    /// Subdirectories of the current directory to descend into.
+    subdirs: Vec<(Path<'static>, HdfsFileStatusProto)>,
+}
+
+impl<'h, R, SRef> Walk<'h, R, SRef>
+where
+    R: RpcConnection,
+    SRef: BorrowMut<ClientNamenodeService<R>>,
+{
+    pub(crate) fn new(
+        hdfs: &'h mut Hdfs<R, SRef>,
+        root: Path<'static>,
+        root_id: u64,
+        filter: &'h dyn PathFilter,
+    ) -> Self {
+        let listing = Listing {
+            cursor: ListingCursor::new(&root),
+            entries: Vec::new().into_iter(),
+            done: false,
+            ancestors: vec![root_id],
+        };
+        Self {
+            hdfs,
+            filter,
+            follow_links: false,
+            max_depth: None,
+            order: None,
+            pending: vec![],
+            dir: root,
+            depth: 1,
+            listing: Some(listing),
+            subdirs: vec![],
+        }
+    }
+
+    /// Walk the directories that symbolic links point to.
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Do not descend below the depth; 1 lists the root only.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Walk the subdirectories of each directory in the order of
+    /// their listing entries.
+    pub fn sort_subdirs_by<F>(mut self, compare: F) -> Self
+    where
+        F: FnMut(&HdfsFileStatusProto, &HdfsFileStatusProto) -> Ordering + 'h,
+    {
+        self.order = Some(Box::new(compare));
+        self
+    }
+
+    /// The directory of the last returned entry or error.
+    pub fn dir(&self) -> &Path<'static> {
+        &self.dir
+    }
+
+    /// The depth of the last returned entry.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Queue the subdirectories of the finished listing.
+    fn schedule_subdirs(&mut self, ancestors: &[u64]) {
+        if let Some(order) = &mut self.order {
+            self.subdirs.sort_by(|a, b| order(&a.1, &b.1));
+        }
+        let depth = self.depth + 1;
+        let ancestors = if self.follow_links {
+            ancestors.to_vec()
+        } else {
+            vec![]
+        };
+        self.pending
+            .extend(self.subdirs.drain(..).rev().map(|(path, status)| Subdir {
+                path,
+                status,
+                depth,
+                ancestors: ancestors.clone(),
+            }));
+    }
+
+    /// Start listing the subdirectory, unless it is a dangling link
+    /// or a link to a file, or the filter prunes the link's target.
+    fn enter(&mut self, subdir: Subdir) -> Result<(), HdfsError> {
+        let Subdir {
+            path,
+            status,
+            depth,
+            mut ancestors,
+        } = subdir;
+        self.dir = path;
+        self.depth = depth;
+        let status = if status.get_fileType() == HdfsFileStatusProto_FileType::IS_SYMLINK {
+            // The namenode follows the link.
+            match self.hdfs.get_file_info(&self.dir) {
+                Ok(target) => target,
+                Err(FsError::NotFound(_)) => return Ok(()),
+                Err(e) => return Err(HdfsError::src(e)),
+            }
+        } else {
+            status
+        };
+        if status.get_fileType() != HdfsFileStatusProto_FileType::IS_DIR
+            || !self.filter.descend(&self.dir, &status)
+        {
+            return Ok(());
+        }
+        if self.follow_links {
+            let file_id = status.get_fileId();
+            // Old namenodes report no IDs.
+            if file_id != 0 && ancestors.contains(&file_id) {
+                return Err(HdfsError::src(FsError::SymlinkLoop(
+                    self.dir.to_path_string(),
+                )));
+            }
+            ancestors.push(file_id);
+        }
+        self.listing = Some(Listing {
+            cursor: ListingCursor::new(&self.dir),
+            entries: Vec::new().into_iter(),
+            done: false,
+            ancestors,
+        });
+        Ok(())
+    }
+}
+
+impl<'h, R, SRef> Iterator for Walk<'h, R, SRef>
+where
+    R: RpcConnection,
+    SRef: BorrowMut<ClientNamenodeService<R>>,
+{
+    type Item = Result<(Path<'static>, HdfsFileStatusProto), HdfsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let descend = self.max_depth.map(|max| self.depth < max).unwrap_or(true);
+            if let Some(listing) = &mut self.listing {
+                for status in &mut listing.entries {
+                    let path = match status.absolute_path(&self.dir) {
+                        Ok(path) => path,
+                        Err(e) => return Some(Err(HdfsError::op(e))),
+                    };
+                    let is_subdir = match status.get_fileType() {
+                        HdfsFileStatusProto_FileType::IS_DIR => self.filter.descend(&path, &status),
+                        HdfsFileStatusProto_FileType::IS_SYMLINK => self.follow_links,
+                        HdfsFileStatusProto_FileType::IS_FILE => false,
+                    };
+                    if descend && is_subdir {
+                        self.subdirs.push((path.clone(), status.clone()));
+                    }
+                    if self.filter.accept(&path, &status) {
+                        return Some(Ok((path, status)));
+                    }
+                }
+                if !listing.done {
+                    match self.hdfs.list_page(&mut listing.cursor) {
+                        Ok(Some(page)) => listing.entries = page.into_iter(),
+                        Ok(None) => listing.done = true,
+                        Err(e) => {
+                            listing.done = true;
+                            return Some(Err(e));
+                        }
+                    }
+                    continue;
+                }
+                let ancestors = std::mem::take(&mut listing.ancestors);
+                self.listing = None;
+                self.schedule_subdirs(&ancestors);
+            }
+            let subdir = self.pending.pop()?;
+            if let Err(e) = self.enter(subdir) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
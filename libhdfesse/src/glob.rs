@@ -0,0 +1,371 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Glob patterns of FsShell arguments, see Java's GlobPattern and
+ * Globber: `?` and `*` match any character and any sequence, `[a-z]`
+ * and `[^a-z]` (or `[!a-z]`) match a character of a class or not of
+ * it, `{a,b}` matches any of the alternatives, and a backslash
+ * escapes the next character.  Alternatives may contain `/`; other
+ * wildcards match within a path component only.
+ */
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("`{pattern}': Illegal file pattern: {reason}")]
+pub struct GlobError {
+    pub pattern: String,
+    pub reason: &'static str,
+}
+
+impl GlobError {
+    fn new(pattern: &str, reason: &'static str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            reason,
+        }
+    }
+}
+
+/// True if the pattern has unescaped special characters; otherwise
+/// it is a plain path, possibly with escapes.
+pub fn has_wildcard(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' | '{' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/**
+ * The pattern with the alternatives of `{}` groups expanded, in
+ * order; escapes are kept.  A `}` without a group is a plain
+ * character, like a `,` outside of a group.
+ */
+pub fn expand_braces(pattern: &str) -> Result<Vec<String>, GlobError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut expanded = vec![];
+    expand_into(pattern, &chars, String::new(), &mut expanded)?;
+    Ok(expanded)
+}
+
+fn expand_into(
+    pattern: &str,
+    chars: &[char],
+    mut prefix: String,
+    expanded: &mut Vec<String>,
+) -> Result<(), GlobError> {
+    let mut idx = 0;
+    while idx < chars.len() {
+        match chars[idx] {
+            '\\' => {
+                prefix.push('\\');
+                idx += 1;
+                match chars.get(idx) {
+                    Some(c) => prefix.push(*c),
+                    None => return Err(GlobError::new(pattern, "Missing escaped character")),
+                }
+            }
+            '{' => {
+                let (alternatives, end) = split_group(pattern, chars, idx)?;
+                let rest = &chars[end + 1..];
+                for alternative in alternatives {
+                    // Alternatives may have groups too.
+                    let mut with_rest = alternative.to_vec();
+                    with_rest.extend_from_slice(rest);
+                    expand_into(pattern, &with_rest, prefix.clone(), expanded)?;
+                }
+                return Ok(());
+            }
+            c => prefix.push(c),
+        }
+        idx += 1;
+    }
+    expanded.push(prefix);
+    Ok(())
+}
+
+/// The alternatives of the group that starts at the index, and the
+/// index of its closing brace.
+fn split_group<'c>(
+    pattern: &str,
+    chars: &'c [char],
+    start: usize,
+) -> Result<(Vec<&'c [char]>, usize), GlobError> {
+    let mut alternatives = vec![];
+    let mut depth = 0;
+    let mut alternative_start = start + 1;
+    let mut idx = start + 1;
+    while idx < chars.len() {
+        match chars[idx] {
+            '\\' => idx += 1,
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' => {
+                alternatives.push(&chars[alternative_start..idx]);
+                return Ok((alternatives, idx));
+            }
+            ',' if depth == 0 => {
+                alternatives.push(&chars[alternative_start..idx]);
+                alternative_start = idx + 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    Err(GlobError::new(pattern, "Unclosed group"))
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    Any,
+    AnySeq,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => *expected == c,
+            Token::Any => true,
+            Token::AnySeq => unreachable!("sequences are matched by match_tokens"),
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(from, to)| (*from..=*to).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/**
+ * A compiled pattern of a single name, like a path component.
+ */
+#[derive(Debug, Clone)]
+pub struct GlobPattern {
+    alternatives: Vec<Vec<Token>>,
+}
+
+impl GlobPattern {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let alternatives = expand_braces(pattern)?
+            .iter()
+            .map(|alternative| tokenize(pattern, alternative))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { alternatives })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        self.alternatives
+            .iter()
+            .any(|tokens| match_tokens(tokens, &name))
+    }
+
+    /// The name if the pattern matches it only, with escapes removed.
+    pub fn literal(&self) -> Option<String> {
+        match self.alternatives.as_slice() {
+            [tokens] => tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Char(c) => Some(*c),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(pattern: &str, alternative: &str) -> Result<Vec<Token>, GlobError> {
+    let mut tokens = vec![];
+    let mut chars = alternative.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            // Escapes are checked by expand_braces.
+            '\\' => Token::Char(chars.next().unwrap_or('\\')),
+            '?' => Token::Any,
+            '*' => {
+                if let Some(Token::AnySeq) = tokens.last() {
+                    continue;
+                }
+                Token::AnySeq
+            }
+            '[' => {
+                let negated = matches!(chars.peek(), Some('^') | Some('!'));
+                if negated {
+                    chars.next();
+                }
+                let mut ranges = vec![];
+                let mut first = true;
+                loop {
+                    let from = match chars.next() {
+                        None => return Err(GlobError::new(pattern, "Unclosed character class")),
+                        // A leading ] is a plain character.
+                        Some(']') if !first => break,
+                        Some('\\') => chars.next().unwrap_or('\\'),
+                        Some(from) => from,
+                    };
+                    first = false;
+                    let to = if chars.peek() == Some(&'-') {
+                        chars.next();
+                        match chars.next() {
+                            None => {
+                                return Err(GlobError::new(pattern, "Unclosed character class"))
+                            }
+                            // A trailing - is a plain character.
+                            Some(']') => {
+                                ranges.push((from, from));
+                                ranges.push(('-', '-'));
+                                break;
+                            }
+                            Some('\\') => chars.next().unwrap_or('\\'),
+                            Some(to) => to,
+                        }
+                    } else {
+                        from
+                    };
+                    if to < from {
+                        return Err(GlobError::new(pattern, "Illegal character range"));
+                    }
+                    ranges.push((from, to));
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn match_tokens(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((Token::AnySeq, rest)) => {
+            (0..=name.len()).any(|skip| match_tokens(rest, &name[skip..]))
+        }
+        Some((token, rest)) => match name.split_first() {
+            Some((c, name_rest)) => token.matches(*c) && match_tokens(rest, name_rest),
+            None => false,
+        },
+    }
+}
+
+/// The scheme and authority of the pattern, if any, and its path.
+pub(crate) fn split_authority(pattern: &str) -> (&str, &str) {
+    match pattern.find("://") {
+        Some(scheme_end) => {
+            let authority_start = scheme_end + "://".len();
+            let path_start = pattern[authority_start..]
+                .find('/')
+                .map_or(pattern.len(), |idx| authority_start + idx);
+            pattern.split_at(path_start)
+        }
+        None => ("", pattern),
+    }
+}
+
+/// The path of the named entry of the directory, in the form of the
+/// directory; an empty directory is the current one.
+pub(crate) fn child(dir: &str, name: &str) -> String {
+    if dir.is_empty() || dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        GlobPattern::new(pattern).unwrap().matches(name)
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", ".hidden"));
+        assert!(matches("a*c", "abbc"));
+        assert!(!matches("a*c", "abcd"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("*.txt", "dir.txt"));
+        assert!(matches("a**b*", "ab"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[^a-c]x", "dx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(!matches("[!a-c]x", "ax"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[\\]]", "]"));
+        assert_eq!(
+            GlobPattern::new("[ab").unwrap_err().reason,
+            "Unclosed character class"
+        );
+        assert!(GlobPattern::new("[z-a]").is_err());
+    }
+
+    #[test]
+    fn test_braces() {
+        assert_eq!(
+            expand_braces("/{a,b{c,d}}/x").unwrap(),
+            ["/a/x", "/bc/x", "/bd/x"]
+        );
+        assert_eq!(expand_braces("a\\{b,c}").unwrap(), ["a\\{b,c}"]);
+        assert_eq!(expand_braces("a}b,c").unwrap(), ["a}b,c"]);
+        assert_eq!(expand_braces("{,x}").unwrap(), ["", "x"]);
+        assert!(expand_braces("{a,b").is_err());
+        assert!(matches("{dir,file}*", "file1"));
+        assert!(!matches("{dir,file}*", "link"));
+    }
+
+    #[test]
+    fn test_literal() {
+        assert_eq!(
+            GlobPattern::new("a\\*b").unwrap().literal().as_deref(),
+            Some("a*b")
+        );
+        assert_eq!(GlobPattern::new("a*b").unwrap().literal(), None);
+        assert!(!has_wildcard("a\\*b"));
+        assert!(has_wildcard("/x/{a,b}"));
+        assert!(GlobPattern::new("a\\").is_err());
+    }
+
+    #[test]
+    fn test_split_authority() {
+        assert_eq!(split_authority("hdfs://ns/a/*"), ("hdfs://ns", "/a/*"));
+        assert_eq!(split_authority("hdfs://ns"), ("hdfs://ns", ""));
+        assert_eq!(split_authority("a/*"), ("", "a/*"));
+        assert_eq!(child("", "a"), "a");
+        assert_eq!(child("/", "a"), "/a");
+        assert_eq!(child("hdfs://ns/d", "a"), "hdfs://ns/d/a");
+    }
+}
@@ -18,35 +18,226 @@
  * if current fails and the query is retriable.
  */
 
-use std::iter::Cycle;
-use tracing::{instrument, trace};
+use crate::trace::{info, trace};
+use std::{
+    fmt::Display,
+    iter::Cycle,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
+    auth::{AuthProvider, LoginUser},
     hdconfig,
-    rpc::{Connector, HdfsConnection, RpcConnection, RpcError, RpcErrorCode},
-    util,
+    rpc::{
+        new_client_id, CallLimits, CallOptions, CallRetry, Connector, HandshakeError,
+        HdfsConnection, InfiniteSeq, RpcConnection, RpcError, RpcErrorCode,
+    },
+    service::Idempotency,
+    user::User,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailoverReason {
+    /// The namenode is not active.
+    Standby,
+    /// The namenode cannot be connected to.
+    ConnectFailed,
+    /// The connection failed during a call that may be re-sent.
+    CallFailed,
+}
+
+impl Display for FailoverReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FailoverReason::Standby => "standby",
+            FailoverReason::ConnectFailed => "connect failed",
+            FailoverReason::CallFailed => "call failed",
+        })
+    }
+}
+
+/**
+ * The client switches from one namenode to another.  Frequent events
+ * mean that namenodes are flapping or unreachable.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailoverEvent {
+    pub from_node: String,
+    pub to_node: String,
+    pub reason: FailoverReason,
+    /// The number of the connection attempt to to_node within the
+    /// call, starting from 1.
+    pub attempt: usize,
+}
+
+/// Callback for failover events; they are also logged as tracing
+/// events with target "failover".
+#[derive(Clone)]
+pub struct FailoverListener(Arc<dyn Fn(&FailoverEvent) + Send + Sync>);
+
+impl FailoverListener {
+    pub fn new<F: Fn(&FailoverEvent) + Send + Sync + 'static>(f: F) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl std::fmt::Debug for FailoverListener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FailoverListener")
+    }
+}
+
+/// Failovers of the connection since its creation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FailoverCounts {
+    pub standby: u64,
+    pub connect_failed: u64,
+    pub call_failed: u64,
+    /// Calls sent again to the next namenode.
+    pub retries: u64,
+}
+
+/// A namenode tried by a call that has failed there: connecting to it
+/// or the call itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallAttempt {
+    pub node: String,
+    pub error: String,
+    pub elapsed: Duration,
+}
+
+/// A call that has failed, with the namenodes it has tried in order;
+/// the last attempt has the error returned to the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedCall {
+    pub method: String,
+    pub attempts: Vec<CallAttempt>,
+}
+
+fn report_failover(listener: Option<&FailoverListener>, event: FailoverEvent) {
+    info!(
+        target: "failover",
+        from_node = %event.from_node,
+        to_node = %event.to_node,
+        reason = %event.reason,
+        attempt = event.attempt,
+        "namenode failover"
+    );
+    if let Some(listener) = listener {
+        (listener.0)(&event);
+    }
+}
+
+/// The connection is broken and has to be reopened.
+fn is_connection_failure(e: &RpcError) -> bool {
+    matches!(
+        e,
+        RpcError::Io(_)
+            | RpcError::Protobuf(protobuf::ProtobufError::IoError(_))
+            | RpcError::Handshake(HandshakeError::Closed)
+    )
+}
+
 /**
 * High-availability RPC connection.
 */
 #[derive(Debug)]
 pub struct HaHdfsConnection<C: Connector + std::fmt::Debug> {
     user: Box<str>,
+    real_user: Option<Box<str>>,
+    // Asked for credentials on each connection.
+    auth: Arc<dyn AuthProvider>,
     current: Option<HdfsConnection>,
     connector: C,
     connection_num: usize,
     // TODO: it allocates String on each connection attempt, but it seems
     // to be minor problem.
     connections: Cycle<std::vec::IntoIter<String>>,
+    options: CallOptions,
+    // Shared by all the connections; see HdfsConnection::last_seen_state_id.
+    state_id: Option<i64>,
+    current_node: Option<String>,
+    // The node left and why; reported on the next connection attempt.
+    failed_node: Option<(String, FailoverReason)>,
+    failover_listener: Option<FailoverListener>,
+    failover_counts: FailoverCounts,
+    // Attempts of the current call.
+    attempts: Vec<CallAttempt>,
+    last_failure: Option<FailedCall>,
+    // Shared by all the connections, so a call re-sent to another
+    // namenode is recognized by its retry cache.
+    client_id: [u8; 16],
+    call_id: InfiniteSeq,
 }
 
 impl<C: Connector + std::fmt::Debug> HaHdfsConnection<C> {
+    /// Connect as User::current, which may be a proxy user.
     pub fn new(namenode: &hdconfig::NameserviceConfig, connector: C) -> Result<Self, RpcError> {
+        let user = User::current().map_err(RpcError::NoUser)?;
+        match user.real_user() {
+            Some(_) => Self::new_as_proxy(namenode, user.name(), connector),
+            None => Self::with_auth_provider(namenode, Arc::new(LoginUser), connector),
+        }
+    }
+
+    /// Connect as the user on behalf of the login one (which is the
+    /// real user); see HdfsConnection::new_as_proxy.
+    pub fn new_as_proxy(
+        namenode: &hdconfig::NameserviceConfig,
+        user: &str,
+        connector: C,
+    ) -> Result<Self, RpcError> {
+        Self::with_auth_provider_as_proxy(namenode, user, Arc::new(LoginUser), connector)
+    }
+
+    /// Connect as the user of the provider's credentials.
+    pub fn with_auth_provider(
+        namenode: &hdconfig::NameserviceConfig,
+        auth: Arc<dyn AuthProvider>,
+        connector: C,
+    ) -> Result<Self, RpcError> {
+        let user = auth.credentials().map_err(RpcError::NoUser)?.user().into();
+        Self::with_auth(namenode, user, None, auth, connector)
+    }
+
+    /// Connect as the user on behalf of the provider's one.
+    pub fn with_auth_provider_as_proxy(
+        namenode: &hdconfig::NameserviceConfig,
+        user: &str,
+        auth: Arc<dyn AuthProvider>,
+        connector: C,
+    ) -> Result<Self, RpcError> {
+        let real_user = auth.credentials().map_err(RpcError::NoUser)?.user().into();
+        Self::with_auth(namenode, user.into(), Some(real_user), auth, connector)
+    }
+
+    #[cfg(test)]
+    fn with_users(
+        namenode: &hdconfig::NameserviceConfig,
+        user: Box<str>,
+        real_user: Option<Box<str>>,
+        connector: C,
+    ) -> Result<Self, RpcError> {
+        let auth = Arc::new(crate::auth::StaticUser(
+            real_user.as_deref().unwrap_or(&user).to_owned(),
+        ));
+        Self::with_auth(namenode, user, real_user, auth, connector)
+    }
+
+    fn with_auth(
+        namenode: &hdconfig::NameserviceConfig,
+        user: Box<str>,
+        real_user: Option<Box<str>>,
+        auth: Arc<dyn AuthProvider>,
+        connector: C,
+    ) -> Result<Self, RpcError> {
         let connection_num = namenode.rpc_nodes.len();
 
         Ok(Self {
-            user: util::get_username().unwrap().into(),
+            user,
+            real_user,
+            auth,
             current: None,
             connector,
             connection_num,
@@ -57,25 +248,45 @@ impl<C: Connector + std::fmt::Debug> HaHdfsConnection<C> {
                 .collect::<Vec<String>>()
                 .into_iter()
                 .cycle(),
+            options: Default::default(),
+            state_id: None,
+            current_node: None,
+            failed_node: None,
+            failover_listener: None,
+            failover_counts: FailoverCounts::default(),
+            attempts: vec![],
+            last_failure: None,
+            client_id: new_client_id(),
+            call_id: InfiniteSeq::new(),
         })
     }
 
     fn ensure_connection(
         &mut self,
         attempts_left: &mut usize,
+        method: &str,
+        limits: &CallLimits,
     ) -> Result<&mut HdfsConnection, RpcError> {
         if let Some(ref mut conn) = self.current {
             Ok(conn)
         } else {
-            self.try_connect(attempts_left)
+            self.try_connect(attempts_left, method, limits)
         }
     }
 
-    #[instrument]
-    fn try_connect(&mut self, attempts_left: &mut usize) -> Result<&mut HdfsConnection, RpcError> {
+    /// Every connect attempt gets the time left until the call
+    /// deadline, so a blackholed namenode doesn't hang the call.
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    fn try_connect(
+        &mut self,
+        attempts_left: &mut usize,
+        method: &str,
+        limits: &CallLimits,
+    ) -> Result<&mut HdfsConnection, RpcError> {
         let mut last_err = None;
 
         for addr in self.connections.by_ref().take(*attempts_left) {
+            limits.check(method)?;
             // TODO: retry on network error?
             trace!(
                 target = "connect",
@@ -84,17 +295,49 @@ impl<C: Connector + std::fmt::Debug> HaHdfsConnection<C> {
                 attempts_left
             );
             *attempts_left -= 1;
-            match HdfsConnection::new_with_user(
-                Some(self.user.as_ref().into()),
+            if let Some((from_node, reason)) = self.failed_node.take() {
+                report_failover(
+                    self.failover_listener.as_ref(),
+                    FailoverEvent {
+                        from_node,
+                        to_node: addr.clone(),
+                        reason,
+                        attempt: self.connection_num - *attempts_left,
+                    },
+                );
+            }
+            let start = Instant::now();
+            // The provider may have refreshed the credentials; the
+            // authenticated user is the real one of a proxy.
+            let credentials = self.auth.credentials().map_err(RpcError::NoUser)?;
+            match &mut self.real_user {
+                Some(real_user) => *real_user = credentials.user().into(),
+                None => self.user = credentials.user().into(),
+            }
+            let conn = HdfsConnection::connect(
+                self.user.as_ref().into(),
+                self.real_user.as_deref().map(Into::into),
                 &addr,
                 &self.connector,
-            ) {
-                Ok(conn) => {
+                self.client_id,
+                limits.remaining(),
+            );
+            match conn {
+                Ok(mut conn) => {
+                    conn.update_last_seen_state_id(self.state_id);
                     self.current = Some(conn);
+                    self.current_node = Some(addr);
                     return Ok(self.current.as_mut().unwrap());
                 }
                 Err(e) => {
+                    self.attempts.push(CallAttempt {
+                        node: addr.clone(),
+                        error: format!("connect failed: {}", e),
+                        elapsed: start.elapsed(),
+                    });
                     last_err = Some(e);
+                    self.failover_counts.connect_failed += 1;
+                    self.failed_node = Some((addr, FailoverReason::ConnectFailed));
                     // TODO: pause
                 }
             }
@@ -103,8 +346,31 @@ impl<C: Connector + std::fmt::Debug> HaHdfsConnection<C> {
         Err(last_err.unwrap())
     }
 
-    fn fail(&mut self) {
+    /// The authenticated user of a proxy connection.
+    pub fn get_real_user(&self) -> Option<&str> {
+        self.real_user.as_deref()
+    }
+
+    pub fn set_failover_listener(&mut self, listener: Option<FailoverListener>) {
+        self.failover_listener = listener;
+    }
+
+    pub fn failover_counts(&self) -> FailoverCounts {
+        self.failover_counts
+    }
+
+    /// The last call that has failed, to explain the error: with
+    /// failover, the error alone does not tell that other namenodes
+    /// have been tried.
+    pub fn last_failure(&self) -> Option<&FailedCall> {
+        self.last_failure.as_ref()
+    }
+
+    fn fail(&mut self, reason: FailoverReason) {
         self.current.take().map(|c| c.shutdown());
+        if let Some(node) = self.current_node.take() {
+            self.failed_node = Some((node, reason));
+        }
     }
 }
 
@@ -113,44 +379,573 @@ impl<C: Connector + std::fmt::Debug> RpcConnection for HaHdfsConnection<C> {
         &self.user
     }
 
-    #[instrument]
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
     fn call<Output: protobuf::Message>(
         &mut self,
         method_name: std::borrow::Cow<'_, str>,
         input: &dyn protobuf::Message,
+    ) -> Result<Output, RpcError> {
+        self.attempts.clear();
+        let res = self.call_with_failover(method_name.clone(), input);
+        if res.is_err() {
+            self.last_failure = Some(FailedCall {
+                method: method_name.into_owned(),
+                attempts: std::mem::take(&mut self.attempts),
+            });
+        }
+        res
+    }
+
+    fn shutdown(self) -> Result<(), RpcError> {
+        match self.current {
+            Some(conn) => conn.shutdown(),
+            None => Ok(()),
+        }
+    }
+
+    fn call_options(&self) -> &CallOptions {
+        &self.options
+    }
+
+    fn set_call_options(&mut self, options: CallOptions) {
+        self.options = options;
+    }
+
+    fn failover_counts(&self) -> FailoverCounts {
+        self.failover_counts
+    }
+}
+
+impl<C: Connector + std::fmt::Debug> HaHdfsConnection<C> {
+    fn call_with_failover<Output: protobuf::Message>(
+        &mut self,
+        method_name: std::borrow::Cow<'_, str>,
+        input: &dyn protobuf::Message,
     ) -> Result<Output, RpcError> {
         // It has to share count with HaHdfsconnection::connect loop.
 
         let mut attempts_left = self.connection_num;
+        // Single deadline for all the failover attempts.
+        let limits = CallLimits::new(&self.options);
+        let mut retry = CallRetry {
+            call_id: self.call_id.next(),
+            retry_count: 0,
+        };
 
         loop {
-            let conn = self.ensure_connection(&mut attempts_left)?;
-            let res = conn.call(method_name.clone(), input);
+            limits.check(&method_name)?;
+            let conn = self.ensure_connection(&mut attempts_left, &method_name, &limits)?;
+            let start = Instant::now();
+            let res = conn.call_limited(method_name.clone(), input, &limits, Some(retry));
+            retry.retry_count += 1;
+            let state_id = conn.last_seen_state_id();
+            self.state_id = state_id;
+            // Successful calls are not recorded: they are the hot path.
+            if let Err(e) = &res {
+                self.attempts.push(CallAttempt {
+                    node: self.current_node.clone().unwrap_or_default(),
+                    error: e.to_string(),
+                    elapsed: start.elapsed(),
+                });
+            }
+            if let Err(RpcError::TimedOut { .. }) | Err(RpcError::Cancelled { .. }) = &res {
+                // The connection is already closed; we have no time
+                // left for other nodes.
+                self.current = None;
+                self.current_node = None;
+                return res;
+            }
             if let Err(RpcError::ErrorResponse {
                 error_detail: RpcErrorCode::ERROR_APPLICATION,
                 exception: ref ex,
                 ..
             }) = &res
             {
-                if ex == "org.apache.hadoop.ipc.StandbyException" {
+                if ex == "org.apache.hadoop.ipc.StandbyException" && attempts_left > 0 {
                     trace!(
                         taget = "call",
                         "Use next service because of StandbyException: {:?}",
                         res
                     );
-                    self.fail();
+                    self.failover_counts.standby += 1;
+                    self.failover_counts.retries += 1;
+                    self.fail(FailoverReason::Standby);
                     continue;
                 }
             }
+            if let Err(e) = &res {
+                if is_connection_failure(e) {
+                    // The namenode may or may not have executed the
+                    // call.  Idempotent calls are safe to repeat, and
+                    // at-most-once ones are deduplicated by the retry
+                    // cache, as the call ID is the same.
+                    self.failover_counts.call_failed += 1;
+                    self.fail(FailoverReason::CallFailed);
+                    if attempts_left > 0
+                        && Idempotency::of_method(&method_name) != Idempotency::Unsafe
+                    {
+                        trace!(target = "call", "Re-send call: {:?}", res);
+                        self.failover_counts.retries += 1;
+                        continue;
+                    }
+                }
+            }
             // else
             return res;
         }
     }
+}
 
-    fn shutdown(self) -> Result<(), RpcError> {
-        match self.current {
-            Some(conn) => conn.shutdown(),
-            None => Ok(()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdconfig::{NamenodeConfig, NameserviceConfig};
+    use crate::rpc::RpcStatus;
+    use hdfesse_proto::{
+        ClientNamenodeProtocol::{
+            GetFileInfoRequestProto, GetFileInfoResponseProto, RenameRequestProto,
+            RenameResponseProto,
+        },
+        IpcConnectionContext::IpcConnectionContextProto,
+        RpcHeader::{RpcRequestHeaderProto, RpcResponseHeaderProto},
+    };
+    use protobuf::Message;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    struct RefusingConnector;
+
+    impl Connector for RefusingConnector {
+        fn get_connection<T: ToSocketAddrs>(&self, _addr: T) -> Result<TcpStream, std::io::Error> {
+            Err(std::io::ErrorKind::ConnectionRefused.into())
+        }
+    }
+
+    /// Refuses connections, recording the connect timeouts.
+    #[derive(Debug, Default)]
+    struct TimeoutConnector {
+        timeouts: Arc<Mutex<Vec<Option<Duration>>>>,
+    }
+
+    impl Connector for TimeoutConnector {
+        fn get_connection<T: ToSocketAddrs>(&self, addr: T) -> Result<TcpStream, std::io::Error> {
+            self.get_connection_timeout(addr, None)
+        }
+
+        fn get_connection_timeout<T: ToSocketAddrs>(
+            &self,
+            _addr: T,
+            timeout: Option<Duration>,
+        ) -> Result<TcpStream, std::io::Error> {
+            self.timeouts.lock().unwrap().push(timeout);
+            Err(std::io::ErrorKind::ConnectionRefused.into())
+        }
+    }
+
+    /// Connects to a local server that reads the request and closes the
+    /// connection without responding.  The data received by each
+    /// connection is recorded.
+    #[derive(Debug)]
+    struct ClosingConnector {
+        addr: SocketAddr,
+        received: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl ClosingConnector {
+        fn new() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let received = Arc::new(Mutex::new(vec![]));
+            let record = received.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    stream
+                        .set_read_timeout(Some(Duration::from_millis(100)))
+                        .unwrap();
+                    let mut data = vec![];
+                    let mut buf = [0; 1024];
+                    while let Ok(n) = stream.read(&mut buf) {
+                        if n == 0 {
+                            break;
+                        }
+                        data.extend_from_slice(&buf[..n]);
+                    }
+                    record.lock().unwrap().push(data);
+                }
+            });
+            Self { addr, received }
+        }
+    }
+
+    /// Split a length-prefixed message group from the data.
+    fn group(data: &[u8]) -> (&[u8], &[u8]) {
+        let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        (&data[4..4 + len], &data[4 + len..])
+    }
+
+    /// Connection context of the handshake.
+    fn connection_context(data: &[u8]) -> IpcConnectionContextProto {
+        let (handshake, _) = group(&data[7..]);
+        let mut cis = protobuf::CodedInputStream::from_bytes(handshake);
+        let _header: RpcRequestHeaderProto = cis.read_message().unwrap();
+        cis.read_message().unwrap()
+    }
+
+    /// Header of the call that follows the connection handshake.
+    fn call_header(data: &[u8]) -> RpcRequestHeaderProto {
+        // "hrpc", version, service class and auth protocol.
+        let (_handshake, rest) = group(&data[7..]);
+        let (call, _) = group(rest);
+        protobuf::CodedInputStream::from_bytes(call)
+            .read_message()
+            .unwrap()
+    }
+
+    impl Connector for ClosingConnector {
+        fn get_connection<T: ToSocketAddrs>(&self, _addr: T) -> Result<TcpStream, std::io::Error> {
+            TcpStream::connect(self.addr)
         }
     }
+
+    /// A namenode that answers a single call, with StandbyException
+    /// if it is a standby one.
+    fn answering_namenode(standby: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut preamble = [0u8; 7];
+            stream.read_exact(&mut preamble).unwrap();
+            let read_frame = |stream: &mut TcpStream| {
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len).unwrap();
+                let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut data).unwrap();
+                data
+            };
+            // The connection context.
+            read_frame(&mut stream);
+            let call = read_frame(&mut stream);
+            let hh: RpcRequestHeaderProto = protobuf::CodedInputStream::from_bytes(&call)
+                .read_message()
+                .unwrap();
+
+            let mut resp_header = RpcResponseHeaderProto::default();
+            resp_header.set_callId(hh.get_callId() as u32);
+            let mut reply = if standby {
+                resp_header.set_status(RpcStatus::ERROR);
+                resp_header.set_errorDetail(RpcErrorCode::ERROR_APPLICATION);
+                resp_header.set_exceptionClassName("org.apache.hadoop.ipc.StandbyException".into());
+                resp_header.set_errorMsg("Operation category READ is not supported".into());
+                resp_header.write_length_delimited_to_bytes().unwrap()
+            } else {
+                resp_header.set_status(RpcStatus::SUCCESS);
+                let mut reply = resp_header.write_length_delimited_to_bytes().unwrap();
+                reply.extend(
+                    GetFileInfoResponseProto::default()
+                        .write_length_delimited_to_bytes()
+                        .unwrap(),
+                );
+                reply
+            };
+            let mut frame = (reply.len() as u32).to_be_bytes().to_vec();
+            frame.append(&mut reply);
+            stream.write_all(&frame).unwrap();
+            // Wait for the client to close the connection.
+            let mut buf = [0; 1024];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+        addr
+    }
+
+    /// Connects to the addresses in turn, whatever node is asked.
+    #[derive(Debug)]
+    struct SequenceConnector {
+        addrs: Mutex<std::vec::IntoIter<SocketAddr>>,
+    }
+
+    impl Connector for SequenceConnector {
+        fn get_connection<T: ToSocketAddrs>(&self, _addr: T) -> Result<TcpStream, std::io::Error> {
+            match self.addrs.lock().unwrap().next() {
+                Some(addr) => TcpStream::connect(addr),
+                None => Err(std::io::ErrorKind::ConnectionRefused.into()),
+            }
+        }
+    }
+
+    fn namenode(name: &str) -> NamenodeConfig {
+        NamenodeConfig {
+            name: name.into(),
+            rpc_address: format!("{}:8020", name).into(),
+            servicerpc_address: format!("{}:8020", name).into(),
+        }
+    }
+
+    #[test]
+    fn test_connect_limited_by_deadline() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let connector = TimeoutConnector::default();
+        let timeouts = connector.timeouts.clone();
+        let mut conn = HaHdfsConnection::with_users(&ns, "test".into(), None, connector).unwrap();
+        conn.set_call_options(CallOptions::default().with_timeout(Duration::from_secs(10)));
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        assert!(res.is_err());
+        let timeouts = timeouts.lock().unwrap();
+        assert_eq!(timeouts.len(), 2);
+        for timeout in timeouts.iter() {
+            assert!(timeout.unwrap() <= Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn test_connect_after_deadline() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let connector = TimeoutConnector::default();
+        let timeouts = connector.timeouts.clone();
+        let mut conn = HaHdfsConnection::with_users(&ns, "test".into(), None, connector).unwrap();
+        conn.set_call_options(CallOptions::default().with_deadline(Instant::now()));
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        assert!(matches!(res, Err(RpcError::TimedOut { .. })));
+        assert!(timeouts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failover_events() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let mut conn =
+            HaHdfsConnection::with_users(&ns, "test".into(), None, RefusingConnector).unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        conn.set_failover_listener(Some(FailoverListener::new(move |event| {
+            seen.lock().unwrap().push(event.clone())
+        })));
+
+        let res: Result<hdfesse_proto::ClientNamenodeProtocol::MsyncResponseProto, _> = conn.call(
+            "msync".into(),
+            &hdfesse_proto::ClientNamenodeProtocol::MsyncRequestProto::default(),
+        );
+        assert!(res.is_err());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [FailoverEvent {
+                from_node: "nn1:8020".into(),
+                to_node: "nn2:8020".into(),
+                reason: FailoverReason::ConnectFailed,
+                attempt: 2,
+            }]
+        );
+        assert_eq!(
+            conn.failover_counts(),
+            FailoverCounts {
+                standby: 0,
+                connect_failed: 2,
+                call_failed: 0,
+                retries: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_standby_failover() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let connector = SequenceConnector {
+            addrs: Mutex::new(
+                vec![answering_namenode(true), answering_namenode(false)].into_iter(),
+            ),
+        };
+        let mut conn = HaHdfsConnection::with_users(&ns, "test".into(), None, connector).unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        conn.set_failover_listener(Some(FailoverListener::new(move |event| {
+            seen.lock().unwrap().push(event.clone())
+        })));
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        res.unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [FailoverEvent {
+                from_node: "nn1:8020".into(),
+                to_node: "nn2:8020".into(),
+                reason: FailoverReason::Standby,
+                attempt: 2,
+            }]
+        );
+        assert_eq!(
+            conn.failover_counts(),
+            FailoverCounts {
+                standby: 1,
+                connect_failed: 0,
+                call_failed: 0,
+                retries: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_last_failure() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let mut conn =
+            HaHdfsConnection::with_users(&ns, "test".into(), None, RefusingConnector).unwrap();
+        assert!(conn.last_failure().is_none());
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        assert!(res.is_err());
+        let failure = conn.last_failure().unwrap();
+        assert_eq!(failure.method, "getFileInfo");
+        let nodes: Vec<_> = failure
+            .attempts
+            .iter()
+            .map(|attempt| attempt.node.as_str())
+            .collect();
+        assert_eq!(nodes, ["nn1:8020", "nn2:8020"]);
+        assert!(failure.attempts[0].error.starts_with("connect failed"));
+    }
+
+    #[test]
+    fn test_unsafe_calls_not_resent() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let connector = ClosingConnector::new();
+        let received = connector.received.clone();
+        let mut conn = HaHdfsConnection::with_users(&ns, "test".into(), None, connector).unwrap();
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("noSuchMethod".into(), &GetFileInfoRequestProto::default());
+        assert!(res.is_err());
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(conn.failover_counts().call_failed, 1);
+    }
+
+    #[test]
+    fn test_resent_call_keeps_call_id() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let connector = ClosingConnector::new();
+        let received = connector.received.clone();
+        let mut conn = HaHdfsConnection::with_users(&ns, "test".into(), None, connector).unwrap();
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        assert!(res.is_err());
+        let res: Result<RenameResponseProto, _> =
+            conn.call("rename".into(), &RenameRequestProto::default());
+        assert!(res.is_err());
+
+        let headers: Vec<_> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| call_header(data))
+            .collect();
+        assert_eq!(
+            headers
+                .iter()
+                .map(|hh| (hh.get_callId(), hh.get_retryCount()))
+                .collect::<Vec<_>>(),
+            [(0, 0), (0, 1), (1, 0), (1, 1)]
+        );
+        assert!(headers
+            .iter()
+            .all(|hh| hh.get_clientId() == headers[0].get_clientId()));
+        assert_eq!(conn.failover_counts().call_failed, 4);
+    }
+
+    #[test]
+    fn test_method_idempotency() {
+        assert_eq!(
+            Idempotency::of_method("getFileInfo"),
+            Idempotency::Idempotent
+        );
+        assert_eq!(Idempotency::of_method("rename"), Idempotency::AtMostOnce);
+        assert_eq!(Idempotency::of_method("noSuchMethod"), Idempotency::Unsafe);
+    }
+
+    /// Numbered users, a new one for each request.
+    #[derive(Debug, Default)]
+    struct RotatingUser(Mutex<usize>);
+
+    impl AuthProvider for RotatingUser {
+        fn credentials(
+            &self,
+        ) -> Result<crate::auth::Credentials, Box<dyn std::error::Error + Send + Sync>> {
+            let mut n = self.0.lock().unwrap();
+            *n += 1;
+            Ok(crate::auth::Credentials::Simple {
+                user: format!("svc{}", n),
+            })
+        }
+    }
+
+    #[test]
+    fn test_auth_provider_per_connection() {
+        let ns = NameserviceConfig {
+            name: "test".into(),
+            rpc_nodes: vec![namenode("nn1"), namenode("nn2")],
+        };
+        let connector = ClosingConnector::new();
+        let received = connector.received.clone();
+        let auth = Arc::new(RotatingUser::default());
+        let mut conn =
+            HaHdfsConnection::with_auth_provider_as_proxy(&ns, "alice", auth, connector).unwrap();
+        assert_eq!(conn.get_user(), "alice");
+        assert_eq!(conn.get_real_user(), Some("svc1"));
+
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        assert!(res.is_err());
+        let users: Vec<_> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| {
+                let user_info = connection_context(data).take_userInfo();
+                (
+                    user_info.get_effectiveUser().to_owned(),
+                    user_info.get_realUser().to_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            users,
+            [
+                ("alice".to_owned(), "svc2".to_owned()),
+                ("alice".to_owned(), "svc3".to_owned())
+            ]
+        );
+        assert_eq!(conn.get_real_user(), Some("svc3"));
+    }
 }
@@ -13,21 +13,28 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
+use crate::trace::{debug, info, warn};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 use std::{collections::HashMap, fmt::Debug, ops::Deref};
 use thiserror::Error;
-use tracing::{debug, info, warn};
 use xml::reader::{EventReader, XmlEvent};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-/// Try to get path to config from the environment.  It is either from
+/// Config directory from the environment.  It is either from
 /// HADOOP_CONF_DIR default variable or "/etc/hadoop/conf" directory.
+pub fn get_config_dir() -> PathBuf {
+    std::env::var_os("HADOOP_CONF_DIR")
+        .unwrap_or_else(|| "/etc/hadoop/conf".into())
+        .into()
+}
+
+/// Try to get path to config from the environment; see
+/// get_config_dir.
 pub fn get_config_path(path: &str) -> PathBuf {
-    let conf_dir = std::env::var_os("HADOOP_CONF_DIR").unwrap_or_else(|| "/etc/hadoop/conf".into());
-    PathBuf::from(conf_dir).join(path)
+    get_config_dir().join(path)
 }
 
 #[derive(Debug)]
@@ -87,8 +94,21 @@ pub enum ConfigError {
     // TODO: xml Error has ErrorKind::Io(std::io::Error).
     #[error("failed to read config {:?}: {:?}", .1, .0)]
     Xml(xml::reader::Error, PathBuf),
+    /// The chain of keys, like "a -> b -> a".
+    #[error("cyclic variable substitution: {0}")]
+    Cycle(Box<str>),
+    /// The includes are nested deeper than MAX_INCLUDE_DEPTH, which
+    /// is likely a file that includes itself.
+    #[error("config includes are nested too deeply at {0:?}")]
+    IncludeDepth(PathBuf),
 }
 
+const XINCLUDE_NS: &str = "http://www.w3.org/2001/XInclude";
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Lookup of the environment variables of `${env.NAME}`.
+type EnvLookup<'e> = &'e dyn Fn(&str) -> Option<String>;
+
 #[derive(Clone, Debug)]
 pub struct ConfigData {
     value: Box<str>,
@@ -140,7 +160,96 @@ impl ConfigMap {
         self.0.get(key)
     }
 
-    #[tracing::instrument]
+    /**
+    The value with `${var}` references expanded, like Java's
+    Configuration.get does.  A var is either another key or an
+    environment variable as `${env.NAME}`, with an optional default:
+    `${env.NAME:-default}` if it is unset or empty and
+    `${env.NAME-default}` if it is unset.  Undefined vars are kept
+    as is; references forming a cycle are an error.
+    */
+    pub fn get_expanded(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.get_expanded_with(key, &|name| std::env::var(name).ok())
+    }
+
+    /// Same as get_expanded, with the environment variables from the
+    /// lookup.
+    pub fn get_expanded_with(
+        &self,
+        key: &str,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Option<String>, ConfigError> {
+        self.expand_key(key, env, &mut vec![])
+    }
+
+    fn expand_key(
+        &self,
+        key: &str,
+        env: EnvLookup<'_>,
+        stack: &mut Vec<String>,
+    ) -> Result<Option<String>, ConfigError> {
+        if stack.iter().any(|seen| seen == key) {
+            stack.push(key.to_owned());
+            return Err(ConfigError::Cycle(stack.join(" -> ").into()));
+        }
+        let data = match self.get(key) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        stack.push(key.to_owned());
+        let value = self.expand(data.value(), env, stack)?;
+        stack.pop();
+        Ok(Some(value))
+    }
+
+    fn expand(
+        &self,
+        value: &str,
+        env: EnvLookup<'_>,
+        stack: &mut Vec<String>,
+    ) -> Result<String, ConfigError> {
+        let mut res = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            let end = match rest[start..].find('}') {
+                Some(len) => start + len,
+                None => break,
+            };
+            res.push_str(&rest[..start]);
+            match self.lookup_var(&rest[start + 2..end], env, stack)? {
+                Some(var) => res.push_str(&var),
+                None => res.push_str(&rest[start..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        res.push_str(rest);
+        Ok(res)
+    }
+
+    fn lookup_var(
+        &self,
+        var: &str,
+        env: EnvLookup<'_>,
+        stack: &mut Vec<String>,
+    ) -> Result<Option<String>, ConfigError> {
+        let env_var = match var.strip_prefix("env.") {
+            Some(env_var) => env_var,
+            None => return self.expand_key(var, env, stack),
+        };
+        let (name, default) = match env_var.find('-') {
+            Some(pos) => (&env_var[..pos], Some(&env_var[pos + 1..])),
+            None => (env_var, None),
+        };
+        Ok(match (name.strip_suffix(':'), default) {
+            (Some(name), Some(default)) => env(name)
+                .filter(|val| !val.is_empty())
+                .or_else(|| Some(default.to_owned())),
+            (None, Some(default)) => env(name).or_else(|| Some(default.to_owned())),
+            (_, None) => env(name),
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
     pub fn insert<T: Into<Box<str>> + AsRef<str> + Debug>(
         &mut self,
         key: T,
@@ -164,17 +273,100 @@ impl ConfigMap {
         old_final
     }
 
-    #[tracing::instrument(skip(r))]
+    /// Set the value even if the key is final, like Java's
+    /// Configuration.set does for command line overrides.
+    pub fn set<T: Into<Box<str>> + AsRef<str> + Debug>(&mut self, key: T, val: T) {
+        if self.0.get(key.as_ref()).map(ConfigData::is_final) == Some(true) {
+            info!(key = key.as_ref(), "overriding final value");
+        }
+        self.0.insert(key.into(), ConfigData::new(val, false));
+    }
+
+    /**
+    Merge the properties of the config file.  Like Java's
+    Configuration, the file may include others with XInclude:
+    `<xi:include href="other.xml"/>`, relative to the file's
+    directory.  A missing file is an error, unless the include has an
+    `<xi:fallback>`, whose properties are merged instead.
+    */
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(r)))]
     pub fn merge_config<R: Read>(&mut self, r: R, config_path: &Path) -> Result<(), ConfigError> {
+        self.merge_config_nested(r, config_path, 0)
+    }
+
+    fn merge_config_nested<R: Read>(
+        &mut self,
+        r: R,
+        config_path: &Path,
+        depth: usize,
+    ) -> Result<(), ConfigError> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(ConfigError::IncludeDepth(config_path.to_owned()));
+        }
         let parser = EventReader::new(r);
 
         let mut elt = None;
         let mut key = None;
         let mut val: Option<String> = None;
         let mut final_: Option<String> = None;
+        // The error of the current include, until its fallback is
+        // seen.
+        let mut include_error = None;
+        let mut in_fallback = false;
+        let mut skip_fallback = false;
 
         for e in parser {
             match e.map_err(|e| ConfigError::Xml(e, config_path.to_owned()))? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } if name.namespace.as_deref() == Some(XINCLUDE_NS) => {
+                    match name.local_name.as_str() {
+                        "include" => {
+                            let href = attributes
+                                .iter()
+                                .find(|attr| attr.name.local_name == "href")
+                                .map(|attr| attr.value.as_str())
+                                .unwrap_or_default();
+                            let included = config_path
+                                .parent()
+                                .unwrap_or_else(|| Path::new(""))
+                                .join(href);
+                            match std::fs::File::open(&included) {
+                                Ok(f) => {
+                                    debug!("including config file {:?}", included);
+                                    self.merge_config_nested(
+                                        io::BufReader::new(f),
+                                        &included,
+                                        depth + 1,
+                                    )?;
+                                    skip_fallback = true;
+                                }
+                                Err(e) => {
+                                    include_error = Some(ConfigError::Io(e, included));
+                                    skip_fallback = false;
+                                }
+                            }
+                        }
+                        "fallback" => {
+                            include_error = None;
+                            in_fallback = true;
+                        }
+                        _ => {}
+                    }
+                }
+                XmlEvent::EndElement { name } if name.namespace.as_deref() == Some(XINCLUDE_NS) => {
+                    match name.local_name.as_str() {
+                        "include" => {
+                            if let Some(e) = include_error.take() {
+                                return Err(e);
+                            }
+                        }
+                        "fallback" => in_fallback = false,
+                        _ => {}
+                    }
+                }
+                // The fallback of a successful include is ignored.
+                _ if in_fallback && skip_fallback => {}
                 XmlEvent::StartElement { name, .. } => {
                     let name = name.to_string();
                     if name == "property" {
@@ -223,11 +415,16 @@ impl Default for ConfigMap {
 Load the XML Hadoop/HDFS configs from a config groups, and return
 ConfigMap.
 */
-#[tracing::instrument]
 pub fn load_config(config_path_group: &ConfigPathGroup) -> ConfigMap {
+    load_config_from_dir(&get_config_dir(), config_path_group)
+}
+
+/// Same as load_config, but from the explicit directory.
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub fn load_config_from_dir(conf_dir: &Path, config_path_group: &ConfigPathGroup) -> ConfigMap {
     let mut config_map = ConfigMap::new();
 
-    let config_paths = config_path_group.iter().map(get_config_path);
+    let config_paths = config_path_group.iter().map(|path| conf_dir.join(path));
 
     for config_path in config_paths {
         debug!("merging config file {:?}", config_path);
@@ -255,7 +452,7 @@ pub fn load_config(config_path_group: &ConfigPathGroup) -> ConfigMap {
     config_map
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct NamenodeConfig {
     pub name: Box<str>,
@@ -265,13 +462,21 @@ pub struct NamenodeConfig {
     pub servicerpc_address: Box<str>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct NameserviceConfig {
     pub name: Box<str>,
     pub rpc_nodes: Vec<NamenodeConfig>,
 }
 
+/// The expanded value; on a substitution cycle, the value as is.
+fn get_value(conf: &ConfigMap, key: &str) -> Option<String> {
+    conf.get_expanded(key).unwrap_or_else(|e| {
+        warn!("{}: {}", key, e);
+        conf.get(key).map(|data| data.value().to_owned())
+    })
+}
+
 fn parse_namenode(conf: &ConfigMap, namenode: &str, nameservice: &str) -> Option<NamenodeConfig> {
     let rpc_key = format!("dfs.namenode.rpc-address.{}.{}", nameservice, namenode);
     let servicerpc_key = format!(
@@ -279,46 +484,64 @@ fn parse_namenode(conf: &ConfigMap, namenode: &str, nameservice: &str) -> Option
         nameservice, namenode
     );
 
-    let rpc = conf.get(rpc_key.as_str());
-    let servicerpc = conf.get(servicerpc_key.as_str());
+    let rpc = get_value(conf, &rpc_key);
+    let servicerpc = get_value(conf, &servicerpc_key);
 
     rpc.map(|rpc| {
-        let servicerpc = servicerpc.unwrap_or(rpc);
+        let servicerpc = servicerpc.unwrap_or_else(|| rpc.clone());
         NamenodeConfig {
             name: namenode.into(),
-            rpc_address: rpc.deref().into(),
-            servicerpc_address: servicerpc.deref().into(),
+            rpc_address: rpc.into(),
+            servicerpc_address: servicerpc.into(),
         }
     })
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Java's HdfsClientConfigKeys.DFS_NAMENODE_RPC_PORT_DEFAULT.
+pub const DEFAULT_NAMENODE_RPC_PORT: u16 = 8020;
+
+/// The suffix of the client name, see
+/// lease::generate_client_name_with_suffix.
+pub const CLIENT_NAME_SUFFIX_KEY: &str = "hdfesse.client.name.suffix";
+
+/// The maximal rate of namenode calls per second, for bulk tools.  The
+/// CLI shares one rate_limit::RateLimiter between its connections.
+pub const NAMENODE_QPS_KEY: &str = "hdfesse.client.namenode.qps";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct Config {
     pub default_fs: Option<Box<str>>,
     pub services: Vec<NameserviceConfig>,
+    pub client_name_suffix: Option<Box<str>>,
+    pub namenode_qps: Option<u32>,
 }
 
 impl Config {
     pub fn auto() -> Self {
         get_auto_config(&HDFS_CONFIG)
     }
+
+    /// The nameservice of a path's authority, like "mycluster" in
+    /// hdfs://mycluster/a/b.  Host names are case-insensitive.
+    pub fn nameservice(&self, authority: &str) -> Option<&NameserviceConfig> {
+        self.services
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(authority))
+    }
 }
 
 /// Get useful data as a config object.
 pub fn parse_config(conf: &ConfigMap) -> Config {
     let mut services = vec![];
 
-    for name in conf
-        .get("dfs.nameservices")
-        .map(Deref::deref)
-        .unwrap_or("")
+    for name in get_value(conf, "dfs.nameservices")
+        .unwrap_or_default()
         .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
     {
-        let namenodes = conf
-            .get(format!("dfs.ha.namenodes.{}", name).as_str())
-            .map(Deref::deref)
-            .unwrap_or("");
+        let namenodes = get_value(conf, &format!("dfs.ha.namenodes.{}", name)).unwrap_or_default();
 
         let serv = NameserviceConfig {
             name: name.into(),
@@ -331,12 +554,59 @@ pub fn parse_config(conf: &ConfigMap) -> Config {
         services.push(serv);
     }
 
-    let default_fs = conf.get("fs.defaultFS").map(|x| x.value.trim().into());
+    let default_fs: Option<Box<str>> = get_value(conf, "fs.defaultFS").map(|x| x.trim().into());
+    if let Some(serv) = default_fs.as_deref().and_then(single_namenode_service) {
+        if !services
+            .iter()
+            .any(|s| s.name.eq_ignore_ascii_case(&serv.name))
+        {
+            services.push(serv);
+        }
+    }
+
+    let client_name_suffix = get_value(conf, CLIENT_NAME_SUFFIX_KEY)
+        .map(|suffix| suffix.trim().to_owned())
+        .filter(|suffix| !suffix.is_empty())
+        .map(Into::into);
+    // An invalid or zero rate means no limit.
+    let namenode_qps = get_value(conf, NAMENODE_QPS_KEY)
+        .and_then(|qps| qps.trim().parse().ok())
+        .filter(|qps| *qps > 0);
 
     Config {
         default_fs,
         services,
+        client_name_suffix,
+        namenode_qps,
+    }
+}
+
+/**
+ * The nameservice of a non-HA cluster, where the authority of
+ * `hdfs://host:port` is the namenode's address.  It is named by the
+ * host, as paths are matched to nameservices by the host only.
+ */
+fn single_namenode_service(default_fs: &str) -> Option<NameserviceConfig> {
+    let path = crate::path::Path::new(default_fs).ok()?;
+    if path.scheme().as_deref() != Some("hdfs") {
+        return None;
     }
+    let host = path.host().filter(|host| !host.is_empty())?;
+    let port = path.port().unwrap_or(DEFAULT_NAMENODE_RPC_PORT);
+    let address: Box<str> = if host.contains(':') {
+        // IPv6 literal.
+        format!("[{}]:{}", host, port).into()
+    } else {
+        format!("{}:{}", host, port).into()
+    };
+    Some(NameserviceConfig {
+        name: host.as_str().into(),
+        rpc_nodes: vec![NamenodeConfig {
+            name: host.into(),
+            rpc_address: address.clone(),
+            servicerpc_address: address,
+        }],
+    })
 }
 
 pub fn get_auto_config(config_path_group: &ConfigPathGroup) -> Config {
@@ -451,7 +721,241 @@ mod tests {
 
         match config.merge_config(&mut Cursor::new(data), Path::new("/test/me")) {
             Err(ConfigError::Xml(_, path)) => assert_eq!(path.to_str(), Some("/test/me")),
-            _ => assert!(false, "Expecint XML error"),
+            _ => panic!("Expecint XML error"),
         }
     }
+
+    #[test]
+    fn test_config_nameservice() {
+        let mut conf = ConfigMap::new();
+        conf.insert("dfs.nameservices", "one,two", false);
+        let config = parse_config(&conf);
+        assert_eq!(config.nameservice("two").unwrap().name.as_ref(), "two");
+        assert_eq!(config.nameservice("ONE").unwrap().name.as_ref(), "one");
+        assert!(config.nameservice("three").is_none());
+    }
+
+    #[test]
+    fn test_config_set_overrides_final() {
+        let mut conf = ConfigMap::new();
+        conf.insert("key", "old", true);
+        assert!(conf.insert("key", "ignored", false));
+        conf.set("key", "new");
+        assert_eq!(conf.get("key").unwrap().value(), "new");
+    }
+
+    #[test]
+    fn test_get_expanded() {
+        let mut conf = ConfigMap::new();
+        conf.set("nn.host", "nn1.example.com");
+        conf.set("nn.port", "${nn.rpc.port}");
+        conf.set("nn.rpc.port", "8020");
+        conf.set("dfs.namenode.rpc-address.mc.nn1", "${nn.host}:${nn.port}");
+        conf.set("undefined", "${no.such.key}/x");
+        conf.set("unclosed", "a${nn.host");
+        conf.set(
+            "env",
+            "${env.HDFESSE_TEST_UNSET:-dflt}:${env.HDFESSE_TEST_EMPTY-x}",
+        );
+        let env = |name: &str| match name {
+            "HDFESSE_TEST_EMPTY" => Some(String::new()),
+            _ => None,
+        };
+
+        assert_eq!(
+            conf.get_expanded("dfs.namenode.rpc-address.mc.nn1")
+                .unwrap()
+                .as_deref(),
+            Some("nn1.example.com:8020")
+        );
+        assert_eq!(
+            conf.get_expanded("undefined").unwrap().as_deref(),
+            Some("${no.such.key}/x")
+        );
+        assert_eq!(
+            conf.get_expanded("unclosed").unwrap().as_deref(),
+            Some("a${nn.host")
+        );
+        assert_eq!(
+            conf.get_expanded_with("env", &env).unwrap().as_deref(),
+            Some("dflt:")
+        );
+        assert_eq!(conf.get_expanded("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_expanded_cycle() {
+        let mut conf = ConfigMap::new();
+        conf.set("a", "${b}");
+        conf.set("b", "x${a}");
+        conf.set("c", "${c}");
+        // The same var twice is not a cycle.
+        conf.set("d", "${e}${e}");
+        conf.set("e", "e");
+        match conf.get_expanded("a") {
+            Err(ConfigError::Cycle(chain)) => assert_eq!(chain.as_ref(), "a -> b -> a"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(conf.get_expanded("c"), Err(ConfigError::Cycle(_))));
+        assert_eq!(conf.get_expanded("d").unwrap().as_deref(), Some("ee"));
+    }
+
+    #[test]
+    fn test_parse_config_expanded() {
+        let mut conf = ConfigMap::new();
+        conf.set("cluster", "mc");
+        conf.set("fs.defaultFS", "hdfs://${cluster}");
+        conf.set("dfs.nameservices", "${cluster}");
+        conf.set("dfs.ha.namenodes.mc", "nn1");
+        conf.set(
+            "dfs.namenode.rpc-address.mc.nn1",
+            "${dfs.namenode.rpc-address.mc.nn1}",
+        );
+        let config = parse_config(&conf);
+        assert_eq!(config.default_fs.as_deref(), Some("hdfs://mc"));
+        let nn1 = &config.nameservice("mc").unwrap().rpc_nodes[0];
+        // The cyclic value is used as is.
+        assert_eq!(
+            nn1.rpc_address.as_ref(),
+            "${dfs.namenode.rpc-address.mc.nn1}"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_client_name_suffix() {
+        let mut conf = ConfigMap::new();
+        assert_eq!(parse_config(&conf).client_name_suffix, None);
+        conf.set(CLIENT_NAME_SUFFIX_KEY, " ");
+        assert_eq!(parse_config(&conf).client_name_suffix, None);
+        conf.set("app", "myservice");
+        conf.set(CLIENT_NAME_SUFFIX_KEY, "hdfesse/0.3 ${app}");
+        assert_eq!(
+            parse_config(&conf).client_name_suffix.as_deref(),
+            Some("hdfesse/0.3 myservice")
+        );
+    }
+
+    #[test]
+    fn test_parse_config_namenode_qps() {
+        let mut conf = ConfigMap::new();
+        assert_eq!(parse_config(&conf).namenode_qps, None);
+        conf.set(NAMENODE_QPS_KEY, " 200 ");
+        assert_eq!(parse_config(&conf).namenode_qps, Some(200));
+        conf.set(NAMENODE_QPS_KEY, "0");
+        assert_eq!(parse_config(&conf).namenode_qps, None);
+        conf.set(NAMENODE_QPS_KEY, "fast");
+        assert_eq!(parse_config(&conf).namenode_qps, None);
+    }
+
+    #[test]
+    fn test_parse_config_non_ha() {
+        let mut conf = ConfigMap::new();
+        conf.set("fs.defaultFS", "hdfs://nn.example.com:9000/");
+        let config = parse_config(&conf);
+        assert_eq!(
+            config.services,
+            vec![NameserviceConfig {
+                name: "nn.example.com".into(),
+                rpc_nodes: vec![NamenodeConfig {
+                    name: "nn.example.com".into(),
+                    rpc_address: "nn.example.com:9000".into(),
+                    servicerpc_address: "nn.example.com:9000".into(),
+                }],
+            }]
+        );
+
+        conf.set("fs.defaultFS", "hdfs://nn.example.com");
+        let config = parse_config(&conf);
+        assert_eq!(
+            config.nameservice("nn.example.com").unwrap().rpc_nodes[0]
+                .rpc_address
+                .as_ref(),
+            "nn.example.com:8020"
+        );
+
+        // A configured nameservice is not replaced.
+        conf.set("fs.defaultFS", "hdfs://mycluster");
+        conf.set("dfs.nameservices", "mycluster");
+        let config = parse_config(&conf);
+        assert_eq!(config.services.len(), 1);
+        assert!(config.services[0].rpc_nodes.is_empty());
+
+        conf.set("fs.defaultFS", "file:///");
+        conf.set("dfs.nameservices", "");
+        assert!(parse_config(&conf).services.is_empty());
+    }
+
+    #[test]
+    fn test_load_config_from_dir() {
+        let dir = std::env::temp_dir().join(format!("hdfesse-conf-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join("core-site.xml"),
+            "<configuration><property><name>fs.defaultFS</name>\
+             <value>hdfs://other</value></property></configuration>",
+        )
+        .unwrap();
+        let conf = load_config_from_dir(&dir, &HDFS_CONFIG);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            parse_config(&conf).default_fs.as_deref(),
+            Some("hdfs://other")
+        );
+    }
+
+    #[test]
+    fn test_merge_config_include() {
+        let dir = std::env::temp_dir().join(format!("hdfesse-conf-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join("extra.xml"),
+            "<configuration><property><name>included</name>\
+             <value>yes</value></property></configuration>",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("loop.xml"),
+            r#"<configuration xmlns:xi="http://www.w3.org/2001/XInclude">
+                 <xi:include href="loop.xml"/></configuration>"#,
+        )
+        .unwrap();
+        let site = dir.join("site.xml");
+        let mut conf = ConfigMap::new();
+        let res = conf.merge_config(
+            Cursor::new(
+                r#"<configuration xmlns:xi="http://www.w3.org/2001/XInclude">
+                     <xi:include href="extra.xml">
+                       <xi:fallback><property><name>fallback1</name><value>x</value></property></xi:fallback>
+                     </xi:include>
+                     <xi:include href="missing.xml">
+                       <xi:fallback><property><name>fallback2</name><value>y</value></property></xi:fallback>
+                     </xi:include>
+                     <property><name>own</name><value>z</value></property>
+                   </configuration>"#,
+            ),
+            &site,
+        );
+        let missing = ConfigMap::new().merge_config(
+            Cursor::new(
+                r#"<configuration xmlns:xi="http://www.w3.org/2001/XInclude">
+                     <xi:include href="missing.xml"/></configuration>"#,
+            ),
+            &site,
+        );
+        let looped = ConfigMap::new().merge_config(
+            std::fs::File::open(dir.join("loop.xml")).unwrap(),
+            &dir.join("loop.xml"),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        res.unwrap();
+        assert_eq!(conf.get("included").unwrap().value(), "yes");
+        assert!(conf.get("fallback1").is_none());
+        assert_eq!(conf.get("fallback2").unwrap().value(), "y");
+        assert_eq!(conf.get("own").unwrap().value(), "z");
+        assert!(
+            matches!(missing, Err(ConfigError::Io(_, path)) if path == dir.join("missing.xml"))
+        );
+        assert!(matches!(looped, Err(ConfigError::IncludeDepth(_))));
+    }
 }
@@ -0,0 +1,168 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Client identity for the namenode leases.  A file open for writing
+ * is leased to the client name that created it; the lease expires
+ * unless the client renews it within the soft limit (one minute).
+ */
+use std::time::{Duration, Instant};
+
+/// Same as Java's dfs.client.task.id default for non-MapReduce
+/// clients.
+const TASK_ID: &str = "NONMAPREDUCE";
+
+/// Half of the namenode's soft limit, like Java's LeaseRenewer.
+pub const DEFAULT_RENEWAL_PERIOD: Duration = Duration::from_secs(30);
+
+/**
+ * Generate a client name in Java's DFSClient format,
+ * `DFSClient_NONMAPREDUCE_<random>_<thread id>`.  Rust has no numeric
+ * thread id, so the process id takes its place.
+ */
+pub fn generate_client_name() -> String {
+    let bytes = uuid::Uuid::new_v4();
+    let bytes = bytes.as_bytes();
+    let random = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    format!("DFSClient_{}_{}_{}", TASK_ID, random, std::process::id())
+}
+
+/**
+ * A generated client name with the suffix appended, e.g.
+ * `DFSClient_NONMAPREDUCE_<random>_<pid>_hdfesse/0.3 myservice`.  The
+ * namenode shows the client name in its logs and in `fsck
+ * -openforwrite`, so the suffix tells which application holds a lease.
+ */
+pub fn generate_client_name_with_suffix(suffix: &str) -> String {
+    format!("{}_{}", generate_client_name(), suffix)
+}
+
+/**
+ * Lease state of a client: its name and the files it keeps open for
+ * writing.  The lease needs renewal only while there are such files.
+ */
+#[derive(Debug, Clone)]
+pub struct Lease {
+    client_name: String,
+    open_files: usize,
+    renewal_period: Duration,
+    last_renewal: Option<Instant>,
+}
+
+impl Lease {
+    pub fn new() -> Self {
+        Self::with_client_name(generate_client_name())
+    }
+
+    pub fn with_client_name(client_name: String) -> Self {
+        Self {
+            client_name,
+            open_files: 0,
+            renewal_period: DEFAULT_RENEWAL_PERIOD,
+            last_renewal: None,
+        }
+    }
+
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    /// Files already open for writing stay leased to the old name,
+    /// so the name should be set before opening any.
+    pub fn set_client_name(&mut self, client_name: String) {
+        self.client_name = client_name;
+    }
+
+    pub fn set_renewal_period(&mut self, period: Duration) {
+        self.renewal_period = period;
+    }
+
+    /// A writer opened a file under the lease.
+    pub fn file_opened(&mut self, now: Instant) {
+        if self.open_files == 0 {
+            // Creating the file has granted a fresh lease.
+            self.last_renewal = Some(now);
+        }
+        self.open_files += 1;
+    }
+
+    /// A writer closed its file.
+    pub fn file_closed(&mut self) {
+        self.open_files = self.open_files.saturating_sub(1);
+    }
+
+    pub fn open_files(&self) -> usize {
+        self.open_files
+    }
+
+    /// Whether the lease should be renewed now.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.open_files != 0
+            && self
+                .last_renewal
+                .map(|last| now.saturating_duration_since(last) >= self.renewal_period)
+                .unwrap_or(true)
+    }
+
+    pub fn renewed(&mut self, now: Instant) {
+        self.last_renewal = Some(now);
+    }
+}
+
+impl Default for Lease {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_name() {
+        let name = generate_client_name();
+        let parts: Vec<_> = name.split('_').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "DFSClient");
+        assert_eq!(parts[1], "NONMAPREDUCE");
+        assert!(parts[2].parse::<i32>().is_ok());
+        assert_eq!(parts[3], std::process::id().to_string());
+    }
+
+    #[test]
+    fn test_client_name_suffix() {
+        let name = generate_client_name_with_suffix("hdfesse/0.3 myservice");
+        assert!(name.starts_with("DFSClient_NONMAPREDUCE_"), "{}", name);
+        assert!(name.ends_with("_hdfesse/0.3 myservice"), "{}", name);
+    }
+
+    #[test]
+    fn test_renewal_due() {
+        let start = Instant::now();
+        let mut lease = Lease::with_client_name("test".to_owned());
+        assert!(!lease.is_due(start));
+
+        lease.file_opened(start);
+        assert!(!lease.is_due(start + Duration::from_secs(29)));
+        assert!(lease.is_due(start + DEFAULT_RENEWAL_PERIOD));
+
+        lease.renewed(start + DEFAULT_RENEWAL_PERIOD);
+        assert!(!lease.is_due(start + Duration::from_secs(59)));
+
+        lease.file_closed();
+        assert!(!lease.is_due(start + Duration::from_secs(3600)));
+    }
+}
@@ -13,15 +13,102 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
+/*!
+ * Pure Rust HDFS client.
+ *
+ * Paths, configs, statuses and errors are Send + Sync.  Clients and
+ * connections are Send but not Sync; use a client per thread or wrap
+ * it in a Mutex.  The tests below keep these guarantees.
+ */
 #![forbid(unsafe_code)]
 #![warn(rust_2018_idioms)]
+pub mod auth;
+pub mod block_cache;
+pub mod cancel;
+pub mod clock;
+pub mod clusters;
+#[cfg(feature = "data-transfer")]
+pub mod data_transfer;
+#[cfg(feature = "envelope")]
+pub mod envelope;
 pub mod erasure;
+pub mod error_code;
+pub mod filesystem;
+pub mod filter;
+pub mod follow;
 pub mod fs;
+mod fs_corrupt;
 mod fs_ls;
+mod fs_walk;
+pub mod glob;
 pub mod ha_rpc;
 pub mod hdconfig;
+pub mod lease;
+pub mod local;
+pub mod open_options;
+pub mod paged;
 pub mod path;
+pub mod preserve;
+pub mod rate_limit;
+pub mod registry;
 pub mod rpc;
+#[cfg(feature = "serde")]
+mod serde_proto;
 pub mod service;
+pub mod splits;
+pub mod stats;
 pub mod status;
+pub mod symlink;
+#[cfg(feature = "integration_test")]
+pub mod test_cluster;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod trace;
+pub mod user;
 mod util;
+pub mod version;
+
+/// Protobuf types used by the API; the path stays stable even if the
+/// proto crate is renamed.
+pub use hdfesse_proto as proto;
+
+#[cfg(test)]
+mod tests {
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_send_sync() {
+        use crate::*;
+        assert_send_sync::<path::Path<'static>>();
+        assert_send_sync::<path::UriResolver>();
+        assert_send_sync::<path::PathError>();
+        assert_send_sync::<hdconfig::Config>();
+        assert_send_sync::<hdconfig::ConfigMap>();
+        assert_send_sync::<status::HdfsFileStatus>();
+        assert_send_sync::<status::LocatedBlocks>();
+        assert_send_sync::<status::DatanodeInfo>();
+        assert_send_sync::<status::DatanodeStorageReport>();
+        assert_send_sync::<status::RollingUpgradeInfo>();
+        assert_send_sync::<status::QuotaUsage>();
+        assert_send_sync::<status::ContentSummary>();
+        assert_send_sync::<status::ErasureCodingPolicyInfo>();
+        assert_send_sync::<fs::FsStatus>();
+        assert_send_sync::<fs::HdfsError>();
+        assert_send_sync::<rpc::RpcError>();
+        assert_send_sync::<registry::RegistryError>();
+        assert_send_sync::<splits::Split>();
+        assert_send_sync::<cancel::CancellationToken>();
+        assert_send_sync::<block_cache::BlockLocationCache>();
+        assert_send_sync::<registry::SchemeRegistry<fs::Hdfs>>();
+        assert_send::<fs::Hdfs>();
+        assert_send::<rpc::HdfsConnection>();
+        assert_send::<clusters::ClusterManager>();
+        assert_send::<ha_rpc::HaHdfsConnection<rpc::SimpleConnector>>();
+        assert_send::<Box<dyn filesystem::FileSystem>>();
+        assert_send_sync::<ha_rpc::FailoverListener>();
+        assert_send_sync::<lease::Lease>();
+        assert_send_sync::<open_options::OpenOptions>();
+        assert_send_sync::<local::LocalFs>();
+    }
+}
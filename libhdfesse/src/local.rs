@@ -0,0 +1,324 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The local filesystem, i.e. the "file" scheme.
+ */
+use std::{
+    fs::{self, Metadata},
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    filesystem::{FileSystem, ListIterator},
+    fs::{FsError, HdfsError},
+    path::Path,
+    status::{FsPermission, HdfsFileStatus},
+};
+
+/// Java's RawLocalFileSystem reports the same block size.
+pub const LOCAL_BLOCK_SIZE: u64 = 32 * 1024 * 1024;
+
+const SCHEME: &str = "file";
+
+/**
+ * The local filesystem.  Relative paths are relative to the current
+ * directory of the process; the authority of a path is ignored.
+ */
+#[derive(Debug, Default)]
+pub struct LocalFs {}
+
+impl LocalFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn local_path(path: &Path<'_>) -> Result<String, FsError> {
+    match path.scheme() {
+        Some(scheme) if scheme != SCHEME => Err(FsError::WrongFs {
+            path: path.to_string(),
+            expected: "file:///".to_owned(),
+        }),
+        _ => Ok(path.to_path_string()),
+    }
+}
+
+/// Well-known IO errors are converted to the specific variants.
+fn local_error(e: io::Error, path: &str) -> FsError {
+    match e.kind() {
+        io::ErrorKind::NotFound => FsError::NotFound(path.to_owned()),
+        io::ErrorKind::PermissionDenied => FsError::AccessDenied(path.to_owned()),
+        io::ErrorKind::AlreadyExists => FsError::FileExists(path.to_owned()),
+        _ => FsError::Local(e),
+    }
+}
+
+fn millis(time: io::Result<SystemTime>) -> u64 {
+    time.ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn owner_perm(meta: &Metadata) -> (Box<str>, Box<str>, u16) {
+    use std::os::unix::fs::MetadataExt;
+    // Numeric ids, as there is no portable way to get the names.
+    (
+        meta.uid().to_string().into(),
+        meta.gid().to_string().into(),
+        (meta.mode() & 0o7777) as u16,
+    )
+}
+
+#[cfg(not(unix))]
+fn owner_perm(meta: &Metadata) -> (Box<str>, Box<str>, u16) {
+    let perm = if meta.permissions().readonly() {
+        0o555
+    } else {
+        0o777
+    };
+    ("".into(), "".into(), perm)
+}
+
+fn to_status(meta: &Metadata, name: &[u8], symlink: Option<Box<[u8]>>) -> HdfsFileStatus {
+    let (owner, group, perm) = owner_perm(meta);
+    let isdir = meta.is_dir();
+    HdfsFileStatus {
+        length: if isdir { 0 } else { meta.len() },
+        isdir,
+        replication: if isdir { 0 } else { 1 },
+        blocksize: if isdir { 0 } else { LOCAL_BLOCK_SIZE },
+        mtime: millis(meta.modified()),
+        atime: millis(meta.accessed()),
+        perm: FsPermission { perm },
+        flags: 0,
+        owner,
+        group,
+        symlink,
+        path: name.into(),
+        field_id: None,
+        locations: None,
+        children: None,
+        fe_info: None,
+        storage_policy: None,
+        ec_policty: None,
+    }
+}
+
+fn stat_local(path: &std::path::Path, name: &[u8]) -> io::Result<HdfsFileStatus> {
+    let meta = fs::symlink_metadata(path)?;
+    let symlink = if meta.file_type().is_symlink() {
+        Some(
+            fs::read_link(path)?
+                .to_string_lossy()
+                .into_owned()
+                .into_bytes()
+                .into(),
+        )
+    } else {
+        None
+    };
+    Ok(to_status(&meta, name, symlink))
+}
+
+impl FileSystem for LocalFs {
+    fn list<'s>(&'s mut self, path: &Path<'_>) -> Result<ListIterator<'s>, HdfsError> {
+        let path = local_path(path).map_err(HdfsError::src)?;
+        let meta = fs::metadata(&path)
+            .map_err(|e| local_error(e, &path))
+            .map_err(HdfsError::src)?;
+        if !meta.is_dir() {
+            return Err(HdfsError::src(FsError::NotDir(path)));
+        }
+        let entries = fs::read_dir(&path)
+            .map_err(|e| local_error(e, &path))
+            .map_err(HdfsError::src)?;
+        Ok(Box::new(entries.map(move |entry| {
+            let entry = entry
+                .map_err(|e| local_error(e, &path))
+                .map_err(HdfsError::op)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            stat_local(&entry.path(), name.as_bytes())
+                .map_err(|e| local_error(e, &name))
+                .map_err(HdfsError::op)
+        })))
+    }
+
+    fn stat(&mut self, path: &Path<'_>) -> Result<HdfsFileStatus, HdfsError> {
+        let path = local_path(path).map_err(HdfsError::src)?;
+        stat_local(path.as_ref(), b"")
+            .map_err(|e| local_error(e, &path))
+            .map_err(HdfsError::src)
+    }
+
+    fn rename(&mut self, src: &Path<'_>, dst: &Path<'_>) -> Result<(), HdfsError> {
+        let src = local_path(src).map_err(HdfsError::src)?;
+        let dst = local_path(dst).map_err(HdfsError::dst)?;
+        fs::rename(&src, &dst)
+            .map_err(|e| local_error(e, &src))
+            .map_err(HdfsError::op)
+    }
+
+    fn delete(&mut self, path: &Path<'_>, recursive: bool) -> Result<bool, HdfsError> {
+        let path = local_path(path).map_err(HdfsError::src)?;
+        let meta = match fs::symlink_metadata(&path) {
+            Ok(meta) => meta,
+            // Like HDFS, a missing path is not deleted, and it is
+            // not an error.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(HdfsError::src(local_error(e, &path))),
+        };
+        let res = if !meta.is_dir() {
+            fs::remove_file(&path)
+        } else if recursive {
+            fs::remove_dir_all(&path)
+        } else {
+            return Err(HdfsError::src(FsError::IsDir(path)));
+        };
+        res.map_err(|e| local_error(e, &path))
+            .map_err(HdfsError::op)?;
+        Ok(true)
+    }
+
+    fn mkdirs(&mut self, path: &Path<'_>, create_parent: bool) -> Result<bool, HdfsError> {
+        let path = local_path(path).map_err(HdfsError::src)?;
+        let res = if create_parent {
+            fs::create_dir_all(&path)
+        } else {
+            fs::create_dir(&path)
+        };
+        res.map_err(|e| local_error(e, &path))
+            .map_err(HdfsError::src)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::HdfsErrorKind;
+
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("hdfesse-{}", uuid::Uuid::new_v4()));
+            fs::create_dir(&path).unwrap();
+            Self(path)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_mkdirs_list_stat() {
+        let tmp = TempDir::new();
+        let mut local = LocalFs::new();
+        let nested = tmp.path("a/b");
+        assert!(local.mkdirs(&Path::new(&nested).unwrap(), true).unwrap());
+        fs::write(tmp.path("a/file"), b"hello").unwrap();
+
+        let a = tmp.path("a");
+        let mut names: Vec<(Vec<u8>, bool)> = local
+            .list(&Path::new(&a).unwrap())
+            .unwrap()
+            .map(|entry| entry.map(|status| (status.path.into_vec(), status.isdir)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![(b"b".to_vec(), true), (b"file".to_vec(), false)]
+        );
+
+        let file = tmp.path("a/file");
+        let status = local.stat(&Path::new(&file).unwrap()).unwrap();
+        assert_eq!(status.length, 5);
+        assert!(!status.isdir);
+        assert!(status.path.is_empty());
+    }
+
+    #[test]
+    fn test_mkdirs_exists() {
+        let tmp = TempDir::new();
+        let mut local = LocalFs::new();
+        let dir = tmp.path("dir");
+        let path = Path::new(&dir).unwrap();
+        local.mkdirs(&path, false).unwrap();
+        assert!(matches!(
+            local.mkdirs(&path, false),
+            Err(HdfsError {
+                source: FsError::FileExists(_),
+                ..
+            })
+        ));
+        assert!(local.mkdirs(&path, true).unwrap());
+    }
+
+    #[test]
+    fn test_rename_delete() {
+        let tmp = TempDir::new();
+        let mut local = LocalFs::new();
+        let (src, dst) = (tmp.path("src"), tmp.path("dst"));
+        local.mkdirs(&Path::new(&src).unwrap(), false).unwrap();
+        fs::write(tmp.path("src/file"), b"").unwrap();
+
+        local
+            .rename(&Path::new(&src).unwrap(), &Path::new(&dst).unwrap())
+            .unwrap();
+        let dst = Path::new(&dst).unwrap();
+        assert!(matches!(
+            local.delete(&dst, false),
+            Err(HdfsError {
+                kind: HdfsErrorKind::Src,
+                source: FsError::IsDir(_),
+            })
+        ));
+        assert!(local.delete(&dst, true).unwrap());
+        assert!(!local.delete(&dst, true).unwrap());
+        assert!(!local.delete(&dst, false).unwrap());
+        assert!(matches!(
+            local.stat(&dst),
+            Err(HdfsError {
+                source: FsError::NotFound(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_wrong_fs() {
+        let mut local = LocalFs::new();
+        assert!(local.stat(&Path::new("file:///").unwrap()).unwrap().isdir);
+        assert!(matches!(
+            local.stat(&Path::new("hdfs://cluster/").unwrap()),
+            Err(HdfsError {
+                source: FsError::WrongFs { .. },
+                ..
+            })
+        ));
+    }
+}
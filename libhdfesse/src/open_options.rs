@@ -0,0 +1,162 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Options of opening a file, like Java's FSDataInputStreamBuilder and
+ * FSDataOutputStreamBuilder.  Unset values mean the server or config
+ * defaults.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    Read,
+    /// Create or overwrite.
+    Write,
+    Append,
+}
+
+/// How the reader is going to access the file; it affects
+/// prefetching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStrategy {
+    Default,
+    /// Read the file in order; read ahead aggressively.
+    Sequential,
+    /// Positioned reads; do not read ahead.
+    Random,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    mode: OpenMode,
+    buffer_size: Option<usize>,
+    replication: Option<u16>,
+    block_size: Option<u64>,
+    verify_checksums: bool,
+    read_strategy: ReadStrategy,
+    short_circuit: bool,
+}
+
+impl OpenOptions {
+    pub fn new(mode: OpenMode) -> Self {
+        Self {
+            mode,
+            buffer_size: None,
+            replication: None,
+            block_size: None,
+            verify_checksums: true,
+            read_strategy: ReadStrategy::Default,
+            // Same as dfs.client.read.shortcircuit.
+            short_circuit: false,
+        }
+    }
+
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Replication of a new file.
+    pub fn with_replication(mut self, replication: u16) -> Self {
+        self.replication = Some(replication);
+        self
+    }
+
+    /// Block size of a new file.
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    pub fn with_verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    pub fn with_read_strategy(mut self, read_strategy: ReadStrategy) -> Self {
+        self.read_strategy = read_strategy;
+        self
+    }
+
+    /// Allow reading local replicas directly, bypassing the datanode.
+    pub fn with_short_circuit(mut self, short_circuit: bool) -> Self {
+        self.short_circuit = short_circuit;
+        self
+    }
+
+    pub fn mode(&self) -> OpenMode {
+        self.mode
+    }
+
+    pub fn buffer_size(&self) -> Option<usize> {
+        self.buffer_size
+    }
+
+    pub fn replication(&self) -> Option<u16> {
+        self.replication
+    }
+
+    pub fn block_size(&self) -> Option<u64> {
+        self.block_size
+    }
+
+    pub fn verify_checksums(&self) -> bool {
+        self.verify_checksums
+    }
+
+    pub fn read_strategy(&self) -> ReadStrategy {
+        self.read_strategy
+    }
+
+    pub fn short_circuit(&self) -> bool {
+        self.short_circuit
+    }
+
+    /// Whether the options create a new file.
+    pub fn is_create(&self) -> bool {
+        self.mode == OpenMode::Write
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let opts = OpenOptions::new(OpenMode::Read);
+        assert_eq!(opts.buffer_size(), None);
+        assert!(opts.verify_checksums());
+        assert!(!opts.short_circuit());
+        assert_eq!(opts.read_strategy(), ReadStrategy::Default);
+        assert!(!opts.is_create());
+    }
+
+    #[test]
+    fn test_builder() {
+        let opts = OpenOptions::new(OpenMode::Write)
+            .with_buffer_size(4096)
+            .with_replication(2)
+            .with_block_size(64 << 20)
+            .with_verify_checksums(false)
+            .with_read_strategy(ReadStrategy::Random);
+        assert_eq!(opts.buffer_size(), Some(4096));
+        assert_eq!(opts.replication(), Some(2));
+        assert_eq!(opts.block_size(), Some(64 << 20));
+        assert!(!opts.verify_checksums());
+        assert_eq!(opts.read_strategy(), ReadStrategy::Random);
+        assert!(opts.is_create());
+    }
+}
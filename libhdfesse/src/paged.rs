@@ -0,0 +1,238 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Iteration over paged namenode listings.  Namenode returns long
+ * listings (directories, corrupt files, cache directives, open files,
+ * etc.) by pages; a page source knows how to request the next page,
+ * and PagedIter turns the pages into a flat iterator with the same
+ * error and size_hint semantics for all of them.
+ */
+use std::iter::FusedIterator;
+
+/// A page of a listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The page is not the last one.
+    pub has_more: bool,
+    /// Number of entries after this page, if namenode reports it.
+    pub remaining: Option<usize>,
+}
+
+impl<T> Page<T> {
+    /// The last page.
+    pub fn last(items: Vec<T>) -> Self {
+        Self {
+            items,
+            has_more: false,
+            remaining: Some(0),
+        }
+    }
+
+    /// A page with more pages after it.
+    pub fn more(items: Vec<T>) -> Self {
+        Self {
+            items,
+            has_more: true,
+            remaining: None,
+        }
+    }
+
+    /// A page followed by the `remaining` entries; zero means the last
+    /// page.
+    pub fn with_remaining(items: Vec<T>, remaining: usize) -> Self {
+        Self {
+            items,
+            has_more: remaining != 0,
+            remaining: Some(remaining),
+        }
+    }
+}
+
+/**
+ * Source of pages.  It keeps the cursor (a cookie, the last key, etc.)
+ * between calls.  A page with `has_more` has to advance the cursor,
+ * even if it is empty, or the iteration would never end.
+ *
+ * Closures returning `Result<Page<T>, E>` are page sources too.
+ */
+pub trait FetchPage {
+    type Item;
+    type Error;
+
+    fn fetch_page(&mut self) -> Result<Page<Self::Item>, Self::Error>;
+}
+
+impl<T, E, F> FetchPage for F
+where
+    F: FnMut() -> Result<Page<T>, E>,
+{
+    type Item = T;
+    type Error = E;
+
+    fn fetch_page(&mut self) -> Result<Page<T>, E> {
+        self()
+    }
+}
+
+/**
+ * Iterator over the items of all the pages.  The next page is fetched
+ * only when the current one is consumed.  An error is returned as an
+ * item and ends the iteration.
+ *
+ * The size_hint upper bound trusts namenode's count of remaining
+ * entries; it is not exact, as entries may be created or removed
+ * concurrently, thus no ExactSizeIterator.
+ */
+pub struct PagedIter<F: FetchPage> {
+    fetch: F,
+    items: std::vec::IntoIter<F::Item>,
+    remaining: Option<usize>,
+    done: bool,
+}
+
+impl<F: FetchPage> PagedIter<F> {
+    pub fn new(fetch: F) -> Self {
+        Self {
+            fetch,
+            items: vec![].into_iter(),
+            remaining: None,
+            done: false,
+        }
+    }
+}
+
+impl<F: FetchPage> Iterator for PagedIter<F> {
+    type Item = Result<F::Item, F::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Some(Ok(item));
+            }
+            if self.done {
+                return None;
+            }
+            match self.fetch.fetch_page() {
+                Ok(page) => {
+                    self.done = !page.has_more;
+                    self.remaining = page.remaining;
+                    self.items = page.items.into_iter();
+                }
+                Err(e) => {
+                    // We never read after the error.
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.items.len();
+        if self.done {
+            (buffered, Some(buffered))
+        } else {
+            // An error may take place of the remaining entries, but
+            // never adds an item.
+            (buffered, self.remaining.map(|rem| buffered + rem))
+        }
+    }
+}
+
+impl<F: FetchPage> FusedIterator for PagedIter<F> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Eq, PartialEq, Debug)]
+    struct Error {}
+
+    fn pages(
+        pages: Vec<Result<Page<i32>, Error>>,
+    ) -> PagedIter<impl FnMut() -> Result<Page<i32>, Error>> {
+        let mut pages = pages.into_iter();
+        PagedIter::new(move || pages.next().expect("page after the last one"))
+    }
+
+    #[test]
+    fn test_empty() {
+        let it = pages(vec![Ok(Page::last(vec![]))]);
+        assert_eq!(it.size_hint(), (0, None));
+        assert_eq!(it.collect::<Vec<_>>(), vec![]);
+    }
+
+    #[test]
+    fn test_single() {
+        let mut it = pages(vec![Ok(Page::last(vec![1, 2]))]);
+        assert_eq!(it.next(), Some(Ok(1)));
+        assert_eq!(it.size_hint(), (1, Some(1)));
+        assert_eq!(it.collect::<Vec<_>>(), vec![Ok(2)]);
+    }
+
+    #[test]
+    fn test_pages() {
+        let it = pages(vec![
+            Ok(Page::more(vec![1, 2])),
+            Ok(Page::more(vec![])),
+            Ok(Page::last(vec![3, 4])),
+        ]);
+        assert_eq!(it.collect::<Vec<_>>(), vec![Ok(1), Ok(2), Ok(3), Ok(4)]);
+    }
+
+    #[test]
+    fn test_remaining_size_hint() {
+        let mut it = pages(vec![
+            Ok(Page::with_remaining(vec![1, 2], 2)),
+            Ok(Page::with_remaining(vec![3, 4], 0)),
+        ]);
+        it.next();
+        assert_eq!(it.size_hint(), (1, Some(3)));
+        it.next();
+        assert_eq!(it.size_hint(), (0, Some(2)));
+        it.next();
+        assert_eq!(it.size_hint(), (1, Some(1)));
+        it.next();
+        assert_eq!(it.size_hint(), (0, Some(0)));
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn test_unknown_remaining_size_hint() {
+        let mut it = pages(vec![Ok(Page::more(vec![1, 2])), Ok(Page::last(vec![]))]);
+        it.next();
+        assert_eq!(it.size_hint(), (1, None));
+    }
+
+    #[test]
+    fn test_error_ends() {
+        // The page after the error is never requested.
+        let mut it = pages(vec![Ok(Page::more(vec![1, 2])), Err(Error {})]);
+        assert_eq!(
+            it.by_ref().collect::<Vec<_>>(),
+            vec![Ok(1), Ok(2), Err(Error {})]
+        );
+        assert_eq!(it.size_hint(), (0, Some(0)));
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn test_first_error() {
+        let it = pages(vec![Err(Error {})]);
+        assert_eq!(it.collect::<Vec<_>>(), vec![Err(Error {})]);
+    }
+}
@@ -23,6 +23,9 @@ use uriparse::{Scheme, SchemeError, URIError, URIReference, URIReferenceError, U
 
 // https://url.spec.whatwg.org/#path-percent-encode-set
 const PATH_PERCENT_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    // HDFS paths are never percent-encoded, so '%' is a plain char.
+    // Java's multi-argument URI constructors always quote it too.
+    .add(b'%')
     // query percent-encode set
     .add(b' ')
     .add(b'"')
@@ -35,6 +38,12 @@ const PATH_PERCENT_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::
     .add(b'{')
     .add(b'}');
 
+/// Prefix of the paths that refer to a file by its ID.
+pub const INODES_PREFIX: &str = "/.reserved/.inodes/";
+/// Prefix of the paths that expose the raw extended attributes of
+/// files in encryption zones.
+pub const RAW_PREFIX: &str = "/.reserved/raw";
+
 #[derive(Debug, Error)]
 pub enum PathError {
     #[error(transparent)]
@@ -45,8 +54,24 @@ pub enum PathError {
     PartError(URIReferenceError),
 }
 
-fn drop_empty_segments(mut path: uriparse::Path<'_>) -> uriparse::Path<'_> {
-    path.normalize(true);
+/// Prefixes of the paths the namenode interprets itself, including
+/// their dot segments (e.g. /.reserved/.inodes/<id>/.. is the parent of
+/// the inode).
+const RESERVED_PREFIXES: [&str; 2] = [".reserved/raw", ".reserved/.inodes"];
+
+fn is_reserved(path: &str) -> bool {
+    let path = path.trim_start_matches('/');
+    RESERVED_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .map(|rest| rest.is_empty() || rest.starts_with('/'))
+            .unwrap_or(false)
+    })
+}
+
+fn drop_empty_segments(mut path: uriparse::Path<'_>, normalize: bool) -> uriparse::Path<'_> {
+    if normalize {
+        path.normalize(true);
+    }
     if (path.segments().len() > 1) & path.segments().iter().any(|seg| seg.is_empty()) {
         let mut new_path = uriparse::Path::try_from("").unwrap(); // Well...  I do not expect it to fail.
         new_path.set_absolute(path.is_absolute());
@@ -104,6 +129,8 @@ fn hdfs_path_to_uri(path: &str) -> Result<URIReference<'static>, PathError> {
         (None, rest)
     };
 
+    // Reserved paths are passed to the namenode as is.
+    let reserved = is_reserved(path);
     let percent_path =
         percent_encoding::utf8_percent_encode(path, PATH_PERCENT_ENCODE_SET).to_string();
     let path = drop_empty_segments(
@@ -111,6 +138,7 @@ fn hdfs_path_to_uri(path: &str) -> Result<URIReference<'static>, PathError> {
             .as_str()
             .try_into()
             .map_err(|e: uriparse::PathError| PathError::PartError(e.into()))?,
+        !reserved,
     );
 
     let mut uri_builder = URIReference::builder().with_path(path);
@@ -133,7 +161,9 @@ fn hdfs_path_to_uri(path: &str) -> Result<URIReference<'static>, PathError> {
     }
 
     let mut uriref = uri_builder.build().map_err(PathError::PartError)?;
-    uriref.normalize();
+    if !reserved {
+        uriref.normalize();
+    }
     Ok(uriref.into_owned())
 }
 
@@ -262,6 +292,12 @@ impl UriResolver {
         Ok(Path { path: res })
     }
 
+    /// The host of relative and absolute paths.
+    pub fn default_host(&self) -> String {
+        // The default URL always has host.
+        self.default_uri.host().unwrap().to_string()
+    }
+
     /**
     Resolve only path part, without user, host, etc.
      */
@@ -288,7 +324,7 @@ impl UriResolver {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Path<'a> {
     path: URIReference<'a>,
 }
@@ -314,7 +350,9 @@ impl<'a> Path<'a> {
         for more_segment in more_uri.path().segments() {
             path.push(more_segment.clone())?;
         }
-        path.normalize(true);
+        if !is_reserved(&path.to_string()) {
+            path.normalize(true);
+        }
 
         // We don't need to remove empty segments in the result as
         // both joining parts do not have them.
@@ -324,6 +362,44 @@ impl<'a> Path<'a> {
         })
     }
 
+    /// The path that refers to the file by its ID (the fileId of
+    /// HdfsFileStatusProto).  It remains valid while the file or its
+    /// ancestors are renamed; the namenode has no call that gives the
+    /// current full path back.
+    pub fn from_file_id(file_id: u64) -> Path<'static> {
+        hdfs_path_to_uri(&format!("{}{}", INODES_PREFIX, file_id))
+            .map(|path| Path { path })
+            .expect("an inode path is valid")
+    }
+
+    /// The file ID of a path made by from_file_id.
+    pub fn file_id(&self) -> Option<u64> {
+        self.to_path_string()
+            .strip_prefix(INODES_PREFIX)?
+            .parse()
+            .ok()
+    }
+
+    /// The path of the named entry of the directory, as the local
+    /// names of a listing are; an empty name is the directory itself.
+    /// Unlike join, the name is never split or normalized.
+    pub fn child(&self, name: &str) -> Result<Path<'static>, PathError> {
+        let base = self.to_string();
+        if name.is_empty() {
+            return hdfs_path_to_uri(&base).map(|path| Path { path });
+        }
+        let sep = if base.ends_with('/') { "" } else { "/" };
+        hdfs_path_to_uri(&format!("{}{}{}", base, sep, name)).map(|path| Path { path })
+    }
+
+    /// If the path is under RAW_PREFIX.
+    pub fn is_raw(&self) -> bool {
+        let path = self.to_path_string();
+        path.strip_prefix(RAW_PREFIX)
+            .map(|rest| rest.is_empty() || rest.starts_with('/'))
+            .unwrap_or(false)
+    }
+
     pub fn into_owned(self) -> Path<'static> {
         Path {
             path: self.path.into_owned(),
@@ -349,13 +425,18 @@ impl<'a> Path<'a> {
 
     pub fn basename(&self) -> Cow<'_, str> {
         // Unwrap is valid as uriparse::Path always contains at least
-        // one segment.
-        // TODO encode or decode?
-        percent_encoding::utf8_percent_encode(
-            self.path.path().segments().last().unwrap().as_str(),
-            PATH_PERCENT_ENCODE_SET,
-        )
-        .into()
+        // one segment.  Segments are always valid UTF-8 as they are
+        // created from &str.
+        percent_encoding::percent_decode_str(self.path.path().segments().last().unwrap().as_str())
+            .decode_utf8()
+            .unwrap()
+    }
+
+    /// The scheme in lowercase, if any.
+    pub fn scheme(&self) -> Option<String> {
+        self.path
+            .scheme()
+            .map(|scheme| scheme.as_str().to_ascii_lowercase())
     }
 
     pub fn host(&self) -> Option<String> {
@@ -370,6 +451,10 @@ impl<'a> Path<'a> {
         )
     }
 
+    pub fn port(&self) -> Option<u16> {
+        self.path.port()
+    }
+
     pub fn user(&self) -> Option<String> {
         self.path.username().map(
             // TODO encode or decode?
@@ -431,19 +516,91 @@ mod tests {
 
     #[test]
     fn test_resolver_new_error1() {
-        assert!(matches!(
-            UriResolver::new("myh ost", "myself", None, None),
-            Err(_)
-        ));
+        assert!(UriResolver::new("myh ost", "myself", None, None).is_err());
     }
 
     #[test]
     fn test_resolver_new_error2() {
-        assert!(matches!(
-            // TODO: should we urlescape host and user?
-            UriResolver::new("myhost", "my self", None, None),
-            Err(_)
-        ));
+        assert!(UriResolver::new("myhost", "my self", None, None).is_err());
+    }
+
+    #[test]
+    fn test_reserved_not_normalized() {
+        for src in &[
+            "/.reserved/.inodes/16386/..",
+            "/.reserved/raw/a/./b/../c",
+            "/.reserved/raw/a%2Fb c",
+            "/.reserved/raw",
+        ] {
+            let path = Path::new(src).unwrap();
+            assert_eq!(&path.to_path_string(), src);
+            let res = UriResolver::new("myhost", "myself", None, None).unwrap();
+            assert_eq!(&res.resolve(&path).unwrap().to_path_string(), src);
+        }
+        assert_eq!(
+            Path::new("hdfs://myhost/.reserved/raw/a/../b")
+                .unwrap()
+                .to_path_string(),
+            "/.reserved/raw/a/../b"
+        );
+        assert_eq!(
+            Path::new("/.reserved/.inodes/16386/..")
+                .unwrap()
+                .join("a")
+                .unwrap()
+                .to_path_string(),
+            "/.reserved/.inodes/16386/../a"
+        );
+        assert_eq!(
+            Path::new("/.reserved/raw//a/").unwrap().to_path_string(),
+            "/.reserved/raw/a"
+        );
+        assert_eq!(
+            Path::new("/.reserved/rawdata/a/../b")
+                .unwrap()
+                .to_path_string(),
+            "/.reserved/rawdata/b"
+        );
+        assert_eq!(
+            Path::new("/data/.reserved/raw/../b")
+                .unwrap()
+                .to_path_string(),
+            "/data/.reserved/b"
+        );
+    }
+
+    #[test]
+    fn test_child() {
+        let base = Path::new("/data/a b").unwrap();
+        let child = base.child("c%20d").unwrap();
+        assert_eq!(child.to_string(), "/data/a b/c%20d");
+        assert_eq!(child.basename(), "c%20d");
+        assert_eq!(base.child("").unwrap().to_string(), "/data/a b");
+        assert_eq!(
+            Path::new("/").unwrap().child("f").unwrap().to_string(),
+            "/f"
+        );
+        assert_eq!(
+            Path::new("hdfs://ns/dir")
+                .unwrap()
+                .child("f")
+                .unwrap()
+                .to_string(),
+            "hdfs://ns/dir/f"
+        );
+        assert_eq!(
+            Path::new("rel").unwrap().child("f").unwrap().to_string(),
+            "rel/f"
+        );
+    }
+
+    #[test]
+    fn test_file_id() {
+        let path = Path::from_file_id(16386);
+        assert_eq!(path.to_string(), "/.reserved/.inodes/16386");
+        assert_eq!(path.file_id(), Some(16386));
+        assert_eq!(Path::new("/.reserved/.inodes/1/a").unwrap().file_id(), None);
+        assert_eq!(Path::new("/data/16386").unwrap().file_id(), None);
     }
 
     #[test]
@@ -646,49 +803,57 @@ mod tests {
 
     #[test]
     fn test_path_new_absolute() {
-        let path = Path::new("/abs/path".into()).unwrap();
+        let path = Path::new("/abs/path").unwrap();
         assert_eq!(path.to_string(), "/abs/path");
     }
 
     #[test]
     fn test_path_new_space() {
-        let path = Path::new("/abs/pa th".into()).unwrap();
+        let path = Path::new("/abs/pa th").unwrap();
         assert_eq!(path.to_string(), "/abs/pa th");
     }
 
+    #[test]
+    fn test_path_new_percent() {
+        let path = Path::new("/abs/pa%20th").unwrap();
+        assert_eq!(path.to_string(), "/abs/pa%20th");
+        assert_eq!(path.to_path_string(), "/abs/pa%20th");
+        assert_eq!(path.basename(), "pa%20th");
+    }
+
     #[test]
     fn test_path_new_rel() {
-        let path = Path::new("./path".into()).unwrap();
+        let path = Path::new("./path").unwrap();
         assert_eq!(path.to_string(), "path");
     }
 
     #[test]
     fn test_path_new_dotdot() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(path.to_string(), "../path");
     }
 
     #[test]
     fn test_path_join() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(path.join("test/me").unwrap().to_string(), "../path/test/me");
     }
 
     #[test]
     fn test_path_join_absolute() {
-        let path = Path::new("/path".into()).unwrap();
+        let path = Path::new("/path").unwrap();
         assert_eq!(path.join("test/me").unwrap().to_string(), "/path/test/me");
     }
 
     #[test]
     fn test_path_join_slash() {
-        let path = Path::new("../path/".into()).unwrap();
+        let path = Path::new("../path/").unwrap();
         assert_eq!(path.join("test/me").unwrap().to_string(), "../path/test/me");
     }
 
     #[test]
     fn test_path_join_dot() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(
             path.join("./test/me").unwrap().to_string(),
             "../path/test/me"
@@ -697,7 +862,7 @@ mod tests {
 
     #[test]
     fn test_path_join_dot_dot() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(
             path.join("././test/me").unwrap().to_string(),
             "../path/test/me"
@@ -706,13 +871,13 @@ mod tests {
 
     #[test]
     fn test_path_join_dotdot() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(path.join("../test/me").unwrap().to_string(), "../test/me");
     }
 
     #[test]
     fn test_path_join_abs() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(
             path.join("/test/me").unwrap().to_string(),
             "../path/test/me"
@@ -721,19 +886,19 @@ mod tests {
 
     #[test]
     fn test_path_join_empty() {
-        let path = Path::new("../path".into()).unwrap();
+        let path = Path::new("../path").unwrap();
         assert_eq!(path.join("").unwrap().to_string(), "../path");
     }
 
     #[test]
     fn test_path_join_dot_empty() {
-        let path = Path::new(".".into()).unwrap();
+        let path = Path::new(".").unwrap();
         assert_eq!(path.join("").unwrap().to_string(), ".");
     }
 
     #[test]
     fn test_path_string_join_dot_empty() {
-        let path = Path::new(".".into()).unwrap();
+        let path = Path::new(".").unwrap();
         assert_eq!(path.join("").unwrap().to_path_string(), ".");
     }
 
@@ -805,4 +970,195 @@ mod tests {
         let path = Path::new("//the%20user@test%20me/test").unwrap();
         assert_eq!(path.user(), Some("the user".to_string()));
     }
+
+    #[test]
+    fn test_path_is_raw() {
+        assert!(Path::new("/.reserved/raw/a").unwrap().is_raw());
+        assert!(Path::new("hdfs://nn/.reserved/raw").unwrap().is_raw());
+        assert!(!Path::new("/.reserved/rawdata").unwrap().is_raw());
+        assert!(!Path::new("/a/.reserved/raw").unwrap().is_raw());
+    }
+}
+
+/*
+ * Property-based tests.  Hadoop's Path is built with the
+ * multi-argument java.net.URI constructor that quotes everything
+ * that is not allowed in the path, including the '%' char itself.
+ * Thus, whatever path string the user provides, it has to survive
+ * parsing and printing unchanged (modulo normalization).
+ */
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// A path segment that is not "." or "..", without '/' and ':'.
+    fn plain_segment() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9 %_\\-~!$&'()*+,;=@\u{e9}\u{436}\u{4e2d}\u{1F600}]{1,8}"
+            .prop_filter("dot segments are tested separately", |s| {
+                s != "." && s != ".."
+            })
+    }
+
+    fn segment() -> impl Strategy<Value = String> {
+        prop_oneof![
+            4 => plain_segment(),
+            1 => Just(".".to_owned()),
+            1 => Just("..".to_owned()),
+            1 => Just("".to_owned()),
+        ]
+    }
+
+    fn plain_segments() -> impl Strategy<Value = Vec<String>> {
+        prop::collection::vec(plain_segment(), 1..6)
+    }
+
+    fn any_path() -> impl Strategy<Value = String> {
+        (any::<bool>(), prop::collection::vec(segment(), 1..8))
+            .prop_map(|(abs, segs)| {
+                let joined = segs.join("/");
+                if abs {
+                    format!("/{}", joined)
+                } else {
+                    joined
+                }
+            })
+            // "//name" is an authority, not a path, both for us and Java.
+            .prop_filter("no authority", |s| !s.starts_with("//"))
+    }
+
+    fn authority() -> impl Strategy<Value = String> {
+        (
+            prop::option::of("[a-z][a-z0-9]{0,6}"),
+            "[a-z][a-z0-9\\-]{0,10}",
+            prop::option::of(1u16..),
+        )
+            .prop_map(|(user, host, port)| {
+                let mut res = String::new();
+                if let Some(user) = user {
+                    res.push_str(&user);
+                    res.push('@');
+                }
+                res.push_str(&host);
+                if let Some(port) = port {
+                    res.push_str(&format!(":{}", port));
+                }
+                res
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_plain_path_round_trip(segs in plain_segments()) {
+            let src = format!("/{}", segs.join("/"));
+            let path = Path::new(&src).unwrap();
+            prop_assert_eq!(path.to_path_string(), src.clone());
+            prop_assert_eq!(path.to_string(), src);
+        }
+
+        #[test]
+        fn prop_relative_path_round_trip(segs in plain_segments()) {
+            let src = segs.join("/");
+            let path = Path::new(&src).unwrap();
+            prop_assert_eq!(path.to_string(), src);
+        }
+
+        #[test]
+        fn prop_normalization_idempotent(src in any_path()) {
+            let path = Path::new(&src).unwrap();
+            let printed = path.to_string();
+            let reparsed = Path::new(&printed).unwrap();
+            let reprinted = reparsed.to_string();
+            let reparsed_path_string = reparsed.to_path_string();
+            prop_assert_eq!(reprinted, printed.clone());
+            prop_assert_eq!(reparsed_path_string, path.to_path_string());
+        }
+
+        #[test]
+        fn prop_no_empty_segments(src in any_path()) {
+            let path = Path::new(&src).unwrap();
+            let path_string = path.to_path_string();
+            prop_assert!(!path_string.contains("//"), "{:?}", path_string);
+        }
+
+        #[test]
+        fn prop_join_associative(
+            base in plain_segments(),
+            a in plain_segments(),
+            b in plain_segments(),
+        ) {
+            let base_str = format!("/{}", base.join("/"));
+            let a_str = a.join("/");
+            let b_str = b.join("/");
+            let ab_str = format!("{}/{}", a_str, b_str);
+
+            let base = Path::new(&base_str).unwrap();
+            let left = base.join(&a_str).unwrap().join(&b_str).unwrap();
+            let right = base.join(&ab_str).unwrap();
+            prop_assert_eq!(left.to_string(), right.to_string());
+            prop_assert_eq!(
+                left.to_path_string(),
+                format!("{}/{}", base_str, ab_str)
+            );
+        }
+
+        #[test]
+        fn prop_join_empty_is_identity(src in any_path()) {
+            let path = Path::new(&src).unwrap();
+            prop_assert_eq!(path.join("").unwrap().to_string(), path.to_string());
+        }
+
+        #[test]
+        fn prop_basename_is_last_segment(segs in plain_segments()) {
+            let path = Path::new(&format!("/{}", segs.join("/"))).unwrap().into_owned();
+            prop_assert_eq!(path.basename(), segs.last().unwrap().as_str());
+        }
+
+        #[test]
+        fn prop_resolve_path_absolute_is_identity(segs in plain_segments()) {
+            let res = UriResolver::new("myhost", "myself", None, None).unwrap();
+            let src = format!("/{}", segs.join("/"));
+            let path = Path::new(&src).unwrap();
+            prop_assert_eq!(res.resolve_path(&path).unwrap().to_path_string(), src);
+        }
+
+        #[test]
+        fn prop_resolve_path_relative(segs in plain_segments()) {
+            let res = UriResolver::new("myhost", "myself", None, None).unwrap();
+            let src = segs.join("/");
+            let path = Path::new(&src).unwrap();
+            prop_assert_eq!(
+                res.resolve_path(&path).unwrap().to_path_string(),
+                format!("/user/myself/{}", src)
+            );
+        }
+
+        #[test]
+        fn prop_resolve_agrees_with_resolve_path(src in any_path()) {
+            let res = UriResolver::new("myhost", "myself", None, None).unwrap();
+            let path = Path::new(&src).unwrap();
+            prop_assert_eq!(
+                res.resolve(&path).unwrap().to_path_string(),
+                res.resolve_path(&path).unwrap().to_path_string()
+            );
+        }
+
+        #[test]
+        fn prop_resolve_full_uri(auth in authority(), segs in plain_segments()) {
+            let res = UriResolver::new("myhost", "myself", None, None).unwrap();
+            let src = format!("hdfs://{}/{}", auth, segs.join("/"));
+            let path = Path::new(&src).unwrap();
+            let resolved = res.resolve(&path).unwrap();
+            prop_assert_eq!(
+                resolved.to_path_string(),
+                format!("/{}", segs.join("/"))
+            );
+            prop_assert_eq!(
+                res.resolve_path(&path).unwrap().to_path_string(),
+                format!("/{}", segs.join("/"))
+            );
+            let host = auth.split('@').next_back().unwrap().split(':').next().unwrap();
+            prop_assert_eq!(resolved.host(), Some(host.to_owned()));
+        }
+    }
 }
@@ -0,0 +1,107 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Attributes that a copy takes from its source, like the -p options
+ * of FsShell's cp and of distcp.  Hdfs::preserve_attributes sets them
+ * on the destination after the data is copied.
+ */
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("unknown attribute to preserve: {0:?}")]
+pub struct UnknownAttribute(pub char);
+
+/**
+ * The set of attributes to preserve.  It is parsed from the letters
+ * of distcp's -p: u (user), g (group), p (permission), t (times), x
+ * (extended attributes) and a (ACLs); o is FsShell's ownership, both
+ * user and group.  ACLs are set over the permission, so a also
+ * preserves the permission.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Preserve {
+    pub user: bool,
+    pub group: bool,
+    pub permission: bool,
+    /// The modification and access times.
+    pub times: bool,
+    /// Extended attributes; raw ones are preserved too if both the
+    /// source and the destination are in encryption zones.
+    pub xattrs: bool,
+    pub acls: bool,
+}
+
+impl Preserve {
+    pub fn all() -> Self {
+        Self {
+            user: true,
+            group: true,
+            permission: true,
+            times: true,
+            xattrs: true,
+            acls: true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl FromStr for Preserve {
+    type Err = UnknownAttribute;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut preserve = Self::default();
+        for c in s.chars() {
+            match c {
+                'u' => preserve.user = true,
+                'g' => preserve.group = true,
+                'o' => {
+                    preserve.user = true;
+                    preserve.group = true;
+                }
+                'p' => preserve.permission = true,
+                't' => preserve.times = true,
+                'x' => preserve.xattrs = true,
+                'a' => {
+                    preserve.acls = true;
+                    preserve.permission = true;
+                }
+                c => return Err(UnknownAttribute(c)),
+            }
+        }
+        Ok(preserve)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!("".parse::<Preserve>().unwrap().is_empty());
+        assert_eq!("topax".parse::<Preserve>().unwrap(), Preserve::all());
+        let ownership = "o".parse::<Preserve>().unwrap();
+        assert!(ownership.user && ownership.group && !ownership.permission);
+        let acls = "a".parse::<Preserve>().unwrap();
+        assert!(acls.acls && acls.permission);
+        assert_eq!("pz".parse::<Preserve>(), Err(UnknownAttribute('z')));
+    }
+}
@@ -0,0 +1,93 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Limiting the rate of namenode calls, so that bulk tools do not
+ * flood the namenode RPC queue.
+ */
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::clock::{Clock, SystemClock};
+
+/**
+ * Spaces the calls evenly at the rate, without bursts.  Clones share
+ * the same schedule, so a limiter given to several connections limits
+ * their calls together.
+ */
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    // The earliest time of the next call.
+    next: Arc<Mutex<Option<Instant>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimiter {
+    /// At most qps calls per second; zero is treated as one.
+    pub fn new(qps: u32) -> Self {
+        Self::with_clock(qps, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(qps: u32, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / qps.max(1),
+            next: Arc::new(Mutex::new(None)),
+            clock,
+        }
+    }
+
+    /// Wait for the turn of a call.
+    pub fn acquire(&self) {
+        let now = self.clock.now();
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let start = next.map(|next| next.max(now)).unwrap_or(now);
+            *next = Some(start + self.interval);
+            start
+        };
+        if start > now {
+            self.clock.sleep(start - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_rate_shared() {
+        let clock = ManualClock::new();
+        let limiter = RateLimiter::with_clock(10, Arc::new(clock.clone()));
+        let other = limiter.clone();
+        let start = clock.now();
+        limiter.acquire();
+        assert_eq!(clock.now(), start);
+        other.acquire();
+        limiter.acquire();
+        assert_eq!(clock.now() - start, Duration::from_millis(200));
+
+        // Idle time is not saved up for a burst.
+        clock.advance(Duration::from_secs(5));
+        let idle = clock.now();
+        limiter.acquire();
+        other.acquire();
+        assert_eq!(clock.now() - idle, Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,302 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Registry of filesystem factories by URI scheme, in the spirit of
+ * Java's FileSystem service loader.  The "hdfs" and "file" schemes
+ * are available out of the box; "webhdfs" and "viewfs" are reserved
+ * for the future backends, and any other scheme can be registered by
+ * the application.
+ */
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    error_code::ErrorCode,
+    filesystem::FileSystem,
+    fs::Hdfs,
+    ha_rpc::HaHdfsConnection,
+    hdconfig::Config,
+    local::LocalFs,
+    path::{Path, PathError, UriResolver},
+    rpc::{RpcError, SimpleConnector},
+    service::ClientNamenodeService,
+};
+
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("no filesystem is registered for scheme {0:?}")]
+    UnknownScheme(Box<str>),
+    #[error("path {0:?} has no scheme and defaultFS is not configured")]
+    NoScheme(Box<str>),
+    #[error("path {0:?} has no authority")]
+    NoAuthority(Box<str>),
+    #[error("nameservice {0:?} is not found in the config")]
+    UnknownNameservice(Box<str>),
+    #[error("`{src}' and `{dst}' do not belong to the same filesystem")]
+    DifferentFilesystems { src: Box<str>, dst: Box<str> },
+    #[error(transparent)]
+    Path(#[from] PathError),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    /// Errors of factories registered by the application.
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl RegistryError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RegistryError::UnknownScheme(_) => ErrorCode::Unsupported,
+            RegistryError::NoScheme(_) | RegistryError::NoAuthority(_) | RegistryError::Path(_) => {
+                ErrorCode::InvalidPath
+            }
+            RegistryError::UnknownNameservice(_) => ErrorCode::NotFound,
+            RegistryError::DifferentFilesystems { .. } => ErrorCode::InvalidArgument,
+            RegistryError::Rpc(e) => e.code(),
+            RegistryError::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+/**
+ * A factory gets the config and the URI of the filesystem; only the
+ * scheme and the authority of the URI are meaningful.
+ */
+pub type Factory<F> = Box<dyn Fn(&Config, &Path<'_>) -> Result<F, RegistryError> + Send + Sync>;
+
+/**
+ * Scheme to factory mapping.  The filesystem type F is chosen by the
+ * application: it is Hdfs for the default registry, and it may be a
+ * boxed trait object for a registry with several backends.
+ */
+pub struct SchemeRegistry<F> {
+    factories: HashMap<Box<str>, Factory<F>>,
+}
+
+impl<F> SchemeRegistry<F> {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register the factory for the scheme (case-insensitive),
+    /// returning the factory it replaces.
+    pub fn register<G>(&mut self, scheme: &str, factory: G) -> Option<Factory<F>>
+    where
+        G: Fn(&Config, &Path<'_>) -> Result<F, RegistryError> + Send + Sync + 'static,
+    {
+        self.factories
+            .insert(scheme.to_ascii_lowercase().into(), Box::new(factory))
+    }
+
+    pub fn unregister(&mut self, scheme: &str) -> Option<Factory<F>> {
+        self.factories.remove(scheme.to_ascii_lowercase().as_str())
+    }
+
+    pub fn is_registered(&self, scheme: &str) -> bool {
+        self.factories
+            .contains_key(scheme.to_ascii_lowercase().as_str())
+    }
+
+    pub fn schemes(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(AsRef::as_ref)
+    }
+
+    /// Get the filesystem of the path.  A path without scheme refers
+    /// to the config's defaultFS, like in Java.
+    pub fn open(&self, config: &Config, path: &Path<'_>) -> Result<F, RegistryError> {
+        let default_fs;
+        let target = if path.scheme().is_some() {
+            path
+        } else {
+            let default = config
+                .default_fs
+                .as_deref()
+                .ok_or_else(|| RegistryError::NoScheme(path.to_string().into()))?;
+            default_fs = Path::new(default)?;
+            &default_fs
+        };
+        let scheme = target
+            .scheme()
+            .ok_or_else(|| RegistryError::NoScheme(target.to_string().into()))?;
+        let factory = self
+            .factories
+            .get(scheme.as_str())
+            .ok_or_else(|| RegistryError::UnknownScheme(scheme.into()))?;
+        factory(config, target)
+    }
+
+    /// Get the config's defaultFS.
+    pub fn open_default(&self, config: &Config) -> Result<F, RegistryError> {
+        self.open(config, &Path::new("/")?)
+    }
+}
+
+impl<F> Default for SchemeRegistry<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchemeRegistry<Hdfs> {
+    /// The registry with the "hdfs" scheme.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register("hdfs", |config, path| connect_hdfs(config, path, None));
+        registry
+    }
+}
+
+impl SchemeRegistry<Box<dyn FileSystem>> {
+    /// The registry with the "hdfs" and "file" schemes.
+    pub fn with_backends() -> Self {
+        let mut registry = Self::new();
+        registry.register("hdfs", |config, path| {
+            Ok(Box::new(connect_hdfs(config, path, None)?) as Box<dyn FileSystem>)
+        });
+        registry.register("file", |_config, _path| {
+            Ok(Box::new(LocalFs::new()) as Box<dyn FileSystem>)
+        });
+        registry
+    }
+}
+
+/**
+ * The "hdfs" factory: the authority of the path is a nameservice of
+ * the config.  With proxy_user, the login user acts on behalf of it;
+ * otherwise, the connection acts as User::current.
+ */
+pub fn connect_hdfs(
+    config: &Config,
+    path: &Path<'_>,
+    proxy_user: Option<&str>,
+) -> Result<Hdfs, RegistryError> {
+    let host = path
+        .host()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| RegistryError::NoAuthority(path.to_string().into()))?;
+    let ns = config
+        .nameservice(&host)
+        .ok_or_else(|| RegistryError::UnknownNameservice(host.as_str().into()))?;
+    let client = match proxy_user {
+        Some(proxy_user) => HaHdfsConnection::new_as_proxy(ns, proxy_user, SimpleConnector {})?,
+        None => HaHdfsConnection::new(ns, SimpleConnector {})?,
+    };
+    let service = ClientNamenodeService::new(client);
+    let resolve = UriResolver::new(&host, service.get_user(), None, None)?;
+    let mut hdfs = Hdfs::new(service, resolve);
+    if let Some(suffix) = &config.client_name_suffix {
+        hdfs.set_client_name_suffix(suffix);
+    }
+    Ok(hdfs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(default_fs: Option<&str>) -> Config {
+        Config {
+            default_fs: default_fs.map(Into::into),
+            services: vec![],
+            client_name_suffix: None,
+            namenode_qps: None,
+        }
+    }
+
+    fn host_registry() -> SchemeRegistry<String> {
+        let mut registry = SchemeRegistry::new();
+        registry.register("test", |_config, path| Ok(path.host().unwrap_or_default()));
+        registry
+    }
+
+    #[test]
+    fn test_open_by_scheme() {
+        let registry = host_registry();
+        let fs = registry
+            .open(&config(None), &Path::new("TEST://host1/a/b").unwrap())
+            .unwrap();
+        assert_eq!(fs, "host1");
+    }
+
+    #[test]
+    fn test_open_default_fs() {
+        let registry = host_registry();
+        let config = config(Some("test://default"));
+        assert_eq!(
+            registry.open(&config, &Path::new("/a/b").unwrap()).unwrap(),
+            "default"
+        );
+        assert_eq!(registry.open_default(&config).unwrap(), "default");
+    }
+
+    #[test]
+    fn test_open_no_scheme() {
+        let registry = host_registry();
+        assert!(matches!(
+            registry.open(&config(None), &Path::new("/a/b").unwrap()),
+            Err(RegistryError::NoScheme(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_unknown_scheme() {
+        let registry = host_registry();
+        match registry.open(&config(None), &Path::new("webhdfs://host/a").unwrap()) {
+            Err(RegistryError::UnknownScheme(scheme)) => assert_eq!(scheme.as_ref(), "webhdfs"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_register_replaces() {
+        let mut registry = host_registry();
+        assert!(registry
+            .register("Test", |_, _| Ok("new".to_owned()))
+            .is_some());
+        assert_eq!(registry.schemes().collect::<Vec<_>>(), vec!["test"]);
+        assert_eq!(
+            registry
+                .open(&config(None), &Path::new("test://host/").unwrap())
+                .unwrap(),
+            "new"
+        );
+        assert!(registry.unregister("TEST").is_some());
+        assert!(!registry.is_registered("test"));
+    }
+
+    #[test]
+    fn test_backends_file() {
+        let registry = SchemeRegistry::with_backends();
+        let mut local = registry
+            .open(&config(None), &Path::new("file:///").unwrap())
+            .unwrap();
+        assert!(local.stat(&Path::new("/").unwrap()).unwrap().isdir);
+    }
+
+    #[test]
+    fn test_hdfs_unknown_nameservice() {
+        let registry = SchemeRegistry::with_defaults();
+        assert!(registry.is_registered("hdfs"));
+        assert!(matches!(
+            registry.open(&config(None), &Path::new("hdfs://nowhere/a").unwrap()),
+            Err(RegistryError::UnknownNameservice(_))
+        ));
+    }
+}
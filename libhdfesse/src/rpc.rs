@@ -13,25 +13,41 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
+use std::collections::HashMap;
 use std::io::{self, Read, Write};
-use std::net::TcpStream;
 use std::net::ToSocketAddrs;
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant};
 use std::{borrow::Cow, fmt::Debug, ops::Deref};
 
+use crate::trace::trace;
 use thiserror::Error;
-use tracing::{instrument, trace};
 
+use crate::cancel::CancellationToken;
+use crate::error_code::ErrorCode;
 use crate::hdconfig;
 use crate::path::Path;
-use crate::util;
+use crate::user::User;
 use hdfesse_proto::IpcConnectionContext::*;
 use hdfesse_proto::ProtobufRpcEngine::RequestHeaderProto;
 use hdfesse_proto::RpcHeader::*;
 use protobuf::{CodedInputStream, CodedOutputStream, Message};
 
 const RPC_HEADER: &[u8; 4] = b"hrpc";
-const RPC_VERSION: u8 = 9;
+/// The only version of the protobuf-based protocol, used since
+/// Hadoop 2.0; there is nothing to downgrade to.
+pub(crate) const RPC_VERSION: u8 = 9;
+const RPC_SERVICE_CLASS: u8 = 80;
+/// AuthProtocol.NONE, i.e. SIMPLE authentication.
+const RPC_AUTH_PROTOCOL_NONE: u8 = 0;
+/// The default of Hadoop's ipc.maximum.response.length.
+const MAX_RESPONSE_LEN: u32 = 128 * 1024 * 1024;
 const RPC_HDFS_PROTOCOL: &str = "org.apache.hadoop.hdfs.protocol.ClientProtocol";
+/// How often a blocked read checks the cancellation token.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Larger buffers, like the ones of big listings, are freed after the
+/// call instead of being kept for the next ones.
+const SCRATCH_RETAINED_LEN: usize = 1024 * 1024;
 
 /**
  * Creating a TCP connection.  This trait may implement different strategies
@@ -41,6 +57,17 @@ const RPC_HDFS_PROTOCOL: &str = "org.apache.hadoop.hdfs.protocol.ClientProtocol"
 pub trait Connector {
     // async
     fn get_connection<T: ToSocketAddrs>(&self, addr: T) -> Result<TcpStream, io::Error>;
+
+    /// Connect within the timeout, if there is one.  Connectors that
+    /// cannot limit the time ignore it.
+    fn get_connection_timeout<T: ToSocketAddrs>(
+        &self,
+        addr: T,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, io::Error> {
+        let _ = timeout;
+        self.get_connection(addr)
+    }
 }
 
 /**
@@ -54,21 +81,45 @@ impl Connector for SimpleConnector {
     fn get_connection<T: ToSocketAddrs>(&self, addr: T) -> Result<TcpStream, io::Error> {
         TcpStream::connect(addr)
     }
+
+    /// Each address of the name gets the whole timeout, like
+    /// TcpStream::connect tries them in order.
+    fn get_connection_timeout<T: ToSocketAddrs>(
+        &self,
+        addr: T,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, io::Error> {
+        let timeout = match timeout {
+            Some(timeout) if timeout == Duration::ZERO => {
+                return Err(io::ErrorKind::TimedOut.into())
+            }
+            Some(timeout) => timeout,
+            None => return self.get_connection(addr),
+        };
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no addresses");
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
 }
 
 #[derive(Debug)]
-struct InfiniteSeq {
+pub(crate) struct InfiniteSeq {
     val: i32,
 }
 
 impl InfiniteSeq {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             val: -1, // Sequence starts with 0.
         }
     }
 
-    fn next(&mut self) -> i32 {
+    pub(crate) fn next(&mut self) -> i32 {
         // when used for call_id, negative numbers are rejected by the
         // HDFS.  So far we do not care.
         self.val += 1;
@@ -82,6 +133,24 @@ impl Default for InfiniteSeq {
     }
 }
 
+/**
+ * Call ID and retry count of a call re-sent by HaHdfsConnection.  The
+ * namenode retry cache recognizes a re-sent call by the client ID and
+ * the call ID, so they have to be the same for all the attempts.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CallRetry {
+    pub(crate) call_id: i32,
+    /// Zero for the first attempt.
+    pub(crate) retry_count: i32,
+}
+
+/// "ClientId must be a UUID - that is 16 octets"
+/// (hadoop/../RetryCache.java).
+pub(crate) fn new_client_id() -> [u8; 16] {
+    *uuid::Uuid::new_v4().as_bytes()
+}
+
 pub type RpcStatus = RpcResponseHeaderProto_RpcStatusProto;
 pub type RpcErrorCode = RpcResponseHeaderProto_RpcErrorCodeProto;
 
@@ -90,6 +159,29 @@ pub enum RpcErrorKind {
     Snapshot,
 }
 
+/**
+ * The server has rejected the connection or is not a Hadoop RPC
+ * server at all.  The server reports problems with the connection
+ * header only in response to the first call, so these errors come
+ * from it.
+ */
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("the server responded with HTTP; is it the HTTP port instead of the RPC one?")]
+    Http,
+    #[error("the server closed the connection; is it a Hadoop RPC port?")]
+    Closed,
+    #[error("unexpected response frame of {len} bytes; is it a Hadoop RPC port?")]
+    BadFrame { len: u32 },
+    #[error("the server does not support RPC version {}: {}", RPC_VERSION, .error_msg)]
+    VersionMismatch { error_msg: String },
+    #[error("the server rejected the connection: {}", .error_msg)]
+    Unauthorized {
+        error_msg: String,
+        exception: String,
+    },
+}
+
 #[derive(Debug, Error)]
 pub enum RpcError {
     #[error(transparent)]
@@ -134,20 +226,207 @@ pub enum RpcError {
     },
     #[error("incomplete protobuf record")]
     IncompleteResponse,
+    #[error("response of {} bytes exceeds the limit", .len)]
+    ResponseTooLong { len: u32 },
+    #[error(transparent)]
+    Handshake(HandshakeError),
+    /// The call has not completed before its deadline.  If the request
+    /// was already sent, the connection is closed, as the late response
+    /// would confuse the next call.
+    #[error("{}: call timed out", .method)]
+    TimedOut { method: String },
+    /// The call was cancelled with a CancellationToken.  The connection
+    /// is closed the same way as for TimedOut.
+    #[error("{}: call cancelled", .method)]
+    Cancelled { method: String },
 }
 
 impl RpcError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RpcError::Connector(_) | RpcError::Io(_) => ErrorCode::Io,
+            RpcError::NoUser(_) => ErrorCode::Internal,
+            RpcError::Protobuf(protobuf::ProtobufError::IoError(_)) => ErrorCode::Io,
+            RpcError::Protobuf(_)
+            | RpcError::FatalResponse { .. }
+            | RpcError::IncompleteResponse
+            | RpcError::ResponseTooLong { .. } => ErrorCode::Protocol,
+            RpcError::Handshake(HandshakeError::Closed) => ErrorCode::Io,
+            RpcError::Handshake(HandshakeError::Unauthorized { .. }) => ErrorCode::AccessDenied,
+            RpcError::Handshake(_) => ErrorCode::Protocol,
+            RpcError::KnownError { exception, .. } | RpcError::ErrorResponse { exception, .. } => {
+                ErrorCode::from_exception_class(exception)
+            }
+            RpcError::TimedOut { .. } => ErrorCode::TimedOut,
+            RpcError::Cancelled { .. } => ErrorCode::Cancelled,
+        }
+    }
+
     #[inline]
     pub fn get_class_name(&self) -> Option<&str> {
         match self {
             RpcError::KnownError { exception, .. } => Some(exception),
             RpcError::ErrorResponse { exception, .. } => Some(exception),
             RpcError::FatalResponse { exception, .. } => Some(exception),
+            RpcError::Handshake(HandshakeError::Unauthorized { exception, .. }) => Some(exception),
             _ => None,
         }
     }
 }
 
+/**
+ * Java's CallerContext: it is sent in the header of each call, and the
+ * namenode writes it to the audit log (with
+ * hadoop.caller.context.enabled), attributing the calls to a job or a
+ * service.  The namenode truncates the context to
+ * hadoop.caller.context.max.size and the signature to
+ * hadoop.caller.context.signature.max.size.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallerContext {
+    pub context: String,
+    pub signature: Option<Vec<u8>>,
+}
+
+impl CallerContext {
+    pub fn new<S: Into<String>>(context: S) -> Self {
+        Self {
+            context: context.into(),
+            signature: None,
+        }
+    }
+
+    pub fn with_signature<B: Into<Vec<u8>>>(mut self, signature: B) -> Self {
+        self.signature = Some(signature.into());
+        self
+    }
+}
+
+impl From<&CallerContext> for RPCCallerContextProto {
+    fn from(caller_context: &CallerContext) -> Self {
+        let mut proto = RPCCallerContextProto::default();
+        proto.set_context(caller_context.context.clone());
+        if let Some(signature) = &caller_context.signature {
+            proto.set_signature(signature.clone());
+        }
+        proto
+    }
+}
+
+/**
+ * Limits applied to each RPC call made over a connection, and the
+ * caller context sent with it.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// Maximal duration of a single call, including failover attempts
+    /// of the HA connection.  None means waiting forever.
+    pub timeout: Option<Duration>,
+    /// A point in time shared by all calls, e.g. the deadline of a
+    /// whole command; a call started or still running after it fails
+    /// with RpcError::TimedOut.  Combined with the timeout, the
+    /// earlier one applies.
+    pub deadline: Option<Instant>,
+    /// The call fails with RpcError::Cancelled as soon as the token
+    /// is cancelled.
+    pub cancel: Option<CancellationToken>,
+    pub caller_context: Option<CallerContext>,
+}
+
+impl CallOptions {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn with_caller_context(mut self, caller_context: CallerContext) -> Self {
+        self.caller_context = Some(caller_context);
+        self
+    }
+}
+
+/**
+ * CallOptions materialized for a particular call.
+ */
+#[derive(Debug)]
+pub(crate) struct CallLimits {
+    deadline: Option<Instant>,
+    cancel: Option<CancellationToken>,
+    caller_context: Option<RPCCallerContextProto>,
+}
+
+impl CallLimits {
+    pub(crate) fn new(options: &CallOptions) -> Self {
+        Self {
+            deadline: match (
+                options.timeout.map(|timeout| Instant::now() + timeout),
+                options.deadline,
+            ) {
+                (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+                (timeout, deadline) => timeout.or(deadline),
+            },
+            cancel: options.cancel.clone(),
+            caller_context: options.caller_context.as_ref().map(Into::into),
+        }
+    }
+
+    /// Fail if the call is cancelled or its deadline is passed.
+    pub(crate) fn check(&self, method: &str) -> Result<(), RpcError> {
+        if self
+            .cancel
+            .as_ref()
+            .map(|c| c.is_cancelled())
+            .unwrap_or(false)
+        {
+            return Err(RpcError::Cancelled {
+                method: method.to_owned(),
+            });
+        }
+        if self.deadline.map(|d| d <= Instant::now()).unwrap_or(false) {
+            return Err(RpcError::TimedOut {
+                method: method.to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The time left until the deadline.
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|d| d.saturating_duration_since(Instant::now()))
+    }
+
+    /// Socket timeout for a single blocking operation.  With a
+    /// cancellation token, it never exceeds CANCEL_POLL_INTERVAL.
+    fn io_timeout(&self) -> Option<Duration> {
+        let remaining = self.remaining();
+        let poll = self.cancel.as_ref().map(|_| CANCEL_POLL_INTERVAL);
+        let timeout = match (remaining, poll) {
+            (Some(remaining), Some(poll)) => Some(remaining.min(poll)),
+            (remaining, poll) => remaining.or(poll),
+        };
+        // Zero timeout is an error for set_read_timeout.
+        timeout.map(|t| t.max(Duration::from_millis(1)))
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
 pub static ERROR_CLASS_MAP: ::phf::Map<&'static str, RpcErrorKind> = ::phf::phf_map! {
     "org/apache/hadoop/hdfs/protocol/SnapshotException" => RpcErrorKind::Snapshot,
 };
@@ -170,6 +449,18 @@ pub trait RpcConnection {
 
     /// Shoutdown the connection.
     fn shutdown(self) -> Result<(), RpcError>;
+
+    /// Limits for subsequent calls.
+    fn call_options(&self) -> &CallOptions;
+
+    /// Set limits for subsequent calls.
+    fn set_call_options(&mut self, options: CallOptions);
+
+    /// Failovers since the connection's creation; a connection to a
+    /// single namenode has none.
+    fn failover_counts(&self) -> crate::ha_rpc::FailoverCounts {
+        Default::default()
+    }
 }
 
 /**
@@ -179,8 +470,74 @@ pub trait RpcConnection {
 pub struct HdfsConnection {
     stream: TcpStream,
     user: Box<str>,
+    // The authenticated user when `user` is impersonated.
+    real_user: Option<Box<str>>,
     call_id: InfiniteSeq,
     client_id: [u8; 16],
+    options: CallOptions,
+    // Last values passed to set_{read,write}_timeout, to avoid extra
+    // syscalls.
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    // The last namenode state ID seen in responses; it is sent with
+    // each call, so observer namenodes do not serve stale data.
+    state_id: Option<i64>,
+    // A response was received, so the server has accepted the
+    // connection header.
+    handshake_done: bool,
+    scratch: CallScratch,
+}
+
+/**
+ * Buffers and headers reused by the calls of a connection, so that a
+ * call of a method seen before allocates little beyond its response.
+ */
+#[derive(Debug)]
+struct CallScratch {
+    // The request frame.
+    request: Vec<u8>,
+    // The response frame.
+    response: Vec<u8>,
+    // The fields that change between calls are set by each call.
+    rpc_header: RpcRequestHeaderProto,
+    method_headers: HashMap<String, RequestHeaderProto>,
+}
+
+impl CallScratch {
+    fn new(client_id: &[u8; 16]) -> Self {
+        let mut rpc_header = RpcRequestHeaderProto::default();
+        rpc_header.set_rpcKind(RpcKindProto::RPC_PROTOCOL_BUFFER);
+        rpc_header.set_rpcOp(RpcRequestHeaderProto_OperationProto::RPC_FINAL_PACKET);
+        rpc_header.set_clientId(client_id.to_vec());
+        Self {
+            request: vec![],
+            response: vec![],
+            rpc_header,
+            method_headers: HashMap::new(),
+        }
+    }
+
+    /// The cached header of the method.  It takes the map rather
+    /// than self, so that the other fields may be borrowed with it.
+    fn method_header<'h>(
+        method_headers: &'h mut HashMap<String, RequestHeaderProto>,
+        method_name: &str,
+    ) -> &'h RequestHeaderProto {
+        if !method_headers.contains_key(method_name) {
+            let mut rh = RequestHeaderProto::default();
+            rh.set_declaringClassProtocolName(RPC_HDFS_PROTOCOL.to_owned());
+            rh.set_clientProtocolVersion(1);
+            rh.set_methodName(method_name.to_owned());
+            method_headers.insert(method_name.to_owned(), rh);
+        }
+        &method_headers[method_name]
+    }
+
+    fn recycle(buf: &mut Vec<u8>) {
+        if buf.capacity() > SCRATCH_RETAINED_LEN {
+            *buf = vec![];
+        }
+    }
 }
 
 impl HdfsConnection {
@@ -200,17 +557,26 @@ impl HdfsConnection {
         unimplemented!("TODO: handle service undefined in the config")
     }
 
+    /// Without the user, connect as User::current.
     pub fn new_with_user<C: Connector, A: ToSocketAddrs>(
         user: Option<Cow<'_, str>>,
         addr: A,
         connector: &C,
     ) -> Result<Self, RpcError> {
-        let user = user.map(Ok).unwrap_or_else(|| {
-            util::get_username()
-                .map(Into::into)
-                .map_err(RpcError::NoUser)
-        })?;
-        Self::new(user, addr, connector)
+        match user {
+            Some(user) => Self::new(user, addr, connector),
+            None => {
+                let user = User::current().map_err(RpcError::NoUser)?;
+                Self::connect(
+                    user.name().into(),
+                    user.real_user().map(Into::into),
+                    addr,
+                    connector,
+                    new_client_id(),
+                    None,
+                )
+            }
+        }
     }
 
     pub fn new_without_user<C: Connector, A: ToSocketAddrs>(
@@ -226,33 +592,73 @@ impl HdfsConnection {
         user: Cow<'_, str>,
         addr: A,
         connector: &C,
+    ) -> Result<Self, RpcError> {
+        Self::connect(user, None, addr, connector, new_client_id(), None)
+    }
+
+    /** Connect as a proxy user: the real user acts on behalf of the
+     * user.  The cluster has to allow the real user to impersonate
+     * (hadoop.proxyuser.* settings).
+     */
+    pub fn new_as_proxy<C: Connector, A: ToSocketAddrs>(
+        user: Cow<'_, str>,
+        real_user: Cow<'_, str>,
+        addr: A,
+        connector: &C,
+    ) -> Result<Self, RpcError> {
+        Self::connect(
+            user,
+            Some(real_user),
+            addr,
+            connector,
+            new_client_id(),
+            None,
+        )
+    }
+
+    /// Connect with the given client ID; HaHdfsConnection uses the same
+    /// ID for all the namenodes, and limits the connect by the call
+    /// deadline.
+    pub(crate) fn connect<C: Connector, A: ToSocketAddrs>(
+        user: Cow<'_, str>,
+        real_user: Option<Cow<'_, str>>,
+        addr: A,
+        connector: &C,
+        client_id: [u8; 16],
+        timeout: Option<Duration>,
     ) -> Result<Self, RpcError> {
         let stream = connector
-            .get_connection(addr)
+            .get_connection_timeout(addr, timeout)
             .map_err(RpcError::Connector)?;
         Self {
             stream,
             user: user.into(),
+            real_user: real_user.map(Into::into),
             call_id: Default::default(),
-            // "ClientId must be a UUID - that is 16 octets"
-            // (hadoop/../RetryCache.java).
-            client_id: *uuid::Uuid::new_v4().as_bytes(),
+            client_id,
+            options: Default::default(),
+            read_timeout: None,
+            write_timeout: None,
+            state_id: None,
+            handshake_done: false,
+            scratch: CallScratch::new(&client_id),
         }
         .init_connection()
     }
 
-    #[instrument]
+    /// The authenticated user of a proxy connection.
+    pub fn get_real_user(&self) -> Option<&str> {
+        self.real_user.as_deref()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
     fn init_connection(mut self) -> Result<Self, RpcError> {
         self.stream.set_nodelay(true)?;
         {
             let mut cos = CodedOutputStream::new(&mut self.stream);
 
             cos.write_all(&RPC_HEADER[..])?;
-            cos.write_all(&[
-                RPC_VERSION,
-                80, // TODO no magic
-                0,  // TODO no magic
-            ])?;
+            cos.write_all(&[RPC_VERSION, RPC_SERVICE_CLASS, RPC_AUTH_PROTOCOL_NONE])?;
 
             let mut hh = RpcRequestHeaderProto::default();
             hh.set_rpcKind(RpcKindProto::RPC_PROTOCOL_BUFFER);
@@ -263,6 +669,9 @@ impl HdfsConnection {
 
             let mut cc = IpcConnectionContextProto::default();
             cc.mut_userInfo().set_effectiveUser(self.user.to_string());
+            if let Some(real_user) = &self.real_user {
+                cc.mut_userInfo().set_realUser(real_user.to_string());
+            }
             cc.set_protocol(RPC_HDFS_PROTOCOL.to_owned());
 
             Self::send_message_group(&mut cos, &[&hh, &cc])?;
@@ -271,7 +680,7 @@ impl HdfsConnection {
         Ok(self)
     }
 
-    #[instrument(skip(cos))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(cos)))]
     fn send_message_group(
         cos: &mut CodedOutputStream<'_>,
         messages: &[&dyn Message],
@@ -291,46 +700,200 @@ impl HdfsConnection {
     }
 }
 
-impl RpcConnection for HdfsConnection {
-    fn get_user(&self) -> &str {
-        &self.user
+impl HdfsConnection {
+    /// The last namenode state ID seen, if the namenode reports it.
+    pub fn last_seen_state_id(&self) -> Option<i64> {
+        self.state_id
     }
 
-    #[instrument]
-    fn call<Output: Message>(
+    /// The state ID never decreases; it is used to carry the state
+    /// over to a new connection on failover.
+    pub fn update_last_seen_state_id(&mut self, state_id: Option<i64>) {
+        self.state_id = self.state_id.max(state_id);
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        if self.read_timeout != timeout {
+            self.stream.set_read_timeout(timeout)?;
+            self.read_timeout = timeout;
+        }
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        if self.write_timeout != timeout {
+            self.stream.set_write_timeout(timeout)?;
+            self.write_timeout = timeout;
+        }
+        Ok(())
+    }
+
+    /// Until the first response, a closed connection means that the
+    /// server has rejected the connection header.
+    fn handshake_error(&self, e: RpcError) -> RpcError {
+        if self.handshake_done {
+            return e;
+        }
+        match e {
+            RpcError::Io(ref ioe)
+            | RpcError::Protobuf(protobuf::ProtobufError::IoError(ref ioe))
+                if matches!(
+                    ioe.kind(),
+                    io::ErrorKind::UnexpectedEof
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::BrokenPipe
+                ) =>
+            {
+                RpcError::Handshake(HandshakeError::Closed)
+            }
+            e => e,
+        }
+    }
+
+    /// Read exactly buf.len() bytes, respecting the limits.
+    fn read_exact_limited(
+        &mut self,
+        mut buf: &mut [u8],
+        limits: &CallLimits,
+        method_name: &str,
+    ) -> Result<(), RpcError> {
+        while !buf.is_empty() {
+            limits.check(method_name)?;
+            self.set_read_timeout(limits.io_timeout())?;
+            match self.stream.read(buf) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => buf = &mut buf[n..],
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Perform a call with explicit limits.  HaHdfsConnection uses it
+    /// to share single deadline between several namenodes, and to
+    /// re-send the call with the same call ID.
+    pub(crate) fn call_limited<Output: Message>(
         &mut self,
         method_name: Cow<'_, str>,
         input: &dyn Message,
+        limits: &CallLimits,
+        retry: Option<CallRetry>,
     ) -> Result<Output, RpcError> {
-        // TODO smallvec buffer for async IO? also, const generic can be used
-        // for expected header size.  But it makes no lot sense for async, as it
-        // does not use stack, but creates structs all the time.
-        let mut hh = RpcRequestHeaderProto::default();
-        hh.set_rpcKind(RpcKindProto::RPC_PROTOCOL_BUFFER);
-        hh.set_rpcOp(RpcRequestHeaderProto_OperationProto::RPC_FINAL_PACKET);
-        hh.set_callId(self.call_id.next());
-        hh.set_retryCount(-1);
-        hh.set_clientId(Vec::from(&self.client_id[..]));
+        limits.check(&method_name)?;
 
-        let mut rh = RequestHeaderProto::default();
-        rh.set_declaringClassProtocolName(RPC_HDFS_PROTOCOL.to_owned());
-        rh.set_clientProtocolVersion(1);
-        rh.set_methodName(method_name.to_string());
+        let res = self.call_unchecked(method_name.as_ref(), input, limits, retry);
+        if let Err(RpcError::TimedOut { .. }) | Err(RpcError::Cancelled { .. }) = res {
+            // The response may arrive later; nobody will read it.
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+        res
+    }
 
-        let mut pbs = CodedOutputStream::new(&mut self.stream);
+    fn call_unchecked<Output: Message>(
+        &mut self,
+        method_name: &str,
+        input: &dyn Message,
+        limits: &CallLimits,
+        retry: Option<CallRetry>,
+    ) -> Result<Output, RpcError> {
+        let attempt = match retry {
+            Some(retry) => retry,
+            None => CallRetry {
+                call_id: self.call_id.next(),
+                retry_count: -1,
+            },
+        };
+        let CallScratch {
+            request: scratch_request,
+            rpc_header: hh,
+            method_headers,
+            ..
+        } = &mut self.scratch;
+        hh.set_callId(attempt.call_id);
+        hh.set_retryCount(attempt.retry_count);
+        match self.state_id {
+            Some(state_id) => hh.set_stateId(state_id),
+            None => hh.clear_stateId(),
+        }
+        match &limits.caller_context {
+            Some(caller_context) => hh.set_callerContext(caller_context.clone()),
+            None => hh.clear_callerContext(),
+        }
+        let rh = CallScratch::method_header(method_headers, method_name);
 
-        Self::send_message_group(&mut pbs, &[&hh, &rh, input])?;
+        // The whole request is serialized first and sent with a single
+        // write.
+        let mut request = std::mem::take(scratch_request);
+        request.clear();
+        {
+            let mut pbs = CodedOutputStream::vec(&mut request);
+            Self::send_message_group(&mut pbs, &[&*hh, rh, input])?;
+        }
+        // Write timeout doesn't need polling: a blocked write is quite
+        // unlikely.
+        self.set_write_timeout(limits.io_timeout())?;
+        let sent = self.stream.write_all(&request);
+        CallScratch::recycle(&mut request);
+        self.scratch.request = request;
+        sent.map_err(|e| {
+            if is_timeout(&e) {
+                RpcError::TimedOut {
+                    method: method_name.to_owned(),
+                }
+            } else {
+                self.handshake_error(e.into())
+            }
+        })?;
 
         // TODO: byteorder
         let mut data = [0u8; 4];
-        self.stream.read_exact(&mut data)?;
+        self.read_exact_limited(&mut data, limits, method_name)
+            .map_err(|e| self.handshake_error(e))?;
+        if !self.handshake_done && &data == b"HTTP" {
+            return Err(RpcError::Handshake(HandshakeError::Http));
+        }
         let resp_len = u32::from_be_bytes(data);
+        if resp_len > MAX_RESPONSE_LEN {
+            return Err(if self.handshake_done {
+                RpcError::ResponseTooLong { len: resp_len }
+            } else {
+                RpcError::Handshake(HandshakeError::BadFrame { len: resp_len })
+            });
+        }
 
-        let mut frame = (&mut self.stream).take(resp_len as u64);
-        let mut pis = CodedInputStream::new(&mut frame);
+        let mut frame = std::mem::take(&mut self.scratch.response);
+        frame.clear();
+        frame.resize(resp_len as usize, 0);
+        let res = self
+            .read_exact_limited(&mut frame, limits, method_name)
+            .map_err(|e| self.handshake_error(e))
+            .and_then(|()| self.parse_response(&frame, resp_len, method_name));
+        CallScratch::recycle(&mut frame);
+        self.scratch.response = frame;
+        res
+    }
+
+    fn parse_response<Output: Message>(
+        &mut self,
+        frame: &[u8],
+        resp_len: u32,
+        method_name: &str,
+    ) -> Result<Output, RpcError> {
+        let mut pis = CodedInputStream::from_bytes(frame);
 
         // Delimited message
-        let mut resp_header: RpcResponseHeaderProto = pis.read_message()?;
+        let mut resp_header: RpcResponseHeaderProto = pis.read_message().map_err(|e| match e {
+            protobuf::ProtobufError::WireError(_) if !self.handshake_done => {
+                RpcError::Handshake(HandshakeError::BadFrame { len: resp_len })
+            }
+            e => e.into(),
+        })?;
+        self.handshake_done = true;
+        if resp_header.has_stateId() {
+            self.update_last_seen_state_id(Some(resp_header.get_stateId()));
+        }
 
         let res = match resp_header.get_status() {
             // Delimited message
@@ -358,12 +921,25 @@ impl RpcConnection for HdfsConnection {
                     })
                 }
             }
-            RpcStatus::FATAL => Err(RpcError::FatalResponse {
-                status: resp_header.get_status(),
-                error_msg: resp_header.take_errorMsg(),
-                error_detail: resp_header.get_errorDetail(),
-                exception: resp_header.take_exceptionClassName(),
-                method: method_name.to_string(),
+            RpcStatus::FATAL => Err(match resp_header.get_errorDetail() {
+                RpcErrorCode::FATAL_VERSION_MISMATCH => {
+                    RpcError::Handshake(HandshakeError::VersionMismatch {
+                        error_msg: resp_header.take_errorMsg(),
+                    })
+                }
+                RpcErrorCode::FATAL_UNAUTHORIZED => {
+                    RpcError::Handshake(HandshakeError::Unauthorized {
+                        error_msg: resp_header.take_errorMsg(),
+                        exception: resp_header.take_exceptionClassName(),
+                    })
+                }
+                _ => RpcError::FatalResponse {
+                    status: resp_header.get_status(),
+                    error_msg: resp_header.take_errorMsg(),
+                    error_detail: resp_header.get_errorDetail(),
+                    exception: resp_header.take_exceptionClassName(),
+                    method: method_name.to_string(),
+                },
             }),
         };
 
@@ -376,11 +952,27 @@ impl RpcConnection for HdfsConnection {
 
         res
     }
+}
+
+impl RpcConnection for HdfsConnection {
+    fn get_user(&self) -> &str {
+        &self.user
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    fn call<Output: Message>(
+        &mut self,
+        method_name: Cow<'_, str>,
+        input: &dyn Message,
+    ) -> Result<Output, RpcError> {
+        let limits = CallLimits::new(&self.options);
+        self.call_limited(method_name, input, &limits, None)
+    }
 
     /// Send a closing packet to the server.  It should be just
     /// Drop::drop, but it wouldn't work for the anticipated async
     /// version.
-    #[instrument]
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
     fn shutdown(mut self) -> Result<(), RpcError> {
         let mut hh = RpcRequestHeaderProto::default();
         hh.set_rpcKind(RpcKindProto::RPC_PROTOCOL_BUFFER);
@@ -397,6 +989,14 @@ impl RpcConnection for HdfsConnection {
         // the stream will be closed by drop.
         Ok(())
     }
+
+    fn call_options(&self) -> &CallOptions {
+        &self.options
+    }
+
+    fn set_call_options(&mut self, options: CallOptions) {
+        self.options = options;
+    }
 }
 
 #[cfg(test)]
@@ -410,4 +1010,412 @@ mod tests {
         assert_eq!(is.next(), 1);
         assert_eq!(is.next(), 2);
     }
+
+    /// A server that accepts connections and never responds.
+    fn silent_server() -> (std::net::SocketAddr, std::thread::JoinHandle<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            // Consume everything until the client closes the connection.
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_call_state_id() {
+        use hdfesse_proto::ClientNamenodeProtocol::MsyncResponseProto;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut resp_header = RpcResponseHeaderProto::default();
+            resp_header.set_callId(0);
+            resp_header.set_status(RpcStatus::SUCCESS);
+            resp_header.set_stateId(42);
+            let mut cos = CodedOutputStream::new(&mut stream);
+            HdfsConnection::send_message_group(
+                &mut cos,
+                &[&resp_header, &MsyncResponseProto::default()],
+            )
+            .unwrap();
+            drop(cos);
+            let mut buf = [0u8; 1024];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+        conn.update_last_seen_state_id(Some(7));
+        let _: MsyncResponseProto = conn
+            .call("msync".into(), &MsyncResponseProto::default())
+            .unwrap();
+        assert_eq!(conn.last_seen_state_id(), Some(42));
+
+        // Never goes back.
+        conn.update_last_seen_state_id(Some(10));
+        assert_eq!(conn.last_seen_state_id(), Some(42));
+
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    /// A server that replies to the connection with the data and
+    /// waits for the client to close the connection.
+    fn replying_server(reply: Vec<u8>) -> (std::net::SocketAddr, std::thread::JoinHandle<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(&reply).unwrap();
+            let mut buf = [0u8; 1024];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+        (addr, handle)
+    }
+
+    fn first_call(addr: std::net::SocketAddr) -> Result<RpcResponseHeaderProto, RpcError> {
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {})?;
+        conn.call("getFileInfo".into(), &RpcRequestHeaderProto::default())
+    }
+
+    #[test]
+    fn test_handshake_http() {
+        let (addr, server) =
+            replying_server(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n".to_vec());
+        let res = first_call(addr);
+        assert!(
+            matches!(res, Err(RpcError::Handshake(HandshakeError::Http))),
+            "{:?}",
+            res
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_handshake_bad_frame() {
+        let (addr, server) = replying_server(b"SSH-2.0-OpenSSH\r\n".to_vec());
+        let res = first_call(addr);
+        assert!(
+            matches!(
+                res,
+                Err(RpcError::Handshake(HandshakeError::BadFrame { .. }))
+            ),
+            "{:?}",
+            res
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_response_too_long() {
+        use hdfesse_proto::ClientNamenodeProtocol::MsyncResponseProto;
+
+        let mut reply = vec![];
+        let mut resp_header = RpcResponseHeaderProto::default();
+        resp_header.set_callId(0);
+        resp_header.set_status(RpcStatus::SUCCESS);
+        let mut cos = CodedOutputStream::vec(&mut reply);
+        HdfsConnection::send_message_group(
+            &mut cos,
+            &[&resp_header, &MsyncResponseProto::default()],
+        )
+        .unwrap();
+        cos.flush().unwrap();
+        drop(cos);
+        // The next frame claims 4 GiB; nothing is allocated for it.
+        reply.extend_from_slice(&u32::MAX.to_be_bytes());
+        let (addr, server) = replying_server(reply);
+
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+        let input = MsyncResponseProto::default();
+        let _: MsyncResponseProto = conn.call("msync".into(), &input).unwrap();
+        let res: Result<MsyncResponseProto, _> = conn.call("msync".into(), &input);
+        assert!(
+            matches!(res, Err(RpcError::ResponseTooLong { len: u32::MAX })),
+            "{:?}",
+            res
+        );
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_handshake_closed() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            stream
+        });
+        let res = first_call(addr);
+        assert!(
+            matches!(res, Err(RpcError::Handshake(HandshakeError::Closed))),
+            "{:?}",
+            res
+        );
+        assert_eq!(res.unwrap_err().code(), ErrorCode::Io);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_handshake_version_mismatch() {
+        let mut resp_header = RpcResponseHeaderProto::default();
+        resp_header.set_callId(0);
+        resp_header.set_status(RpcStatus::FATAL);
+        resp_header.set_errorDetail(RpcErrorCode::FATAL_VERSION_MISMATCH);
+        resp_header
+            .set_errorMsg("Server IPC version 10 cannot communicate with client version 9".into());
+        let mut reply = vec![];
+        {
+            let mut cos = CodedOutputStream::new(&mut reply);
+            HdfsConnection::send_message_group(&mut cos, &[&resp_header]).unwrap();
+        }
+        let (addr, server) = replying_server(reply);
+        let res = first_call(addr);
+        match res {
+            Err(RpcError::Handshake(HandshakeError::VersionMismatch { error_msg })) => {
+                assert!(error_msg.starts_with("Server IPC version 10"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_call_timeout() {
+        let (addr, server) = silent_server();
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+        conn.set_call_options(CallOptions::default().with_timeout(Duration::from_millis(50)));
+
+        let res: Result<RpcResponseHeaderProto, _> =
+            conn.call("getFileInfo".into(), &RpcRequestHeaderProto::default());
+        match res {
+            Err(RpcError::TimedOut { method }) => assert_eq!(method, "getFileInfo"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_call_deadline() {
+        let passed = CallOptions::default()
+            .with_timeout(Duration::from_secs(60))
+            .with_deadline(Instant::now());
+        match CallLimits::new(&passed).check("getFileInfo") {
+            Err(RpcError::TimedOut { method }) => assert_eq!(method, "getFileInfo"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let far = CallOptions::default()
+            .with_timeout(Duration::from_millis(50))
+            .with_deadline(Instant::now() + Duration::from_secs(60));
+        let limits = CallLimits::new(&far);
+        assert!(limits.check("getFileInfo").is_ok());
+        assert!(limits.io_timeout().unwrap() <= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_call_cancelled() {
+        let (addr, server) = silent_server();
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+        let token = CancellationToken::new();
+        conn.set_call_options(CallOptions::default().with_cancel(token.clone()));
+
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        });
+        let res: Result<RpcResponseHeaderProto, _> =
+            conn.call("getFileInfo".into(), &RpcRequestHeaderProto::default());
+        assert!(matches!(res, Err(RpcError::Cancelled { .. })), "{:?}", res);
+
+        canceller.join().unwrap();
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    /// Accept a connection and return its connection context.
+    fn read_connection_context(listener: std::net::TcpListener) -> IpcConnectionContextProto {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut preamble = [0u8; 7];
+        stream.read_exact(&mut preamble).unwrap();
+        assert_eq!(&preamble[..4], RPC_HEADER);
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut data).unwrap();
+        let mut cis = CodedInputStream::from_bytes(&data);
+        let _header: RpcRequestHeaderProto = cis.read_message().unwrap();
+        cis.read_message().unwrap()
+    }
+
+    /// Accept a connection and answer the calls with empty messages;
+    /// return the headers of the calls.
+    fn answering_server(
+        calls: usize,
+    ) -> (
+        std::net::SocketAddr,
+        std::thread::JoinHandle<Vec<(RpcRequestHeaderProto, RequestHeaderProto)>>,
+    ) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut preamble = [0u8; 7];
+            stream.read_exact(&mut preamble).unwrap();
+            let read_frame = |stream: &mut std::net::TcpStream| {
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len).unwrap();
+                let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut data).unwrap();
+                data
+            };
+            // The connection context.
+            read_frame(&mut stream);
+            let mut headers = vec![];
+            for _ in 0..calls {
+                let data = read_frame(&mut stream);
+                let mut cis = CodedInputStream::from_bytes(&data);
+                let hh: RpcRequestHeaderProto = cis.read_message().unwrap();
+                let rh: RequestHeaderProto = cis.read_message().unwrap();
+
+                let mut resp_header = RpcResponseHeaderProto::default();
+                resp_header.set_callId(hh.get_callId() as u32);
+                resp_header.set_status(RpcStatus::SUCCESS);
+                let mut reply = vec![];
+                {
+                    let mut cos = CodedOutputStream::new(&mut reply);
+                    HdfsConnection::send_message_group(
+                        &mut cos,
+                        &[&resp_header, &IpcConnectionContextProto::default()],
+                    )
+                    .unwrap();
+                }
+                stream.write_all(&reply).unwrap();
+                headers.push((hh, rh));
+            }
+            headers
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_call_scratch_reuse() {
+        let (addr, server) = answering_server(4);
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+        let input = RpcRequestHeaderProto::default();
+        let call = |conn: &mut HdfsConnection, method: &'static str| {
+            conn.call::<IpcConnectionContextProto>(method.into(), &input)
+                .unwrap();
+            conn.scratch.request.as_ptr()
+        };
+
+        conn.set_call_options(
+            CallOptions::default().with_caller_context(CallerContext::new("job")),
+        );
+        call(&mut conn, "getFileInfo");
+        conn.set_call_options(CallOptions::default());
+        let buf = call(&mut conn, "getFileInfo");
+        assert_eq!(call(&mut conn, "getFileInfo"), buf);
+        call(&mut conn, "getListing");
+        assert_eq!(conn.scratch.method_headers.len(), 2);
+
+        let headers = server.join().unwrap();
+        let call_ids: Vec<_> = headers.iter().map(|(hh, _)| hh.get_callId()).collect();
+        assert_eq!(call_ids, [0, 1, 2, 3]);
+        // The per-call fields do not leak into the next calls.
+        assert!(headers[0].0.has_callerContext());
+        assert!(!headers[1].0.has_callerContext());
+        assert_eq!(headers[3].1.get_methodName(), "getListing");
+        assert_eq!(headers[3].0.get_clientId(), &conn.client_id[..]);
+    }
+
+    #[test]
+    fn test_caller_context() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut preamble = [0u8; 7];
+            stream.read_exact(&mut preamble).unwrap();
+            // The connection context, then the call.
+            let mut headers = vec![];
+            for _ in 0..2 {
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len).unwrap();
+                let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut data).unwrap();
+                let mut cis = CodedInputStream::from_bytes(&data);
+                headers.push(cis.read_message::<RpcRequestHeaderProto>().unwrap());
+            }
+            headers
+        });
+
+        let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+        conn.set_call_options(CallOptions::default().with_caller_context(
+            CallerContext::new("mapreduce_job_1").with_signature(&b"sig"[..]),
+        ));
+        // The server closes the connection without a response.
+        let _ = conn.call::<RpcResponseHeaderProto>(
+            "getFileInfo".into(),
+            &RpcRequestHeaderProto::default(),
+        );
+        let headers = server.join().unwrap();
+        assert!(!headers[0].has_callerContext());
+        assert_eq!(
+            headers[1].get_callerContext().get_context(),
+            "mapreduce_job_1"
+        );
+        assert_eq!(headers[1].get_callerContext().get_signature(), b"sig");
+    }
+
+    #[test]
+    fn test_connection_context_user() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || read_connection_context(listener));
+
+        let conn = HdfsConnection::new("alice".into(), addr, &SimpleConnector {}).unwrap();
+        let context = server.join().unwrap();
+        assert_eq!(context.get_userInfo().get_effectiveUser(), "alice");
+        assert!(!context.get_userInfo().has_realUser());
+        assert_eq!(conn.get_real_user(), None);
+    }
+
+    #[test]
+    fn test_connection_context_proxy_user() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || read_connection_context(listener));
+
+        let conn = HdfsConnection::new_as_proxy(
+            "alice".into(),
+            "gateway".into(),
+            addr,
+            &SimpleConnector {},
+        )
+        .unwrap();
+        let context = server.join().unwrap();
+        assert_eq!(context.get_userInfo().get_effectiveUser(), "alice");
+        assert_eq!(context.get_userInfo().get_realUser(), "gateway");
+        assert_eq!(conn.get_user(), "alice");
+        assert_eq!(conn.get_real_user(), Some("gateway"));
+    }
 }
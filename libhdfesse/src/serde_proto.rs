@@ -0,0 +1,73 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Serde helpers for protobuf enums used in the status types; an enum
+ * is represented by its protobuf name, e.g. "DECOMMISSIONED".
+ */
+use protobuf::ProtobufEnum;
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+fn from_name<E: ProtobufEnum, Er: Error>(name: &str) -> Result<E, Er> {
+    E::values()
+        .iter()
+        .find(|value| value.descriptor().name() == name)
+        .copied()
+        .ok_or_else(|| Er::custom(format_args!("unknown enum value {:?}", name)))
+}
+
+/// For `#[serde(with = "proto_enum")]`.
+pub(crate) mod proto_enum {
+    use super::*;
+
+    pub(crate) fn serialize<E: ProtobufEnum, S: Serializer>(
+        value: &E,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(value.descriptor().name())
+    }
+
+    pub(crate) fn deserialize<'de, E: ProtobufEnum, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<E, D::Error> {
+        from_name(&String::deserialize(deserializer)?)
+    }
+}
+
+/// For `#[serde(with = "proto_enum_vec")]`.
+pub(crate) mod proto_enum_vec {
+    use super::*;
+    use serde::ser::SerializeSeq;
+
+    pub(crate) fn serialize<E: ProtobufEnum, S: Serializer>(
+        values: &[E],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(value.descriptor().name())?;
+        }
+        seq.end()
+    }
+
+    pub(crate) fn deserialize<'de, E: ProtobufEnum, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<E>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|name| from_name(name))
+            .collect()
+    }
+}
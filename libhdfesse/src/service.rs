@@ -18,17 +18,161 @@ use hdfesse_proto::{
     ClientNamenodeProtocol::*, Security::*,
 };
 
-use crate::rpc;
+use crate::{rate_limit::RateLimiter, rpc, stats::CallCounts};
+use std::borrow::Cow;
 
 type Result<V> = std::result::Result<V, rpc::RpcError>;
 
+/**
+ * Whether a namenode method may be re-sent after the connection
+ * fails during the call, i.e. when it is unknown if the namenode has
+ * executed it.  It follows the annotations of Java's ClientProtocol.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotency {
+    /// Repeating the call has the same effect as a single one.
+    Idempotent,
+    /// The namenode retry cache deduplicates the call if it is
+    /// re-sent with the same client ID and call ID.
+    AtMostOnce,
+    /// Unknown method; it is never re-sent.
+    Unsafe,
+}
+
+static METHOD_IDEMPOTENCY: ::phf::Map<&'static str, Idempotency> = ::phf::phf_map! {
+    "abandonBlock" => Idempotency::Idempotent,
+    "addBlock" => Idempotency::Idempotent,
+    "addCacheDirective" => Idempotency::AtMostOnce,
+    "addCachePool" => Idempotency::AtMostOnce,
+    "addErasureCodingPolicies" => Idempotency::AtMostOnce,
+    "allowSnapshot" => Idempotency::Idempotent,
+    "append" => Idempotency::AtMostOnce,
+    "cancelDelegationToken" => Idempotency::Idempotent,
+    "checkAccess" => Idempotency::Idempotent,
+    "complete" => Idempotency::Idempotent,
+    "concat" => Idempotency::AtMostOnce,
+    "create" => Idempotency::AtMostOnce,
+    "createEncryptionZone" => Idempotency::AtMostOnce,
+    "createSnapshot" => Idempotency::AtMostOnce,
+    "createSymlink" => Idempotency::AtMostOnce,
+    "delete" => Idempotency::AtMostOnce,
+    "deleteSnapshot" => Idempotency::AtMostOnce,
+    "disableErasureCodingPolicy" => Idempotency::AtMostOnce,
+    "disallowSnapshot" => Idempotency::Idempotent,
+    "enableErasureCodingPolicy" => Idempotency::AtMostOnce,
+    "finalizeUpgrade" => Idempotency::Idempotent,
+    "fsync" => Idempotency::Idempotent,
+    "getAclStatus" => Idempotency::Idempotent,
+    "getAdditionalDatanode" => Idempotency::Idempotent,
+    "getBatchedListing" => Idempotency::Idempotent,
+    "getBlockLocations" => Idempotency::Idempotent,
+    "getContentSummary" => Idempotency::Idempotent,
+    "getCurrentEditLogTxid" => Idempotency::Idempotent,
+    "getDataEncryptionKey" => Idempotency::Idempotent,
+    "getDatanodeReport" => Idempotency::Idempotent,
+    "getDatanodeStorageReport" => Idempotency::Idempotent,
+    "getDelegationToken" => Idempotency::Idempotent,
+    "getECTopologyResultForPolicies" => Idempotency::Idempotent,
+    "getEZForPath" => Idempotency::Idempotent,
+    "getEditsFromTxid" => Idempotency::Idempotent,
+    "getErasureCodingCodecs" => Idempotency::Idempotent,
+    "getErasureCodingPolicies" => Idempotency::Idempotent,
+    "getErasureCodingPolicy" => Idempotency::Idempotent,
+    "getFileInfo" => Idempotency::Idempotent,
+    "getFileLinkInfo" => Idempotency::Idempotent,
+    "getFsECBlockGroupStats" => Idempotency::Idempotent,
+    "getFsReplicatedBlockStats" => Idempotency::Idempotent,
+    "getFsStats" => Idempotency::Idempotent,
+    "getLinkTarget" => Idempotency::Idempotent,
+    "getListing" => Idempotency::Idempotent,
+    "getLocatedFileInfo" => Idempotency::Idempotent,
+    "getPreferredBlockSize" => Idempotency::Idempotent,
+    "getQuotaUsage" => Idempotency::Idempotent,
+    "getServerDefaults" => Idempotency::Idempotent,
+    "getHAServiceState" => Idempotency::Idempotent,
+    "getSnapshotDiffReport" => Idempotency::Idempotent,
+    "getSnapshotDiffReportListing" => Idempotency::Idempotent,
+    "getSnapshotListing" => Idempotency::Idempotent,
+    "getSnapshottableDirListing" => Idempotency::Idempotent,
+    "getStoragePolicies" => Idempotency::Idempotent,
+    "getStoragePolicy" => Idempotency::Idempotent,
+    "getXAttrs" => Idempotency::Idempotent,
+    "isFileClosed" => Idempotency::Idempotent,
+    "listCacheDirectives" => Idempotency::Idempotent,
+    "listCachePools" => Idempotency::Idempotent,
+    "listCorruptFileBlocks" => Idempotency::Idempotent,
+    "listEncryptionZones" => Idempotency::Idempotent,
+    "listOpenFiles" => Idempotency::Idempotent,
+    "listReencryptionStatus" => Idempotency::Idempotent,
+    "listXAttrs" => Idempotency::Idempotent,
+    "metaSave" => Idempotency::Idempotent,
+    "mkdirs" => Idempotency::Idempotent,
+    "modifyAclEntries" => Idempotency::Idempotent,
+    "modifyCacheDirective" => Idempotency::AtMostOnce,
+    "modifyCachePool" => Idempotency::AtMostOnce,
+    "msync" => Idempotency::Idempotent,
+    "recoverLease" => Idempotency::Idempotent,
+    "reencryptEncryptionZone" => Idempotency::AtMostOnce,
+    "refreshNodes" => Idempotency::Idempotent,
+    "removeAcl" => Idempotency::Idempotent,
+    "removeAclEntries" => Idempotency::Idempotent,
+    "removeCacheDirective" => Idempotency::AtMostOnce,
+    "removeCachePool" => Idempotency::AtMostOnce,
+    "removeDefaultAcl" => Idempotency::Idempotent,
+    "removeErasureCodingPolicy" => Idempotency::AtMostOnce,
+    "removeXAttr" => Idempotency::AtMostOnce,
+    "rename" => Idempotency::AtMostOnce,
+    "rename2" => Idempotency::AtMostOnce,
+    "renameSnapshot" => Idempotency::AtMostOnce,
+    "renewDelegationToken" => Idempotency::Idempotent,
+    "renewLease" => Idempotency::Idempotent,
+    "reportBadBlocks" => Idempotency::Idempotent,
+    "restoreFailedStorage" => Idempotency::Idempotent,
+    "rollEdits" => Idempotency::AtMostOnce,
+    "rollingUpgrade" => Idempotency::Idempotent,
+    "satisfyStoragePolicy" => Idempotency::AtMostOnce,
+    "saveNamespace" => Idempotency::AtMostOnce,
+    "setAcl" => Idempotency::Idempotent,
+    "setBalancerBandwidth" => Idempotency::Idempotent,
+    "setErasureCodingPolicy" => Idempotency::AtMostOnce,
+    "setOwner" => Idempotency::Idempotent,
+    "setPermission" => Idempotency::Idempotent,
+    "setQuota" => Idempotency::Idempotent,
+    "setReplication" => Idempotency::Idempotent,
+    "setSafeMode" => Idempotency::Idempotent,
+    "setStoragePolicy" => Idempotency::Idempotent,
+    "setTimes" => Idempotency::Idempotent,
+    "setXAttr" => Idempotency::AtMostOnce,
+    "truncate" => Idempotency::Idempotent,
+    "unsetErasureCodingPolicy" => Idempotency::AtMostOnce,
+    "unsetStoragePolicy" => Idempotency::Idempotent,
+    "updateBlockForPipeline" => Idempotency::Idempotent,
+    "updatePipeline" => Idempotency::AtMostOnce,
+    "upgradeStatus" => Idempotency::Idempotent,
+};
+
+impl Idempotency {
+    pub fn of_method(method: &str) -> Self {
+        METHOD_IDEMPOTENCY
+            .get(method)
+            .copied()
+            .unwrap_or(Idempotency::Unsafe)
+    }
+}
+
 pub struct ClientNamenodeService<C: rpc::RpcConnection> {
     conn: C,
+    rate_limiter: Option<RateLimiter>,
+    counts: CallCounts,
 }
 
 impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
     pub fn new(conn: C) -> Self {
-        Self { conn }
+        Self {
+            conn,
+            rate_limiter: None,
+            counts: CallCounts::default(),
+        }
     }
 
     pub fn get_user(&self) -> &str {
@@ -39,6 +183,44 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         self.conn
     }
 
+    pub fn connection(&self) -> &C {
+        &self.conn
+    }
+
+    pub fn call_options(&self) -> &rpc::CallOptions {
+        self.conn.call_options()
+    }
+
+    pub fn set_call_options(&mut self, options: rpc::CallOptions) {
+        self.conn.set_call_options(options)
+    }
+
+    /// Each subsequent call waits for its turn of the limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    fn invoke<Output: protobuf::Message>(
+        &mut self,
+        method: Cow<'_, str>,
+        args: &dyn protobuf::Message,
+    ) -> Result<Output> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire();
+        }
+        let res = self.conn.call(method.clone(), args);
+        self.counts.record(&method, &res);
+        res
+    }
+
+    pub(crate) fn call_counts(&self) -> &CallCounts {
+        &self.counts
+    }
+
+    pub(crate) fn reset_call_counts(&mut self) {
+        self.counts = CallCounts::default();
+    }
+
     // TODO this op takes args, other take proto struct.
     #[allow(non_snake_case)]
     pub fn getListing(
@@ -52,7 +234,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         list.set_startAfter(startAfter);
         list.set_needLocation(needLocation);
 
-        let data: GetListingResponseProto = self.conn.call("getListing".into(), &list)?;
+        let data: GetListingResponseProto = self.invoke("getListing".into(), &list)?;
 
         Ok(data)
     }
@@ -62,7 +244,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetBlockLocationsRequestProto,
     ) -> Result<GetBlockLocationsResponseProto> {
-        self.conn.call("getBlockLocations".into(), args)
+        self.invoke("getBlockLocations".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -70,17 +252,17 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetServerDefaultsRequestProto,
     ) -> Result<GetServerDefaultsResponseProto> {
-        self.conn.call("getServerDefaults".into(), args)
+        self.invoke("getServerDefaults".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn create(&mut self, args: &CreateRequestProto) -> Result<CreateResponseProto> {
-        self.conn.call("create".into(), args)
+        self.invoke("create".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn append(&mut self, args: &AppendRequestProto) -> Result<AppendResponseProto> {
-        self.conn.call("append".into(), args)
+        self.invoke("append".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -88,7 +270,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SetReplicationRequestProto,
     ) -> Result<SetReplicationResponseProto> {
-        self.conn.call("setReplication".into(), args)
+        self.invoke("setReplication".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -96,7 +278,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SetStoragePolicyRequestProto,
     ) -> Result<SetStoragePolicyResponseProto> {
-        self.conn.call("setStoragePolicy".into(), args)
+        self.invoke("setStoragePolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -104,7 +286,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &UnsetStoragePolicyRequestProto,
     ) -> Result<UnsetStoragePolicyResponseProto> {
-        self.conn.call("unsetStoragePolicy".into(), args)
+        self.invoke("unsetStoragePolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -112,7 +294,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetStoragePolicyRequestProto,
     ) -> Result<GetStoragePolicyResponseProto> {
-        self.conn.call("getStoragePolicy".into(), args)
+        self.invoke("getStoragePolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -120,7 +302,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetStoragePoliciesRequestProto,
     ) -> Result<GetStoragePoliciesResponseProto> {
-        self.conn.call("getStoragePolicies".into(), args)
+        self.invoke("getStoragePolicies".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -128,12 +310,12 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SetPermissionRequestProto,
     ) -> Result<SetPermissionResponseProto> {
-        self.conn.call("setPermission".into(), args)
+        self.invoke("setPermission".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn setOwner(&mut self, args: &SetOwnerRequestProto) -> Result<SetOwnerResponseProto> {
-        self.conn.call("setOwner".into(), args)
+        self.invoke("setOwner".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -141,12 +323,12 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &AbandonBlockRequestProto,
     ) -> Result<AbandonBlockResponseProto> {
-        self.conn.call("abandonBlock".into(), args)
+        self.invoke("abandonBlock".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn addBlock(&mut self, args: &AddBlockRequestProto) -> Result<AddBlockResponseProto> {
-        self.conn.call("addBlock".into(), args)
+        self.invoke("addBlock".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -154,12 +336,12 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetAdditionalDatanodeRequestProto,
     ) -> Result<GetAdditionalDatanodeResponseProto> {
-        self.conn.call("getAdditionalDatanode".into(), args)
+        self.invoke("getAdditionalDatanode".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn complete(&mut self, args: &CompleteRequestProto) -> Result<CompleteResponseProto> {
-        self.conn.call("complete".into(), args)
+        self.invoke("complete".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -167,17 +349,17 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ReportBadBlocksRequestProto,
     ) -> Result<ReportBadBlocksResponseProto> {
-        self.conn.call("reportBadBlocks".into(), args)
+        self.invoke("reportBadBlocks".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn concat(&mut self, args: &ConcatRequestProto) -> Result<ConcatResponseProto> {
-        self.conn.call("concat".into(), args)
+        self.invoke("concat".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn truncate(&mut self, args: &TruncateRequestProto) -> Result<TruncateResponseProto> {
-        self.conn.call("truncate".into(), args)
+        self.invoke("truncate".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -186,27 +368,27 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         args.set_src(src);
         args.set_dst(dst);
 
-        self.conn.call("rename".into(), &args)
+        self.invoke("rename".into(), &args)
     }
 
     #[allow(non_snake_case)]
     pub fn rename2(&mut self, args: &Rename2RequestProto) -> Result<Rename2ResponseProto> {
-        self.conn.call("rename2".into(), args)
+        self.invoke("rename2".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn delete(&mut self, args: &DeleteRequestProto) -> Result<DeleteResponseProto> {
-        self.conn.call("delete".into(), args)
+        self.invoke("delete".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn mkdirs(&mut self, args: &MkdirsRequestProto) -> Result<MkdirsResponseProto> {
-        self.conn.call("mkdirs".into(), args)
+        self.invoke("mkdirs".into(), args)
     }
 
     // #[allow(non_snake_case)]
     // pub fn getListing(&mut self, args: &GetListingRequestProto) -> Result<GetListingResponseProto> {
-    //     self.conn.call("getListing".into(), args)
+    //     self.invoke("getListing".into(), args)
     // }
 
     #[allow(non_snake_case)]
@@ -214,12 +396,12 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetBatchedListingRequestProto,
     ) -> Result<GetBatchedListingResponseProto> {
-        self.conn.call("getBatchedListing".into(), args)
+        self.invoke("getBatchedListing".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn renewLease(&mut self, args: &RenewLeaseRequestProto) -> Result<RenewLeaseResponseProto> {
-        self.conn.call("renewLease".into(), args)
+        self.invoke("renewLease".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -227,7 +409,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RecoverLeaseRequestProto,
     ) -> Result<RecoverLeaseResponseProto> {
-        self.conn.call("recoverLease".into(), args)
+        self.invoke("recoverLease".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -235,7 +417,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetFsStatusRequestProto,
     ) -> Result<GetFsStatsResponseProto> {
-        self.conn.call("getFsStats".into(), args)
+        self.invoke("getFsStats".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -243,7 +425,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetFsReplicatedBlockStatsRequestProto,
     ) -> Result<GetFsReplicatedBlockStatsResponseProto> {
-        self.conn.call("getFsReplicatedBlockStats".into(), args)
+        self.invoke("getFsReplicatedBlockStats".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -251,7 +433,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetFsECBlockGroupStatsRequestProto,
     ) -> Result<GetFsECBlockGroupStatsResponseProto> {
-        self.conn.call("getFsECBlockGroupStats".into(), args)
+        self.invoke("getFsECBlockGroupStats".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -259,7 +441,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetDatanodeReportRequestProto,
     ) -> Result<GetDatanodeReportResponseProto> {
-        self.conn.call("getDatanodeReport".into(), args)
+        self.invoke("getDatanodeReport".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -267,7 +449,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetDatanodeStorageReportRequestProto,
     ) -> Result<GetDatanodeStorageReportResponseProto> {
-        self.conn.call("getDatanodeStorageReport".into(), args)
+        self.invoke("getDatanodeStorageReport".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -275,7 +457,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetPreferredBlockSizeRequestProto,
     ) -> Result<GetPreferredBlockSizeResponseProto> {
-        self.conn.call("getPreferredBlockSize".into(), args)
+        self.invoke("getPreferredBlockSize".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -283,7 +465,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SetSafeModeRequestProto,
     ) -> Result<SetSafeModeResponseProto> {
-        self.conn.call("setSafeMode".into(), args)
+        self.invoke("setSafeMode".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -291,12 +473,12 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SaveNamespaceRequestProto,
     ) -> Result<SaveNamespaceResponseProto> {
-        self.conn.call("saveNamespace".into(), args)
+        self.invoke("saveNamespace".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn rollEdits(&mut self, args: &RollEditsRequestProto) -> Result<RollEditsResponseProto> {
-        self.conn.call("rollEdits".into(), args)
+        self.invoke("rollEdits".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -304,7 +486,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RestoreFailedStorageRequestProto,
     ) -> Result<RestoreFailedStorageResponseProto> {
-        self.conn.call("restoreFailedStorage".into(), args)
+        self.invoke("restoreFailedStorage".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -312,7 +494,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RefreshNodesRequestProto,
     ) -> Result<RefreshNodesResponseProto> {
-        self.conn.call("refreshNodes".into(), args)
+        self.invoke("refreshNodes".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -320,7 +502,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &FinalizeUpgradeRequestProto,
     ) -> Result<FinalizeUpgradeResponseProto> {
-        self.conn.call("finalizeUpgrade".into(), args)
+        self.invoke("finalizeUpgrade".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -328,7 +510,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &UpgradeStatusRequestProto,
     ) -> Result<UpgradeStatusResponseProto> {
-        self.conn.call("upgradeStatus".into(), args)
+        self.invoke("upgradeStatus".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -336,7 +518,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RollingUpgradeRequestProto,
     ) -> Result<RollingUpgradeResponseProto> {
-        self.conn.call("rollingUpgrade".into(), args)
+        self.invoke("rollingUpgrade".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -344,19 +526,19 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ListCorruptFileBlocksRequestProto,
     ) -> Result<ListCorruptFileBlocksResponseProto> {
-        self.conn.call("listCorruptFileBlocks".into(), args)
+        self.invoke("listCorruptFileBlocks".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn metaSave(&mut self, args: &MetaSaveRequestProto) -> Result<MetaSaveResponseProto> {
-        self.conn.call("metaSave".into(), args)
+        self.invoke("metaSave".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn getFileInfo(&mut self, src: String) -> Result<Option<HdfsFileStatusProto>> {
         let mut args = GetFileInfoRequestProto::new();
         args.set_src(src);
-        let mut res: GetFileInfoResponseProto = self.conn.call("getFileInfo".into(), &args)?;
+        let mut res: GetFileInfoResponseProto = self.invoke("getFileInfo".into(), &args)?;
         Ok(if res.has_fs() {
             Some(res.take_fs())
         } else {
@@ -369,7 +551,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetLocatedFileInfoRequestProto,
     ) -> Result<GetLocatedFileInfoResponseProto> {
-        self.conn.call("getLocatedFileInfo".into(), args)
+        self.invoke("getLocatedFileInfo".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -377,7 +559,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &AddCacheDirectiveRequestProto,
     ) -> Result<AddCacheDirectiveResponseProto> {
-        self.conn.call("addCacheDirective".into(), args)
+        self.invoke("addCacheDirective".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -385,7 +567,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ModifyCacheDirectiveRequestProto,
     ) -> Result<ModifyCacheDirectiveResponseProto> {
-        self.conn.call("modifyCacheDirective".into(), args)
+        self.invoke("modifyCacheDirective".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -393,7 +575,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RemoveCacheDirectiveRequestProto,
     ) -> Result<RemoveCacheDirectiveResponseProto> {
-        self.conn.call("removeCacheDirective".into(), args)
+        self.invoke("removeCacheDirective".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -401,7 +583,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ListCacheDirectivesRequestProto,
     ) -> Result<ListCacheDirectivesResponseProto> {
-        self.conn.call("listCacheDirectives".into(), args)
+        self.invoke("listCacheDirectives".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -409,7 +591,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &AddCachePoolRequestProto,
     ) -> Result<AddCachePoolResponseProto> {
-        self.conn.call("addCachePool".into(), args)
+        self.invoke("addCachePool".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -417,7 +599,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ModifyCachePoolRequestProto,
     ) -> Result<ModifyCachePoolResponseProto> {
-        self.conn.call("modifyCachePool".into(), args)
+        self.invoke("modifyCachePool".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -425,7 +607,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RemoveCachePoolRequestProto,
     ) -> Result<RemoveCachePoolResponseProto> {
-        self.conn.call("removeCachePool".into(), args)
+        self.invoke("removeCachePool".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -433,7 +615,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ListCachePoolsRequestProto,
     ) -> Result<ListCachePoolsResponseProto> {
-        self.conn.call("listCachePools".into(), args)
+        self.invoke("listCachePools".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -441,7 +623,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetFileLinkInfoRequestProto,
     ) -> Result<GetFileLinkInfoResponseProto> {
-        self.conn.call("getFileLinkInfo".into(), args)
+        self.invoke("getFileLinkInfo".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -449,22 +631,22 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetContentSummaryRequestProto,
     ) -> Result<GetContentSummaryResponseProto> {
-        self.conn.call("getContentSummary".into(), args)
+        self.invoke("getContentSummary".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn setQuota(&mut self, args: &SetQuotaRequestProto) -> Result<SetQuotaResponseProto> {
-        self.conn.call("setQuota".into(), args)
+        self.invoke("setQuota".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn fsync(&mut self, args: &FsyncRequestProto) -> Result<FsyncResponseProto> {
-        self.conn.call("fsync".into(), args)
+        self.invoke("fsync".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn setTimes(&mut self, args: &SetTimesRequestProto) -> Result<SetTimesResponseProto> {
-        self.conn.call("setTimes".into(), args)
+        self.invoke("setTimes".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -472,7 +654,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &CreateSymlinkRequestProto,
     ) -> Result<CreateSymlinkResponseProto> {
-        self.conn.call("createSymlink".into(), args)
+        self.invoke("createSymlink".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -480,7 +662,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetLinkTargetRequestProto,
     ) -> Result<GetLinkTargetResponseProto> {
-        self.conn.call("getLinkTarget".into(), args)
+        self.invoke("getLinkTarget".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -488,7 +670,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &UpdateBlockForPipelineRequestProto,
     ) -> Result<UpdateBlockForPipelineResponseProto> {
-        self.conn.call("updateBlockForPipeline".into(), args)
+        self.invoke("updateBlockForPipeline".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -496,7 +678,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &UpdatePipelineRequestProto,
     ) -> Result<UpdatePipelineResponseProto> {
-        self.conn.call("updatePipeline".into(), args)
+        self.invoke("updatePipeline".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -504,7 +686,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetDelegationTokenRequestProto,
     ) -> Result<GetDelegationTokenResponseProto> {
-        self.conn.call("getDelegationToken".into(), args)
+        self.invoke("getDelegationToken".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -512,7 +694,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RenewDelegationTokenRequestProto,
     ) -> Result<RenewDelegationTokenResponseProto> {
-        self.conn.call("renewDelegationToken".into(), args)
+        self.invoke("renewDelegationToken".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -520,7 +702,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &CancelDelegationTokenRequestProto,
     ) -> Result<CancelDelegationTokenResponseProto> {
-        self.conn.call("cancelDelegationToken".into(), args)
+        self.invoke("cancelDelegationToken".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -528,7 +710,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SetBalancerBandwidthRequestProto,
     ) -> Result<SetBalancerBandwidthResponseProto> {
-        self.conn.call("setBalancerBandwidth".into(), args)
+        self.invoke("setBalancerBandwidth".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -536,7 +718,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetDataEncryptionKeyRequestProto,
     ) -> Result<GetDataEncryptionKeyResponseProto> {
-        self.conn.call("getDataEncryptionKey".into(), args)
+        self.invoke("getDataEncryptionKey".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -544,7 +726,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &CreateSnapshotRequestProto,
     ) -> Result<CreateSnapshotResponseProto> {
-        self.conn.call("createSnapshot".into(), args)
+        self.invoke("createSnapshot".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -552,7 +734,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RenameSnapshotRequestProto,
     ) -> Result<RenameSnapshotResponseProto> {
-        self.conn.call("renameSnapshot".into(), args)
+        self.invoke("renameSnapshot".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -560,7 +742,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &AllowSnapshotRequestProto,
     ) -> Result<AllowSnapshotResponseProto> {
-        self.conn.call("allowSnapshot".into(), args)
+        self.invoke("allowSnapshot".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -568,7 +750,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &DisallowSnapshotRequestProto,
     ) -> Result<DisallowSnapshotResponseProto> {
-        self.conn.call("disallowSnapshot".into(), args)
+        self.invoke("disallowSnapshot".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -576,7 +758,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetSnapshottableDirListingRequestProto,
     ) -> Result<GetSnapshottableDirListingResponseProto> {
-        self.conn.call("getSnapshottableDirListing".into(), args)
+        self.invoke("getSnapshottableDirListing".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -584,7 +766,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetSnapshotListingRequestProto,
     ) -> Result<GetSnapshotListingResponseProto> {
-        self.conn.call("getSnapshotListing".into(), args)
+        self.invoke("getSnapshotListing".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -592,7 +774,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &DeleteSnapshotRequestProto,
     ) -> Result<DeleteSnapshotResponseProto> {
-        self.conn.call("deleteSnapshot".into(), args)
+        self.invoke("deleteSnapshot".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -600,7 +782,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetSnapshotDiffReportRequestProto,
     ) -> Result<GetSnapshotDiffReportResponseProto> {
-        self.conn.call("getSnapshotDiffReport".into(), args)
+        self.invoke("getSnapshotDiffReport".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -608,7 +790,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetSnapshotDiffReportListingRequestProto,
     ) -> Result<GetSnapshotDiffReportListingResponseProto> {
-        self.conn.call("getSnapshotDiffReportListing".into(), args)
+        self.invoke("getSnapshotDiffReportListing".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -616,7 +798,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &IsFileClosedRequestProto,
     ) -> Result<IsFileClosedResponseProto> {
-        self.conn.call("isFileClosed".into(), args)
+        self.invoke("isFileClosed".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -624,7 +806,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ModifyAclEntriesRequestProto,
     ) -> Result<ModifyAclEntriesResponseProto> {
-        self.conn.call("modifyAclEntries".into(), args)
+        self.invoke("modifyAclEntries".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -632,7 +814,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RemoveAclEntriesRequestProto,
     ) -> Result<RemoveAclEntriesResponseProto> {
-        self.conn.call("removeAclEntries".into(), args)
+        self.invoke("removeAclEntries".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -640,17 +822,17 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RemoveDefaultAclRequestProto,
     ) -> Result<RemoveDefaultAclResponseProto> {
-        self.conn.call("removeDefaultAcl".into(), args)
+        self.invoke("removeDefaultAcl".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn removeAcl(&mut self, args: &RemoveAclRequestProto) -> Result<RemoveAclResponseProto> {
-        self.conn.call("removeAcl".into(), args)
+        self.invoke("removeAcl".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn setAcl(&mut self, args: &SetAclRequestProto) -> Result<SetAclResponseProto> {
-        self.conn.call("setAcl".into(), args)
+        self.invoke("setAcl".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -658,22 +840,22 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetAclStatusRequestProto,
     ) -> Result<GetAclStatusResponseProto> {
-        self.conn.call("getAclStatus".into(), args)
+        self.invoke("getAclStatus".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn setXAttr(&mut self, args: &SetXAttrRequestProto) -> Result<SetXAttrResponseProto> {
-        self.conn.call("setXAttr".into(), args)
+        self.invoke("setXAttr".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn getXAttrs(&mut self, args: &GetXAttrsRequestProto) -> Result<GetXAttrsResponseProto> {
-        self.conn.call("getXAttrs".into(), args)
+        self.invoke("getXAttrs".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn listXAttrs(&mut self, args: &ListXAttrsRequestProto) -> Result<ListXAttrsResponseProto> {
-        self.conn.call("listXAttrs".into(), args)
+        self.invoke("listXAttrs".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -681,7 +863,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RemoveXAttrRequestProto,
     ) -> Result<RemoveXAttrResponseProto> {
-        self.conn.call("removeXAttr".into(), args)
+        self.invoke("removeXAttr".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -689,7 +871,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &CheckAccessRequestProto,
     ) -> Result<CheckAccessResponseProto> {
-        self.conn.call("checkAccess".into(), args)
+        self.invoke("checkAccess".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -697,7 +879,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &CreateEncryptionZoneRequestProto,
     ) -> Result<CreateEncryptionZoneResponseProto> {
-        self.conn.call("createEncryptionZone".into(), args)
+        self.invoke("createEncryptionZone".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -705,7 +887,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ListEncryptionZonesRequestProto,
     ) -> Result<ListEncryptionZonesResponseProto> {
-        self.conn.call("listEncryptionZones".into(), args)
+        self.invoke("listEncryptionZones".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -713,7 +895,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ReencryptEncryptionZoneRequestProto,
     ) -> Result<ReencryptEncryptionZoneResponseProto> {
-        self.conn.call("reencryptEncryptionZone".into(), args)
+        self.invoke("reencryptEncryptionZone".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -721,7 +903,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &ListReencryptionStatusRequestProto,
     ) -> Result<ListReencryptionStatusResponseProto> {
-        self.conn.call("listReencryptionStatus".into(), args)
+        self.invoke("listReencryptionStatus".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -729,7 +911,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetEZForPathRequestProto,
     ) -> Result<GetEZForPathResponseProto> {
-        self.conn.call("getEZForPath".into(), args)
+        self.invoke("getEZForPath".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -737,7 +919,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SetErasureCodingPolicyRequestProto,
     ) -> Result<SetErasureCodingPolicyResponseProto> {
-        self.conn.call("setErasureCodingPolicy".into(), args)
+        self.invoke("setErasureCodingPolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -745,7 +927,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &UnsetErasureCodingPolicyRequestProto,
     ) -> Result<UnsetErasureCodingPolicyResponseProto> {
-        self.conn.call("unsetErasureCodingPolicy".into(), args)
+        self.invoke("unsetErasureCodingPolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -762,7 +944,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetCurrentEditLogTxidRequestProto,
     ) -> Result<GetCurrentEditLogTxidResponseProto> {
-        self.conn.call("getCurrentEditLogTxid".into(), args)
+        self.invoke("getCurrentEditLogTxid".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -770,7 +952,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetEditsFromTxidRequestProto,
     ) -> Result<GetEditsFromTxidResponseProto> {
-        self.conn.call("getEditsFromTxid".into(), args)
+        self.invoke("getEditsFromTxid".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -778,7 +960,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetErasureCodingPoliciesRequestProto,
     ) -> Result<GetErasureCodingPoliciesResponseProto> {
-        self.conn.call("getErasureCodingPolicies".into(), args)
+        self.invoke("getErasureCodingPolicies".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -786,7 +968,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &AddErasureCodingPoliciesRequestProto,
     ) -> Result<AddErasureCodingPoliciesResponseProto> {
-        self.conn.call("addErasureCodingPolicies".into(), args)
+        self.invoke("addErasureCodingPolicies".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -794,7 +976,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &RemoveErasureCodingPolicyRequestProto,
     ) -> Result<RemoveErasureCodingPolicyResponseProto> {
-        self.conn.call("removeErasureCodingPolicy".into(), args)
+        self.invoke("removeErasureCodingPolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -802,7 +984,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &EnableErasureCodingPolicyRequestProto,
     ) -> Result<EnableErasureCodingPolicyResponseProto> {
-        self.conn.call("enableErasureCodingPolicy".into(), args)
+        self.invoke("enableErasureCodingPolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -810,7 +992,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &DisableErasureCodingPolicyRequestProto,
     ) -> Result<DisableErasureCodingPolicyResponseProto> {
-        self.conn.call("disableErasureCodingPolicy".into(), args)
+        self.invoke("disableErasureCodingPolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -818,7 +1000,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetErasureCodingPolicyRequestProto,
     ) -> Result<GetErasureCodingPolicyResponseProto> {
-        self.conn.call("getErasureCodingPolicy".into(), args)
+        self.invoke("getErasureCodingPolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -826,7 +1008,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetErasureCodingCodecsRequestProto,
     ) -> Result<GetErasureCodingCodecsResponseProto> {
-        self.conn.call("getErasureCodingCodecs".into(), args)
+        self.invoke("getErasureCodingCodecs".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -834,19 +1016,19 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &GetQuotaUsageRequestProto,
     ) -> Result<GetQuotaUsageResponseProto> {
-        self.conn.call("getQuotaUsage".into(), args)
+        self.invoke("getQuotaUsage".into(), args)
     }
     #[allow(non_snake_case)]
     pub fn listOpenFiles(
         &mut self,
         args: &ListOpenFilesRequestProto,
     ) -> Result<ListOpenFilesResponseProto> {
-        self.conn.call("listOpenFiles".into(), args)
+        self.invoke("listOpenFiles".into(), args)
     }
 
     #[allow(non_snake_case)]
     pub fn msync(&mut self, args: &MsyncRequestProto) -> Result<MsyncResponseProto> {
-        self.conn.call("msync".into(), args)
+        self.invoke("msync".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -854,7 +1036,7 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &SatisfyStoragePolicyRequestProto,
     ) -> Result<SatisfyStoragePolicyResponseProto> {
-        self.conn.call("satisfyStoragePolicy".into(), args)
+        self.invoke("satisfyStoragePolicy".into(), args)
     }
 
     #[allow(non_snake_case)]
@@ -862,7 +1044,17 @@ impl<C: rpc::RpcConnection> ClientNamenodeService<C> {
         &mut self,
         args: &HAServiceStateRequestProto,
     ) -> Result<HAServiceStateResponseProto> {
-        self.conn.call("getServiceState".into(), args)
+        self.invoke("getHAServiceState".into(), args)
+    }
+
+    /// Call any method; see Hdfs::call.
+    #[cfg(feature = "raw-rpc")]
+    pub fn call<Output: protobuf::Message>(
+        &mut self,
+        method: &str,
+        args: &dyn protobuf::Message,
+    ) -> Result<Output> {
+        self.invoke(method.to_owned().into(), args)
     }
 
     #[inline]
@@ -0,0 +1,156 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Block-aligned splits of a file for planning parallel scans, like
+ * Java's FileInputFormat does.
+ */
+use std::collections::HashMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::status::LocatedBlock;
+
+/// A range of a file with the hosts storing most of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Split {
+    pub offset: u64,
+    pub length: u64,
+    /// Sorted by the number of split bytes stored on the host.
+    pub hosts: Vec<Box<str>>,
+}
+
+/**
+ * Group consecutive blocks into splits of at least split_size bytes
+ * (except the last one); a split never starts inside a block.  Zero
+ * split size gives a split per block.
+ */
+pub fn plan_splits(blocks: &[LocatedBlock], split_size: u64) -> Vec<Split> {
+    let mut splits = vec![];
+    let mut current: Option<(Split, HashMap<&str, u64>)> = None;
+
+    for block in blocks {
+        let length = block.b.num_bytes;
+        if length == 0 {
+            continue;
+        }
+        let (split, host_bytes) = current.get_or_insert_with(|| {
+            (
+                Split {
+                    offset: block.offset,
+                    length: 0,
+                    hosts: vec![],
+                },
+                HashMap::new(),
+            )
+        });
+        split.length += length;
+        for loc in &block.locs {
+            *host_bytes.entry(loc.id.host_name.as_ref()).or_default() += length;
+        }
+        if split.length >= split_size {
+            splits.push(finish(current.take().unwrap()));
+        }
+    }
+    splits.extend(current.map(finish));
+    splits
+}
+
+fn finish((mut split, host_bytes): (Split, HashMap<&str, u64>)) -> Split {
+    let mut hosts: Vec<_> = host_bytes.into_iter().collect();
+    // By bytes descending, then by name for stable results.
+    hosts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    split.hosts = hosts.into_iter().map(|(host, _)| host.into()).collect();
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::{DatanodeInfo, ExtendedBlock};
+    use hdfesse_proto::{
+        hdfs::{DatanodeIDProto, DatanodeInfoProto},
+        Security::TokenProto,
+    };
+    use std::sync::Arc;
+
+    fn datanode(host: &str) -> Arc<DatanodeInfo> {
+        let mut id = DatanodeIDProto::default();
+        id.set_hostName(host.to_owned());
+        let mut info = DatanodeInfoProto::default();
+        info.set_id(id);
+        Arc::new(info.into())
+    }
+
+    fn block(offset: u64, num_bytes: u64, hosts: &[&str]) -> LocatedBlock {
+        LocatedBlock {
+            b: ExtendedBlock {
+                pool_id: "pool".into(),
+                block_id: offset,
+                num_bytes,
+                generation_stamp: 1,
+            },
+            offset,
+            locs: hosts.iter().map(|host| datanode(host)).collect(),
+            storage_ids: vec![],
+            storage_types: vec![],
+            corrupt: false,
+            block_token: TokenProto::default().into(),
+            cached_locs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_plan_splits() {
+        let blocks = [
+            block(0, 128, &["a", "b"]),
+            block(128, 128, &["b", "c"]),
+            block(256, 128, &["c", "a"]),
+            block(384, 10, &["c"]),
+        ];
+        let splits = plan_splits(&blocks, 200);
+        assert_eq!(
+            splits,
+            [
+                Split {
+                    offset: 0,
+                    length: 256,
+                    hosts: vec!["b".into(), "a".into(), "c".into()],
+                },
+                Split {
+                    offset: 256,
+                    length: 138,
+                    hosts: vec!["c".into(), "a".into()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_splits_per_block() {
+        let blocks = [block(0, 128, &["a"]), block(128, 1, &["b"])];
+        let splits = plan_splits(&blocks, 0);
+        assert_eq!(
+            splits
+                .iter()
+                .map(|s| (s.offset, s.length))
+                .collect::<Vec<_>>(),
+            [(0, 128), (128, 1)]
+        );
+        assert!(plan_splits(&[], 100).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Counters of a client handle, for services that export them as
+ * metrics.  Calls are counted by the service, failovers by the
+ * connection; see Hdfs::stats.
+ */
+use std::collections::BTreeMap;
+
+use crate::{ha_rpc::FailoverCounts, rpc::RpcError};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Calls and errors counted by ClientNamenodeService.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CallCounts {
+    pub(crate) calls: BTreeMap<String, u64>,
+    pub(crate) errors: BTreeMap<String, u64>,
+}
+
+impl CallCounts {
+    pub(crate) fn record<T>(&mut self, method: &str, res: &Result<T, RpcError>) {
+        // The method is allocated only by its first call.
+        match self.calls.get_mut(method) {
+            Some(count) => *count += 1,
+            None => {
+                self.calls.insert(method.to_owned(), 1);
+            }
+        }
+        if let Err(e) = res {
+            *self.errors.entry(error_class(e).to_owned()).or_default() += 1;
+        }
+    }
+}
+
+/// The Java exception class of a remote error, or the ErrorCode name
+/// of a local one, like a network error.
+fn error_class(e: &RpcError) -> &str {
+    e.get_class_name().unwrap_or_else(|| e.code().name())
+}
+
+/**
+ * Statistics of a handle since its creation or the last
+ * Hdfs::reset_stats.  Only namenode calls are counted: there is no
+ * data path yet.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct SessionStats {
+    /// Calls by method, including the failed ones.
+    pub calls: BTreeMap<String, u64>,
+    /// Failed calls by the Java exception class, or by the ErrorCode
+    /// name for local errors.
+    pub errors: BTreeMap<String, u64>,
+    /// Switches to another namenode.
+    pub failovers: u64,
+    /// Calls sent again to another namenode after a failover.
+    pub retries: u64,
+}
+
+impl SessionStats {
+    /// The failovers are counted since the baseline, as the
+    /// connection counts them since its creation.
+    pub(crate) fn new(
+        counts: &CallCounts,
+        failovers: FailoverCounts,
+        baseline: FailoverCounts,
+    ) -> Self {
+        let total = |c: FailoverCounts| c.standby + c.connect_failed + c.call_failed;
+        Self {
+            calls: counts.calls.clone(),
+            errors: counts.errors.clone(),
+            failovers: total(failovers).saturating_sub(total(baseline)),
+            retries: failovers.retries.saturating_sub(baseline.retries),
+        }
+    }
+
+    pub fn total_calls(&self) -> u64 {
+        self.calls.values().sum()
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{RpcErrorCode, RpcStatus};
+    use std::io;
+
+    #[test]
+    fn test_call_counts() {
+        let mut counts = CallCounts::default();
+        counts.record("getFileInfo", &Ok(()));
+        counts.record(
+            "getFileInfo",
+            &Err::<(), _>(RpcError::ErrorResponse {
+                status: RpcStatus::ERROR,
+                error_msg: "denied".to_owned(),
+                error_detail: RpcErrorCode::ERROR_APPLICATION,
+                exception: "org.apache.hadoop.security.AccessControlException".to_owned(),
+                method: "getFileInfo".to_owned(),
+            }),
+        );
+        counts.record(
+            "mkdirs",
+            &Err::<(), _>(RpcError::Io(io::ErrorKind::ConnectionReset.into())),
+        );
+
+        let failovers = FailoverCounts {
+            standby: 2,
+            connect_failed: 1,
+            call_failed: 0,
+            retries: 1,
+        };
+        let baseline = FailoverCounts {
+            standby: 1,
+            ..FailoverCounts::default()
+        };
+        let stats = SessionStats::new(&counts, failovers, baseline);
+        assert_eq!(stats.calls["getFileInfo"], 2);
+        assert_eq!(stats.total_calls(), 3);
+        assert_eq!(
+            stats.errors["org.apache.hadoop.security.AccessControlException"],
+            1
+        );
+        assert_eq!(stats.errors["Io"], 1);
+        assert_eq!(stats.total_errors(), 2);
+        assert_eq!(stats.failovers, 2);
+        assert_eq!(stats.retries, 1);
+    }
+}
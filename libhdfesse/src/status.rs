@@ -13,34 +13,78 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, convert::TryFrom, sync::Arc};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::erasure::SystemErasureCodingPolicy;
 pub use crate::fs_ls::LsGroupIterator;
+use crate::path::{Path, PathError};
 pub use hdfesse_proto::hdfs::ErasureCodingPolicyState;
 use hdfesse_proto::{
-    acl::FsPermissionProto,
+    acl::{AclEntryProto_FsActionProto, FsPermissionProto},
     hdfs::{
-        CipherSuiteProto, CryptoProtocolVersionProto, DatanodeIDProto, DatanodeInfoProto,
-        DatanodeInfoProto_AdminState, ECSchemaProto, ErasureCodingPolicyProto, ExtendedBlockProto,
-        FileEncryptionInfoProto, HdfsFileStatusProto, HdfsFileStatusProto_FileType,
-        LocatedBlockProto, LocatedBlocksProto, StorageTypeProto,
+        CipherSuiteProto, ContentSummaryProto, CryptoProtocolVersionProto, DatanodeIDProto,
+        DatanodeInfoProto, DatanodeInfoProto_AdminState, DatanodeStorageProto,
+        DatanodeStorageProto_StorageState, ECSchemaProto, ErasureCodingPolicyProto,
+        ExtendedBlockProto, FileEncryptionInfoProto, HdfsFileStatusProto,
+        HdfsFileStatusProto_FileType, HdfsFileStatusProto_Flags, LocatedBlockProto,
+        LocatedBlocksProto, QuotaUsageProto, SnapshotStatusProto,
+        SnapshottableDirectoryStatusProto, StorageReportProto, StorageTypeProto,
+        StorageTypeQuotaInfosProto,
+    },
+    ClientNamenodeProtocol::{
+        DatanodeReportTypeProto, DatanodeStorageReportProto, GetFsECBlockGroupStatsResponseProto,
+        GetFsReplicatedBlockStatsResponseProto, RollingUpgradeActionProto, RollingUpgradeInfoProto,
     },
     Security::TokenProto,
 };
 
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct FsPermission {
     pub perm: u16,
 }
 
+// Namenodes before Hadoop 3.0 have no flags field and put the flags
+// into the high permission bits; see PBHelperClient.convertFlags.
+const PERM_ACL_BIT: u32 = 1 << 12;
+const PERM_ENCRYPTED_BIT: u32 = 1 << 13;
+const PERM_ERASURE_CODED_BIT: u32 = 1 << 14;
+// Mode bits with sticky bit.
+const PERM_MASK: u32 = 0o1777;
+
+fn flags_from_permission(perm: u32) -> u32 {
+    [
+        (PERM_ACL_BIT, HdfsFileStatusProto_Flags::HAS_ACL),
+        (PERM_ENCRYPTED_BIT, HdfsFileStatusProto_Flags::HAS_CRYPT),
+        (PERM_ERASURE_CODED_BIT, HdfsFileStatusProto_Flags::HAS_EC),
+    ]
+    .iter()
+    .filter(|(bit, _)| perm & bit != 0)
+    .fold(0, |flags, (_, flag)| flags | *flag as u32)
+}
+
+/// A protobuf message lacks a field the conversion requires.
+#[derive(Debug, Error)]
+#[error("{message}: missing field {field}")]
+pub struct MissingField {
+    pub message: &'static str,
+    pub field: &'static str,
+}
+
 impl From<&FsPermissionProto> for FsPermission {
     fn from(perm: &FsPermissionProto) -> Self {
         Self {
-            perm: perm.get_perm() as u16,
+            perm: (perm.get_perm() & PERM_MASK) as u16,
         }
     }
 }
 
+pub type FsAction = AclEntryProto_FsActionProto;
+
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct DatanodeID {
     pub ip_addr: Box<str>,
     pub host_name: Box<str>,
@@ -72,6 +116,7 @@ impl From<DatanodeIDProto> for DatanodeID {
 pub type AdminState = DatanodeInfoProto_AdminState;
 pub type StorageType = StorageTypeProto;
 
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct DatanodeInfo {
     pub id: DatanodeID,
     pub network_location: Option<Box<str>>,
@@ -86,6 +131,7 @@ pub struct DatanodeInfo {
     pub last_update: u64,
     pub last_update_monotonic: u64,
     pub xceiver_count: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
     pub admin_state: AdminState,
     pub last_block_report_time: u64,
     pub last_block_report_monotonic: u64,
@@ -101,7 +147,7 @@ impl From<DatanodeInfoProto> for DatanodeInfo {
             } else {
                 None
             },
-            capacity: proto.get_cacheCapacity(),
+            capacity: proto.get_capacity(),
             dfs_used: proto.get_dfsUsed(),
             non_dfs_used: proto.get_nonDfsUsed(),
             remaining: proto.get_remaining(),
@@ -124,8 +170,101 @@ impl From<DatanodeInfoProto> for DatanodeInfo {
     }
 }
 
+pub type DatanodeReportType = DatanodeReportTypeProto;
+pub type StorageState = DatanodeStorageProto_StorageState;
+
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DatanodeStorage {
+    pub storage_id: Box<str>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
+    pub state: StorageState,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
+    pub storage_type: StorageType,
+}
+
+impl From<DatanodeStorageProto> for DatanodeStorage {
+    fn from(mut proto: DatanodeStorageProto) -> Self {
+        Self {
+            storage_id: proto.take_storageUuid().into(),
+            state: proto.get_state(),
+            storage_type: proto.get_storageType(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct StorageReport {
+    pub storage: DatanodeStorage,
+    pub failed: bool,
+    pub capacity: u64,
+    pub dfs_used: u64,
+    pub non_dfs_used: u64,
+    pub remaining: u64,
+    pub block_pool_used: u64,
+    pub mount: Option<Box<str>>,
+}
+
+// See PBHelperClient.convert(StorageReportProto).
+impl From<StorageReportProto> for StorageReport {
+    fn from(mut proto: StorageReportProto) -> Self {
+        let storage = if proto.has_storage() {
+            proto.take_storage().into()
+        } else {
+            DatanodeStorage {
+                storage_id: proto.take_storageUuid().into(),
+                state: StorageState::NORMAL,
+                storage_type: StorageType::DISK,
+            }
+        };
+        Self {
+            storage,
+            failed: proto.get_failed(),
+            capacity: proto.get_capacity(),
+            dfs_used: proto.get_dfsUsed(),
+            non_dfs_used: if proto.has_nonDfsUsed() {
+                proto.get_nonDfsUsed()
+            } else {
+                proto
+                    .get_capacity()
+                    .saturating_sub(proto.get_dfsUsed())
+                    .saturating_sub(proto.get_remaining())
+            },
+            remaining: proto.get_remaining(),
+            block_pool_used: proto.get_blockPoolUsed(),
+            mount: if proto.has_mount() {
+                Some(proto.take_mount().into())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DatanodeStorageReport {
+    pub datanode_info: DatanodeInfo,
+    pub storage_reports: Vec<StorageReport>,
+}
+
+impl From<DatanodeStorageReportProto> for DatanodeStorageReport {
+    fn from(mut proto: DatanodeStorageReportProto) -> Self {
+        Self {
+            datanode_info: proto.take_datanodeInfo().into(),
+            storage_reports: proto
+                .take_storageReports()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct Token {
     pub identifier: Vec<u8>,
+    // Never serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub password: Vec<u8>,
     pub kind: Box<str>,
     pub service: Box<str>,
@@ -142,11 +281,25 @@ impl From<TokenProto> for Token {
     }
 }
 
+impl From<&Token> for TokenProto {
+    fn from(token: &Token) -> Self {
+        let mut proto = TokenProto::default();
+        proto.set_identifier(token.identifier.clone());
+        proto.set_password(token.password.clone());
+        proto.set_kind(token.kind.to_string());
+        proto.set_service(token.service.to_string());
+        proto
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct LocatedBlock {
     pub b: ExtendedBlock,
     pub offset: u64,
     pub locs: Vec<Arc<DatanodeInfo>>,
     pub storage_ids: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum_vec"))]
     pub storage_types: Vec<StorageType>,
     pub corrupt: bool,
     pub block_token: Token,
@@ -183,8 +336,11 @@ impl From<LocatedBlockProto> for LocatedBlock {
 pub type CipherSuite = CipherSuiteProto;
 pub type CryptoProtocolVersion = CryptoProtocolVersionProto;
 
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct FileEncryptionInfo {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
     pub suite: CipherSuite,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
     pub version: CryptoProtocolVersion,
     pub edek: Box<[u8]>,
     pub iv: Box<[u8]>,
@@ -206,6 +362,7 @@ impl From<FileEncryptionInfoProto> for FileEncryptionInfo {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct EcSchema {
     pub codec_name: Cow<'static, str>,
     pub data_units: u32,
@@ -229,6 +386,7 @@ impl From<ECSchemaProto> for EcSchema {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ErasureCodingPolicy {
     pub name: Cow<'static, str>,
     pub schema: EcSchema,
@@ -254,22 +412,32 @@ impl From<&ErasureCodingPolicyProto> for ErasureCodingPolicy {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ErasureCodingPolicyInfo {
     pub policy: ErasureCodingPolicy,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
     pub state: ErasureCodingPolicyState,
 }
 
-impl From<ErasureCodingPolicyProto> for ErasureCodingPolicyInfo {
-    fn from(source: ErasureCodingPolicyProto) -> Self {
-        // TODO: use TryInto, but it will affect many other conversions.
-        assert!(source.has_state());
-        Self {
+impl TryFrom<ErasureCodingPolicyProto> for ErasureCodingPolicyInfo {
+    type Error = MissingField;
+
+    fn try_from(source: ErasureCodingPolicyProto) -> Result<Self, Self::Error> {
+        if !source.has_state() {
+            return Err(MissingField {
+                message: "ErasureCodingPolicyProto",
+                field: "state",
+            });
+        }
+        Ok(Self {
             policy: (&source).into(),
             state: source.get_state(),
-        }
+        })
     }
 }
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ExtendedBlock {
     pub pool_id: Box<str>,
     pub block_id: u64,
@@ -288,6 +456,18 @@ impl From<ExtendedBlockProto> for ExtendedBlock {
     }
 }
 
+impl From<&ExtendedBlock> for ExtendedBlockProto {
+    fn from(block: &ExtendedBlock) -> Self {
+        let mut proto = ExtendedBlockProto::default();
+        proto.set_poolId(block.pool_id.to_string());
+        proto.set_blockId(block.block_id);
+        proto.set_generationStamp(block.generation_stamp);
+        proto.set_numBytes(block.num_bytes);
+        proto
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct LocatedBlocks {
     pub length: u64,
     pub under_construction: bool,
@@ -312,6 +492,7 @@ impl From<LocatedBlocksProto> for LocatedBlocks {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct HdfsFileStatus {
     pub length: u64,
     pub isdir: bool,
@@ -339,7 +520,7 @@ impl From<HdfsFileStatusProto> for HdfsFileStatus {
         let flags = if fs.has_flags() {
             fs.get_flags()
         } else {
-            fs.get_permission().get_perm()
+            flags_from_permission(fs.get_permission().get_perm())
         };
         Self {
             length: fs.get_length(),
@@ -391,3 +572,753 @@ impl From<HdfsFileStatusProto> for HdfsFileStatus {
         }
     }
 }
+
+/**
+ * An entry of a directory listing.  Its path is the local name of the
+ * entry, and is empty for getFileInfo results.
+ */
+pub trait DirEntry {
+    fn local_name(&self) -> &[u8];
+
+    /// The name decoded like Java's DFSUtilClient.bytes2String.
+    fn name(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.local_name())
+    }
+
+    /// The path of the entry of the listing of base.
+    fn absolute_path(&self, base: &Path<'_>) -> Result<Path<'static>, PathError> {
+        base.child(&self.name())
+    }
+}
+
+impl DirEntry for HdfsFileStatusProto {
+    fn local_name(&self) -> &[u8] {
+        self.get_path()
+    }
+}
+
+impl DirEntry for HdfsFileStatus {
+    fn local_name(&self) -> &[u8] {
+        &self.path
+    }
+}
+
+// Java's DFSUtilClient.bytes2String + Path joining of the parent
+// path and the local name.
+fn join_full_path(parent: &[u8], name: &[u8]) -> String {
+    let parent = String::from_utf8_lossy(parent);
+    let name = String::from_utf8_lossy(name);
+    if name.is_empty() {
+        parent.into_owned()
+    } else if parent.ends_with('/') {
+        format!("{}{}", parent, name)
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+/// A directory where snapshots are allowed.
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct SnapshottableDirectoryStatus {
+    /// The path is the local name of the directory.
+    pub dir_status: HdfsFileStatus,
+    pub snapshot_number: u32,
+    pub snapshot_quota: u32,
+    pub parent_full_path: Box<[u8]>,
+}
+
+impl SnapshottableDirectoryStatus {
+    pub fn full_path(&self) -> String {
+        join_full_path(&self.parent_full_path, &self.dir_status.path)
+    }
+}
+
+impl From<SnapshottableDirectoryStatusProto> for SnapshottableDirectoryStatus {
+    fn from(mut proto: SnapshottableDirectoryStatusProto) -> Self {
+        Self {
+            dir_status: proto.take_dirStatus().into(),
+            snapshot_number: proto.get_snapshot_number(),
+            snapshot_quota: proto.get_snapshot_quota(),
+            parent_full_path: proto.take_parent_fullpath().into(),
+        }
+    }
+}
+
+/// A snapshot of a snapshottable directory.
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct SnapshotStatus {
+    /// The path is the snapshot name.
+    pub dir_status: HdfsFileStatus,
+    pub snapshot_id: u32,
+    /// The snapshot is deleted, but still referenced by other ones.
+    pub is_deleted: bool,
+    /// Path of the snapshottable directory.
+    pub parent_full_path: Box<[u8]>,
+}
+
+impl SnapshotStatus {
+    /// Path of the snapshot, like `/dir/.snapshot/name`.
+    pub fn full_path(&self) -> String {
+        let snapshots = join_full_path(&self.parent_full_path, b".snapshot");
+        join_full_path(snapshots.as_bytes(), &self.dir_status.path)
+    }
+}
+
+impl From<SnapshotStatusProto> for SnapshotStatus {
+    fn from(mut proto: SnapshotStatusProto) -> Self {
+        Self {
+            dir_status: proto.take_dirStatus().into(),
+            snapshot_id: proto.get_snapshotID(),
+            is_deleted: proto.get_isDeleted(),
+            parent_full_path: proto.take_parent_fullpath().into(),
+        }
+    }
+}
+
+/// Block statistics of replicated files.
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ReplicatedBlockStats {
+    pub low_redundancy_blocks: u64,
+    pub corrupt_blocks: u64,
+    pub missing_blocks: u64,
+    pub missing_replication_one_blocks: u64,
+    pub blocks_in_future: u64,
+    pub pending_deletion_blocks: u64,
+    // Older namenodes do not report it.
+    pub highest_priority_low_redundancy_blocks: Option<u64>,
+}
+
+impl From<GetFsReplicatedBlockStatsResponseProto> for ReplicatedBlockStats {
+    fn from(proto: GetFsReplicatedBlockStatsResponseProto) -> Self {
+        Self {
+            low_redundancy_blocks: proto.get_low_redundancy(),
+            corrupt_blocks: proto.get_corrupt_blocks(),
+            missing_blocks: proto.get_missing_blocks(),
+            missing_replication_one_blocks: proto.get_missing_repl_one_blocks(),
+            blocks_in_future: proto.get_blocks_in_future(),
+            pending_deletion_blocks: proto.get_pending_deletion_blocks(),
+            highest_priority_low_redundancy_blocks: if proto
+                .has_highest_prio_low_redundancy_blocks()
+            {
+                Some(proto.get_highest_prio_low_redundancy_blocks())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Block group statistics of erasure coded files.
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct EcBlockGroupStats {
+    pub low_redundancy_block_groups: u64,
+    pub corrupt_block_groups: u64,
+    pub missing_block_groups: u64,
+    pub block_groups_in_future: u64,
+    pub pending_deletion_blocks: u64,
+    // Older namenodes do not report it.
+    pub highest_priority_low_redundancy_blocks: Option<u64>,
+}
+
+impl From<GetFsECBlockGroupStatsResponseProto> for EcBlockGroupStats {
+    fn from(proto: GetFsECBlockGroupStatsResponseProto) -> Self {
+        Self {
+            low_redundancy_block_groups: proto.get_low_redundancy(),
+            corrupt_block_groups: proto.get_corrupt_blocks(),
+            missing_block_groups: proto.get_missing_blocks(),
+            block_groups_in_future: proto.get_blocks_in_future(),
+            pending_deletion_blocks: proto.get_pending_deletion_blocks(),
+            highest_priority_low_redundancy_blocks: if proto
+                .has_highest_prio_low_redundancy_blocks()
+            {
+                Some(proto.get_highest_prio_low_redundancy_blocks())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// START is what Java's DFSAdmin calls PREPARE.
+pub type RollingUpgradeAction = RollingUpgradeActionProto;
+
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct RollingUpgradeInfo {
+    pub block_pool_id: Box<str>,
+    /// Milliseconds since epoch; zero if not started.
+    pub start_time: u64,
+    /// Milliseconds since epoch; zero if not finalized.
+    pub finalize_time: u64,
+    pub created_rollback_images: bool,
+}
+
+impl RollingUpgradeInfo {
+    pub fn is_started(&self) -> bool {
+        self.start_time != 0
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.finalize_time != 0
+    }
+}
+
+impl From<RollingUpgradeInfoProto> for RollingUpgradeInfo {
+    fn from(mut proto: RollingUpgradeInfoProto) -> Self {
+        Self {
+            block_pool_id: proto.mut_status().take_blockPoolId().into(),
+            start_time: proto.get_startTime(),
+            finalize_time: proto.get_finalizeTime(),
+            created_rollback_images: proto.get_createdRollbackImages(),
+        }
+    }
+}
+
+/// Java sends -1 for a quota that is not set.
+fn quota(value: u64) -> Option<u64> {
+    if value > i64::MAX as u64 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Quota and usage of a storage type, like SSD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct StorageTypeQuota {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_proto::proto_enum"))]
+    pub storage_type: StorageType,
+    /// None if not set.
+    pub quota: Option<u64>,
+    pub consumed: u64,
+}
+
+fn type_quotas(proto: &StorageTypeQuotaInfosProto) -> Vec<StorageTypeQuota> {
+    proto
+        .get_typeQuotaInfo()
+        .iter()
+        .map(|info| StorageTypeQuota {
+            storage_type: info.get_field_type(),
+            quota: quota(info.get_quota()),
+            consumed: info.get_consumed(),
+        })
+        .collect()
+}
+
+/**
+ * Quota and usage of a directory.  The space is the raw space,
+ * i.e. it includes replicas.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct QuotaUsage {
+    pub file_and_directory_count: u64,
+    /// None if not set.
+    pub quota: Option<u64>,
+    pub space_consumed: u64,
+    /// None if not set.
+    pub space_quota: Option<u64>,
+    /// Only the storage types namenode reports, usually the ones with
+    /// a quota.
+    pub type_quotas: Vec<StorageTypeQuota>,
+}
+
+impl QuotaUsage {
+    pub fn headroom(&self) -> QuotaHeadroom {
+        QuotaHeadroom {
+            names: self
+                .quota
+                .map(|quota| quota.saturating_sub(self.file_and_directory_count)),
+            space: self
+                .space_quota
+                .map(|quota| quota.saturating_sub(self.space_consumed)),
+        }
+    }
+}
+
+impl From<&QuotaUsageProto> for QuotaUsage {
+    fn from(proto: &QuotaUsageProto) -> Self {
+        Self {
+            file_and_directory_count: proto.get_fileAndDirectoryCount(),
+            quota: quota(proto.get_quota()),
+            space_consumed: proto.get_spaceConsumed(),
+            space_quota: quota(proto.get_spaceQuota()),
+            type_quotas: type_quotas(proto.get_typeQuotaInfos()),
+        }
+    }
+}
+
+impl From<&ContentSummaryProto> for QuotaUsage {
+    fn from(proto: &ContentSummaryProto) -> Self {
+        ContentSummary::from(proto).quota_usage()
+    }
+}
+
+/**
+ * Sizes and counts of a subtree, with the quotas of its root.  The
+ * length is the logical size of the files; the space consumed
+ * includes replicas.  The snapshot fields count the data that exists
+ * only in snapshots.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ContentSummary {
+    pub length: u64,
+    pub file_count: u64,
+    pub directory_count: u64,
+    /// None if not set.
+    pub quota: Option<u64>,
+    pub space_consumed: u64,
+    /// None if not set.
+    pub space_quota: Option<u64>,
+    pub type_quotas: Vec<StorageTypeQuota>,
+    pub snapshot_length: u64,
+    pub snapshot_file_count: u64,
+    pub snapshot_directory_count: u64,
+    pub snapshot_space_consumed: u64,
+    /// The erasure coding policy of the root, if any.
+    pub erasure_coding_policy: Option<Box<str>>,
+}
+
+impl ContentSummary {
+    pub fn quota_usage(&self) -> QuotaUsage {
+        QuotaUsage {
+            file_and_directory_count: self.file_count + self.directory_count,
+            quota: self.quota,
+            space_consumed: self.space_consumed,
+            space_quota: self.space_quota,
+            type_quotas: self.type_quotas.clone(),
+        }
+    }
+}
+
+impl From<&ContentSummaryProto> for ContentSummary {
+    fn from(proto: &ContentSummaryProto) -> Self {
+        Self {
+            length: proto.get_length(),
+            file_count: proto.get_fileCount(),
+            directory_count: proto.get_directoryCount(),
+            quota: quota(proto.get_quota()),
+            space_consumed: proto.get_spaceConsumed(),
+            space_quota: quota(proto.get_spaceQuota()),
+            type_quotas: type_quotas(proto.get_typeQuotaInfos()),
+            snapshot_length: proto.get_snapshotLength(),
+            snapshot_file_count: proto.get_snapshotFileCount(),
+            snapshot_directory_count: proto.get_snapshotDirectoryCount(),
+            snapshot_space_consumed: proto.get_snapshotSpaceConsumed(),
+            erasure_coding_policy: Some(proto.get_erasureCodingPolicy())
+                .filter(|policy| !policy.is_empty())
+                .map(Into::into),
+        }
+    }
+}
+
+/**
+ * What may be added to a directory before its own quotas are
+ * exceeded; None means no quota.  Quotas of the ancestors are not
+ * taken into account.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct QuotaHeadroom {
+    pub names: Option<u64>,
+    /// Raw bytes, i.e. file size multiplied by replication.
+    pub space: Option<u64>,
+}
+
+impl QuotaHeadroom {
+    /// Check if the names and the raw bytes fit.
+    pub fn allows(&self, names: u64, space: u64) -> bool {
+        self.names.map(|left| names <= left).unwrap_or(true)
+            && self.space.map(|left| space <= left).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfesse_proto::hdfs::StorageTypeQuotaInfoProto;
+
+    // Every field of the fixtures has a distinct value, so that a field
+    // mapped from a wrong source is caught.
+
+    fn datanode_id_proto() -> DatanodeIDProto {
+        let mut id = DatanodeIDProto::default();
+        id.set_ipAddr("10.0.0.1".to_owned());
+        id.set_hostName("dn1".to_owned());
+        id.set_datanodeUuid("uuid".to_owned());
+        id.set_xferPort(1);
+        id.set_infoPort(2);
+        id.set_ipcPort(3);
+        id
+    }
+
+    #[test]
+    fn test_dir_entry_path() {
+        let base = Path::new("/data").unwrap();
+        let mut entry = HdfsFileStatusProto::default();
+        entry.set_path(b"a%b \xff".to_vec());
+        assert_eq!(entry.name(), "a%b \u{fffd}");
+        assert_eq!(
+            entry.absolute_path(&base).unwrap().to_string(),
+            "/data/a%b \u{fffd}"
+        );
+        let status = HdfsFileStatus::from(HdfsFileStatusProto::default());
+        assert_eq!(status.absolute_path(&base).unwrap().to_string(), "/data");
+    }
+
+    #[test]
+    fn test_datanode_info_fields() {
+        let mut proto = DatanodeInfoProto::default();
+        proto.set_id(datanode_id_proto());
+        proto.set_capacity(10);
+        proto.set_dfsUsed(11);
+        proto.set_remaining(12);
+        proto.set_blockPoolUsed(13);
+        proto.set_lastUpdate(14);
+        proto.set_xceiverCount(15);
+        proto.set_location("/rack".to_owned());
+        proto.set_nonDfsUsed(16);
+        proto.set_adminState(AdminState::DECOMMISSIONED);
+        proto.set_cacheCapacity(17);
+        proto.set_cacheUsed(18);
+        proto.set_lastUpdateMonotonic(19);
+        proto.set_upgradeDomain("ud".to_owned());
+        proto.set_lastBlockReportTime(20);
+        proto.set_lastBlockReportMonotonic(21);
+        proto.set_numBlocks(22);
+
+        let info = DatanodeInfo::from(proto);
+        assert_eq!(info.id.ip_addr.as_ref(), "10.0.0.1");
+        assert_eq!(info.id.host_name.as_ref(), "dn1");
+        assert_eq!(info.id.datanode_uuid.as_ref(), "uuid");
+        assert_eq!(
+            (info.id.xfer_port, info.id.info_port, info.id.ipc_port),
+            (1, 2, 3)
+        );
+        assert_eq!(info.id.info_secure_port, None);
+        assert_eq!(
+            [
+                info.capacity,
+                info.dfs_used,
+                info.remaining,
+                info.block_pool_used,
+                info.last_update,
+                info.non_dfs_used,
+                info.cache_capacity,
+                info.cache_used,
+                info.last_update_monotonic,
+                info.last_block_report_time,
+                info.last_block_report_monotonic,
+            ],
+            [10, 11, 12, 13, 14, 16, 17, 18, 19, 20, 21]
+        );
+        assert_eq!((info.xceiver_count, info.num_blocks), (15, 22));
+        assert_eq!(info.network_location.as_deref(), Some("/rack"));
+        assert_eq!(info.upgrade_domain.as_deref(), Some("ud"));
+        assert_eq!(info.admin_state, AdminState::DECOMMISSIONED);
+    }
+
+    #[test]
+    fn test_storage_report_fields() {
+        let mut proto = StorageReportProto::default();
+        proto.set_storageUuid("old-uuid".to_owned());
+        proto.set_failed(true);
+        proto.set_capacity(100);
+        proto.set_dfsUsed(20);
+        proto.set_remaining(50);
+        proto.set_blockPoolUsed(15);
+        proto.set_mount("/data".to_owned());
+
+        let report = StorageReport::from(proto.clone());
+        assert_eq!(report.storage.storage_id.as_ref(), "old-uuid");
+        assert_eq!(report.storage.storage_type, StorageType::DISK);
+        assert!(report.failed);
+        assert_eq!(
+            [
+                report.capacity,
+                report.dfs_used,
+                report.remaining,
+                report.block_pool_used,
+                report.non_dfs_used,
+            ],
+            [100, 20, 50, 15, 30]
+        );
+        assert_eq!(report.mount.as_deref(), Some("/data"));
+
+        let mut storage = DatanodeStorageProto::default();
+        storage.set_storageUuid("uuid".to_owned());
+        storage.set_state(StorageState::READ_ONLY_SHARED);
+        storage.set_storageType(StorageType::SSD);
+        proto.set_storage(storage);
+        proto.set_nonDfsUsed(7);
+        let report = StorageReport::from(proto);
+        assert_eq!(report.storage.storage_id.as_ref(), "uuid");
+        assert_eq!(report.storage.state, StorageState::READ_ONLY_SHARED);
+        assert_eq!(report.storage.storage_type, StorageType::SSD);
+        assert_eq!(report.non_dfs_used, 7);
+    }
+
+    fn file_status_proto(file_type: HdfsFileStatusProto_FileType) -> HdfsFileStatusProto {
+        let mut proto = HdfsFileStatusProto::default();
+        proto.set_fileType(file_type);
+        proto.set_path(b"name".to_vec());
+        proto.set_length(1);
+        proto.mut_permission().set_perm(0o1755);
+        proto.set_owner("owner".to_owned());
+        proto.set_group("group".to_owned());
+        proto.set_modification_time(2);
+        proto.set_access_time(3);
+        proto.set_symlink(b"target".to_vec());
+        proto.set_block_replication(4);
+        proto.set_blocksize(5);
+        proto.set_fileId(6);
+        proto.set_childrenNum(7);
+        proto.set_storagePolicy(8);
+        proto
+    }
+
+    #[test]
+    fn test_file_status_fields() {
+        let mut proto = file_status_proto(HdfsFileStatusProto_FileType::IS_FILE);
+        proto.set_flags(HdfsFileStatusProto_Flags::HAS_EC as u32);
+
+        let status = HdfsFileStatus::from(proto);
+        assert!(!status.isdir);
+        assert_eq!(status.path.as_ref(), b"name");
+        assert_eq!(
+            [status.length, status.mtime, status.atime, status.blocksize,],
+            [1, 2, 3, 5]
+        );
+        assert_eq!(status.replication, 4);
+        assert_eq!(status.perm.perm, 0o1755);
+        assert_eq!(status.owner.as_ref(), "owner");
+        assert_eq!(status.group.as_ref(), "group");
+        assert_eq!(status.flags, HdfsFileStatusProto_Flags::HAS_EC as u32);
+        assert_eq!(status.field_id, Some(6));
+        assert_eq!(status.children, Some(7));
+        assert_eq!(status.storage_policy, Some(8));
+        // Only symlinks have a target.
+        assert!(status.symlink.is_none());
+
+        let status =
+            HdfsFileStatus::from(file_status_proto(HdfsFileStatusProto_FileType::IS_SYMLINK));
+        assert_eq!(status.symlink.as_deref(), Some(&b"target"[..]));
+    }
+
+    #[test]
+    fn test_file_status_flags_from_permission() {
+        let mut proto = file_status_proto(HdfsFileStatusProto_FileType::IS_DIR);
+        proto
+            .mut_permission()
+            .set_perm(PERM_ACL_BIT | PERM_ENCRYPTED_BIT | 0o755);
+
+        let status = HdfsFileStatus::from(proto);
+        assert!(status.isdir);
+        assert_eq!(status.perm.perm, 0o755);
+        assert_eq!(
+            status.flags,
+            HdfsFileStatusProto_Flags::HAS_ACL as u32 | HdfsFileStatusProto_Flags::HAS_CRYPT as u32
+        );
+    }
+
+    #[test]
+    fn test_snapshot_status_fields() {
+        let mut proto = SnapshottableDirectoryStatusProto::default();
+        proto.set_dirStatus(file_status_proto(HdfsFileStatusProto_FileType::IS_DIR));
+        proto.set_snapshot_number(9);
+        proto.set_snapshot_quota(65536);
+        proto.set_parent_fullpath(b"/data".to_vec());
+        let dir = SnapshottableDirectoryStatus::from(proto);
+        assert_eq!((dir.snapshot_number, dir.snapshot_quota), (9, 65536));
+        assert_eq!(dir.full_path(), "/data/name");
+
+        let mut proto = SnapshotStatusProto::default();
+        proto.set_dirStatus(file_status_proto(HdfsFileStatusProto_FileType::IS_DIR));
+        proto.set_snapshotID(10);
+        proto.set_isDeleted(true);
+        proto.set_parent_fullpath(b"/".to_vec());
+        let snapshot = SnapshotStatus::from(proto);
+        assert_eq!(snapshot.snapshot_id, 10);
+        assert!(snapshot.is_deleted);
+        assert_eq!(snapshot.full_path(), "/.snapshot/name");
+    }
+
+    #[test]
+    fn test_located_block_fields() {
+        let mut proto = LocatedBlockProto::default();
+        let b = proto.mut_b();
+        b.set_poolId("pool".to_owned());
+        b.set_blockId(1);
+        b.set_generationStamp(2);
+        b.set_numBytes(3);
+        proto.set_offset(4);
+        proto.set_corrupt(true);
+        for host in &["dn1", "dn2"] {
+            let mut id = datanode_id_proto();
+            id.set_hostName(host.to_string());
+            proto.mut_locs().push_default().set_id(id);
+        }
+        proto.set_isCached(vec![false, true]);
+        proto.set_storageIDs(vec!["s1".to_owned(), "s2".to_owned()].into());
+        proto.set_storageTypes(vec![StorageType::DISK, StorageType::ARCHIVE]);
+        let token = proto.mut_blockToken();
+        token.set_identifier(b"id".to_vec());
+        token.set_password(b"pwd".to_vec());
+        token.set_kind("kind".to_owned());
+        token.set_service("service".to_owned());
+
+        let block = LocatedBlock::from(proto);
+        assert_eq!(block.b.pool_id.as_ref(), "pool");
+        assert_eq!(
+            [
+                block.b.block_id,
+                block.b.generation_stamp,
+                block.b.num_bytes,
+                block.offset,
+            ],
+            [1, 2, 3, 4]
+        );
+        assert!(block.corrupt);
+        assert_eq!(block.locs.len(), 2);
+        assert_eq!(block.cached_locs.len(), 1);
+        assert_eq!(block.cached_locs[0].id.host_name.as_ref(), "dn2");
+        assert_eq!(block.storage_ids, ["s1", "s2"]);
+        assert_eq!(
+            block.storage_types,
+            [StorageType::DISK, StorageType::ARCHIVE]
+        );
+        assert_eq!(block.block_token.identifier, b"id");
+        assert_eq!(block.block_token.password, b"pwd");
+        assert_eq!(block.block_token.kind.as_ref(), "kind");
+        assert_eq!(block.block_token.service.as_ref(), "service");
+    }
+
+    #[test]
+    fn test_erasure_coding_policy_info_requires_state() {
+        let mut proto = ErasureCodingPolicyProto::default();
+        proto.set_id(1);
+        assert!(ErasureCodingPolicyInfo::try_from(proto.clone()).is_err());
+        proto.set_state(ErasureCodingPolicyState::ENABLED);
+        let info = ErasureCodingPolicyInfo::try_from(proto).unwrap();
+        assert_eq!(info.state, ErasureCodingPolicyState::ENABLED);
+        assert_eq!(info.policy.id, 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut proto = DatanodeInfoProto::default();
+        proto.set_id(datanode_id_proto());
+        proto.set_adminState(AdminState::DECOMMISSIONED);
+        let json = serde_json::to_value(DatanodeInfo::from(proto)).unwrap();
+        assert_eq!(json["admin_state"], "DECOMMISSIONED");
+        assert_eq!(json["id"]["host_name"], "dn1");
+
+        let info: DatanodeInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(info.admin_state, AdminState::DECOMMISSIONED);
+
+        let mut token = TokenProto::default();
+        token.set_password(b"secret".to_vec());
+        let json = serde_json::to_value(Token::from(token)).unwrap();
+        assert!(json.get("password").is_none());
+    }
+
+    fn type_quota_infos() -> StorageTypeQuotaInfosProto {
+        let mut infos = StorageTypeQuotaInfosProto::default();
+        let mut info = StorageTypeQuotaInfoProto::default();
+        info.set_field_type(StorageType::SSD);
+        info.set_quota(40);
+        info.set_consumed(41);
+        infos.mut_typeQuotaInfo().push(info);
+        let mut info = StorageTypeQuotaInfoProto::default();
+        info.set_field_type(StorageType::ARCHIVE);
+        info.set_quota(u64::MAX);
+        info.set_consumed(42);
+        infos.mut_typeQuotaInfo().push(info);
+        infos
+    }
+
+    fn expected_type_quotas() -> Vec<StorageTypeQuota> {
+        vec![
+            StorageTypeQuota {
+                storage_type: StorageType::SSD,
+                quota: Some(40),
+                consumed: 41,
+            },
+            StorageTypeQuota {
+                storage_type: StorageType::ARCHIVE,
+                quota: None,
+                consumed: 42,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_content_summary_fields() {
+        let mut proto = ContentSummaryProto::default();
+        proto.set_length(1);
+        proto.set_fileCount(2);
+        proto.set_directoryCount(3);
+        proto.set_quota(4);
+        proto.set_spaceConsumed(5);
+        proto.set_spaceQuota(u64::MAX);
+        proto.set_typeQuotaInfos(type_quota_infos());
+        proto.set_snapshotLength(6);
+        proto.set_snapshotFileCount(7);
+        proto.set_snapshotDirectoryCount(8);
+        proto.set_snapshotSpaceConsumed(9);
+        proto.set_erasureCodingPolicy("RS-6-3-1024k".to_owned());
+
+        let summary = ContentSummary::from(&proto);
+        assert_eq!(
+            [
+                summary.length,
+                summary.file_count,
+                summary.directory_count,
+                summary.space_consumed,
+                summary.snapshot_length,
+                summary.snapshot_file_count,
+                summary.snapshot_directory_count,
+                summary.snapshot_space_consumed,
+            ],
+            [1, 2, 3, 5, 6, 7, 8, 9]
+        );
+        assert_eq!((summary.quota, summary.space_quota), (Some(4), None));
+        assert_eq!(summary.type_quotas, expected_type_quotas());
+        assert_eq!(
+            summary.erasure_coding_policy.as_deref(),
+            Some("RS-6-3-1024k")
+        );
+
+        let usage = QuotaUsage::from(&proto);
+        assert_eq!(usage, summary.quota_usage());
+        assert_eq!(usage.file_and_directory_count, 5);
+        assert_eq!(usage.type_quotas, expected_type_quotas());
+
+        // Replicated directories have no policy.
+        let summary = ContentSummary::from(&ContentSummaryProto::default());
+        assert_eq!(summary.erasure_coding_policy, None);
+    }
+
+    #[test]
+    fn test_quota_usage_fields() {
+        let mut proto = QuotaUsageProto::default();
+        proto.set_fileAndDirectoryCount(1);
+        proto.set_quota(u64::MAX);
+        proto.set_spaceConsumed(2);
+        proto.set_spaceQuota(3);
+        proto.set_typeQuotaInfos(type_quota_infos());
+
+        assert_eq!(
+            QuotaUsage::from(&proto),
+            QuotaUsage {
+                file_and_directory_count: 1,
+                quota: None,
+                space_consumed: 2,
+                space_quota: Some(3),
+                type_quotas: expected_type_quotas(),
+            }
+        );
+    }
+}
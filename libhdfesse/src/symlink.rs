@@ -0,0 +1,104 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Symlink handling of recursive operations, like the -P, -H and -L
+ * options of find(1).  The namenode resolves links in the middle of a
+ * path by itself; the policy decides whether a link met as an entry
+ * (given as an argument, or listed while walking a tree) is followed.
+ *
+ * Following links may bring the walk back to a directory it is
+ * already in; LoopGuard detects it by the file IDs of the directories
+ * on the current walk path.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Never follow links (-P); a link is an entry by itself.
+    Physical,
+    /// Follow the links given as arguments, but not the ones found
+    /// while walking (-H).  This is what FsShell does.
+    #[default]
+    Arguments,
+    /// Follow all links (-L).
+    Logical,
+}
+
+impl SymlinkPolicy {
+    pub fn follow_arguments(self) -> bool {
+        self != SymlinkPolicy::Physical
+    }
+
+    pub fn follow_in_tree(self) -> bool {
+        self == SymlinkPolicy::Logical
+    }
+}
+
+/**
+ * The directories of the current walk path.  A directory is entered
+ * before its entries are walked and left after that.  File ID zero,
+ * which old namenodes return, is never reported as a loop.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct LoopGuard {
+    path: Vec<u64>,
+}
+
+impl LoopGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enter the directory.  If it is already on the walk path, i.e.
+    /// a followed link makes a loop, false is returned and the
+    /// directory is not entered.
+    pub fn enter(&mut self, file_id: u64) -> bool {
+        if file_id != 0 && self.path.contains(&file_id) {
+            return false;
+        }
+        self.path.push(file_id);
+        true
+    }
+
+    pub fn leave(&mut self) {
+        self.path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        assert!(!SymlinkPolicy::Physical.follow_arguments());
+        assert!(SymlinkPolicy::default().follow_arguments());
+        assert!(!SymlinkPolicy::default().follow_in_tree());
+        assert!(SymlinkPolicy::Logical.follow_in_tree());
+    }
+
+    #[test]
+    fn test_loop_guard() {
+        let mut guard = LoopGuard::new();
+        assert!(guard.enter(1));
+        assert!(guard.enter(2));
+        assert!(!guard.enter(1));
+        guard.leave();
+        // A sibling may be the same directory through another link.
+        assert!(guard.enter(2));
+        assert!(guard.enter(0));
+        assert!(guard.enter(0));
+    }
+}
@@ -0,0 +1,190 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The cluster of the integration tests, shared by the crates of the
+ * workspace.  By default, it is the docker-compose cluster, with the
+ * config hardcoded to match docker/core-site.xml: the auto config is
+ * not used for tests that modify the HDFS tree, as it can be harmful
+ * outside the container.
+ *
+ * HDFESSE_TEST_CONF_DIR points to the Hadoop config of another
+ * cluster, and HDFESSE_TEST_CAPABILITIES lists what the tests may do
+ * there, e.g. "write".  Tests declare the capabilities they need and
+ * fail on clusters without them.  Tests that need what the default
+ * cluster lacks are `#[ignore]`d; docker-compose.ha.yml and
+ * docker-compose.kerberos.yml run them against an HA and a
+ * kerberized cluster.  The tests of Config::auto expect
+ * HADOOP_CONF_DIR to be the same directory.
+ */
+use std::{
+    path::{Path as FsPath, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    fs::Hdfs,
+    hdconfig::{
+        load_config_from_dir, parse_config, Config, NamenodeConfig, NameserviceConfig, HDFS_CONFIG,
+    },
+    path::Path,
+    registry::{connect_hdfs, RegistryError},
+};
+
+pub const CONF_DIR_VAR: &str = "HDFESSE_TEST_CONF_DIR";
+pub const CAPABILITIES_VAR: &str = "HDFESSE_TEST_CAPABILITIES";
+
+pub const DOCKER_HOST: &str = "hadoop";
+pub const DOCKER_PORT: u16 = 9000;
+pub const DOCKER_NAMESERVICE: &str = "default2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// The tests may modify the HDFS tree.
+    Write,
+    /// The nameservice has several namenodes; it is detected from the
+    /// config.
+    Ha,
+    /// The cluster requires Kerberos authentication, which the client
+    /// doesn't support yet; it is detected from the config.
+    Kerberos,
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "write" => Ok(Capability::Write),
+            "ha" => Ok(Capability::Ha),
+            "kerberos" => Ok(Capability::Kerberos),
+            _ => Err(format!("unknown test capability {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestCluster {
+    config: Config,
+    conf_dir: Option<PathBuf>,
+    capabilities: Vec<Capability>,
+}
+
+impl TestCluster {
+    /// The docker-compose cluster.
+    pub fn docker() -> Self {
+        Self::new(docker_config(), None, vec![Capability::Write])
+    }
+
+    /// The cluster of HDFESSE_TEST_CONF_DIR, or the docker one.
+    pub fn from_env() -> Result<Self, String> {
+        let conf_dir = match std::env::var_os(CONF_DIR_VAR) {
+            Some(conf_dir) => PathBuf::from(conf_dir),
+            None => return Ok(Self::docker()),
+        };
+        let conf = load_config_from_dir(&conf_dir, &HDFS_CONFIG);
+        let config = parse_config(&conf);
+        let mut capabilities: Vec<_> = std::env::var(CAPABILITIES_VAR)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|capability| !capability.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        let kerberos = conf
+            .get("hadoop.security.authentication")
+            .is_some_and(|auth| auth.value() == "kerberos");
+        if kerberos && !capabilities.contains(&Capability::Kerberos) {
+            capabilities.push(Capability::Kerberos);
+        }
+        Ok(Self::new(config, Some(conf_dir), capabilities))
+    }
+
+    /**
+     * The cluster, which has to have the capabilities.  Panics if it
+     * lacks them, so that a test run against the wrong cluster
+     * fails instead of passing without testing anything, or if the
+     * environment is invalid.
+     */
+    pub fn require(capabilities: &[Capability]) -> Self {
+        let cluster = Self::from_env().unwrap();
+        let missing: Vec<_> = capabilities
+            .iter()
+            .filter(|capability| !cluster.has(**capability))
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "the test cluster lacks {:?}; see {} and {}",
+            missing,
+            CONF_DIR_VAR,
+            CAPABILITIES_VAR
+        );
+        cluster
+    }
+
+    fn new(config: Config, conf_dir: Option<PathBuf>, mut capabilities: Vec<Capability>) -> Self {
+        let nodes = config
+            .default_fs
+            .as_deref()
+            .and_then(|default_fs| Path::new(default_fs).ok()?.host())
+            .and_then(|host| config.nameservice(&host))
+            .map_or(0, |ns| ns.rpc_nodes.len());
+        if nodes > 1 && !capabilities.contains(&Capability::Ha) {
+            capabilities.push(Capability::Ha);
+        }
+        Self {
+            config,
+            conf_dir,
+            capabilities,
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The config directory for the CLI's --conf; None for the
+    /// docker cluster, whose config is installed in the container.
+    pub fn conf_dir(&self) -> Option<&FsPath> {
+        self.conf_dir.as_deref()
+    }
+
+    /// Connect to the default filesystem.
+    pub fn connect(&self) -> Result<Hdfs, RegistryError> {
+        let default_fs = self.config.default_fs.as_deref().unwrap_or("hdfs:///");
+        connect_hdfs(&self.config, &Path::new(default_fs)?, None)
+    }
+}
+
+/// The config of the docker-compose cluster.
+pub fn docker_config() -> Config {
+    Config {
+        default_fs: Some(format!("hdfs://{}", DOCKER_NAMESERVICE).into()),
+        services: vec![NameserviceConfig {
+            name: DOCKER_NAMESERVICE.into(),
+            rpc_nodes: vec![NamenodeConfig {
+                name: "nn".into(),
+                rpc_address: format!("{}:{}", DOCKER_HOST, DOCKER_PORT).into(),
+                servicerpc_address: format!("{}:{}", DOCKER_HOST, DOCKER_PORT).into(),
+            }],
+        }],
+        client_name_suffix: None,
+        namenode_qps: None,
+    }
+}
@@ -0,0 +1,296 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * In-memory RpcConnection for tests and examples.  Responses are
+ * produced by handlers registered per RPC method; calls to methods
+ * without a handler fail like on a real namenode.
+ */
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use hdfesse_proto::{
+    acl::FsPermissionProto,
+    hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType},
+    ClientNamenodeProtocol::{
+        GetFileInfoRequestProto, GetFileInfoResponseProto, GetListingRequestProto,
+        GetListingResponseProto,
+    },
+};
+use protobuf::Message;
+
+use crate::{
+    fs::Hdfs,
+    ha_rpc::FailoverCounts,
+    path::UriResolver,
+    rpc::{CallOptions, RpcConnection, RpcError, RpcErrorCode, RpcStatus},
+    service::ClientNamenodeService,
+};
+
+type Handler = Box<dyn FnMut(&[u8]) -> Result<Vec<u8>, RpcError> + Send>;
+
+/**
+ * A connection that serves calls with registered handlers.  Requests
+ * and responses are serialized the same way as for the real
+ * connection, so missing required fields are detected.
+ */
+pub struct MockConnection {
+    user: Box<str>,
+    options: CallOptions,
+    handlers: HashMap<String, Handler>,
+    calls: Vec<String>,
+    failover_methods: HashSet<String>,
+    failovers: FailoverCounts,
+}
+
+impl MockConnection {
+    pub fn new(user: &str) -> Self {
+        Self {
+            user: user.into(),
+            options: Default::default(),
+            handlers: Default::default(),
+            calls: Default::default(),
+            failover_methods: Default::default(),
+            failovers: Default::default(),
+        }
+    }
+
+    /// Serve calls of the method with the handler, replacing the
+    /// previous one.
+    pub fn on<I, O, F>(&mut self, method: &str, mut handler: F) -> &mut Self
+    where
+        I: Message,
+        O: Message,
+        F: FnMut(I) -> Result<O, RpcError> + Send + 'static,
+    {
+        self.handlers.insert(
+            method.to_owned(),
+            Box::new(move |input| {
+                let output = handler(I::parse_from_bytes(input)?)?;
+                Ok(output.write_to_bytes()?)
+            }),
+        );
+        self
+    }
+
+    /// Count each call of the method as failed on one namenode and
+    /// re-sent to another, as HaHdfsConnection does.
+    pub fn fail_over_on(&mut self, method: &str) -> &mut Self {
+        self.failover_methods.insert(method.to_owned());
+        self
+    }
+
+    /**
+     * Serve getFileInfo and getListing from a fixed tree of (path,
+     * is_dir, length) entries with absolute paths.  The root and the
+     * parents of the entries are directories.
+     */
+    pub fn serve_tree(&mut self, entries: &[(&str, bool, u64)]) -> &mut Self {
+        let mut tree = BTreeMap::new();
+        tree.insert("/".to_owned(), (true, 0));
+        for (path, is_dir, length) in entries {
+            let mut parent = *path;
+            while let Some(idx) = parent.rfind('/') {
+                parent = &parent[..idx];
+                let dir = if parent.is_empty() { "/" } else { parent };
+                tree.entry(dir.to_owned()).or_insert((true, 0));
+            }
+            tree.insert((*path).to_owned(), (*is_dir, *length));
+        }
+        let tree = Arc::new(tree);
+
+        let info_tree = tree.clone();
+        self.on("getFileInfo", move |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            if let Some((is_dir, length)) = info_tree.get(tree_key(req.get_src())) {
+                resp.set_fs(file_status("", *is_dir, *length));
+            }
+            Ok(resp)
+        })
+        .on("getListing", move |req: GetListingRequestProto| {
+            let mut resp = GetListingResponseProto::default();
+            let dir = tree_key(req.get_src());
+            if tree.get(dir) != Some(&(true, 0)) {
+                return Ok(resp);
+            }
+            let prefix = if dir.ends_with('/') {
+                dir.to_owned()
+            } else {
+                format!("{}/", dir)
+            };
+            let listing = resp.mut_dirList();
+            for (path, (is_dir, length)) in tree.range(prefix.clone()..) {
+                let name = match path.strip_prefix(&prefix) {
+                    Some(name) => name,
+                    None => break,
+                };
+                if !name.is_empty() && !name.contains('/') && name.as_bytes() > req.get_startAfter()
+                {
+                    listing
+                        .mut_partialListing()
+                        .push(file_status(name, *is_dir, *length));
+                }
+            }
+            listing.set_remainingEntries(0);
+            Ok(resp)
+        })
+    }
+
+    /// Names of the methods called so far, in order.
+    pub fn calls(&self) -> &[String] {
+        &self.calls
+    }
+}
+
+/// The path without trailing slashes, which the namenode ignores.
+fn tree_key(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+impl std::fmt::Debug for MockConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockConnection")
+            .field("user", &self.user)
+            .field("methods", &self.handlers.keys().collect::<Vec<_>>())
+            .field("calls", &self.calls)
+            .finish()
+    }
+}
+
+impl RpcConnection for MockConnection {
+    fn get_user(&self) -> &str {
+        &self.user
+    }
+
+    fn call<Output: Message>(
+        &mut self,
+        method_name: Cow<'_, str>,
+        input: &dyn Message,
+    ) -> Result<Output, RpcError> {
+        self.calls.push(method_name.to_string());
+        if self.failover_methods.contains(method_name.as_ref()) {
+            self.failovers.call_failed += 1;
+            self.failovers.retries += 1;
+        }
+        let handler = match self.handlers.get_mut(method_name.as_ref()) {
+            Some(handler) => handler,
+            None => {
+                return Err(remote_error(
+                    &method_name,
+                    "org.apache.hadoop.ipc.RpcNoSuchMethodException",
+                    "Unknown method",
+                ))
+            }
+        };
+        let output = handler(&input.write_to_bytes()?)?;
+        Ok(Output::parse_from_bytes(&output)?)
+    }
+
+    fn shutdown(self) -> Result<(), RpcError> {
+        Ok(())
+    }
+
+    fn call_options(&self) -> &CallOptions {
+        &self.options
+    }
+
+    fn set_call_options(&mut self, options: CallOptions) {
+        self.options = options;
+    }
+
+    fn failover_counts(&self) -> FailoverCounts {
+        self.failovers
+    }
+}
+
+/// Hdfs over the connection, with the paths resolved as for the user
+/// "test" on the namenode "test".
+pub fn mock_hdfs(conn: MockConnection) -> Hdfs<MockConnection> {
+    let resolve = UriResolver::new("test", "test", None, None).expect("valid test resolver");
+    Hdfs::new(ClientNamenodeService::new(conn), resolve)
+}
+
+/// An error as the namenode reports exceptions, for handlers that
+/// fail.
+pub fn remote_error(method: &str, exception: &str, error_msg: &str) -> RpcError {
+    RpcError::ErrorResponse {
+        status: RpcStatus::ERROR,
+        error_msg: error_msg.to_owned(),
+        error_detail: RpcErrorCode::ERROR_APPLICATION,
+        exception: exception.to_owned(),
+        method: method.to_owned(),
+    }
+}
+
+/// A file status with all required fields set.
+pub fn file_status(name: &str, is_dir: bool, length: u64) -> HdfsFileStatusProto {
+    let mut status = HdfsFileStatusProto::default();
+    if is_dir {
+        status.set_fileType(HdfsFileStatusProto_FileType::IS_DIR);
+    } else {
+        status.set_fileType(HdfsFileStatusProto_FileType::IS_FILE);
+    }
+    status.set_path(name.as_bytes().to_vec());
+    status.set_length(length);
+    let mut perm = FsPermissionProto::default();
+    perm.set_perm(if is_dir { 0o755 } else { 0o644 });
+    status.set_permission(perm);
+    status.set_owner("hdfs".to_owned());
+    status.set_group("supergroup".to_owned());
+    status.set_modification_time(0);
+    status.set_access_time(0);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfesse_proto::ClientNamenodeProtocol::{
+        GetFileInfoRequestProto, GetFileInfoResponseProto,
+    };
+
+    #[test]
+    fn test_mock_call() {
+        let mut conn = MockConnection::new("test");
+        conn.on("getFileInfo", |req: GetFileInfoRequestProto| {
+            let mut resp = GetFileInfoResponseProto::default();
+            resp.set_fs(file_status(req.get_src(), false, 42));
+            Ok(resp)
+        });
+        let mut req = GetFileInfoRequestProto::default();
+        req.set_src("/file".to_owned());
+        let resp: GetFileInfoResponseProto = conn.call("getFileInfo".into(), &req).unwrap();
+        assert_eq!(resp.get_fs().get_path(), b"/file");
+        assert_eq!(resp.get_fs().get_length(), 42);
+    }
+
+    #[test]
+    fn test_mock_unknown_method() {
+        let mut conn = MockConnection::new("test");
+        let res: Result<GetFileInfoResponseProto, _> =
+            conn.call("getFileInfo".into(), &GetFileInfoRequestProto::default());
+        assert_eq!(
+            res.unwrap_err().get_class_name(),
+            Some("org.apache.hadoop.ipc.RpcNoSuchMethodException")
+        );
+        assert_eq!(conn.calls(), &["getFileInfo".to_owned()]);
+    }
+}
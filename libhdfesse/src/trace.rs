@@ -0,0 +1,34 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Logging macros: tracing's ones, or no-ops if the crate is built
+ * without the "tracing" feature.
+ */
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, info, trace, warn};
+
+// Plain format arguments are still borrowed, so that variables used
+// only for logging are not reported as unused.
+#[cfg(not(feature = "tracing"))]
+macro_rules! noop {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{
+        let _ = ($(&$arg),*);
+    }};
+    ($($arg:tt)*) => {};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use {noop as debug, noop as info, noop as trace, noop as warn};
@@ -0,0 +1,160 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * The identity of the client, resolved as Java's UserGroupInformation
+ * does with SIMPLE authentication: HADOOP_USER_NAME overrides the OS
+ * user, and HADOOP_PROXY_USER makes the login user act on behalf of
+ * another one.
+ */
+use std::error::Error;
+
+use crate::{auth::Credentials, util};
+
+/// Overrides the login user.
+pub const HADOOP_USER_NAME: &str = "HADOOP_USER_NAME";
+/// The login user acts on behalf of this user.
+pub const HADOOP_PROXY_USER: &str = "HADOOP_PROXY_USER";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    name: String,
+    real_user: Option<String>,
+}
+
+impl User {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            real_user: None,
+        }
+    }
+
+    /// The real user acts on behalf of the user; the cluster has to
+    /// allow it with hadoop.proxyuser.* settings.
+    pub fn proxy(name: &str, real_user: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            real_user: Some(real_user.to_owned()),
+        }
+    }
+
+    /// The user that the credentials authenticate, by its short name.
+    pub fn from_credentials(credentials: &Credentials) -> Self {
+        Self::new(short_name(credentials.user()))
+    }
+
+    /// HADOOP_USER_NAME if set, otherwise the OS user.
+    pub fn login() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::login_from(env_var)
+    }
+
+    /// The login user, on behalf of HADOOP_PROXY_USER if set.
+    pub fn current() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::current_from(env_var)
+    }
+
+    fn login_from(
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let name = match env(HADOOP_USER_NAME) {
+            Some(name) => name,
+            None => util::get_username()?,
+        };
+        Ok(Self::new(&name))
+    }
+
+    fn current_from(
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let login = Self::login_from(&env)?;
+        Ok(match env(HADOOP_PROXY_USER) {
+            Some(proxy_user) => Self::proxy(&proxy_user, &login.name),
+            None => login,
+        })
+    }
+
+    /// The effective user: the namenode checks its permissions.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn real_user(&self) -> Option<&str> {
+        self.real_user.as_deref()
+    }
+
+    /// The user that authenticates the connection: the real user of
+    /// a proxy, otherwise the user itself.
+    pub fn authenticated(&self) -> &str {
+        self.real_user.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Empty variables are ignored, as by Java.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/**
+ * The short name of a Kerberos principal by the DEFAULT rule of
+ * hadoop.security.auth_to_local: the first component, e.g. "nn" of
+ * "nn/host@REALM".  Custom rules are not supported.  Other names are
+ * returned as is.
+ */
+pub fn short_name(principal: &str) -> &str {
+    let end = principal.find(['/', '@']);
+    &principal[..end.unwrap_or(principal.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_current_user() {
+        let user = User::current_from(env(&[(HADOOP_USER_NAME, "alice")])).unwrap();
+        assert_eq!(user, User::new("alice"));
+        assert_eq!(user.authenticated(), "alice");
+
+        let user = User::current_from(env(&[
+            (HADOOP_USER_NAME, "svc"),
+            (HADOOP_PROXY_USER, "bob"),
+        ]))
+        .unwrap();
+        assert_eq!(user.name(), "bob");
+        assert_eq!(user.real_user(), Some("svc"));
+        assert_eq!(user.authenticated(), "svc");
+    }
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name("nn/host.example.com@EXAMPLE.COM"), "nn");
+        assert_eq!(short_name("alice@EXAMPLE.COM"), "alice");
+        assert_eq!(short_name("alice"), "alice");
+        let credentials = Credentials::Simple {
+            user: "alice@EXAMPLE.COM".to_owned(),
+        };
+        assert_eq!(User::from_credentials(&credentials), User::new("alice"));
+    }
+}
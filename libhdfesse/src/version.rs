@@ -0,0 +1,214 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Version and capability introspection.  Tools and bindings report
+ * it at runtime instead of guessing from the crate version what the
+ * library they are linked with can do.
+ */
+
+/// The version of the libhdfesse crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The version of the Hadoop RPC wire protocol the client speaks.
+pub const RPC_VERSION: u8 = crate::rpc::RPC_VERSION;
+
+/**
+ * An optional namenode operation or library feature.  Operations are
+ * listed when the library implements them; the server may still
+ * reject them if it is too old.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Capability {
+    /// Client-side cancellation of RPC calls.
+    Cancellation,
+    /// Namenode HA failover.
+    HighAvailability,
+    /// `msync` and the observer namenode state ID tracking.
+    Msync,
+    CheckAccess,
+    IsFileClosed,
+    ListCorruptFileBlocks,
+    SatisfyStoragePolicy,
+    RollingUpgrade,
+    DatanodeStorageReport,
+    /// Serde support for the config and status structs (the `serde`
+    /// feature).
+    Serde,
+    /// Proxy users via `realUser` in the connection context.
+    ProxyUser,
+    /// Failover events reported to a listener.
+    FailoverEvents,
+    /// Routing of paths to nameservices by their authority.
+    Federation,
+    /// Quota usage, content summaries and cached quota headroom.
+    Quotas,
+    /// Cached block locations and block-aligned split planning.
+    BlockLocations,
+    /// Snapshot listings.
+    Snapshots,
+    /// Re-sending idempotent calls after a failed connection.
+    CallRetry,
+    /// The caller context in RPC headers.
+    CallerContext,
+    /// Addressing files by their inode ID.
+    FileIds,
+    /// The rate limit of namenode calls.
+    RateLimit,
+    /// Session statistics of the Hdfs handle.
+    Stats,
+    /// Recursive walks with path filters.
+    Walk,
+    /// Block-level data transfer messages (the `data-transfer`
+    /// feature).
+    DataTransfer,
+    /// Client-side envelope encryption (the `envelope` feature).
+    Envelope,
+    /// `Hdfs::call` for any namenode method (the `raw-rpc` feature).
+    RawRpc,
+}
+
+/// Every capability, supported by this build or not.
+static ALL: &[Capability] = &[
+    Capability::Cancellation,
+    Capability::HighAvailability,
+    Capability::Msync,
+    Capability::CheckAccess,
+    Capability::IsFileClosed,
+    Capability::ListCorruptFileBlocks,
+    Capability::SatisfyStoragePolicy,
+    Capability::RollingUpgrade,
+    Capability::DatanodeStorageReport,
+    Capability::Serde,
+    Capability::ProxyUser,
+    Capability::FailoverEvents,
+    Capability::Federation,
+    Capability::Quotas,
+    Capability::BlockLocations,
+    Capability::Snapshots,
+    Capability::CallRetry,
+    Capability::CallerContext,
+    Capability::FileIds,
+    Capability::RateLimit,
+    Capability::Stats,
+    Capability::Walk,
+    Capability::DataTransfer,
+    Capability::Envelope,
+    Capability::RawRpc,
+];
+
+lazy_static::lazy_static! {
+    static ref CAPABILITIES: Vec<Capability> =
+        ALL.iter().copied().filter(|c| c.is_supported()).collect();
+}
+
+impl Capability {
+    /// A stable name for reporting, e.g. `"check_access"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Cancellation => "cancellation",
+            Capability::HighAvailability => "high_availability",
+            Capability::Msync => "msync",
+            Capability::CheckAccess => "check_access",
+            Capability::IsFileClosed => "is_file_closed",
+            Capability::ListCorruptFileBlocks => "list_corrupt_file_blocks",
+            Capability::SatisfyStoragePolicy => "satisfy_storage_policy",
+            Capability::RollingUpgrade => "rolling_upgrade",
+            Capability::DatanodeStorageReport => "datanode_storage_report",
+            Capability::Serde => "serde",
+            Capability::ProxyUser => "proxy_user",
+            Capability::FailoverEvents => "failover_events",
+            Capability::Federation => "federation",
+            Capability::Quotas => "quotas",
+            Capability::BlockLocations => "block_locations",
+            Capability::Snapshots => "snapshots",
+            Capability::CallRetry => "call_retry",
+            Capability::CallerContext => "caller_context",
+            Capability::FileIds => "file_ids",
+            Capability::RateLimit => "rate_limit",
+            Capability::Stats => "stats",
+            Capability::Walk => "walk",
+            Capability::DataTransfer => "data_transfer",
+            Capability::Envelope => "envelope",
+            Capability::RawRpc => "raw_rpc",
+        }
+    }
+
+    /// Whether this build of the library has the capability.
+    pub fn is_supported(self) -> bool {
+        // Exhaustive, so that a new capability has to be decided on.
+        match self {
+            Capability::Serde => cfg!(feature = "serde"),
+            Capability::DataTransfer => cfg!(feature = "data-transfer"),
+            Capability::Envelope => cfg!(feature = "envelope"),
+            Capability::RawRpc => cfg!(feature = "raw-rpc"),
+            Capability::Cancellation
+            | Capability::HighAvailability
+            | Capability::Msync
+            | Capability::CheckAccess
+            | Capability::IsFileClosed
+            | Capability::ListCorruptFileBlocks
+            | Capability::SatisfyStoragePolicy
+            | Capability::RollingUpgrade
+            | Capability::DatanodeStorageReport
+            | Capability::ProxyUser
+            | Capability::FailoverEvents
+            | Capability::Federation
+            | Capability::Quotas
+            | Capability::BlockLocations
+            | Capability::Snapshots
+            | Capability::CallRetry
+            | Capability::CallerContext
+            | Capability::FileIds
+            | Capability::RateLimit
+            | Capability::Stats
+            | Capability::Walk => true,
+        }
+    }
+}
+
+/// All capabilities of this build of the library.
+pub fn capabilities() -> &'static [Capability] {
+    &CAPABILITIES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(VERSION, env!("CARGO_PKG_VERSION"));
+        assert_eq!(RPC_VERSION, 9);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        assert!(Capability::Msync.is_supported());
+        assert_eq!(Capability::Serde.is_supported(), cfg!(feature = "serde"));
+        assert!(capabilities().iter().any(|c| c.name() == "check_access"));
+        assert_eq!(
+            Capability::Envelope.is_supported(),
+            cfg!(feature = "envelope")
+        );
+    }
+
+    #[test]
+    fn test_all_capabilities() {
+        let names: std::collections::HashSet<_> = ALL.iter().map(|c| c.name()).collect();
+        assert_eq!(names.len(), ALL.len());
+    }
+}
@@ -4,3 +4,9 @@ mod common;
 mod test_config;
 #[cfg(feature = "integration_test")]
 mod test_connect;
+#[cfg(feature = "integration_test")]
+mod test_ha;
+#[cfg(feature = "integration_test")]
+mod test_kerberos;
+#[cfg(feature = "integration_test")]
+mod test_touch;
@@ -0,0 +1,31 @@
+use libhdfesse::{
+    ha_rpc::HaHdfsConnection,
+    path::UriResolver,
+    rpc::SimpleConnector,
+    service::ClientNamenodeService,
+    test_cluster::{Capability, TestCluster},
+};
+
+/// The active namenode is found whichever namenode is tried first.
+#[test]
+#[ignore = "needs an HA cluster, see docker-compose.ha.yml"]
+fn test_failover_to_active() -> Result<(), Box<dyn std::error::Error>> {
+    let cluster = TestCluster::require(&[Capability::Ha]);
+    let hdfs = cluster.connect()?;
+    let host = hdfs.authority();
+    let mut ns = cluster.config().nameservice(&host).unwrap().clone();
+
+    let mut standby_failovers = 0;
+    for _ in 0..ns.rpc_nodes.len() {
+        let client = HaHdfsConnection::new(&ns, SimpleConnector {})?;
+        let service = ClientNamenodeService::new(client);
+        let resolve = UriResolver::new(&host, service.get_user(), None, None)?;
+        let mut hdfs = libhdfesse::fs::Hdfs::new(service, resolve);
+        hdfs.get_status()?;
+        standby_failovers += hdfs.stats().failovers;
+        ns.rpc_nodes.rotate_left(1);
+    }
+    // Only one of the namenodes is active.
+    assert!(standby_failovers >= 1);
+    Ok(())
+}
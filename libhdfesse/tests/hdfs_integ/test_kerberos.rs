@@ -0,0 +1,35 @@
+use hdfesse_proto::ClientNamenodeProtocol::{
+    GetServerDefaultsRequestProto, GetServerDefaultsResponseProto,
+};
+use libhdfesse::{
+    rpc::{HandshakeError, HdfsConnection, RpcConnection, RpcError, SimpleConnector},
+    test_cluster::{Capability, TestCluster},
+};
+
+/// Without SASL, a kerberized namenode rejects the connection, and it
+/// is reported as such rather than as a protocol error.
+#[test]
+#[ignore = "needs a kerberized cluster, see docker-compose.kerberos.yml"]
+fn test_kerberos_rejects_simple_auth() -> Result<(), Box<dyn std::error::Error>> {
+    let cluster = TestCluster::require(&[Capability::Kerberos]);
+    let ns = &cluster.config().services[0];
+    let mut conn = HdfsConnection::new(
+        "hdfesse".into(),
+        ns.rpc_nodes[0].rpc_address.as_ref(),
+        &SimpleConnector {},
+    )?;
+
+    let res: Result<GetServerDefaultsResponseProto, _> = conn.call(
+        "getServerDefaults".into(),
+        &GetServerDefaultsRequestProto::default(),
+    );
+    assert!(
+        matches!(
+            res,
+            Err(RpcError::Handshake(HandshakeError::Unauthorized { .. }))
+        ),
+        "{:?}",
+        res
+    );
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use libhdfesse::{
+    fs::TouchOptions,
+    path::Path,
+    test_cluster::{Capability, TestCluster},
+};
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn test_touch_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let cluster = TestCluster::require(&[Capability::Write]);
+    let mut hdfs = cluster.connect()?;
+    let path = Path::new("/tmp/hdfesse_test_touch")?;
+    let _ = hdfs.delete(&path, false);
+
+    hdfs.touch(&path, &TouchOptions::new())?;
+    let status = hdfs.get_file_info(&path)?;
+    assert_eq!(status.get_length(), 0);
+
+    // The namenode keeps milliseconds.
+    let time = UNIX_EPOCH + Duration::from_nanos(1_600_000_000_123_456_789);
+    hdfs.touch(&path, &TouchOptions::new().with_time(time))?;
+    let status = hdfs.get_file_info(&path)?;
+    assert_eq!(status.get_modification_time(), 1_600_000_000_123);
+    assert_eq!(status.get_access_time(), 1_600_000_000_123);
+
+    let time = UNIX_EPOCH + Duration::from_millis(1_500_000_000_001);
+    hdfs.set_times(&path, Some(time), None)?;
+    let status = hdfs.get_file_info(&path)?;
+    assert_eq!(status.get_modification_time(), 1_500_000_000_001);
+    assert_eq!(status.get_access_time(), 1_600_000_000_123);
+
+    hdfs.delete(&path, false)?;
+    Ok(())
+}
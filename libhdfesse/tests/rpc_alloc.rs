@@ -0,0 +1,213 @@
+/*
+   Copyright 2021 Ivan Boldyrev
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+/*!
+ * Allocations of the RPC hot path: a steady stream of getFileInfo
+ * calls over loopback, counted by a global allocator.  It is a
+ * separate test binary, so that the allocator counts nothing else.
+ */
+use libhdfesse::proto::{
+    hdfs::{HdfsFileStatusProto, HdfsFileStatusProto_FileType},
+    ClientNamenodeProtocol::{GetFileInfoRequestProto, GetFileInfoResponseProto},
+    ProtobufRpcEngine::RequestHeaderProto,
+    RpcHeader::{
+        RpcRequestHeaderProto, RpcRequestHeaderProto_OperationProto, RpcResponseHeaderProto,
+    },
+};
+use libhdfesse::{
+    auth::StaticUser,
+    ha_rpc::HaHdfsConnection,
+    hdconfig::{NamenodeConfig, NameserviceConfig},
+    rpc::{HdfsConnection, RpcConnection, RpcStatus, SimpleConnector},
+    service::ClientNamenodeService,
+};
+use protobuf::{CodedInputStream, CodedOutputStream, Message};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+/// Counts the allocations of each thread, so that the server thread
+/// doesn't count.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn read_frame(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).ok()?;
+    let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+fn write_frame(stream: &mut TcpStream, messages: &[&dyn Message]) {
+    let mut frame = vec![];
+    {
+        let mut cos = CodedOutputStream::vec(&mut frame);
+        for msg in messages {
+            msg.write_length_delimited_to(&mut cos).unwrap();
+        }
+        cos.flush().unwrap();
+    }
+    stream
+        .write_all(&(frame.len() as u32).to_be_bytes())
+        .unwrap();
+    stream.write_all(&frame).unwrap();
+}
+
+/// Answer the calls with the same status until the client
+/// disconnects.
+fn serve(listener: TcpListener) {
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut preamble = [0u8; 7];
+    stream.read_exact(&mut preamble).unwrap();
+    // The connection context.
+    read_frame(&mut stream).unwrap();
+
+    let mut status = HdfsFileStatusProto::default();
+    status.set_fileType(HdfsFileStatusProto_FileType::IS_FILE);
+    status.set_path(b"part-00000".to_vec());
+    status.set_length(128 * 1024 * 1024);
+    status.mut_permission().set_perm(0o644);
+    status.set_owner("hdfs".to_owned());
+    status.set_group("supergroup".to_owned());
+    status.set_modification_time(1_600_000_000_000);
+    status.set_access_time(1_600_000_000_000);
+    let mut resp = GetFileInfoResponseProto::default();
+    resp.set_fs(status);
+
+    while let Some(data) = read_frame(&mut stream) {
+        let mut cis = CodedInputStream::from_bytes(&data);
+        let hh: RpcRequestHeaderProto = cis.read_message().unwrap();
+        if hh.get_rpcOp() == RpcRequestHeaderProto_OperationProto::RPC_CLOSE_CONNECTION {
+            break;
+        }
+        let _rh: RequestHeaderProto = cis.read_message().unwrap();
+        let mut resp_header = RpcResponseHeaderProto::default();
+        resp_header.set_callId(hh.get_callId() as u32);
+        resp_header.set_status(RpcStatus::SUCCESS);
+        write_frame(&mut stream, &[&resp_header, &resp]);
+    }
+}
+
+#[test]
+fn test_get_file_info_allocations() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || serve(listener));
+
+    let mut conn = HdfsConnection::new("test".into(), addr, &SimpleConnector {}).unwrap();
+    let mut args = GetFileInfoRequestProto::default();
+    args.set_src("/user/test/data/part-00000".to_owned());
+    let mut calls = |n: usize| {
+        let before = allocations();
+        for _ in 0..n {
+            let resp: GetFileInfoResponseProto = conn.call("getFileInfo".into(), &args).unwrap();
+            drop(resp);
+        }
+        allocations() - before
+    };
+
+    // The first call fills the scratch buffers and headers.
+    calls(10);
+    // Only the response status allocates: the box of it and of its
+    // permission, the path, the owner and the group.  A few more are
+    // tolerated for whatever the OS or the runtime does now and then.
+    let allocated = calls(1000);
+    assert!(
+        allocated <= 5 * 1000 + 10,
+        "{} allocations for 1000 calls",
+        allocated
+    );
+
+    conn.shutdown().unwrap();
+    server.join().unwrap();
+}
+
+/// The same calls as a client handle makes them: through the service
+/// and the failover of a single-node nameservice.
+#[test]
+fn test_ha_service_allocations() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = std::thread::spawn(move || serve(listener));
+
+    let ns = NameserviceConfig {
+        name: "test".into(),
+        rpc_nodes: vec![NamenodeConfig {
+            name: "nn1".into(),
+            rpc_address: addr.as_str().into(),
+            servicerpc_address: addr.as_str().into(),
+        }],
+    };
+    let conn = HaHdfsConnection::with_auth_provider(
+        &ns,
+        Arc::new(StaticUser("test".to_owned())),
+        SimpleConnector {},
+    )
+    .unwrap();
+    let mut service = ClientNamenodeService::new(conn);
+    let mut calls = |n: usize| {
+        let before = allocations();
+        for _ in 0..n {
+            let status = service
+                .getFileInfo("/user/test/data/part-00000".to_owned())
+                .unwrap();
+            drop(status);
+        }
+        allocations() - before
+    };
+
+    calls(10);
+    // The path argument and the status, as above: neither the
+    // failover nor the call counts allocate.
+    let allocated = calls(1000);
+    assert!(
+        allocated <= 6 * 1000 + 10,
+        "{} allocations for 1000 calls",
+        allocated
+    );
+
+    service.into_inner().shutdown().unwrap();
+    server.join().unwrap();
+}
@@ -13,8 +13,17 @@
    See the License for the specific language governing permissions and
    limitations under the License.
 */
-use libhdfesse::{fs, rpc};
-use std::os::raw::c_int;
+/*!
+ * Errors are reported twice: with errno, for compatibility with
+ * libhdfs, and as a thread-local last error with the message and the
+ * Java exception class (see hdfesseGetLastError).  Both are set by
+ * set_last_error from the same mapping.
+ */
+use libhdfesse::{error_code::ErrorCode, fs, path::PathError, rpc};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr::null;
 use thiserror::Error;
 
 /**
@@ -26,51 +35,184 @@ pub enum LibError {
     Hdfs(#[from] fs::HdfsError),
     #[error(transparent)]
     NulString(#[from] std::ffi::NulError),
+    #[error(transparent)]
+    Path(#[from] PathError),
     #[error("OOM allocating")]
     Oom,
+    /// An error detected by the shim itself.
+    #[error("{message}")]
+    Errno {
+        errnum: c_int,
+        message: &'static str,
+    },
 }
 
-static EXCEPTION_INFO: ::phf::Map<&'_ str, c_int> = ::phf::phf_map! {
-    // TODO: actually, there are the exception generated by local JVM;
-    // however, we handle it for remote exceptions, what is wrong.
-    // We should further extend the FsError enum (as we handle the
-    // NotFound), using this map as a base.
-    "java.io.FileNotFoundException" => libc::ENOENT,
-    "org.apache.hadoop.security.AccessControlException" => libc::EACCES,
-    "org.apache.hadoop.fs.UnresolvedLinkException" => libc::ENOLINK,
-    "org.apache.hadoop.fs.ParentNotDirectoryException" => libc::ENOTDIR,
-    "java.lang.IllegalArgumentException" => libc::EINVAL,
-    "java.lang.OutOfMemoryError" => libc::ENOMEM,
-    "org.apache.hadoop.hdfs.server.namenode.SafeModeException" => libc::EROFS,
-    "org.apache.hadoop.fs.FileAlreadyExistsException" => libc::EEXIST,
-    "org.apache.hadoop.hdfs.protocol.QuotaExceededException" => libc::EDQUOT,
-    "java.lang.UnsupportedOperationException" => libc::ENOTSUP,
-};
+impl LibError {
+    pub(crate) fn errno(errnum: c_int, message: &'static str) -> Self {
+        LibError::Errno { errnum, message }
+    }
+
+    fn errnum(&self) -> c_int {
+        match self {
+            LibError::Hdfs(he) => match &he.source {
+                // Keep the original errno of local errors.
+                fs::FsError::Rpc(rpc::RpcError::Io(e)) | fs::FsError::Local(e) => e
+                    .raw_os_error()
+                    .unwrap_or_else(|| errno_for_code(ErrorCode::Io)),
+                source => errno_for_code(source.code()),
+            },
+            LibError::NulString(_) | LibError::Path(_) => errno_for_code(ErrorCode::InvalidPath),
+            LibError::Oom => libc::ENOMEM,
+            LibError::Errno { errnum, .. } => *errnum,
+        }
+    }
+
+    fn exception(&self) -> Option<&str> {
+        match self {
+            LibError::Hdfs(fs::HdfsError {
+                source: fs::FsError::Rpc(e),
+                ..
+            }) => e.get_class_name(),
+            _ => None,
+        }
+    }
+}
 
 // TODO: hdfs.h detects if EINTERNAL defined or not.  It seems we have
 // no such possibility, thus we define it with hdfs.h default value.
 // It breaks binary compatibility in this area.
 pub(crate) const EINTERNAL: c_int = 255;
 
-pub(crate) fn get_error_code(class_name: &str) -> libc::c_int {
-    EXCEPTION_INFO.get(class_name).cloned().unwrap_or(EINTERNAL)
+pub(crate) fn errno_for_code(code: ErrorCode) -> c_int {
+    match code {
+        ErrorCode::InvalidPath | ErrorCode::InvalidArgument => libc::EINVAL,
+        ErrorCode::NotFound => libc::ENOENT,
+        ErrorCode::FileExists => libc::EEXIST,
+        ErrorCode::IsDir => libc::EISDIR,
+        ErrorCode::NotDir => libc::ENOTDIR,
+        ErrorCode::AccessDenied => libc::EACCES,
+        ErrorCode::UnresolvedLink => libc::ENOLINK,
+        ErrorCode::SafeMode => libc::EROFS,
+        ErrorCode::QuotaExceeded => libc::EDQUOT,
+        ErrorCode::Unsupported => libc::ENOTSUP,
+        ErrorCode::OutOfMemory => libc::ENOMEM,
+        ErrorCode::Io => libc::EIO,
+        ErrorCode::TimedOut => libc::ETIMEDOUT,
+        ErrorCode::Cancelled => libc::ECANCELED,
+        ErrorCode::Internal | ErrorCode::Protocol => EINTERNAL,
+        // ErrorCode is non-exhaustive.
+        _ => EINTERNAL,
+    }
 }
 
-pub(crate) unsafe fn set_errno_with_hadoop_error<E: Into<LibError>>(e: E) {
-    let the_errno = match e.into() {
-        LibError::Hdfs(he) => match he.source {
-            fs::FsError::NotFound(_) => libc::ENOENT,
-            fs::FsError::FileExists(_) => libc::EEXIST,
-            fs::FsError::IsDir(_) => libc::EISDIR,
-            fs::FsError::NotDir(_) => libc::ENOTDIR,
-            fs::FsError::Rpc(r) => match r {
-                rpc::RpcError::Io(e) => e.raw_os_error().unwrap_or(EINTERNAL),
-                _ => r.get_class_name().map(get_error_code).unwrap_or(EINTERNAL),
-            },
-            fs::FsError::Path(_) => libc::EINVAL,
-        },
-        LibError::NulString(_) => libc::EINVAL,
-        LibError::Oom => libc::ENOMEM,
+struct LastError {
+    errnum: c_int,
+    message: CString,
+    exception: Option<CString>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+fn c_string(s: String) -> CString {
+    // Messages may quote user input.
+    CString::new(s.replace('\0', "")).expect("NUL bytes are removed")
+}
+
+/// Set errno and the thread's last error.
+pub(crate) fn set_last_error<E: Into<LibError>>(e: E) {
+    let e = e.into();
+    let errnum = e.errnum();
+    let last = LastError {
+        errnum,
+        message: c_string(e.to_string()),
+        exception: e.exception().map(|class| c_string(class.to_owned())),
     };
-    errno::set_errno(errno::Errno(the_errno));
+    LAST_ERROR.with(|cell| *cell.borrow_mut() = Some(last));
+    errno::set_errno(errno::Errno(errnum));
+}
+
+/**
+The last error of the calling thread, as returned by
+hdfesseGetLastError.  The strings are owned by the library and stay
+valid until the next failed call in the same thread; exception is null
+unless the namenode has reported the error.  errnum is zero and the
+strings are null if no call has failed in the thread.
+*/
+#[repr(C)]
+pub struct hdfesseError {
+    pub errnum: c_int,
+    pub message: *const c_char,
+    pub exception: *const c_char,
+}
+
+pub(crate) fn last_error() -> hdfesseError {
+    LAST_ERROR.with(|cell| match &*cell.borrow() {
+        Some(last) => hdfesseError {
+            errnum: last.errnum,
+            message: last.message.as_ptr(),
+            exception: last
+                .exception
+                .as_ref()
+                .map(|exception| exception.as_ptr())
+                .unwrap_or_else(null),
+        },
+        None => hdfesseError {
+            errnum: 0,
+            message: null(),
+            exception: null(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_last_error() {
+        let source = rpc::RpcError::ErrorResponse {
+            status: rpc::RpcStatus::ERROR,
+            error_msg: "Permission denied: user=alice".to_owned(),
+            error_detail: rpc::RpcErrorCode::ERROR_APPLICATION,
+            exception: "org.apache.hadoop.security.AccessControlException".to_owned(),
+            method: "getFileInfo".to_owned(),
+        };
+        set_last_error(fs::HdfsError::src(fs::FsError::Rpc(source)));
+        let err = last_error();
+        assert_eq!(err.errnum, libc::EACCES);
+        assert_eq!(errno::errno().0, libc::EACCES);
+        let message = unsafe { CStr::from_ptr(err.message) }.to_str().unwrap();
+        assert!(message.contains("Permission denied"), "{}", message);
+        let exception = unsafe { CStr::from_ptr(err.exception) }.to_str().unwrap();
+        assert_eq!(
+            exception,
+            "org.apache.hadoop.security.AccessControlException"
+        );
+
+        // Other threads have their own errors.
+        std::thread::spawn(|| {
+            let err = last_error();
+            assert_eq!(err.errnum, 0);
+            assert!(err.message.is_null());
+        })
+        .join()
+        .unwrap();
+
+        set_last_error(LibError::errno(libc::ENOTSUP, "no\0streams"));
+        let err = last_error();
+        assert_eq!(err.errnum, libc::ENOTSUP);
+        assert!(err.exception.is_null());
+        let message = unsafe { CStr::from_ptr(err.message) }.to_str().unwrap();
+        assert_eq!(message, "nostreams");
+    }
+
+    #[test]
+    fn test_invalid_path() {
+        let bytes = vec![b'/', 0xff];
+        set_last_error(PathError::Utf8(std::str::from_utf8(&bytes).unwrap_err()));
+        assert_eq!(last_error().errnum, libc::EINVAL);
+    }
 }
@@ -18,15 +18,24 @@
 #![allow(non_camel_case_types)]
 mod errors;
 
+pub use crate::errors::hdfesseError;
 use crate::errors::LibError;
 use hdfesse_proto::hdfs::{
     HdfsFileStatusProto, HdfsFileStatusProto_FileType, HdfsFileStatusProto_Flags,
 };
-use libhdfesse::{fs, path::Path, path::PathError};
+use libhdfesse::{
+    fs,
+    open_options::{OpenMode, OpenOptions},
+    path::Path,
+    path::PathError,
+    status::DirEntry,
+};
 
 use std::ffi::{c_void, CStr, CString};
 use std::os::raw::{c_char, c_int, c_short};
 use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, convert::TryFrom};
 
 macro_rules! expect_mut {
@@ -45,7 +54,7 @@ macro_rules! expect_ref {
     };
 }
 
-/**
+/*
 
 Drop-in replacement of libhdfs.
 
@@ -69,7 +78,6 @@ pub enum tObjectKind {
 #[allow(clippy::upper_case_acronyms)]
 pub type hdfsFS = *mut libhdfesse::fs::Hdfs;
 // TODO make these types distinct
-pub type hdfsStreamBuilder = c_void;
 pub type hadoopRzOptions = c_void;
 pub type hadoopRzBuffer = c_void;
 
@@ -97,6 +105,15 @@ impl hdfsBuilder {
         }
     }
 }
+/// Options collected by the hdfsStreamBuilder* functions.
+pub struct hdfsStreamBuilder {
+    #[allow(unused)]
+    fs: hdfsFS,
+    #[allow(unused)]
+    path: CString,
+    options: OpenOptions,
+}
+
 #[repr(C)]
 pub struct hdfs_internal {}
 
@@ -330,60 +347,179 @@ pub extern "C" fn hdfsDisconnect(_fs: hdfsFS) -> c_int {
     unimplemented!()
 }
 
+/**
+Open a file; the same as building it with hdfsStreamBuilder functions.
+Zero bufferSize, replication or blocksize mean defaults.
+
+# Safety
+
+fs value should be a value constructed with hdfs*Connect* family of
+functions, and path is a null-terminated C string.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsOpenFile(
-    _fs: hdfsFS,
-    _path: *const c_char,
-    _flags: c_int,
-    _bufferSize: c_int,
-    _replication: c_short,
-    _blocksize: tSize,
+pub unsafe extern "C" fn hdfsOpenFile(
+    fs: hdfsFS,
+    path: *const c_char,
+    flags: c_int,
+    bufferSize: c_int,
+    replication: c_short,
+    blocksize: tSize,
 ) -> hdfsFile {
-    unimplemented!()
+    let bld = hdfsStreamBuilderAlloc(fs, path, flags);
+    if bld.is_null() {
+        return null_mut();
+    }
+    let write = expect_ref!(bld).options.mode() != OpenMode::Read;
+    if write
+        && ((bufferSize != 0 && hdfsStreamBuilderSetBufferSize(bld, bufferSize) != 0)
+            || (replication != 0 && hdfsStreamBuilderSetReplication(bld, replication) != 0)
+            || (blocksize != 0 && hdfsStreamBuilderSetDefaultBlockSize(bld, blocksize.into()) != 0))
+    {
+        hdfsStreamBuilderFree(bld);
+        return null_mut();
+    }
+    hdfsStreamBuilderBuild(bld)
 }
 
+/// Open mode for the flags, like in hdfs.h: O_RDONLY, O_WRONLY
+/// (create or overwrite) and O_WRONLY|O_APPEND.
+fn open_mode(flags: c_int) -> Option<OpenMode> {
+    if flags & libc::O_CREAT != 0 && flags & libc::O_EXCL != 0 {
+        return None;
+    }
+    match flags & libc::O_ACCMODE {
+        libc::O_RDONLY => Some(OpenMode::Read),
+        libc::O_WRONLY if flags & libc::O_APPEND != 0 => Some(OpenMode::Append),
+        libc::O_WRONLY => Some(OpenMode::Write),
+        _ => None,
+    }
+}
+
+/**
+Start building a file stream.  The builder is freed by
+hdfsStreamBuilderBuild or hdfsStreamBuilderFree.  O_RDWR and
+O_CREAT|O_EXCL are not supported.
+
+# Safety
+
+fs value should be a value constructed with hdfs*Connect* family of
+functions, and path is a null-terminated C string.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsStreamBuilderAlloc(
-    _fs: hdfsFS,
-    _path: *const c_char,
-    _flags: c_int,
+pub unsafe extern "C" fn hdfsStreamBuilderAlloc(
+    fs: hdfsFS,
+    path: *const c_char,
+    flags: c_int,
 ) -> *mut hdfsStreamBuilder {
-    unimplemented!()
+    match open_mode(flags) {
+        Some(mode) => Box::into_raw(Box::new(hdfsStreamBuilder {
+            fs,
+            path: CStr::from_ptr(path).to_owned(),
+            options: OpenOptions::new(mode),
+        })),
+        None => {
+            errors::set_last_error(LibError::errno(libc::ENOTSUP, "unsupported open flags"));
+            null_mut()
+        }
+    }
 }
 
+/**
+Free the builder without building a stream.
+
+# Safety
+
+bld is a valid pointer returned from hdfsStreamBuilderAlloc function.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsStreamBuilderFree(_bld: *mut hdfsStreamBuilder) {
-    unimplemented!()
+pub unsafe extern "C" fn hdfsStreamBuilderFree(bld: *mut hdfsStreamBuilder) {
+    std::mem::drop(Box::from_raw(bld));
+}
+
+/// Like Java's libhdfs, the setters accept only write streams; other
+/// values fail with EINVAL.
+unsafe fn set_write_option<T, V: TryFrom<T>>(
+    bld: *mut hdfsStreamBuilder,
+    value: T,
+    set: impl FnOnce(OpenOptions, V) -> OpenOptions,
+) -> c_int {
+    let bld = expect_mut!(bld);
+    match V::try_from(value) {
+        Ok(value) if bld.options.mode() != OpenMode::Read => {
+            bld.options = set(bld.options.clone(), value);
+            0
+        }
+        _ => {
+            errors::set_last_error(LibError::errno(
+                libc::EINVAL,
+                "invalid value or read-only stream",
+            ));
+            -1
+        }
+    }
 }
 
+/**
+Set the buffer size of a write stream.
+
+# Safety
+
+bld is a valid pointer returned from hdfsStreamBuilderAlloc function.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsStreamBuilderSetBufferSize(
-    _bld: *mut hdfsStreamBuilder,
-    _bufferSize: i32,
+pub unsafe extern "C" fn hdfsStreamBuilderSetBufferSize(
+    bld: *mut hdfsStreamBuilder,
+    bufferSize: i32,
 ) -> c_int {
-    unimplemented!()
+    set_write_option(bld, bufferSize, OpenOptions::with_buffer_size)
 }
 
+/**
+Set the replication of a new file.
+
+# Safety
+
+bld is a valid pointer returned from hdfsStreamBuilderAlloc function.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsStreamBuilderSetReplication(
-    _bld: *mut hdfsStreamBuilder,
-    _replication: i16,
+pub unsafe extern "C" fn hdfsStreamBuilderSetReplication(
+    bld: *mut hdfsStreamBuilder,
+    replication: i16,
 ) -> c_int {
-    unimplemented!()
+    set_write_option(bld, replication, OpenOptions::with_replication)
 }
 
+/**
+Set the block size of a new file.
+
+# Safety
+
+bld is a valid pointer returned from hdfsStreamBuilderAlloc function.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsStreamBuilderSetDefaultBlockSize(
-    _bld: *mut hdfsStreamBuilder,
-    _defaultBlockSize: i64,
+pub unsafe extern "C" fn hdfsStreamBuilderSetDefaultBlockSize(
+    bld: *mut hdfsStreamBuilder,
+    defaultBlockSize: i64,
 ) -> c_int {
-    unimplemented!()
+    set_write_option(bld, defaultBlockSize, OpenOptions::with_block_size)
 }
 
+/**
+Build the stream and free the builder.  libhdfesse has no file
+streams yet, so it always fails with ENOTSUP.
+
+# Safety
+
+bld is a valid pointer returned from hdfsStreamBuilderAlloc function.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsStreamBuilderBuild(_bld: *mut hdfsStreamBuilder) -> hdfsFile {
-    // TODO free _bld
-    unimplemented!()
+pub unsafe extern "C" fn hdfsStreamBuilderBuild(bld: *mut hdfsStreamBuilder) -> hdfsFile {
+    let _bld = Box::from_raw(bld);
+    errors::set_last_error(LibError::errno(
+        libc::ENOTSUP,
+        "file streams are not supported yet",
+    ));
+    null_mut()
 }
 
 #[no_mangle]
@@ -406,9 +542,39 @@ pub extern "C" fn hdfsCloseFile(_fs: hdfsFS, _file: hdfsFile) -> c_int {
     unimplemented!()
 }
 
+/// Whether hdfsExists follows the original libhdfs semantics.
+static EXISTS_COMPAT: AtomicBool = AtomicBool::new(cfg!(feature = "exists-compat"));
+
+/**
+
+Select hdfsExists semantics: with non-zero compat, it returns 0 if
+path exists, and -1 with errno ENOENT if not, like the original
+libhdfs; otherwise, 1 if path exists and 0 if not.  The default is
+the latter unless the library is built with the "exists-compat"
+feature.  Returns the previous mode.  It is an extension of hdfs.h.
+
+*/
+#[no_mangle]
+pub extern "C" fn hdfsSetExistsCompat(compat: c_int) -> c_int {
+    EXISTS_COMPAT.swap(compat != 0, Ordering::Relaxed) as c_int
+}
+
+/**
+
+Return the error of the last failed call in the calling thread: errno
+value, message and, if the namenode has reported the error, the Java
+exception class.  It is an extension of hdfs.h.
+
+*/
+#[no_mangle]
+pub extern "C" fn hdfesseGetLastError() -> hdfesseError {
+    errors::last_error()
+}
+
 /**
 
-Return 1 if path exists, 0 if not, negative value on error.
+Check if path exists; see hdfsSetExistsCompat for the return values.
+Negative value is returned on error.
 
 # Safety
 
@@ -420,25 +586,60 @@ functions, and path is a null-terminated C string.
 pub unsafe extern "C" fn hdfsExists(fs: hdfsFS, path: *const c_char) -> c_int {
     let path = CStr::from_ptr(path).to_str();
     let fs = expect_mut!(fs);
+    let compat = EXISTS_COMPAT.load(Ordering::Relaxed);
 
     let path = path.map_err(PathError::Utf8).and_then(Path::new);
 
     match path {
-        Ok(path) => match fs.get_file_info(&path) {
-            Ok(_) => 1,
-            Err(e) => match e {
-                // set_errno_with_hadoop_error handles it too, but
-                // for this function it is a normal situation.
-                fs::FsError::NotFound(_) => 0,
-                _ => {
-                    errors::set_errno_with_hadoop_error(fs::HdfsError::src(e));
-                    -1
-                }
-            },
+        Ok(path) => match fs.exists(&path) {
+            Ok(true) if compat => 0,
+            Ok(true) => 1,
+            Ok(false) if compat => {
+                errors::set_last_error(LibError::errno(libc::ENOENT, "path does not exist"));
+                -1
+            }
+            Ok(false) => 0,
+            Err(e) => {
+                errors::set_last_error(e);
+                -1
+            }
         },
-        _ => {
-            // TODO seems to be the only option.
-            errno::set_errno(errno::Errno(errors::EINTERNAL));
+        Err(e) => {
+            errors::set_last_error(e);
+            -1
+        }
+    }
+}
+
+/**
+
+Return 1 if the file at path is closed, 0 if it is still open for
+write, negative value on error.  It is an extension of hdfs.h: unlike
+hdfsFileIsOpenForWrite, it works for files opened by other clients.
+
+# Safety
+
+fs value should be a value constructed with hdfs*Connect* family of
+functions, and path is a null-terminated C string.
+
+*/
+#[no_mangle]
+pub unsafe extern "C" fn hdfsIsFileClosed(fs: hdfsFS, path: *const c_char) -> c_int {
+    let path = CStr::from_ptr(path).to_str();
+    let fs = expect_mut!(fs);
+
+    let path = path.map_err(PathError::Utf8).and_then(Path::new);
+
+    match path {
+        Ok(path) => match fs.is_file_closed(&path) {
+            Ok(closed) => closed as _,
+            Err(e) => {
+                errors::set_last_error(e);
+                -1
+            }
+        },
+        Err(e) => {
+            errors::set_last_error(e);
             -1
         }
     }
@@ -559,13 +760,12 @@ pub unsafe extern "C" fn hdfsDelete(fs: hdfsFS, path: *const c_char, recursive:
         Ok(path) => match fs.delete(&path, recursive != 0) {
             Ok(n) => n as _,
             Err(e) => {
-                errors::set_errno_with_hadoop_error(e);
+                errors::set_last_error(e);
                 -1
             }
         },
-        _ => {
-            // TODO seems to be the only option.
-            errno::set_errno(errno::Errno(errors::EINTERNAL));
+        Err(e) => {
+            errors::set_last_error(e);
             -1
         }
     }
@@ -608,8 +808,8 @@ pub unsafe extern "C" fn hdfsCreateDirectory(fs: hdfsFS, path: *const c_char) ->
 
     let path = match path.map_err(PathError::Utf8).and_then(Path::new) {
         Ok(path) => path,
-        Err(_) => {
-            errno::set_errno(errno::Errno(libc::EINVAL));
+        Err(e) => {
+            errors::set_last_error(e);
             return -1;
         }
     };
@@ -619,11 +819,11 @@ pub unsafe extern "C" fn hdfsCreateDirectory(fs: hdfsFS, path: *const c_char) ->
         Ok(false) => {
             // Actually, mkdirs's success value is *always* true.  We
             // repeat hdfs.c's code that handles this case anyway.
-            errno::set_errno(errno::Errno(libc::EIO));
+            errors::set_last_error(LibError::errno(libc::EIO, "mkdirs failed"));
             -1
         }
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             -1
         }
     }
@@ -665,20 +865,19 @@ struct hdfsExtendedFileInfo {
 }
 
 fn align_to_file_info(len: usize) -> usize {
-    // Compiles to (x & !7)
-    (len + 7) / 8 * 8
+    len.next_multiple_of(8)
 }
 
-impl TryFrom<&HdfsFileStatusProto> for hdfsFileInfo {
-    type Error = LibError;
-
-    fn try_from(fstat: &HdfsFileStatusProto) -> Result<Self, Self::Error> {
+impl hdfsFileInfo {
+    /// The info of the entry of the listing of dir; for getFileInfo
+    /// results, dir is the path itself.
+    fn new(fstat: &HdfsFileStatusProto, dir: &Path<'_>) -> Result<Self, LibError> {
         let mKind = if fstat.get_fileType() == HdfsFileStatusProto_FileType::IS_DIR {
             tObjectKind::kObjectKindDirectory
         } else {
             tObjectKind::kObjectKindFile
         };
-        let mName = CString::new(fstat.get_path())?;
+        let mName = CString::new(fstat.absolute_path(dir)?.to_string())?;
 
         // The original libhdfs has an ugly hack: it places
         // another struct (extInfo) just behind the mOwner allocated string.
@@ -718,6 +917,7 @@ impl TryFrom<&HdfsFileStatusProto> for hdfsFileInfo {
         Ok(hdfsFileInfo {
             mKind,
             mName: mName.into_raw(),
+            // Seconds, as in hdfs.c; the milliseconds are truncated.
             mLastMod: (fstat.get_modification_time() / 1000) as _,
             mSize: fstat.get_length() as _,
             mReplication: fstat.get_block_replication() as _,
@@ -769,7 +969,7 @@ pub unsafe extern "C" fn hdfsListDirectory(
             ptr
         }
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             null_mut()
         }
     }
@@ -792,7 +992,7 @@ unsafe fn hdfs_list_directory_impl(
     stat_iter
         .map(|r| {
             r.map_err(LibError::Hdfs)
-                .and_then(|entry| hdfsFileInfo::try_from(&entry))
+                .and_then(|entry| hdfsFileInfo::new(&entry, &path))
         })
         .collect()
 }
@@ -827,8 +1027,14 @@ pub unsafe extern "C" fn hdfsGetPathInfo(fs: hdfsFS, path: *const c_char) -> *mu
             Ok(fstat) => {
                 // TODO as we deallocate as Box<[T]>, one can create
                 // it from Box<T> instead of Vec.
-                // TODO check instead of unwrap
-                let cont = vec![hdfsFileInfo::try_from(&fstat).unwrap()];
+                let info = match hdfsFileInfo::new(&fstat, &path) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        errors::set_last_error(e);
+                        return null_mut();
+                    }
+                };
+                let cont = vec![info];
 
                 let mut sl = cont.into_boxed_slice();
                 let ptr = sl.as_mut_ptr();
@@ -836,13 +1042,12 @@ pub unsafe extern "C" fn hdfsGetPathInfo(fs: hdfsFS, path: *const c_char) -> *mu
                 ptr
             }
             Err(e) => {
-                errors::set_errno_with_hadoop_error(e);
+                errors::set_last_error(e);
                 null_mut()
             }
         },
-        _ => {
-            // it seems this is the most sane value for non-UTF8 strings.
-            errno::set_errno(errno::Errno(libc::EINVAL));
+        Err(e) => {
+            errors::set_last_error(e);
             null_mut()
         }
     }
@@ -861,7 +1066,7 @@ it is a value put into numEntries pointer.
 */
 #[no_mangle]
 pub unsafe extern "C" fn hdfsFreeFileInfo(hdfsFileInfo: *mut hdfsFileInfo, numEntries: c_int) {
-    let mut data = Box::from_raw(std::slice::from_raw_parts_mut(
+    let mut data = Box::from_raw(std::ptr::slice_from_raw_parts_mut(
         hdfsFileInfo,
         numEntries as _,
     ));
@@ -920,8 +1125,8 @@ pub unsafe extern "C" fn hdfsGetHosts(
     let path = path.map_err(PathError::Utf8).and_then(Path::new);
     let path = match path {
         Ok(path) => path,
-        Err(_) => {
-            errno::set_errno(errno::Errno(libc::EINVAL));
+        Err(e) => {
+            errors::set_last_error(e);
             return null();
         }
     };
@@ -929,7 +1134,7 @@ pub unsafe extern "C" fn hdfsGetHosts(
     let info = match fs.get_file_info(&path) {
         Ok(info) => info,
         Err(e) => {
-            errors::set_errno_with_hadoop_error(fs::HdfsError::op(e));
+            errors::set_last_error(fs::HdfsError::op(e));
             return null();
         }
     };
@@ -937,7 +1142,7 @@ pub unsafe extern "C" fn hdfsGetHosts(
     let block_info = match fs.get_file_block_locations(&info, start as _, length as _) {
         Ok(block_info) => block_info,
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             return null();
         }
     };
@@ -976,9 +1181,35 @@ pub extern "C" fn hdfsGetDefaultBlockSize(_fs: hdfsFS) -> tOffset {
     unimplemented!()
 }
 
+/**
+Return the block size of an existing file, -1 on error.  Unlike the
+original, it doesn't return the default block size of the cluster for
+a path that doesn't exist.
+
+# Safety
+
+fs value should be a value constructed with hdfs*Connect* family of
+functions.  path should be a null-terminated string.
+*/
 #[no_mangle]
-pub extern "C" fn hdfsGetDefaultBlockSizeAtPath(_fs: hdfsFS, _path: *const c_char) -> tOffset {
-    unimplemented!()
+pub unsafe extern "C" fn hdfsGetDefaultBlockSizeAtPath(fs: hdfsFS, path: *const c_char) -> tOffset {
+    let fs = expect_mut!(fs);
+    let path = CStr::from_ptr(path).to_str();
+    let path = match path.map_err(PathError::Utf8).and_then(Path::new) {
+        Ok(path) => path,
+        Err(e) => {
+            errors::set_last_error(e);
+            return -1;
+        }
+    };
+
+    match fs.preferred_block_size(&path) {
+        Ok(size) => size as _,
+        Err(e) => {
+            errors::set_last_error(e);
+            -1
+        }
+    }
 }
 
 /**
@@ -999,7 +1230,7 @@ pub unsafe extern "C" fn hdfsGetCapacity(fs: hdfsFS) -> tOffset {
     match fs.get_status() {
         Ok(stats) => stats.capacity as _,
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             -1
         }
     }
@@ -1023,7 +1254,7 @@ pub unsafe extern "C" fn hdfsGetUsed(fs: hdfsFS) -> tOffset {
     match fs.get_status() {
         Ok(stats) => stats.used as _,
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             -1
         }
     }
@@ -1060,8 +1291,8 @@ pub unsafe extern "C" fn hdfsChmod(fs: hdfsFS, path: *const c_char, mode: c_shor
 
     let path = match path.map_err(PathError::Utf8).and_then(Path::new) {
         Ok(path) => path,
-        Err(_) => {
-            errno::set_errno(errno::Errno(libc::EINVAL));
+        Err(e) => {
+            errors::set_last_error(e);
             return -1;
         }
     };
@@ -1069,7 +1300,7 @@ pub unsafe extern "C" fn hdfsChmod(fs: hdfsFS, path: *const c_char, mode: c_shor
     match fs.chmod(&path, mode as _) {
         Ok(()) => 0,
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             -1
         }
     }
@@ -1077,11 +1308,15 @@ pub unsafe extern "C" fn hdfsChmod(fs: hdfsFS, path: *const c_char, mode: c_shor
 
 const NO_TIME: i64 = -1;
 
-fn time_to_option(time: i64) -> Option<u64> {
-    if time == NO_TIME {
-        None
-    } else {
-        Some(time as _)
+/// hdfsUtime takes seconds while the namenode stores milliseconds.
+/// Negative times are passed on as the Java client does, and -1
+/// means no change.
+fn time_to_system(time: tTime) -> Option<SystemTime> {
+    let since = Duration::from_secs(time.unsigned_abs());
+    match time {
+        NO_TIME => None,
+        time if time < 0 => UNIX_EPOCH.checked_sub(since),
+        _ => UNIX_EPOCH.checked_add(since),
     }
 }
 
@@ -1090,8 +1325,8 @@ fn time_to_option(time: i64) -> Option<u64> {
 hdfsUtime
 @param fs The configured filesystem handle.
 @param path the path to the file or directory
-@param mtime new modification time or -1 for no change
-@param atime new access time or -1 for no change
+@param mtime new modification time in seconds or -1 for no change
+@param atime new access time in seconds or -1 for no change
 @return 0 on success else -1
 
 # Safety
@@ -1112,16 +1347,16 @@ pub unsafe extern "C" fn hdfsUtime(
 
     let path = match path.map_err(PathError::Utf8).and_then(Path::new) {
         Ok(path) => path,
-        Err(_) => {
-            errno::set_errno(errno::Errno(libc::EINVAL));
+        Err(e) => {
+            errors::set_last_error(e);
             return -1;
         }
     };
 
-    match fs.set_time(&path, time_to_option(mtime), time_to_option(atime)) {
+    match fs.set_times(&path, time_to_system(mtime), time_to_system(atime)) {
         Ok(()) => 0,
         Err(e) => {
-            errors::set_errno_with_hadoop_error(e);
+            errors::set_last_error(e);
             -1
         }
     }